@@ -0,0 +1,393 @@
+use crate::evdev_helpers::{self, AXIS_MAX, VIRTUAL_KEYS, VirtualGamepadInfo, VirtualLayout};
+use crate::ff_helpers::FfCapabilities;
+use evdev::{
+    AbsoluteAxisCode, Device, EventSummary, EventType, InputEvent, KeyCode, RelativeAxisCode,
+};
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// How often the combiner and readers check for shutdown while no input arrives
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+/// Holder code of encoder taps, beyond any evdev key code
+const ENCODER_HOLDER: u16 = u16::MAX;
+
+/// Axes of the virtual gamepad
+const VIRTUAL_AXES: [AbsoluteAxisCode; 8] = [
+    AbsoluteAxisCode::ABS_X,
+    AbsoluteAxisCode::ABS_Y,
+    AbsoluteAxisCode::ABS_Z,
+    AbsoluteAxisCode::ABS_RX,
+    AbsoluteAxisCode::ABS_RY,
+    AbsoluteAxisCode::ABS_RZ,
+    AbsoluteAxisCode::ABS_HAT0X,
+    AbsoluteAxisCode::ABS_HAT0Y,
+];
+
+/// Mapping file combining simple evdev devices into one virtual gamepad
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArcadeMapping {
+    /// Name of the virtual gamepad
+    #[serde(default = "default_name")]
+    pub name: String,
+    /// Identity presented to games, e.g. of a pad they recognize
+    pub vendor_id: Option<u16>,
+    pub product_id: Option<u16>,
+    pub devices: Vec<ArcadeDevice>,
+}
+
+fn default_name() -> String {
+    "CtrlAssist Arcade".into()
+}
+
+/// One input device and how its inputs map onto the virtual gamepad, by evdev name
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArcadeDevice {
+    /// Event node, preferably a stable /dev/input/by-id path
+    pub path: PathBuf,
+    /// Grab the device so e.g. a GPIO keyboard doesn't also type on the desktop
+    #[serde(default = "default_grab")]
+    pub grab: bool,
+    /// Keys to virtual buttons, e.g. `KEY_LEFTCTRL = "BTN_SOUTH"`
+    #[serde(default)]
+    pub keys: BTreeMap<String, String>,
+    /// Absolute axes to virtual axes, rescaled to the virtual range
+    #[serde(default)]
+    pub axes: BTreeMap<String, String>,
+    /// Relative axes, e.g. rotary encoders, to buttons tapped per detent in
+    /// the negative and positive direction
+    #[serde(default)]
+    pub encoders: BTreeMap<String, [String; 2]>,
+}
+
+fn default_grab() -> bool {
+    true
+}
+
+/// A device's mapping resolved to evdev codes
+struct ResolvedDevice {
+    keys: HashMap<u16, u16>,
+    axes: HashMap<u16, u16>,
+    encoders: HashMap<u16, [u16; 2]>,
+}
+
+fn parse_key(name: &str) -> Result<u16, String> {
+    KeyCode::from_str(name)
+        .map(|key| key.0)
+        .map_err(|_| format!("Unknown key '{}'", name))
+}
+
+fn parse_button(name: &str) -> Result<u16, String> {
+    let code = parse_key(name)?;
+    if VIRTUAL_KEYS.iter().any(|key| key.0 == code) {
+        Ok(code)
+    } else {
+        Err(format!("'{}' is not a button of the virtual gamepad", name))
+    }
+}
+
+fn parse_axis(name: &str) -> Result<u16, String> {
+    AbsoluteAxisCode::from_str(name)
+        .map(|axis| axis.0)
+        .map_err(|_| format!("Unknown axis '{}'", name))
+}
+
+impl ArcadeMapping {
+    /// Load and check a mapping file
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        let mapping: Self = toml::from_str(&content)?;
+        if mapping.devices.is_empty() {
+            return Err("Mapping file lists no devices".into());
+        }
+        for device in &mapping.devices {
+            device
+                .resolve()
+                .map_err(|e| format!("{}: {}", device.path.display(), e))?;
+        }
+        Ok(mapping)
+    }
+}
+
+impl ArcadeDevice {
+    fn resolve(&self) -> Result<ResolvedDevice, String> {
+        let keys = self
+            .keys
+            .iter()
+            .map(|(from, to)| Ok((parse_key(from)?, parse_button(to)?)))
+            .collect::<Result<_, String>>()?;
+        let axes = self
+            .axes
+            .iter()
+            .map(|(from, to)| {
+                let code = parse_axis(to)?;
+                if !VIRTUAL_AXES.iter().any(|axis| axis.0 == code) {
+                    return Err(format!("'{}' is not an axis of the virtual gamepad", to));
+                }
+                Ok((parse_axis(from)?, code))
+            })
+            .collect::<Result<_, String>>()?;
+        let encoders = self
+            .encoders
+            .iter()
+            .map(|(from, [neg, pos])| {
+                let from = RelativeAxisCode::from_str(from)
+                    .map_err(|_| format!("Unknown relative axis '{}'", from))?;
+                Ok((from.0, [parse_button(neg)?, parse_button(pos)?]))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(ResolvedDevice {
+            keys,
+            axes,
+            encoders,
+        })
+    }
+}
+
+/// Device reader threads, stopped and joined when dropped so grabs are
+/// released by the time the combiner returns
+#[derive(Default)]
+struct Readers {
+    stop: Arc<AtomicBool>,
+    handles: Vec<thread::JoinHandle<()>>,
+}
+
+impl Drop for Readers {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Forward events of a device to the combiner until it goes away or the
+/// readers are stopped
+///
+/// Returns the device's absolute axis ranges, read up front as the reader
+/// thread owns the device.
+fn spawn_device_reader(
+    index: usize,
+    config: &ArcadeDevice,
+    events: Sender<(usize, InputEvent)>,
+    readers: &mut Readers,
+) -> Result<HashMap<u16, (i32, i32)>, Box<dyn Error>> {
+    let mut device = Device::open(&config.path)?;
+    if config.grab {
+        device.grab()?;
+    }
+    let ranges = device
+        .get_absinfo()
+        .map(|infos| {
+            infos
+                .map(|(axis, info)| (axis.0, (info.minimum(), info.maximum())))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    info!("Arcade: reading {}", config.path.display());
+    let stop = Arc::clone(&readers.stop);
+    readers.handles.push(thread::spawn(move || {
+        while !stop.load(Ordering::SeqCst) {
+            let fetched = match evdev_helpers::wait_readable(&device, SHUTDOWN_POLL)
+                .and_then(|readable| readable.then(|| device.fetch_events()).transpose())
+            {
+                Ok(Some(fetched)) => fetched,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Arcade: device {} stopped: {}", index, e);
+                    return;
+                }
+            };
+            for event in fetched {
+                if events.send((index, event)).is_err() {
+                    return;
+                }
+            }
+        }
+    }));
+    Ok(ranges)
+}
+
+/// Combines mapped device inputs into virtual gamepad events
+struct Combiner {
+    devices: Vec<ResolvedDevice>,
+    /// Input range of each mapped absolute axis, per device
+    ranges: Vec<HashMap<u16, (i32, i32)>>,
+    /// Inputs holding each virtual button, as (device, key) pairs
+    held: HashMap<u16, HashSet<(usize, u16)>>,
+}
+
+impl Combiner {
+    fn is_held(&self, button: KeyCode) -> bool {
+        self.held.get(&button.0).is_some_and(|set| !set.is_empty())
+    }
+
+    /// Virtual button events, plus the axis a button also drives, if any
+    fn button_events(&self, button: u16, pressed: bool, out: &mut Vec<InputEvent>) {
+        out.push(InputEvent::new(EventType::KEY.0, button, pressed as i32));
+
+        // Mirror buttons onto axes, as the mux modes do for D-pads and triggers
+        let hat = |neg: KeyCode, pos: KeyCode| {
+            let value = self.is_held(pos) as i32 - self.is_held(neg) as i32;
+            evdev_helpers::scale_stick(value as f32, false)
+        };
+        let (axis, value) = match KeyCode(button) {
+            KeyCode::BTN_DPAD_LEFT | KeyCode::BTN_DPAD_RIGHT => (
+                AbsoluteAxisCode::ABS_HAT0X,
+                hat(KeyCode::BTN_DPAD_LEFT, KeyCode::BTN_DPAD_RIGHT),
+            ),
+            KeyCode::BTN_DPAD_UP | KeyCode::BTN_DPAD_DOWN => (
+                AbsoluteAxisCode::ABS_HAT0Y,
+                hat(KeyCode::BTN_DPAD_UP, KeyCode::BTN_DPAD_DOWN),
+            ),
+            KeyCode::BTN_TL2 => (
+                AbsoluteAxisCode::ABS_Z,
+                evdev_helpers::scale_trigger(pressed as i32 as f32),
+            ),
+            KeyCode::BTN_TR2 => (
+                AbsoluteAxisCode::ABS_RZ,
+                evdev_helpers::scale_trigger(pressed as i32 as f32),
+            ),
+            _ => return,
+        };
+        out.push(InputEvent::new(EventType::ABSOLUTE.0, axis.0, value));
+    }
+
+    /// Translate one device event into virtual gamepad frames
+    ///
+    /// Encoder taps need a press and a release in separate frames, so each
+    /// inner vector is written as its own frame.
+    fn process(&mut self, index: usize, event: InputEvent) -> Vec<Vec<InputEvent>> {
+        let device = &self.devices[index];
+        match event.destructure() {
+            EventSummary::Key(_, key, value) if value <= 1 => {
+                let Some(&button) = device.keys.get(&key.0) else {
+                    return Vec::new();
+                };
+                let holders = self.held.entry(button).or_default();
+                let was_pressed = !holders.is_empty();
+                if value == 1 {
+                    holders.insert((index, key.0));
+                } else {
+                    holders.remove(&(index, key.0));
+                }
+                let pressed = !holders.is_empty();
+                if was_pressed == pressed {
+                    return Vec::new();
+                }
+                let mut out = Vec::new();
+                self.button_events(button, pressed, &mut out);
+                vec![out]
+            }
+            EventSummary::AbsoluteAxis(_, axis, value) => {
+                let Some(&target) = device.axes.get(&axis.0) else {
+                    return Vec::new();
+                };
+                let (min, max) = self.ranges[index]
+                    .get(&axis.0)
+                    .copied()
+                    .unwrap_or((0, AXIS_MAX as i32));
+                let span = (max - min).max(1) as f32;
+                let scaled = ((value - min) as f32 / span * AXIS_MAX).clamp(0.0, AXIS_MAX);
+                vec![vec![InputEvent::new(
+                    EventType::ABSOLUTE.0,
+                    target,
+                    scaled.round() as i32,
+                )]]
+            }
+            EventSummary::RelativeAxis(_, axis, value) => {
+                let Some(&[neg, pos]) = device.encoders.get(&axis.0) else {
+                    return Vec::new();
+                };
+                let button = if value < 0 { neg } else { pos };
+                // Buttons already held by another input aren't tapped over
+                if self.held.get(&button).is_some_and(|set| !set.is_empty()) {
+                    return Vec::new();
+                }
+                let mut frames = Vec::new();
+                for _ in 0..value.unsigned_abs() {
+                    // Hold the button for the press, so mirrored axes see it
+                    let mut press = Vec::new();
+                    self.held
+                        .entry(button)
+                        .or_default()
+                        .insert((index, ENCODER_HOLDER));
+                    self.button_events(button, true, &mut press);
+                    frames.push(press);
+                    let mut release = Vec::new();
+                    self.held.remove(&button);
+                    self.button_events(button, false, &mut release);
+                    frames.push(release);
+                }
+                frames
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Run the combiner until a shutdown signal or every device goes away
+pub fn run_arcade(mapping: ArcadeMapping, shutdown: Receiver<()>) -> Result<(), Box<dyn Error>> {
+    let info = VirtualGamepadInfo {
+        name: mapping.name.clone(),
+        vendor_id: mapping.vendor_id,
+        product_id: mapping.product_id,
+        layout: VirtualLayout::Default,
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut readers = Readers::default();
+    let mut devices = Vec::new();
+    let mut ranges = Vec::new();
+    for (index, config) in mapping.devices.iter().enumerate() {
+        devices.push(config.resolve()?);
+        ranges.push(
+            spawn_device_reader(index, config, tx.clone(), &mut readers)
+                .map_err(|e| format!("Failed to open {}: {}", config.path.display(), e))?,
+        );
+    }
+    // Only readers hold senders now, so the channel closes once all are gone
+    drop(tx);
+
+    // Nothing renders force feedback here, so none is advertised
+    let mut virtual_dev = evdev_helpers::create_virtual_gamepad(&info, &FfCapabilities::default())?;
+    info!(
+        "Arcade: combining {} devices into '{}'",
+        devices.len(),
+        info.name
+    );
+
+    let mut combiner = Combiner {
+        devices,
+        ranges,
+        held: HashMap::new(),
+    };
+    loop {
+        if shutdown.try_recv().is_ok() {
+            return Ok(());
+        }
+        let (index, event) = match rx.recv_timeout(SHUTDOWN_POLL) {
+            Ok(received) => received,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err("All mapped devices disconnected".into());
+            }
+        };
+        for frame in combiner.process(index, event) {
+            virtual_dev.emit(&frame)?;
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::state;
+use crate::sudo_helpers;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the audit log within the state directory
+const LOG_NAME: &str = "audit.log";
+
+/// Append-only record of system changes, `$XDG_STATE_HOME/ctrlassist/audit.log`
+///
+/// Under sudo this is in the invoking user's state directory, so they can
+/// read it with plain `ctrlassist log`.
+fn log_path() -> Option<PathBuf> {
+    Some(state::dir()?.join(LOG_NAME))
+}
+
+/// Record a change to the system, with its value before and after
+///
+/// A failure to record is only logged, never blocking the change itself.
+pub fn record(action: &str, target: &str, before: &str, after: &str) {
+    let line = format!(
+        "{} [pid {}] {} {}: {} -> {}\n",
+        local_time(),
+        std::process::id(),
+        action,
+        target,
+        before,
+        after
+    );
+    let result = state::path(LOG_NAME)
+        .map_err(|e| io::Error::other(e.to_string()))
+        .and_then(|path| {
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            sudo_helpers::hand_over(&path);
+            Ok(file)
+        })
+        .and_then(|mut file| file.write_all(line.as_bytes()));
+    if let Err(e) = result {
+        log::warn!("Failed to write audit log: {}", e);
+    }
+}
+
+/// The last `count` recorded changes, or all if omitted
+pub fn read_entries(count: Option<usize>) -> io::Result<Vec<String>> {
+    let path = log_path().ok_or_else(|| io::Error::other("could not determine state directory"))?;
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let skip = count.map_or(0, |count| lines.len().saturating_sub(count));
+    Ok(lines.into_iter().skip(skip).collect())
+}
+
+/// Current local time as `YYYY-MM-DD HH:MM:SS`
+fn local_time() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs()) as libc::time_t;
+    // SAFETY: tm is plain old data, filled in by localtime_r
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    // SAFETY: both pointers are valid for the duration of the call
+    if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+        return secs.to_string();
+    }
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
@@ -0,0 +1,443 @@
+use crate::notifier::{self, Note};
+use crate::quirks::TargetButton;
+use gilrs::ev::Code;
+use gilrs::{Button, Event, EventType, GamepadId};
+use log::info;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Per-controller debounce windows, in milliseconds (0 disables)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct DebounceConfig {
+    pub primary_ms: u64,
+    pub assist_ms: u64,
+}
+
+/// Accessibility hold timing for one controller, in milliseconds (0 disables)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct HoldConfig {
+    /// How long a button must be held before its press is forwarded
+    pub min_hold_ms: u64,
+    /// How long a forwarded press is kept down, even if released sooner
+    pub min_press_ms: u64,
+}
+
+/// Hold timing for each controller
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct HoldTimingConfig {
+    pub primary: HoldConfig,
+    pub assist: HoldConfig,
+}
+
+/// Buttons of each controller that need a second press to go through, e.g.
+/// ones a game binds to deleting a save or confirming a purchase
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct ConfirmConfig {
+    pub primary: Vec<TargetButton>,
+    pub assist: Vec<TargetButton>,
+    /// How long a first press keeps the button primed for the second
+    pub window_ms: u64,
+}
+
+impl Default for ConfirmConfig {
+    fn default() -> Self {
+        Self {
+            primary: Vec::new(),
+            assist: Vec::new(),
+            window_ms: 2_000,
+        }
+    }
+}
+
+/// Digital button level carried by an event, if it is a filterable button
+///
+/// Both the press/release and the paired value change carry a level, so
+/// whichever arrives first decides and the other follows suit.
+fn button_level(event: &Event) -> Option<(Button, bool, Code)> {
+    let (btn, level, code) = match event.event {
+        EventType::ButtonPressed(btn, code) => (btn, true, code),
+        EventType::ButtonReleased(btn, code) => (btn, false, code),
+        EventType::ButtonChanged(btn, value, code) => (btn, value >= 0.5, code),
+        _ => return None,
+    };
+
+    // Analog triggers and unmapped buttons are passed through untouched
+    if matches!(
+        btn,
+        Button::LeftTrigger2 | Button::RightTrigger2 | Button::Unknown
+    ) {
+        return None;
+    }
+    Some((btn, level, code))
+}
+
+/// Append the event pair for a synthesized button transition
+fn push_transition(id: GamepadId, btn: Button, level: bool, code: Code, out: &mut Vec<Event>) {
+    let (transition, value) = if level {
+        (EventType::ButtonPressed(btn, code), 1.0)
+    } else {
+        (EventType::ButtonReleased(btn, code), 0.0)
+    };
+    out.push(Event::new(id, transition));
+    out.push(Event::new(id, EventType::ButtonChanged(btn, value, code)));
+}
+
+/// Debounce state of a single button
+struct DebounceState {
+    /// Level last forwarded to the mux
+    emitted: bool,
+    /// Level last reported by the controller
+    raw: bool,
+    /// Further transitions are held back until this instant
+    locked_until: Instant,
+    code: Code,
+}
+
+/// Suppresses rapid press/release chatter from worn button switches
+///
+/// A transition is forwarded immediately, then the button is locked for the
+/// debounce window. Transitions during the window are held back, and only the
+/// final level is forwarded once the window ends, so no press is ever stuck.
+pub struct Debouncer {
+    windows: HashMap<GamepadId, Duration>,
+    buttons: HashMap<(GamepadId, Button), DebounceState>,
+}
+
+impl Debouncer {
+    pub fn new(config: &DebounceConfig, primary_id: GamepadId, assist_id: GamepadId) -> Self {
+        let windows = [
+            (primary_id, config.primary_ms),
+            (assist_id, config.assist_ms),
+        ]
+        .into_iter()
+        .filter(|(_, ms)| *ms > 0)
+        .map(|(id, ms)| (id, Duration::from_millis(ms)))
+        .collect();
+
+        Self {
+            windows,
+            buttons: HashMap::new(),
+        }
+    }
+
+    /// Filter an incoming event, returning `None` if it is held back
+    pub fn filter(&mut self, event: Event, now: Instant) -> Option<Event> {
+        let Some(&window) = self.windows.get(&event.id) else {
+            return Some(event);
+        };
+        let Some((btn, level, code)) = button_level(&event) else {
+            return Some(event);
+        };
+
+        let state = self
+            .buttons
+            .entry((event.id, btn))
+            .or_insert(DebounceState {
+                emitted: false,
+                raw: false,
+                locked_until: now,
+                code,
+            });
+        state.raw = level;
+        state.code = code;
+
+        if level == state.emitted {
+            return Some(event);
+        }
+        if now < state.locked_until {
+            return None;
+        }
+
+        state.emitted = level;
+        state.locked_until = now + window;
+        Some(event)
+    }
+
+    /// Append events for held-back transitions whose window has ended
+    pub fn expire(&mut self, now: Instant, out: &mut Vec<Event>) {
+        for (&(id, btn), state) in &mut self.buttons {
+            if state.raw == state.emitted || now < state.locked_until {
+                continue;
+            }
+
+            state.emitted = state.raw;
+            state.locked_until = now + self.windows[&id];
+            push_transition(id, btn, state.raw, state.code, out);
+        }
+    }
+
+    /// Earliest instant a held-back transition becomes due, if any
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.buttons
+            .values()
+            .filter(|state| state.raw != state.emitted)
+            .map(|state| state.locked_until)
+            .min()
+    }
+}
+
+/// Hold timing state of a single button
+struct HoldState {
+    /// Level last forwarded to the mux
+    emitted: bool,
+    /// Level last reported upstream
+    raw: bool,
+    /// When the current physical press began
+    pressed_at: Instant,
+    /// When the current forwarded press began
+    emitted_at: Instant,
+    code: Code,
+}
+
+/// Delays presses until held long enough, and keeps short presses down longer
+///
+/// Taps shorter than the minimum hold never reach the mux, while forwarded
+/// presses stay down for at least the minimum press duration.
+pub struct HoldFilter {
+    configs: HashMap<GamepadId, (Duration, Duration)>,
+    buttons: HashMap<(GamepadId, Button), HoldState>,
+}
+
+impl HoldFilter {
+    pub fn new(config: &HoldTimingConfig, primary_id: GamepadId, assist_id: GamepadId) -> Self {
+        let configs = [(primary_id, &config.primary), (assist_id, &config.assist)]
+            .into_iter()
+            .filter(|(_, hold)| hold.min_hold_ms > 0 || hold.min_press_ms > 0)
+            .map(|(id, hold)| {
+                (
+                    id,
+                    (
+                        Duration::from_millis(hold.min_hold_ms),
+                        Duration::from_millis(hold.min_press_ms),
+                    ),
+                )
+            })
+            .collect();
+
+        Self {
+            configs,
+            buttons: HashMap::new(),
+        }
+    }
+
+    /// Filter an incoming event, returning `None` if it is held back
+    pub fn filter(&mut self, event: Event, now: Instant) -> Option<Event> {
+        let Some(&(min_hold, min_press)) = self.configs.get(&event.id) else {
+            return Some(event);
+        };
+        let Some((btn, level, code)) = button_level(&event) else {
+            return Some(event);
+        };
+
+        let state = self.buttons.entry((event.id, btn)).or_insert(HoldState {
+            emitted: false,
+            raw: false,
+            pressed_at: now,
+            emitted_at: now,
+            code,
+        });
+        if level && !state.raw {
+            state.pressed_at = now;
+        }
+        state.raw = level;
+        state.code = code;
+
+        if level == state.emitted {
+            return Some(event);
+        }
+
+        let due = if level {
+            state.pressed_at + min_hold
+        } else {
+            state.emitted_at + min_press
+        };
+        if now < due {
+            return None;
+        }
+
+        state.emitted = level;
+        state.emitted_at = now;
+        Some(event)
+    }
+
+    /// Append events for held-back transitions that are now due
+    pub fn expire(&mut self, now: Instant, out: &mut Vec<Event>) {
+        for (&(id, btn), state) in &mut self.buttons {
+            if state.raw == state.emitted {
+                continue;
+            }
+            let (min_hold, min_press) = self.configs[&id];
+            let due = if state.raw {
+                state.pressed_at + min_hold
+            } else {
+                state.emitted_at + min_press
+            };
+            if now < due {
+                continue;
+            }
+
+            state.emitted = state.raw;
+            state.emitted_at = now;
+            push_transition(id, btn, state.raw, state.code, out);
+        }
+    }
+
+    /// Earliest instant a held-back transition becomes due, if any
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.buttons
+            .iter()
+            .filter(|(_, state)| state.raw != state.emitted)
+            .map(|((id, _), state)| {
+                let (min_hold, min_press) = self.configs[id];
+                if state.raw {
+                    state.pressed_at + min_hold
+                } else {
+                    state.emitted_at + min_press
+                }
+            })
+            .min()
+    }
+}
+
+/// Confirmation state of a single button
+#[derive(Default)]
+struct ConfirmState {
+    /// Level last reported upstream
+    raw: bool,
+    /// Whether the current press was let through
+    forwarded: bool,
+    /// Until when a second press goes through, after a first one
+    primed_until: Option<Instant>,
+}
+
+/// Lets a guarded button through only when pressed twice in a row
+///
+/// The first press primes the button, showing a notification, and never
+/// reaches the mux; pressing it again within the window does. A lapsed
+/// window primes it afresh.
+pub struct ConfirmFilter {
+    buttons: HashMap<(GamepadId, Button), ConfirmState>,
+    window: Duration,
+}
+
+impl ConfirmFilter {
+    pub fn new(config: &ConfirmConfig, primary_id: GamepadId, assist_id: GamepadId) -> Self {
+        let buttons = [(primary_id, &config.primary), (assist_id, &config.assist)]
+            .into_iter()
+            .flat_map(|(id, buttons)| buttons.iter().map(move |button| (id, button.button())))
+            .map(|key| (key, ConfirmState::default()))
+            .collect();
+        Self {
+            buttons,
+            window: Duration::from_millis(config.window_ms),
+        }
+    }
+
+    /// Filter an incoming event, returning `None` if it is held back
+    pub fn filter(&mut self, event: Event, now: Instant) -> Option<Event> {
+        let Some((btn, level, _)) = button_level(&event) else {
+            return Some(event);
+        };
+        let Some(state) = self.buttons.get_mut(&(event.id, btn)) else {
+            return Some(event);
+        };
+
+        // The other half of a press or release pair follows the first
+        if level == state.raw {
+            return state.forwarded.then_some(event);
+        }
+        state.raw = level;
+        if !level {
+            return std::mem::take(&mut state.forwarded).then_some(event);
+        }
+
+        if state.primed_until.take().is_some_and(|until| now < until) {
+            info!("{:?} on {} confirmed", btn, event.id);
+            state.forwarded = true;
+            return Some(event);
+        }
+        info!("{:?} on {} primed, awaiting confirmation", btn, event.id);
+        state.primed_until = Some(now + self.window);
+        notify_primed(btn, self.window);
+        None
+    }
+}
+
+/// Tell the players a guarded button awaits its second press
+fn notify_primed(btn: Button, window: Duration) {
+    notifier::send(Note {
+        timeout: Some(window),
+        ..Note::new(
+            "CtrlAssist",
+            format!(
+                "Press {:?} again within {:.1}s to confirm",
+                btn,
+                window.as_secs_f32()
+            ),
+        )
+    });
+}
+
+/// Button timing stage applied before events reach the mux: debounce first,
+/// so chatter never starts a hold, then hold timing, then confirmation of
+/// guarded buttons
+pub struct ButtonFilters {
+    debouncer: Debouncer,
+    hold: HoldFilter,
+    confirm: ConfirmFilter,
+    /// Scratch buffer for transitions released by the debouncer
+    debounced: Vec<Event>,
+    /// Scratch buffer for transitions released by hold timing
+    held: Vec<Event>,
+}
+
+impl ButtonFilters {
+    pub fn new(
+        debounce: &DebounceConfig,
+        hold: &HoldTimingConfig,
+        confirm: &ConfirmConfig,
+        primary_id: GamepadId,
+        assist_id: GamepadId,
+    ) -> Self {
+        Self {
+            debouncer: Debouncer::new(debounce, primary_id, assist_id),
+            hold: HoldFilter::new(hold, primary_id, assist_id),
+            confirm: ConfirmFilter::new(confirm, primary_id, assist_id),
+            debounced: Vec::new(),
+            held: Vec::new(),
+        }
+    }
+
+    /// Filter an incoming event, returning `None` if it is held back
+    pub fn filter(&mut self, event: Event, now: Instant) -> Option<Event> {
+        let event = self.debouncer.filter(event, now)?;
+        let event = self.hold.filter(event, now)?;
+        self.confirm.filter(event, now)
+    }
+
+    /// Append events for held-back transitions that are now due
+    pub fn expire(&mut self, now: Instant, out: &mut Vec<Event>) {
+        self.debouncer.expire(now, &mut self.debounced);
+        for event in self.debounced.drain(..) {
+            self.held.extend(self.hold.filter(event, now));
+        }
+        self.hold.expire(now, &mut self.held);
+        for event in self.held.drain(..) {
+            out.extend(self.confirm.filter(event, now));
+        }
+    }
+
+    /// Earliest instant a held-back transition becomes due, if any
+    pub fn next_deadline(&self) -> Option<Instant> {
+        [self.debouncer.next_deadline(), self.hold.next_deadline()]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+}
@@ -0,0 +1,139 @@
+use crate::control_socket;
+use crate::evdev_helpers::VIRTUAL_PHYS_PREFIX;
+use crate::steam_backup::{self, SteamJournal};
+use crate::udev_helpers::{self, HideJournal};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a session gets to shut down on SIGTERM before it's killed
+const TERM_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Undo what crashed or killed sessions left behind, describing what was done
+///
+/// Virtual devices live as long as the process that created them, so they
+/// are reaped by ending their session, which only happens with `kill` as a
+/// session may well be running on purpose. Hidden devices and Steam blacklist
+/// changes are only restored once no other session is running, as they may
+/// be its own.
+pub fn cleanup(kill: bool) -> Result<String, Box<dyn Error>> {
+    let mut report = Vec::new();
+
+    let mut running = false;
+    for (pid, paths) in virtual_devices_by_session() {
+        let nodes: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+        // The PID may since have been reused by an unrelated process
+        if !is_ctrlassist(pid) {
+            report.push(format!(
+                "Process {} of {} is not CtrlAssist, leaving it alone",
+                pid,
+                nodes.join(", ")
+            ));
+            continue;
+        }
+        if !kill {
+            report.push(format!(
+                "Session {} is running with {}; pass --kill to end it",
+                pid,
+                nodes.join(", ")
+            ));
+            running = true;
+        } else if terminate(pid) {
+            report.push(format!(
+                "Ended session {}, removing {}",
+                pid,
+                nodes.join(", ")
+            ));
+        } else {
+            report.push(format!("Failed to end session {}", pid));
+            running = true;
+        }
+    }
+
+    if running {
+        report.push("Left hidden devices and Steam blacklist as they are".into());
+    } else {
+        if HideJournal::load().is_some() {
+            report.push(udev_helpers::restore_hidden().unwrap_or_else(|e| e.to_string()));
+        }
+        if SteamJournal::load().is_some() {
+            report.push(steam_backup::restore_steam().unwrap_or_else(|e| e.to_string()));
+        }
+        // A socket nothing listens on keeps the next `set` from finding a mux
+        let socket = control_socket::socket_path();
+        if socket.exists() && UnixStream::connect(&socket).is_err() {
+            fs::remove_file(&socket)?;
+            report.push(format!("Removed stale control socket {}", socket.display()));
+        }
+    }
+
+    if report.is_empty() {
+        report.push("Nothing to clean up".into());
+    }
+    Ok(report.join("\n"))
+}
+
+/// Event nodes of CtrlAssist virtual devices, by the other session that
+/// created them
+fn virtual_devices_by_session() -> BTreeMap<u32, Vec<PathBuf>> {
+    let own_pid = std::process::id();
+    let mut sessions: BTreeMap<u32, Vec<PathBuf>> = BTreeMap::new();
+    for (path, device) in evdev::enumerate() {
+        let Some(pid) = device
+            .physical_path()
+            .and_then(|phys| phys.strip_prefix(VIRTUAL_PHYS_PREFIX))
+            .and_then(|pid| pid.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if pid != own_pid {
+            sessions.entry(pid).or_default().push(path);
+        }
+    }
+    sessions
+}
+
+/// Whether a process is CtrlAssist, by its executable or, if that can't be
+/// read, its command name
+fn is_ctrlassist(pid: u32) -> bool {
+    let is_named = |name: &str| name == "ctrlassist" || name == "ctrlassist (deleted)";
+    match fs::read_link(format!("/proc/{}/exe", pid)) {
+        Ok(exe) => exe
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(is_named),
+        Err(_) => fs::read_to_string(format!("/proc/{}/comm", pid))
+            .is_ok_and(|comm| is_named(comm.trim_end())),
+    }
+}
+
+/// End a session, letting it restore what it hid before killing it outright,
+/// returning whether it's gone
+///
+/// The process is checked to still be CtrlAssist before each signal.
+fn terminate(pid: u32) -> bool {
+    let alive = || Path::new(&format!("/proc/{}", pid)).exists() && is_ctrlassist(pid);
+    let Ok(pid_t) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if !alive() {
+        return true;
+    }
+    // SAFETY: kill has no memory safety requirements
+    unsafe { libc::kill(pid_t, libc::SIGTERM) };
+    let deadline = Instant::now() + TERM_TIMEOUT;
+    while alive() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(100));
+    }
+    if alive() {
+        log::warn!("Session {} ignored SIGTERM, killing it", pid);
+        // SAFETY: as above
+        unsafe { libc::kill(pid_t, libc::SIGKILL) };
+        thread::sleep(Duration::from_millis(100));
+    }
+    !alive()
+}
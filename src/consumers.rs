@@ -0,0 +1,206 @@
+use crate::evdev_helpers;
+use log::{debug, warn};
+use parking_lot::RwLock;
+use std::ffi::CString;
+use std::fmt;
+use std::fs;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// How often the watcher checks for shutdown and for nodes appearing, e.g.
+/// the hidraw node of the uhid device
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A process with a device node open
+#[derive(Debug, Clone, PartialEq)]
+pub struct Consumer {
+    pub pid: u32,
+    /// Command name, as in /proc/<pid>/comm
+    pub name: String,
+}
+
+impl fmt::Display for Consumer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.pid)
+    }
+}
+
+/// Other processes with any of `paths` open, found through their
+/// /proc/<pid>/fd links
+///
+/// Processes of other users can't be inspected without privileges, so a game
+/// running as another user goes unseen.
+pub fn find(paths: &[PathBuf]) -> Vec<Consumer> {
+    let own_pid = std::process::id();
+    let Ok(procs) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let mut consumers: Vec<Consumer> = procs
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|&pid| pid != own_pid && has_open(pid, paths))
+        .map(|pid| Consumer {
+            pid,
+            name: fs::read_to_string(format!("/proc/{}/comm", pid))
+                .map(|name| name.trim_end().to_string())
+                .unwrap_or_else(|_| "?".into()),
+        })
+        .collect();
+    consumers.sort_by_key(|consumer| consumer.pid);
+    consumers
+}
+
+fn has_open(pid: u32, paths: &[PathBuf]) -> bool {
+    let Ok(fds) = fs::read_dir(format!("/proc/{}/fd", pid)) else {
+        return false;
+    };
+    fds.flatten()
+        .any(|fd| fs::read_link(fd.path()).is_ok_and(|target| paths.contains(&target)))
+}
+
+/// Nodes a game may read the virtual gamepad through: its event node, the
+/// joystick node beside it, and the hidraw node of this session's uhid device
+fn virtual_nodes(event_path: &Path) -> Vec<PathBuf> {
+    let mut nodes = vec![event_path.to_path_buf()];
+    if let Some(name) = event_path.file_name() {
+        let device = Path::new("/sys/class/input").join(name).join("device");
+        let joysticks = fs::read_dir(device).into_iter().flatten().flatten();
+        nodes.extend(
+            joysticks
+                .map(|entry| entry.file_name())
+                .filter(|name| name.as_bytes().starts_with(b"js"))
+                .map(|name| Path::new("/dev/input").join(name)),
+        );
+    }
+    let phys = format!("HID_PHYS={}", evdev_helpers::virtual_phys());
+    let hidraws = fs::read_dir("/sys/class/hidraw")
+        .into_iter()
+        .flatten()
+        .flatten();
+    nodes.extend(
+        hidraws
+            .filter(|entry| {
+                fs::read_to_string(entry.path().join("device/uevent"))
+                    .is_ok_and(|uevent| uevent.lines().any(|line| line == phys))
+            })
+            .map(|entry| Path::new("/dev").join(entry.file_name())),
+    );
+    nodes
+}
+
+/// inotify watches for the virtual gamepad's nodes being opened and closed,
+/// so /proc is only scanned when that happens
+struct NodeWatch {
+    fd: OwnedFd,
+    watched: Vec<PathBuf>,
+}
+
+impl NodeWatch {
+    fn new() -> Option<Self> {
+        // SAFETY: inotify_init1 has no memory safety requirements
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            warn!(
+                "Failed to watch the virtual device's nodes, polling instead: {}",
+                std::io::Error::last_os_error()
+            );
+            return None;
+        }
+        Some(Self {
+            // SAFETY: fd was just returned by inotify_init1 and is owned here
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            watched: Vec::new(),
+        })
+    }
+
+    /// Watch nodes not yet watched, returning whether any were added
+    fn add(&mut self, nodes: &[PathBuf]) -> bool {
+        let mut added = false;
+        for node in nodes {
+            if self.watched.contains(node) {
+                continue;
+            }
+            let Ok(path) = CString::new(node.as_os_str().as_bytes()) else {
+                continue;
+            };
+            let mask = libc::IN_OPEN | libc::IN_CLOSE;
+            // SAFETY: path is a valid NUL-terminated string for the call
+            if unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), mask) } >= 0 {
+                debug!("Watching {} for consumers", node.display());
+                self.watched.push(node.clone());
+                added = true;
+            }
+        }
+        added
+    }
+
+    /// Wait up to `timeout` for nodes to be opened or closed, draining the
+    /// events, and returning whether there were any
+    fn wait(&self, timeout: Duration) -> bool {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: pollfd is valid for the call, which only writes its revents
+        let ready = unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) };
+        if ready <= 0 {
+            return false;
+        }
+        let mut buf = [0u8; 4096];
+        // SAFETY: buf is valid for writes of its length
+        while unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+        true
+    }
+}
+
+/// Keep `consumers` up to date with the processes reading the virtual
+/// gamepad through any of its nodes, logging as they come and go, until
+/// shutdown is flagged
+pub fn spawn_watcher(
+    event_path: PathBuf,
+    consumers: Arc<RwLock<Vec<Consumer>>>,
+    shutdown: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut watch = NodeWatch::new();
+        let mut changed = true;
+        while !shutdown.load(Ordering::SeqCst) {
+            let nodes = virtual_nodes(&event_path);
+            // Without inotify, fall back to scanning every interval
+            changed |= watch.as_mut().is_none_or(|watch| watch.add(&nodes));
+            if changed {
+                let found = find(&nodes);
+                if *consumers.read() != found {
+                    if found.is_empty() {
+                        debug!("Virtual device no longer in use");
+                    } else {
+                        debug!("Virtual device in use by {}", describe(&found));
+                    }
+                    *consumers.write() = found;
+                }
+            }
+            changed = match &watch {
+                Some(watch) => watch.wait(POLL_INTERVAL),
+                None => {
+                    thread::sleep(POLL_INTERVAL);
+                    true
+                }
+            };
+        }
+    })
+}
+
+/// Consumers as a list for display, e.g. "steam (1234), game (5678)"
+pub fn describe(consumers: &[Consumer]) -> String {
+    consumers
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,252 @@
+use crate::RumbleTarget;
+use crate::ff_helpers;
+use crate::mux_modes::{self, ModeType};
+use crate::mux_runtime::RuntimeSettings;
+use crate::sudo_helpers;
+use clap::ValueEnum;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// How long a client may take to send its request
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Request for the mux's state rather than a setting change
+const STATUS_REQUEST: &str = "status";
+
+/// State of the running mux, as sent to `ctrlassist status`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusReport {
+    pub mode: String,
+    pub rumble: String,
+    /// A source controller is disconnected or rumble is failing
+    pub degraded: bool,
+    /// Settings and force feedback state, for display
+    pub details: String,
+    /// Latency histograms of the session, for `status --timing`
+    #[serde(default)]
+    pub timing: String,
+}
+
+/// Runtime setting changeable with `ctrlassist set`
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Setting {
+    /// Mux mode, e.g. average
+    Mode,
+    /// Rumble target, e.g. assist
+    Rumble,
+    /// Stick smoothing, on or off
+    Smoothing,
+    /// Share of the assist in Average mode (0.0-1.0)
+    Weight,
+    /// Blend mode weights as `<primary>,<assist>`, e.g. 0.7,0.3
+    BlendWeights,
+}
+
+impl Setting {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Mode => "mode",
+            Self::Rumble => "rumble",
+            Self::Smoothing => "smoothing",
+            Self::Weight => "weight",
+            Self::BlendWeights => "blend-weights",
+        }
+    }
+}
+
+/// Control socket of the running mux, in the user's runtime directory, that
+/// of the invoking user under sudo
+pub fn socket_path() -> PathBuf {
+    sudo_helpers::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("ctrlassist.sock")
+}
+
+/// Serves `ctrlassist set` requests, stopping its thread and removing the
+/// socket when dropped
+pub struct ControlServer {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake the accept thread, which then sees the stop flag
+        let _ = UnixStream::connect(&self.path);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Start serving runtime setting changes for a running mux
+pub fn start_control_server(settings: RuntimeSettings) -> io::Result<ControlServer> {
+    let path = socket_path();
+    // A socket left behind by a crashed session refuses connections
+    if UnixStream::connect(&path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "another mux is already running",
+        ));
+    }
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    sudo_helpers::hand_over(&path);
+
+    info!("Control socket at {}", path.display());
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = Arc::clone(&stop);
+    let thread = thread::spawn(move || {
+        for stream in listener.incoming() {
+            if thread_stop.load(Ordering::SeqCst) {
+                break;
+            }
+            match stream {
+                Ok(stream) => handle_client(stream, &settings),
+                Err(e) => warn!("Control socket: failed to accept: {}", e),
+            }
+        }
+    });
+    Ok(ControlServer {
+        path,
+        stop,
+        thread: Some(thread),
+    })
+}
+
+fn handle_client(stream: UnixStream, settings: &RuntimeSettings) {
+    // One idle client must not hold up the socket for everyone else
+    let _ = stream.set_read_timeout(Some(CLIENT_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(CLIENT_TIMEOUT));
+    let mut request = String::new();
+    if BufReader::new(&stream).read_line(&mut request).is_err() {
+        return;
+    }
+    if request.trim() == STATUS_REQUEST {
+        match serde_json::to_string(&status_report(settings)) {
+            Ok(reply) => {
+                let _ = writeln!(&stream, "{}", reply);
+            }
+            Err(e) => warn!("Control socket: failed to encode status: {}", e),
+        }
+        return;
+    }
+    let reply = match apply_setting(request.trim(), settings) {
+        Ok(msg) => {
+            info!("{}", msg);
+            format!("ok {}\n", msg)
+        }
+        Err(e) => format!("error {}\n", e),
+    };
+    let _ = (&stream).write_all(reply.as_bytes());
+}
+
+/// State of the running mux for `ctrlassist status`, force feedback included
+fn status_report(settings: &RuntimeSettings) -> StatusReport {
+    let mode = format!("{:?}", settings.get_mode());
+    let rumble = format!("{:?}", settings.get_rumble());
+    let latency = settings.get_latency();
+    let details = format!(
+        "Mode: {}\nRumble: {}\n{}\n{}\nLatency: {}",
+        mode,
+        rumble,
+        settings.describe_handoffs(),
+        ff_helpers::describe(&settings.get_ff_health()),
+        latency.summary()
+    );
+    StatusReport {
+        mode,
+        rumble,
+        degraded: settings.is_degraded(),
+        details,
+        timing: latency.to_string(),
+    }
+}
+
+/// Apply a `<setting> <value>` request to the running mux
+fn apply_setting(request: &str, settings: &RuntimeSettings) -> Result<String, String> {
+    let (name, value) = request
+        .split_once(' ')
+        .ok_or("Expected a setting and a value")?;
+    let setting = Setting::from_str(name, true)?;
+    match setting {
+        Setting::Mode => {
+            let mode = ModeType::from_str(value, true)?;
+            settings.update_mode(mode.clone());
+            Ok(format!("Mode: {:?}", mode))
+        }
+        Setting::Rumble => {
+            let rumble = RumbleTarget::from_str(value, true)?;
+            settings.update_rumble(rumble.clone());
+            Ok(format!("Rumble: {:?}", rumble))
+        }
+        Setting::Smoothing => {
+            let enabled = match value {
+                "on" | "true" => true,
+                "off" | "false" => false,
+                _ => return Err(format!("Expected on or off, got '{}'", value)),
+            };
+            settings.update_smoothing(enabled);
+            Ok(format!("Smoothing: {}", if enabled { "on" } else { "off" }))
+        }
+        Setting::Weight => {
+            let weight = mux_modes::parse_share(value)?;
+            let mut options = settings.get_mode_options();
+            options.average.assist_weight = weight;
+            settings.update_mode_options(options);
+            Ok(format!("Assist weight: {}", weight))
+        }
+        Setting::BlendWeights => {
+            let (primary, assist) = value
+                .split_once(',')
+                .ok_or_else(|| format!("Expected <primary>,<assist>, got '{}'", value))?;
+            let (primary, assist) = (
+                mux_modes::parse_weight(primary)?,
+                mux_modes::parse_weight(assist)?,
+            );
+            settings.update_blend_weights(primary, assist)?;
+            Ok(format!(
+                "Blend weights: {} primary, {} assist",
+                primary, assist
+            ))
+        }
+    }
+}
+
+/// State of the running mux
+pub fn request_status() -> Result<StatusReport, Box<dyn Error>> {
+    let mut stream = UnixStream::connect(socket_path())
+        .map_err(|_| "No running mux found; start one with `ctrlassist mux`")?;
+    writeln!(stream, "{}", STATUS_REQUEST)?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    serde_json::from_str(&reply).map_err(|_| "Unexpected reply from the running mux".into())
+}
+
+/// Change a setting of the running mux, returning its confirmation
+pub fn send_setting(setting: Setting, value: &str) -> Result<String, Box<dyn Error>> {
+    let mut stream = UnixStream::connect(socket_path())
+        .map_err(|_| "No running mux found; start one with `ctrlassist mux`")?;
+    writeln!(stream, "{} {}", setting.name(), value)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    match reply.trim_end().split_once(' ') {
+        Some(("ok", msg)) => Ok(msg.into()),
+        Some(("error", msg)) => Err(msg.into()),
+        _ => Err("Unexpected reply from the running mux".into()),
+    }
+}
@@ -0,0 +1,187 @@
+use crate::notifier::{self, Note};
+use crate::state;
+use crate::tray::config::TrayConfig;
+use log::{Log, Metadata, Record};
+use parking_lot::Mutex;
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::panic::PanicHookInfo;
+use std::path::PathBuf;
+
+/// How many recent log lines are kept for crash reports
+const LOG_HISTORY: usize = 50;
+
+static HISTORY: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static SESSION: Mutex<Option<String>> = Mutex::new(None);
+static CONFIG: Mutex<Option<String>> = Mutex::new(None);
+
+/// Forwards to env_logger while remembering recent lines for crash reports
+struct HistoryLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for HistoryLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        // Formatted before locking, so a panicking Display impl can't leave
+        // the history locked for the panic hook
+        let line = format!("[{} {}] {}", record.level(), record.target(), record.args());
+        let mut history = HISTORY.lock();
+        if history.len() == LOG_HISTORY {
+            history.pop_front();
+        }
+        history.push_back(line);
+        drop(history);
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Initialize logging from `RUST_LOG`, keeping recent lines for crash reports
+pub fn init_logging() {
+    let inner = env_logger::Builder::from_default_env().build();
+    let max_level = inner.filter();
+    if log::set_boxed_logger(Box::new(HistoryLogger { inner })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Describe the running session, e.g. its controllers, for crash reports
+pub fn set_session_summary(summary: Option<String>) {
+    *SESSION.lock() = summary;
+}
+
+/// Remember the config in use, as crash reports can't safely load it
+pub fn set_config_snapshot(config: &TrayConfig) {
+    *CONFIG.lock() = scrub(config);
+}
+
+/// Replace raw panic output with a saved crash report and likely causes
+///
+/// With `notify`, a desktop notification is also shown, since tray users
+/// otherwise only see the icon vanish.
+pub fn install_panic_hook(notify: bool) {
+    std::panic::set_hook(Box::new(move |info| {
+        let report = build_report(info);
+        let saved = save_report(&report);
+
+        eprintln!(
+            "\nCtrlAssist hit an internal error: {}",
+            panic_message(info)
+        );
+        match &saved {
+            Some(path) => eprintln!("A crash report was saved to {}", path.display()),
+            None => eprintln!("{}", report),
+        }
+        for cause in likely_causes(info) {
+            eprintln!("  - {}", cause);
+        }
+        eprintln!("Please attach the report when filing an issue.");
+
+        if notify {
+            let body = match &saved {
+                Some(path) => format!("Crash report saved to {}", path.display()),
+                None => panic_message(info),
+            };
+            notifier::send_blocking(Note::new("CtrlAssist - Crashed", body));
+        }
+    }));
+}
+
+fn panic_message(info: &PanicHookInfo) -> String {
+    let payload = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    match info.location() {
+        Some(location) => format!("{} at {}", payload, location),
+        None => payload,
+    }
+}
+
+/// Hints for the usual suspects, matched against the panic message
+fn likely_causes(info: &PanicHookInfo) -> Vec<&'static str> {
+    let message = panic_message(info).to_lowercase();
+    let mut causes = Vec::new();
+    if message.contains("permission denied") {
+        causes.push(
+            "Missing permissions for /dev/uinput or /dev/input: grant access via the input group or a udev rule",
+        );
+    }
+    if message.contains("uinput") || message.contains("no such file") {
+        causes.push("The uinput kernel module may not be loaded: try `sudo modprobe uinput`");
+    }
+    if message.contains("no such device") {
+        causes.push("A controller disconnected while in use");
+    }
+    causes
+}
+
+/// Build a crash report from within the panic hook
+///
+/// Shared state is only read if its lock is free, since the panicking thread
+/// may hold it and waiting would hang instead of restoring devices.
+fn build_report(info: &PanicHookInfo) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "CtrlAssist {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(
+        report,
+        "Thread: {}",
+        std::thread::current().name().unwrap_or("unnamed")
+    );
+    let _ = writeln!(report, "Panic: {}\n", panic_message(info));
+
+    if let Some(session) = SESSION.try_lock().as_deref().and_then(Option::as_ref) {
+        let _ = writeln!(report, "Session:\n{}\n", session);
+    }
+
+    if let Some(config) = CONFIG.try_lock().as_deref().and_then(Option::as_ref) {
+        let _ = writeln!(report, "Config:\n{}", config);
+    }
+
+    let _ = writeln!(report, "Recent log:");
+    match HISTORY.try_lock() {
+        Some(history) => {
+            for line in history.iter() {
+                let _ = writeln!(report, "{}", line);
+            }
+        }
+        None => {
+            let _ = writeln!(report, "(unavailable)");
+        }
+    }
+
+    let _ = writeln!(report, "\nBacktrace:\n{}", Backtrace::force_capture());
+    report
+}
+
+/// The current config for reports, without anything likely to be private
+pub fn scrubbed_config() -> Option<String> {
+    scrub(&TrayConfig::load())
+}
+
+fn scrub(config: &TrayConfig) -> Option<String> {
+    // Hook commands and the webhook URL are left out, as they may embed
+    // tokens or personal paths
+    let mut config = config.clone();
+    config.hooks = Default::default();
+    config.notify.webhook_url = None;
+    toml::to_string_pretty(&config).ok()
+}
+
+/// Save a crash report to the state directory, returning its path
+fn save_report(report: &str) -> Option<PathBuf> {
+    state::save_timestamped("crash", report)
+}
@@ -0,0 +1,106 @@
+use gilrs::ev::AxisOrBtn;
+use gilrs::{Event, EventType, GamepadId, Gilrs};
+use log::info;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// What happens to the inputs of a source controller that disconnects
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum DisconnectPolicy {
+    /// Let go of everything it held at once
+    #[default]
+    Release,
+    /// Keep what it held for this many seconds, in case it reconnects, e.g.
+    /// a Bluetooth pad dropping out mid-turn, then let go
+    Hold(u64),
+    /// Let go, and hand control to the other controller in modes with a
+    /// single holder, e.g. Toggle or Turn
+    Transfer,
+}
+
+/// Lost source controllers awaiting their inputs being let go
+pub struct DisconnectWatch {
+    policy: DisconnectPolicy,
+    /// Lost gamepads and when to let go of them
+    pending: HashMap<GamepadId, Instant>,
+}
+
+impl DisconnectWatch {
+    pub fn new(policy: DisconnectPolicy) -> Self {
+        Self {
+            policy,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Whether control passes to the other controller once one is let go
+    pub fn transfers(&self) -> bool {
+        self.policy == DisconnectPolicy::Transfer
+    }
+
+    /// Note a source gamepad lost as of `now`
+    pub fn lost(&mut self, id: GamepadId, now: Instant) {
+        let hold = match self.policy {
+            DisconnectPolicy::Hold(secs) => {
+                info!("Holding inputs of controller {} for {}s", id, secs);
+                Duration::from_secs(secs)
+            }
+            DisconnectPolicy::Release | DisconnectPolicy::Transfer => Duration::ZERO,
+        };
+        self.pending.insert(id, now + hold);
+    }
+
+    /// Note a gamepad back, keeping its inputs if still held
+    pub fn reconnected(&mut self, id: GamepadId) {
+        if self.pending.remove(&id).is_some() {
+            info!("Controller {} reconnected while held", id);
+        }
+    }
+
+    /// Gamepads due to be let go, appended to `out`
+    pub fn expire(&mut self, now: Instant, out: &mut Vec<GamepadId>) {
+        self.pending.retain(|&id, &mut at| {
+            if now < at {
+                return true;
+            }
+            out.push(id);
+            false
+        });
+    }
+
+    /// Next instant a held gamepad is due to be let go, if any
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.values().min().copied()
+    }
+}
+
+/// Events returning everything a lost gamepad last held to rest, to update
+/// gilrs with and feed the mux mode as if it let go
+///
+/// gilrs keeps a disconnected gamepad's last state, which modes reading it
+/// would otherwise keep forwarding.
+pub fn release_events(gilrs: &Gilrs, id: GamepadId, out: &mut Vec<Event>) {
+    let gamepad = gilrs.gamepad(id);
+    let state = gamepad.state();
+    for (code, data) in state.buttons() {
+        let Some(AxisOrBtn::Btn(btn)) = gamepad.axis_or_btn_name(code) else {
+            continue;
+        };
+        if data.is_pressed() {
+            out.push(Event::new(id, EventType::ButtonReleased(btn, code)));
+        }
+        if data.value() != 0.0 {
+            out.push(Event::new(id, EventType::ButtonChanged(btn, 0.0, code)));
+        }
+    }
+    for (code, data) in state.axes() {
+        let Some(AxisOrBtn::Axis(axis)) = gamepad.axis_or_btn_name(code) else {
+            continue;
+        };
+        if data.value() != 0.0 {
+            out.push(Event::new(id, EventType::AxisChanged(axis, 0.0, code)));
+        }
+    }
+}
@@ -1,13 +1,89 @@
+use crate::ff_helpers::FfCapabilities;
 use evdev::{
-    AbsInfo, AbsoluteAxisCode, AttributeSet, FFEffectCode, KeyCode, UinputAbsSetup,
+    AbsInfo, AbsoluteAxisCode, AttributeSet, EventType, InputEvent, KeyCode, UinputAbsSetup,
     uinput::VirtualDevice,
 };
 use gilrs::{Axis, Button};
 use std::error::Error;
+use std::ffi::CString;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::time::Duration;
 
-/// Shared constant for the maximum number of force feedback effects.
+/// Default number of force feedback effect slots on the virtual device.
 pub const MAX_FF_EFFECTS: i16 = 16;
 
+/// Start of the `phys` of every device CtrlAssist creates, so they can be
+/// told apart from real controllers even when spoofing one
+pub const VIRTUAL_PHYS_PREFIX: &str = "ctrlassist/";
+
+/// `phys` marker of this process's virtual devices
+///
+/// Carries the session, i.e. the process ID, as uinput can't set `uniq`.
+pub fn virtual_phys() -> String {
+    format!("{}{}", VIRTUAL_PHYS_PREFIX, session_id())
+}
+
+/// Identifier of this session's virtual devices, e.g. "1234"
+pub fn session_id() -> String {
+    std::process::id().to_string()
+}
+
+/// How long a reader thread waits for events before checking for shutdown
+pub const READ_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Wait until a device has events to read, or the timeout passes
+///
+/// Lets reader threads notice shutdown, and so release a grabbed device,
+/// without waiting on the next event.
+pub fn wait_readable(device: &impl AsRawFd, timeout: Duration) -> io::Result<bool> {
+    let mut fd = libc::pollfd {
+        fd: device.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+    // SAFETY: `fd` is a valid pollfd for the duration of the call
+    match unsafe { libc::poll(&mut fd, 1, timeout) } {
+        -1 => {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                Ok(false)
+            } else {
+                Err(e)
+            }
+        }
+        0 => Ok(false),
+        _ if fd.revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0
+            && fd.revents & libc::POLLIN == 0 =>
+        {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "device is gone"))
+        }
+        _ => Ok(true),
+    }
+}
+
+/// Buttons of the virtual gamepad
+pub const VIRTUAL_KEYS: [KeyCode; 17] = [
+    KeyCode::BTN_NORTH,
+    KeyCode::BTN_SOUTH,
+    KeyCode::BTN_EAST,
+    KeyCode::BTN_WEST,
+    KeyCode::BTN_TL,  // L1
+    KeyCode::BTN_TR,  // R1
+    KeyCode::BTN_TL2, // L2 (as button)
+    KeyCode::BTN_TR2, // R2 (as button)
+    KeyCode::BTN_THUMBL,
+    KeyCode::BTN_THUMBR,
+    KeyCode::BTN_SELECT,
+    KeyCode::BTN_START,
+    KeyCode::BTN_MODE,
+    KeyCode::BTN_DPAD_UP,
+    KeyCode::BTN_DPAD_DOWN,
+    KeyCode::BTN_DPAD_LEFT,
+    KeyCode::BTN_DPAD_RIGHT,
+];
+
 // --- Scaling Helper Functions ---
 
 pub const AXIS_MAX: f32 = u16::MAX as f32;
@@ -24,12 +100,284 @@ pub fn scale_trigger(val: f32) -> i32 {
     (val * AXIS_MAX).round() as i32
 }
 
+/// Buttons xpad exposes for a wired Xbox 360 pad, which reports its D-pad and
+/// triggers as axes only
+const XPAD_KEYS: [KeyCode; 11] = [
+    KeyCode::BTN_SOUTH,
+    KeyCode::BTN_EAST,
+    KeyCode::BTN_NORTH,
+    KeyCode::BTN_WEST,
+    KeyCode::BTN_TL,
+    KeyCode::BTN_TR,
+    KeyCode::BTN_SELECT,
+    KeyCode::BTN_START,
+    KeyCode::BTN_MODE,
+    KeyCode::BTN_THUMBL,
+    KeyCode::BTN_THUMBR,
+];
+
+/// Joystick buttons of the wheel and flight stick layouts, standing in for
+/// the gamepad buttons of `JOYSTICK_SOURCE_KEYS` in order
+const JOYSTICK_KEYS: [KeyCode; 13] = [
+    KeyCode::BTN_TRIGGER,
+    KeyCode::BTN_THUMB,
+    KeyCode::BTN_THUMB2,
+    KeyCode::BTN_TOP,
+    KeyCode::BTN_TOP2,
+    KeyCode::BTN_PINKIE,
+    KeyCode::BTN_BASE,
+    KeyCode::BTN_BASE2,
+    KeyCode::BTN_BASE3,
+    KeyCode::BTN_BASE4,
+    KeyCode::BTN_BASE5,
+    KeyCode::BTN_BASE6,
+    KeyCode::BTN_DEAD,
+];
+
+/// Gamepad buttons carried over to joystick buttons, the D-pad going to the
+/// hat instead
+const JOYSTICK_SOURCE_KEYS: [KeyCode; 13] = [
+    KeyCode::BTN_SOUTH,
+    KeyCode::BTN_EAST,
+    KeyCode::BTN_NORTH,
+    KeyCode::BTN_WEST,
+    KeyCode::BTN_TL,
+    KeyCode::BTN_TR,
+    KeyCode::BTN_TL2,
+    KeyCode::BTN_TR2,
+    KeyCode::BTN_SELECT,
+    KeyCode::BTN_START,
+    KeyCode::BTN_THUMBL,
+    KeyCode::BTN_THUMBR,
+    KeyCode::BTN_MODE,
+];
+
+/// Axis ranges and buttons the virtual gamepad exposes
+///
+/// The mux always outputs the default layout, converted on write.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum VirtualLayout {
+    /// Unsigned full-range axes, plus D-pad and trigger buttons
+    #[default]
+    Default,
+    /// Exactly what the kernel xpad driver exposes for a wired Xbox 360 pad
+    Xpad,
+    /// Racing wheel: steering from the left stick, gas and brake pedals from
+    /// the right and left triggers, and joystick buttons
+    Wheel,
+    /// Flight stick: the left stick as the stick, twist from the right stick,
+    /// throttle from the right trigger, and joystick buttons
+    Joystick,
+}
+
+impl VirtualLayout {
+    fn keys(self) -> &'static [KeyCode] {
+        match self {
+            Self::Default => &VIRTUAL_KEYS,
+            Self::Xpad => &XPAD_KEYS,
+            Self::Wheel | Self::Joystick => &JOYSTICK_KEYS,
+        }
+    }
+
+    fn abs_axes(self) -> Vec<(AbsoluteAxisCode, AbsInfo)> {
+        let max = AXIS_MAX as i32;
+        let full = AbsInfo::new(AXIS_HALF as i32, 0, max, 0, 0, 0);
+        let pedal = AbsInfo::new(0, 0, max, 0, 0, 0);
+        let hat = AbsInfo::new(0, -1, 1, 0, 0, 0);
+        let (stick, trigger, hat) = match self {
+            Self::Default => (full, pedal, full),
+            Self::Xpad => (
+                AbsInfo::new(0, i16::MIN as i32, i16::MAX as i32, 16, 128, 0),
+                AbsInfo::new(0, 0, 255, 0, 0, 0),
+                hat,
+            ),
+            Self::Wheel => {
+                return vec![
+                    (AbsoluteAxisCode::ABS_X, full),      // Steering
+                    (AbsoluteAxisCode::ABS_GAS, pedal),   // Right Trigger (R2)
+                    (AbsoluteAxisCode::ABS_BRAKE, pedal), // Left Trigger (L2)
+                    (AbsoluteAxisCode::ABS_HAT0X, hat),
+                    (AbsoluteAxisCode::ABS_HAT0Y, hat),
+                ];
+            }
+            Self::Joystick => {
+                return vec![
+                    (AbsoluteAxisCode::ABS_X, full),
+                    (AbsoluteAxisCode::ABS_Y, full),
+                    (AbsoluteAxisCode::ABS_RZ, full),        // Twist
+                    (AbsoluteAxisCode::ABS_THROTTLE, pedal), // Right Trigger (R2)
+                    (AbsoluteAxisCode::ABS_HAT0X, hat),
+                    (AbsoluteAxisCode::ABS_HAT0Y, hat),
+                ];
+            }
+        };
+        vec![
+            (AbsoluteAxisCode::ABS_X, stick),    // Left Stick X
+            (AbsoluteAxisCode::ABS_Y, stick),    // Left Stick Y
+            (AbsoluteAxisCode::ABS_Z, trigger),  // Left Trigger (L2)
+            (AbsoluteAxisCode::ABS_RX, stick),   // Right Stick X
+            (AbsoluteAxisCode::ABS_RY, stick),   // Right Stick Y
+            (AbsoluteAxisCode::ABS_RZ, trigger), // Right Trigger (R2)
+            (AbsoluteAxisCode::ABS_HAT0X, hat),  // D-Pad X
+            (AbsoluteAxisCode::ABS_HAT0Y, hat),  // D-Pad Y
+        ]
+    }
+
+    /// Input ID version, which SDL includes in its controller GUIDs
+    fn version(self) -> u16 {
+        match self {
+            Self::Default => 0x4242,
+            Self::Xpad => 0x0114,
+            Self::Wheel | Self::Joystick => 0x4243,
+        }
+    }
+
+    /// Code of the joystick axis a default layout axis becomes, if kept
+    fn joystick_axis(self, code: AbsoluteAxisCode) -> Option<AbsoluteAxisCode> {
+        match (self, code) {
+            (_, AbsoluteAxisCode::ABS_HAT0X | AbsoluteAxisCode::ABS_HAT0Y) => Some(code),
+            (Self::Wheel, AbsoluteAxisCode::ABS_X) => Some(code),
+            (Self::Wheel, AbsoluteAxisCode::ABS_RZ) => Some(AbsoluteAxisCode::ABS_GAS),
+            (Self::Wheel, AbsoluteAxisCode::ABS_Z) => Some(AbsoluteAxisCode::ABS_BRAKE),
+            (Self::Joystick, AbsoluteAxisCode::ABS_X | AbsoluteAxisCode::ABS_Y) => Some(code),
+            (Self::Joystick, AbsoluteAxisCode::ABS_RX) => Some(AbsoluteAxisCode::ABS_RZ),
+            (Self::Joystick, AbsoluteAxisCode::ABS_RZ) => Some(AbsoluteAxisCode::ABS_THROTTLE),
+            _ => None,
+        }
+    }
+
+    /// Convert a default layout event to the wheel or flight stick layout,
+    /// or None to drop it
+    fn convert_joystick(self, event: &InputEvent) -> Option<InputEvent> {
+        if event.event_type() == EventType::KEY {
+            let pos = JOYSTICK_SOURCE_KEYS
+                .iter()
+                .position(|key| key.0 == event.code())?;
+            return Some(InputEvent::new(
+                EventType::KEY.0,
+                JOYSTICK_KEYS[pos].0,
+                event.value(),
+            ));
+        }
+        if event.event_type() != EventType::ABSOLUTE {
+            return Some(*event);
+        }
+        let code = self.joystick_axis(AbsoluteAxisCode(event.code()))?;
+        let value = match code {
+            AbsoluteAxisCode::ABS_HAT0X | AbsoluteAxisCode::ABS_HAT0Y => {
+                hat_direction(event.value() as f32)
+            }
+            _ => event.value(),
+        };
+        Some(InputEvent::new(EventType::ABSOLUTE.0, code.0, value))
+    }
+
+    /// Convert events of the default layout to this one, in place
+    pub fn convert(self, events: &mut Vec<InputEvent>) {
+        match self {
+            Self::Default => return,
+            Self::Wheel | Self::Joystick => {
+                events.retain_mut(|event| match self.convert_joystick(event) {
+                    Some(converted) => {
+                        *event = converted;
+                        true
+                    }
+                    None => false,
+                });
+                return;
+            }
+            Self::Xpad => {}
+        }
+        events.retain_mut(|event| {
+            if event.event_type() == EventType::KEY {
+                return XPAD_KEYS.iter().any(|key| key.0 == event.code());
+            }
+            if event.event_type() != EventType::ABSOLUTE {
+                return true;
+            }
+            let value = event.value() as f32;
+            let converted = match AbsoluteAxisCode(event.code()) {
+                AbsoluteAxisCode::ABS_Z | AbsoluteAxisCode::ABS_RZ => {
+                    (value / AXIS_MAX * 255.0).round() as i32
+                }
+                AbsoluteAxisCode::ABS_HAT0X | AbsoluteAxisCode::ABS_HAT0Y => hat_direction(value),
+                _ => (value - AXIS_HALF)
+                    .round()
+                    .clamp(i16::MIN as f32, i16::MAX as f32) as i32,
+            };
+            *event = InputEvent::new(EventType::ABSOLUTE.0, event.code(), converted);
+            true
+        });
+    }
+}
+
+/// Direction of a hat, -1, 0, or 1, from its default layout value
+fn hat_direction(value: f32) -> i32 {
+    if value < AXIS_HALF / 2.0 {
+        -1
+    } else if value > AXIS_HALF * 1.5 {
+        1
+    } else {
+        0
+    }
+}
+
 /// Struct to represent a virtual gamepad's identity (real or spoofed)
 #[derive(Clone)]
 pub struct VirtualGamepadInfo {
     pub name: String,
     pub vendor_id: Option<u16>,
     pub product_id: Option<u16>,
+    pub layout: VirtualLayout,
+}
+
+impl VirtualGamepadInfo {
+    /// Append the session to the name, e.g. "CtrlAssist Virtual Gamepad
+    /// (1234)", to tell sessions apart
+    pub fn with_session_suffix(mut self) -> Self {
+        self.name = format!("{} ({})", self.name, session_id());
+        self
+    }
+
+    /// Identity and layout of a wired Xbox 360 pad, as exposed by xpad
+    pub fn xbox_360() -> Self {
+        Self {
+            name: "Microsoft X-Box 360 pad".into(),
+            vendor_id: Some(0x045e),
+            product_id: Some(0x028e),
+            layout: VirtualLayout::Xpad,
+        }
+    }
+
+    /// A generic racing wheel, for games that only take wheel input
+    pub fn wheel() -> Self {
+        Self {
+            name: "CtrlAssist Virtual Wheel".into(),
+            vendor_id: None,
+            product_id: None,
+            layout: VirtualLayout::Wheel,
+        }
+    }
+
+    /// A generic flight stick, for games that only take joystick input
+    pub fn joystick() -> Self {
+        Self {
+            name: "CtrlAssist Virtual Joystick".into(),
+            vendor_id: None,
+            product_id: None,
+            layout: VirtualLayout::Joystick,
+        }
+    }
+
+    /// Identity of the Steam Deck's built-in controller, as named by hid-steam
+    pub fn steam_deck() -> Self {
+        Self {
+            name: "Steam Deck".into(),
+            vendor_id: Some(0x28de),
+            product_id: Some(0x1205),
+            layout: VirtualLayout::Default,
+        }
+    }
 }
 
 impl<'a> From<&'a gilrs::Gamepad<'a>> for VirtualGamepadInfo {
@@ -38,6 +386,7 @@ impl<'a> From<&'a gilrs::Gamepad<'a>> for VirtualGamepadInfo {
             name: gp.os_name().to_string(),
             vendor_id: gp.vendor_id(),
             product_id: gp.product_id(),
+            layout: VirtualLayout::Default,
         }
     }
 }
@@ -45,51 +394,22 @@ impl<'a> From<&'a gilrs::Gamepad<'a>> for VirtualGamepadInfo {
 // --- evdev Device Creation ---
 
 /// Helper to create the virtual gamepad device
-pub fn create_virtual_gamepad(info: &VirtualGamepadInfo) -> Result<VirtualDevice, Box<dyn Error>> {
-    let max = AXIS_MAX as i32;
-    let mid = AXIS_HALF as i32;
-    let abs_stick_setup = AbsInfo::new(mid, 0, max, 0, 0, 0);
-    let abs_trigger_setup = AbsInfo::new(0, 0, max, 0, 0, 0);
-
-    let keys = AttributeSet::from_iter([
-        KeyCode::BTN_NORTH,
-        KeyCode::BTN_SOUTH,
-        KeyCode::BTN_EAST,
-        KeyCode::BTN_WEST,
-        KeyCode::BTN_TL,  // L1
-        KeyCode::BTN_TR,  // R1
-        KeyCode::BTN_TL2, // L2 (as button)
-        KeyCode::BTN_TR2, // R2 (as button)
-        KeyCode::BTN_THUMBL,
-        KeyCode::BTN_THUMBR,
-        KeyCode::BTN_SELECT,
-        KeyCode::BTN_START,
-        KeyCode::BTN_MODE,
-        KeyCode::BTN_DPAD_UP,
-        KeyCode::BTN_DPAD_DOWN,
-        KeyCode::BTN_DPAD_LEFT,
-        KeyCode::BTN_DPAD_RIGHT,
-    ]);
-
-    let abs_axes = [
-        (AbsoluteAxisCode::ABS_X, abs_stick_setup), // Left Stick X
-        (AbsoluteAxisCode::ABS_Y, abs_stick_setup), // Left Stick Y
-        (AbsoluteAxisCode::ABS_Z, abs_trigger_setup), // Left Trigger (L2)
-        (AbsoluteAxisCode::ABS_RX, abs_stick_setup), // Right Stick X
-        (AbsoluteAxisCode::ABS_RY, abs_stick_setup), // Right Stick Y
-        (AbsoluteAxisCode::ABS_RZ, abs_trigger_setup), // Right Trigger (R2)
-        (AbsoluteAxisCode::ABS_HAT0X, abs_stick_setup), // D-Pad X
-        (AbsoluteAxisCode::ABS_HAT0Y, abs_stick_setup), // D-Pad Y
-    ];
+pub fn create_virtual_gamepad(
+    info: &VirtualGamepadInfo,
+    ff: &FfCapabilities,
+) -> Result<VirtualDevice, Box<dyn Error>> {
+    let keys = AttributeSet::from_iter(info.layout.keys().iter().copied());
+    let abs_axes = info.layout.abs_axes();
 
     let mut builder = VirtualDevice::builder()?;
     builder = builder.name(&info.name);
+    builder = builder.with_phys(&CString::new(virtual_phys())?)?;
     if let (Some(vendor), Some(product)) = (info.vendor_id, info.product_id) {
         builder = builder.input_id(evdev::InputId::new(
             evdev::BusType::BUS_USB,
             vendor,
             product,
-            0x4242,
+            info.layout.version(),
         ));
     }
     builder = builder.with_keys(&keys)?;
@@ -99,13 +419,12 @@ pub fn create_virtual_gamepad(info: &VirtualGamepadInfo) -> Result<VirtualDevice
         builder = builder.with_absolute_axis(&setup)?;
     }
 
-    // Add force feedback support (rumble)
-    let ff_effects = AttributeSet::from_iter([
-        FFEffectCode::FF_RUMBLE,
-        // Add more effect codes if needed
-    ]);
-    builder = builder.with_ff(&ff_effects)?;
-    builder = builder.with_ff_effects_max(MAX_FF_EFFECTS as u32);
+    // Add force feedback support, unless no effects are advertised
+    if !ff.effects.is_empty() {
+        let ff_effects = AttributeSet::from_iter(ff.effects.iter().copied());
+        builder = builder.with_ff(&ff_effects)?;
+        builder = builder.with_ff_effects_max(ff.slots);
+    }
 
     Ok(builder.build()?)
 }
@@ -160,6 +479,18 @@ pub fn gilrs_axis_to_evdev_axis(axis: Axis) -> Option<AbsoluteAxisCode> {
     }
 }
 
+/// Steam Deck back paddle key codes (BTN_GRIPL, BTN_GRIPR, BTN_GRIPL2, BTN_GRIPR2)
+/// as reported by the hid-steam driver
+pub const STEAM_DECK_PADDLE_CODES: [u16; 4] = [0x224, 0x225, 0x226, 0x227];
+
+/// Returns true if a gilrs button code is one of the Steam Deck back paddles
+pub fn is_steam_deck_paddle(code: gilrs::ev::Code) -> bool {
+    let raw = code.into_u32();
+    let kind = (raw >> 16) as u16;
+    let key = (raw & 0xFFFF) as u16;
+    kind == evdev::EventType::KEY.0 && STEAM_DECK_PADDLE_CODES.contains(&key)
+}
+
 /// Returns the DPad axis pair for a given button, if applicable
 pub fn dpad_axis_pair(button: Button) -> Option<[Button; 2]> {
     match button {
@@ -1,41 +1,456 @@
-use crate::gilrs_helper::GamepadResource;
-use evdev::{Device, FFEffectData};
-use log::{error, warn};
-use std::collections::HashMap;
+use crate::evdev_helpers::MAX_FF_EFFECTS;
+use crate::gilrs_helper::{self, GamepadResource};
+use crate::quirks::{self, Quirk};
+use evdev::{Device, FFEffectCode, FFEffectData, FFEffectKind, FFReplay, FFTrigger};
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Effect ID of the alert pulse, outside the range games are given
+const ALERT_EFFECT_ID: i16 = -1;
+/// How long the alert pulse rumbles
+const ALERT_MS: u16 = 400;
+
+/// Wakes the FF loop from waiting on the virtual device, which otherwise
+/// only returns once the game sends force feedback
+pub struct FfWaker {
+    /// Eventfd written to wake; None if it couldn't be created, leaving the
+    /// loop to notice requests once its wait times out
+    fd: Option<OwnedFd>,
+}
+
+impl FfWaker {
+    pub fn new() -> Self {
+        // SAFETY: eventfd has no preconditions; the result is checked below
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            warn!(
+                "Failed to create FF wake eventfd: {}",
+                io::Error::last_os_error()
+            );
+            return Self { fd: None };
+        }
+        // SAFETY: fd was just created and is owned by nothing else
+        Self {
+            fd: Some(unsafe { OwnedFd::from_raw_fd(fd) }),
+        }
+    }
+
+    /// Have the FF loop act on requests now rather than on the next event
+    pub fn wake(&self) {
+        if let Some(fd) = &self.fd {
+            let one = 1u64;
+            // SAFETY: one is valid for reads of its 8 bytes
+            unsafe { libc::write(fd.as_raw_fd(), (&one as *const u64).cast(), 8) };
+        }
+    }
+
+    /// Wait until `device` has events to read, a wake arrives, or the timeout
+    /// passes, returning whether the device is readable
+    pub fn wait(&self, device: &impl AsRawFd, timeout: Duration) -> io::Result<bool> {
+        let pollfd = |fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let mut fds = [
+            pollfd(device.as_raw_fd()),
+            pollfd(self.fd.as_ref().map_or(-1, |fd| fd.as_raw_fd())),
+        ];
+        let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+        // SAFETY: fds is valid for the call, which only writes their revents;
+        // poll skips the negative fd of a missing eventfd
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } < 0 {
+            let e = io::Error::last_os_error();
+            return match e.kind() {
+                io::ErrorKind::Interrupted => Ok(false),
+                _ => Err(e),
+            };
+        }
+        if fds[1].revents & libc::POLLIN != 0 {
+            let mut count = 0u64;
+            // SAFETY: count is valid for writes of its 8 bytes
+            unsafe { libc::read(fds[1].fd, (&mut count as *mut u64).cast(), 8) };
+        }
+        Ok(fds[0].revents & libc::POLLIN != 0)
+    }
+}
+
+impl Default for FfWaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Force feedback the virtual gamepad advertises to games
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct FfConfig {
+    /// Effect types by evdev name, e.g. "FF_RUMBLE" or "FF_PERIODIC"
+    pub effects: Vec<String>,
+    /// Effects games may upload at once
+    pub slots: u32,
+    /// Narrow effects and slots to what every rumble-capable controller supports
+    pub match_targets: bool,
+    /// Rumble motor mapping of the primary, overriding its quirk
+    pub primary_motors: Option<MotorMap>,
+    /// Rumble motor mapping of the assist, overriding its quirk
+    pub assist_motors: Option<MotorMap>,
+    /// Effect types never played on each controller, which a profile overrides
+    pub blocked_effects: BlockedEffects,
+}
+
+/// Effect types by evdev name kept from each controller, e.g. "FF_CONSTANT"
+/// for a wheel whose force feedback is painful, while rumble still plays
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct BlockedEffects {
+    pub primary: Vec<String>,
+    pub assist: Vec<String>,
+}
+
+/// Parse evdev effect type names, skipping unknown ones with a warning
+fn effect_codes(names: &[String]) -> Vec<FFEffectCode> {
+    names
+        .iter()
+        .filter_map(|name| match FFEffectCode::from_str(name) {
+            Ok(code) => Some(code),
+            Err(_) => {
+                warn!("Force feedback: ignoring unknown effect {}", name);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Effect type of an effect, as advertised and blocked by
+fn effect_code(kind: &FFEffectKind) -> FFEffectCode {
+    match kind {
+        FFEffectKind::Damper => FFEffectCode::FF_DAMPER,
+        FFEffectKind::Inertia => FFEffectCode::FF_INERTIA,
+        FFEffectKind::Constant { .. } => FFEffectCode::FF_CONSTANT,
+        FFEffectKind::Ramp { .. } => FFEffectCode::FF_RAMP,
+        FFEffectKind::Periodic { .. } => FFEffectCode::FF_PERIODIC,
+        FFEffectKind::Spring { .. } => FFEffectCode::FF_SPRING,
+        FFEffectKind::Friction { .. } => FFEffectCode::FF_FRICTION,
+        FFEffectKind::Rumble { .. } => FFEffectCode::FF_RUMBLE,
+    }
+}
+
+impl Default for FfConfig {
+    fn default() -> Self {
+        Self {
+            effects: vec!["FF_RUMBLE".into()],
+            slots: MAX_FF_EFFECTS as u32,
+            match_targets: false,
+            primary_motors: None,
+            assist_motors: None,
+            blocked_effects: BlockedEffects::default(),
+        }
+    }
+}
+
+/// Resolved force feedback capabilities of the virtual gamepad
+#[derive(Debug, Clone, Default)]
+pub struct FfCapabilities {
+    /// Advertised effect types; none disables force feedback
+    pub effects: Vec<FFEffectCode>,
+    pub slots: u32,
+}
+
+impl FfCapabilities {
+    /// Resolve the configured capabilities, narrowed to those of `targets`
+    /// if enabled
+    ///
+    /// Targets without force feedback are skipped, as they never receive
+    /// effects anyway.
+    pub fn resolve<'a>(config: &FfConfig, targets: impl IntoIterator<Item = &'a Device>) -> Self {
+        let mut effects = effect_codes(&config.effects);
+        let mut slots = config.slots.max(1);
+
+        if config.match_targets {
+            let mut any_target = false;
+            for device in targets {
+                let Some(supported) = device.supported_ff() else {
+                    continue;
+                };
+                any_target = true;
+                effects.retain(|effect| supported.contains(*effect));
+                slots = slots.min(device.max_ff_effects().max(1) as u32);
+            }
+            if !any_target {
+                effects.clear();
+            }
+            info!(
+                "Force feedback: advertising {:?} with {} slots to match controllers",
+                effects, slots
+            );
+        }
+
+        Self { effects, slots }
+    }
+}
+
+/// Force feedback health of one physical target
+#[derive(Debug, Clone, Default)]
+pub struct TargetHealth {
+    pub name: String,
+    /// Whether the last effect upload succeeded, if any was attempted
+    pub last_upload_ok: Option<bool>,
+    /// Uploads the device rejected as an unsupported effect
+    pub unsupported: u32,
+    /// Attempts to reopen the device after it went away
+    pub recoveries: u32,
+    /// Effects currently uploaded to the device
+    pub effects: usize,
+    /// Effects that failed to carry over when the device became a target
+    pub sync_failures: u32,
+}
+
+impl TargetHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.last_upload_ok != Some(false)
+    }
+}
+
+/// Force feedback health of every target this session, by device path
+///
+/// Shared with the tray, so rumble silently failing on a controller shows
+/// up without digging through logs.
+#[derive(Debug, Clone, Default)]
+pub struct FfHealth {
+    pub targets: BTreeMap<PathBuf, TargetHealth>,
+    /// Effects the game has uploaded, as of its last force feedback event
+    pub effects: Vec<EffectStatus>,
+}
+
+impl FfHealth {
+    fn target_mut(&mut self, resource: &GamepadResource) -> &mut TargetHealth {
+        self.targets
+            .entry(resource.path.clone())
+            .or_insert_with(|| TargetHealth {
+                name: resource.name.clone(),
+                ..Default::default()
+            })
+    }
+}
+
+/// How a controller's rumble motors are driven from a game's rumble effect
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum MotorMap {
+    /// Strong to strong, weak to weak
+    #[default]
+    Normal,
+    /// Strong and weak exchanged, for pads reporting their motors swapped
+    Swap,
+    /// Both onto the strong motor, for pads with a faint or missing weak one
+    Strong,
+    /// Both onto the weak motor
+    Weak,
+}
+
+impl MotorMap {
+    /// Remap the magnitudes of a rumble effect; other effects pass unchanged
+    pub fn apply(self, mut effect: FFEffectData) -> FFEffectData {
+        if let FFEffectKind::Rumble {
+            strong_magnitude: strong,
+            weak_magnitude: weak,
+        } = effect.kind
+        {
+            let (strong_magnitude, weak_magnitude) = match self {
+                Self::Normal => (strong, weak),
+                Self::Swap => (weak, strong),
+                Self::Strong => (strong.max(weak), 0),
+                Self::Weak => (0, strong.max(weak)),
+            };
+            effect.kind = FFEffectKind::Rumble {
+                strong_magnitude,
+                weak_magnitude,
+            };
+        }
+        effect
+    }
+}
+
+/// Which of a game's rumble magnitudes a target plays
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum RumbleChannel {
+    #[default]
+    Both,
+    /// Only the strong (left) motor's magnitude
+    Strong,
+    /// Only the weak (right) motor's magnitude
+    Weak,
+}
+
+impl RumbleChannel {
+    /// Silence the magnitude of a rumble effect this target doesn't play;
+    /// other effects pass unchanged
+    pub fn apply(self, mut effect: FFEffectData) -> FFEffectData {
+        if let FFEffectKind::Rumble {
+            strong_magnitude,
+            weak_magnitude,
+        } = effect.kind
+        {
+            effect.kind = match self {
+                Self::Both => effect.kind,
+                Self::Strong => FFEffectKind::Rumble {
+                    strong_magnitude,
+                    weak_magnitude: 0,
+                },
+                Self::Weak => FFEffectKind::Rumble {
+                    strong_magnitude: 0,
+                    weak_magnitude,
+                },
+            };
+        }
+        effect
+    }
+}
+
+/// Motor mapping of each rumble target, from config then model quirks, and
+/// the effect types blocked from it
+#[derive(Debug, Clone, Default)]
+pub struct MotorMaps {
+    pub primary: Option<MotorMap>,
+    pub assist: Option<MotorMap>,
+    pub quirks: Vec<Quirk>,
+    pub primary_blocked: Vec<FFEffectCode>,
+    pub assist_blocked: Vec<FFEffectCode>,
+}
+
+impl MotorMaps {
+    pub fn new(config: &FfConfig, quirks: Vec<Quirk>) -> Self {
+        Self {
+            primary: config.primary_motors,
+            assist: config.assist_motors,
+            quirks,
+            primary_blocked: effect_codes(&config.blocked_effects.primary),
+            assist_blocked: effect_codes(&config.blocked_effects.assist),
+        }
+    }
+
+    /// Mapping of a target, given the configured one of the role it rumbles for
+    pub fn resolve(&self, configured: Option<MotorMap>, resource: &GamepadResource) -> MotorMap {
+        configured
+            .or_else(|| quirks::for_resource(&self.quirks, resource).and_then(|quirk| quirk.motors))
+            .unwrap_or_default()
+    }
+}
 
 pub struct PhysicalFFDev {
     pub resource: GamepadResource,
     /// Mapping: virt_id -> physical FFEffect handle
     effects: HashMap<i16, evdev::FFEffect>,
+    health: Arc<Mutex<FfHealth>>,
+    /// Share of the game's rumble this pad plays, e.g. one side when split
+    channel: RumbleChannel,
+    /// How rumble magnitudes reach this pad's motors
+    motors: MotorMap,
+    /// Effect types never uploaded to this pad
+    blocked: Vec<FFEffectCode>,
 }
 
 impl PhysicalFFDev {
-    pub fn new(resource: GamepadResource) -> Self {
+    pub fn new(
+        resource: GamepadResource,
+        health: Arc<Mutex<FfHealth>>,
+        channel: RumbleChannel,
+        motors: MotorMap,
+        blocked: Vec<FFEffectCode>,
+    ) -> Self {
+        health.lock().target_mut(&resource);
         Self {
             resource,
             effects: HashMap::new(),
+            health,
+            channel,
+            motors,
+            blocked,
         }
     }
 
     /// Upload an effect to this device and store the handle
+    ///
+    /// Games change an effect, e.g. the strength of a continuous rumble, by
+    /// uploading it again under the same ID, so a known effect is updated in
+    /// place, keeping it playing, rather than replaced by a stopped one.
+    /// Blocked effect types are left off the device, as if never uploaded.
     pub fn upload_effect(
         &mut self,
         virt_id: i16,
         effect_data: FFEffectData,
     ) -> std::io::Result<()> {
-        let ff_effect = self.resource.device.upload_ff_effect(effect_data)?;
-        self.effects.insert(virt_id, ff_effect);
-        Ok(())
+        if self.blocked.contains(&effect_code(&effect_data.kind)) {
+            return self.erase_effect(virt_id);
+        }
+        let effect_data = self.motors.apply(self.channel.apply(effect_data));
+        let result = match self.effects.get_mut(&virt_id) {
+            Some(effect) => effect.update(effect_data),
+            None => self
+                .resource
+                .device
+                .upload_ff_effect(effect_data)
+                .map(|effect| {
+                    self.effects.insert(virt_id, effect);
+                }),
+        };
+
+        let mut health = self.health.lock();
+        let target = health.target_mut(&self.resource);
+        target.last_upload_ok = Some(result.is_ok());
+        target.effects = self.effects.len();
+        // Drivers reject effect types they can't render with EINVAL
+        if let Err(e) = &result
+            && e.raw_os_error() == Some(libc::EINVAL)
+        {
+            target.unsupported += 1;
+        }
+        result
+    }
+
+    /// Rumble once at full strength, e.g. to warn the session ends soon,
+    /// regardless of what the game plays
+    pub fn alert(&mut self) -> std::io::Result<()> {
+        let pulse = FFEffectData {
+            direction: 0,
+            trigger: FFTrigger::default(),
+            replay: FFReplay {
+                length: ALERT_MS,
+                delay: 0,
+            },
+            kind: FFEffectKind::Rumble {
+                strong_magnitude: u16::MAX,
+                weak_magnitude: u16::MAX,
+            },
+        };
+        self.upload_effect(ALERT_EFFECT_ID, pulse)?;
+        self.control_effect(ALERT_EFFECT_ID, true)
     }
 
     /// Remove an effect from this device
     pub fn erase_effect(&mut self, virt_id: i16) -> std::io::Result<()> {
         if let Some(mut effect) = self.effects.remove(&virt_id) {
+            self.record_loaded();
             effect.stop()?;
         }
         Ok(())
     }
 
+    /// Note how many effects the device holds, for status
+    fn record_loaded(&self) {
+        self.health.lock().target_mut(&self.resource).effects = self.effects.len();
+    }
+
     /// Play or stop an effect on this device
     pub fn control_effect(&mut self, virt_id: i16, is_playing: bool) -> std::io::Result<()> {
         if let Some(effect) = self.effects.get_mut(&virt_id) {
@@ -69,21 +484,26 @@ impl PhysicalFFDev {
             }
         }
 
+        if !errors.is_empty() {
+            self.health.lock().target_mut(&self.resource).sync_failures += errors.len() as u32;
+        }
         errors
     }
 
     /// Attempt to recover a disconnected device
     pub fn recover(&mut self, manager: &EffectManager) -> std::io::Result<()> {
         let path = self.resource.path.clone();
+        self.health.lock().target_mut(&self.resource).recoveries += 1;
 
         // Try to reopen the device
-        let new_device = Device::open(&path)?;
+        let new_device = gilrs_helper::open_device(&path)?;
         self.resource.device = new_device;
 
         warn!("FF device reopened after disconnect: {}", path.display());
 
         // Clear old effect handles (they're invalid now)
         self.effects.clear();
+        self.record_loaded();
 
         // Re-sync all effects from the manager
         let errors = self.sync_effects(manager);
@@ -118,10 +538,13 @@ impl EffectManager {
         }
     }
 
-    /// Record a new effect upload
+    /// Record an effect upload
+    ///
+    /// Uploading over an existing effect modifies it, leaving it playing if it
+    /// was.
     pub fn upload(&mut self, virt_id: i16, effect_data: FFEffectData) {
         self.effects.insert(virt_id, effect_data);
-        self.playing.insert(virt_id, false);
+        self.playing.entry(virt_id).or_insert(false);
     }
 
     /// Remove an effect
@@ -147,4 +570,80 @@ impl EffectManager {
             .filter(|&(_, &is_playing)| is_playing)
             .map(|(&id, _)| id)
     }
+
+    /// All effects and whether they're playing, by ID
+    pub fn snapshot(&self) -> Vec<EffectStatus> {
+        let mut effects: Vec<EffectStatus> = self
+            .get_effects()
+            .map(|(id, data)| EffectStatus {
+                id,
+                data,
+                playing: self.playing.get(&id).copied().unwrap_or(false),
+            })
+            .collect();
+        effects.sort_by_key(|effect| effect.id);
+        effects
+    }
+}
+
+/// An effect uploaded by the game, as shown by `ctrlassist status`
+#[derive(Debug, Clone, Copy)]
+pub struct EffectStatus {
+    pub id: i16,
+    pub data: FFEffectData,
+    pub playing: bool,
+}
+
+impl fmt::Display for EffectStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.data.kind {
+            FFEffectKind::Rumble {
+                strong_magnitude,
+                weak_magnitude,
+            } => write!(
+                f,
+                "{}: rumble strong {} weak {}",
+                self.id, strong_magnitude, weak_magnitude
+            )?,
+            kind => write!(f, "{}: {:?}", self.id, kind)?,
+        }
+        write!(f, ", {} ms", self.data.replay.length)?;
+        if self.playing {
+            write!(f, ", playing")?;
+        }
+        Ok(())
+    }
+}
+
+/// Force feedback state of a session for display, effects first, then how
+/// each target took them
+pub fn describe(health: &FfHealth) -> String {
+    let mut lines = vec!["Effects:".to_string()];
+    if health.effects.is_empty() {
+        lines.push("  none uploaded".into());
+    }
+    lines.extend(health.effects.iter().map(|effect| format!("  {}", effect)));
+
+    lines.push("Targets:".into());
+    if health.targets.is_empty() {
+        lines.push("  none".into());
+    }
+    for (path, target) in &health.targets {
+        let upload = match target.last_upload_ok {
+            Some(true) => "last upload ok",
+            Some(false) => "last upload failed",
+            None => "no uploads",
+        };
+        lines.push(format!(
+            "  {} ({}): {} effects, {}, {} unsupported, {} sync failures, {} recoveries",
+            path.display(),
+            target.name,
+            target.effects,
+            upload,
+            target.unsupported,
+            target.sync_failures,
+            target.recoveries
+        ));
+    }
+    lines.join("\n")
 }
@@ -0,0 +1,141 @@
+use evdev::InputEvent;
+use log::warn;
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+
+/// Frames the writer may fall behind by before the intake keeps a backlog
+const QUEUE_CAPACITY: usize = 256;
+/// Backlogged frames beyond which new frames are merged rather than kept apart
+const MAX_BACKLOG: usize = 1024;
+/// Events a merged frame may grow to before it's collapsed to the latest
+/// value of each code
+const MAX_MERGED_EVENTS: usize = 4096;
+
+/// Create a bounded queue of output frames from the intake stage to the writer
+///
+/// Built on std's bounded channel, a lock-free array ring (crossbeam's), so
+/// queuing a frame is a couple of atomic operations. Emptied frames flow back
+/// for reuse, keeping the steady state free of allocations.
+pub fn frame_queue() -> (FrameSender, FrameReceiver) {
+    let (frames_tx, frames_rx) = mpsc::sync_channel(QUEUE_CAPACITY);
+    let (recycled_tx, recycled_rx) = mpsc::sync_channel(QUEUE_CAPACITY);
+    (
+        FrameSender {
+            frames: frames_tx,
+            recycled: recycled_rx,
+            backlog: VecDeque::new(),
+        },
+        FrameReceiver {
+            frames: frames_rx,
+            recycled: recycled_tx,
+        },
+    )
+}
+
+/// Intake end of the frame queue, which never blocks
+pub struct FrameSender {
+    frames: SyncSender<Vec<InputEvent>>,
+    recycled: Receiver<Vec<InputEvent>>,
+    /// Frames the writer had no room for yet, oldest first
+    backlog: VecDeque<Vec<InputEvent>>,
+}
+
+impl FrameSender {
+    /// Queue events as a frame, leaving `events` empty for reuse
+    ///
+    /// If the writer is behind, the frame is backlogged instead, keeping its
+    /// boundaries, so a press and release never collapse into one frame
+    /// unless the backlog itself overflows. Returns false once the writer is gone.
+    pub fn send(&mut self, events: &mut Vec<InputEvent>) -> bool {
+        if !events.is_empty() {
+            let buffer = match self.recycled.try_recv() {
+                Ok(buffer) => buffer,
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => {
+                    Vec::with_capacity(events.capacity())
+                }
+            };
+            let frame = std::mem::replace(events, buffer);
+            if self.backlog.len() >= MAX_BACKLOG
+                && let Some(last) = self.backlog.back_mut()
+            {
+                warn!("Virtual device writer is far behind, merging frames");
+                last.extend(frame);
+                if last.len() > MAX_MERGED_EVENTS {
+                    collapse(last);
+                }
+            } else {
+                self.backlog.push_back(frame);
+            }
+        }
+        self.flush()
+    }
+
+    /// Queue as much of the backlog as the writer has room for
+    pub fn flush(&mut self) -> bool {
+        while let Some(frame) = self.backlog.pop_front() {
+            match self.frames.try_send(frame) {
+                Ok(()) => {}
+                Err(TrySendError::Full(frame)) => {
+                    self.backlog.push_front(frame);
+                    break;
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    self.backlog.clear();
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Whether frames are waiting for room in the queue
+    pub fn has_backlog(&self) -> bool {
+        !self.backlog.is_empty()
+    }
+
+    /// Hand the backlog to the writer, waiting for room, then close the queue
+    /// so the writer ends once it's written everything
+    pub fn finish(mut self) {
+        for frame in self.backlog.drain(..) {
+            if self.frames.send(frame).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Keep only the latest event of each type and code, in the order they last
+/// changed, so the final state still comes through
+fn collapse(frame: &mut Vec<InputEvent>) {
+    let mut latest: Vec<InputEvent> = Vec::new();
+    for event in frame.iter().rev() {
+        if !latest
+            .iter()
+            .any(|e| e.event_type() == event.event_type() && e.code() == event.code())
+        {
+            latest.push(*event);
+        }
+    }
+    frame.clear();
+    frame.extend(latest.into_iter().rev());
+}
+
+/// Writer end of the frame queue
+pub struct FrameReceiver {
+    frames: Receiver<Vec<InputEvent>>,
+    recycled: SyncSender<Vec<InputEvent>>,
+}
+
+impl FrameReceiver {
+    /// Wait for the next frame, or `None` once the intake is gone and the
+    /// queue is drained
+    pub fn recv(&self) -> Option<Vec<InputEvent>> {
+        self.frames.recv().ok()
+    }
+
+    /// Hand a written frame back to the intake for reuse
+    pub fn recycle(&self, mut frame: Vec<InputEvent>) {
+        frame.clear();
+        let _ = self.recycled.try_send(frame);
+    }
+}
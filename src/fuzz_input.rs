@@ -0,0 +1,191 @@
+use crate::evdev_helpers::{
+    self, AXIS_HALF, AXIS_MAX, VIRTUAL_KEYS, VirtualGamepadInfo, VirtualLayout,
+};
+use crate::ff_helpers::FfCapabilities;
+use evdev::{AbsoluteAxisCode, EventType, InputEvent, KeyCode};
+use log::{debug, info};
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Axes the fuzzer moves; the D-pad is covered by its buttons
+const FUZZ_AXES: [AbsoluteAxisCode; 6] = [
+    AbsoluteAxisCode::ABS_X,
+    AbsoluteAxisCode::ABS_Y,
+    AbsoluteAxisCode::ABS_RX,
+    AbsoluteAxisCode::ABS_RY,
+    AbsoluteAxisCode::ABS_Z,
+    AbsoluteAxisCode::ABS_RZ,
+];
+
+/// Bounds on generated frames per second
+const MIN_RATE: u32 = 1;
+const MAX_RATE: u32 = 1000;
+
+/// A control of the virtual gamepad the fuzzer may change
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Control {
+    Button(KeyCode),
+    Axis(AbsoluteAxisCode),
+}
+
+impl Control {
+    /// Parse an evdev name, e.g. BTN_SOUTH or ABS_X, of a virtual gamepad control
+    fn parse(name: &str) -> Result<Self, String> {
+        if let Ok(key) = KeyCode::from_str(name)
+            && VIRTUAL_KEYS.contains(&key)
+        {
+            return Ok(Self::Button(key));
+        }
+        if let Ok(axis) = AbsoluteAxisCode::from_str(name)
+            && FUZZ_AXES.contains(&axis)
+        {
+            return Ok(Self::Axis(axis));
+        }
+        Err(format!(
+            "'{}' is not a button or axis of the virtual gamepad",
+            name
+        ))
+    }
+
+    /// Value of the control at rest
+    fn rest(self) -> i32 {
+        match self {
+            Self::Button(_) => 0,
+            Self::Axis(AbsoluteAxisCode::ABS_Z | AbsoluteAxisCode::ABS_RZ) => 0,
+            Self::Axis(_) => AXIS_HALF as i32,
+        }
+    }
+
+    fn event(self, value: i32) -> InputEvent {
+        match self {
+            Self::Button(key) => InputEvent::new(EventType::KEY.0, key.0, value),
+            Self::Axis(axis) => InputEvent::new(EventType::ABSOLUTE.0, axis.0, value),
+        }
+    }
+}
+
+/// Deterministic SplitMix64 generator, so a seed reproduces a run exactly
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0..bound`
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound.max(1)
+    }
+}
+
+/// Options of a fuzzing run
+pub struct FuzzOptions {
+    pub seed: u64,
+    /// Frames generated per second
+    pub rate: u32,
+    /// Evdev names of the controls to change, or all if empty
+    pub controls: Vec<String>,
+    /// Stop after this long, or run until shutdown if unset
+    pub duration: Option<Duration>,
+}
+
+/// Drive a virtual gamepad with random but valid input, for soak-testing
+/// games and input handling
+///
+/// Buttons toggle, so every press is eventually released, and axes stay in
+/// range, with sticks biased toward rest and extremes where bugs cluster.
+/// Everything is released when the run ends.
+pub fn run_fuzz(options: FuzzOptions, shutdown: Receiver<()>) -> Result<(), Box<dyn Error>> {
+    let controls: Vec<Control> = if options.controls.is_empty() {
+        VIRTUAL_KEYS
+            .iter()
+            .map(|&key| Control::Button(key))
+            .chain(FUZZ_AXES.iter().map(|&axis| Control::Axis(axis)))
+            .collect()
+    } else {
+        options
+            .controls
+            .iter()
+            .map(|name| Control::parse(name.trim()))
+            .collect::<Result<_, _>>()?
+    };
+
+    let info = VirtualGamepadInfo {
+        name: "CtrlAssist Fuzz".into(),
+        vendor_id: None,
+        product_id: None,
+        layout: VirtualLayout::Default,
+    };
+    // Nothing renders force feedback here, so none is advertised
+    let mut virtual_dev = evdev_helpers::create_virtual_gamepad(&info, &FfCapabilities::default())?;
+
+    let rate = options.rate.clamp(MIN_RATE, MAX_RATE);
+    let interval = Duration::from_secs(1) / rate;
+    let mut rng = SplitMix64(options.seed);
+    let mut values: Vec<i32> = controls.iter().map(|control| control.rest()).collect();
+    info!(
+        "Fuzz: seed {}, {} frames/s over {} controls",
+        options.seed,
+        rate,
+        controls.len()
+    );
+
+    let start = Instant::now();
+    let mut frames: u64 = 0;
+    let result = loop {
+        match shutdown.recv_timeout(interval) {
+            Ok(()) | Err(RecvTimeoutError::Disconnected) => break Ok(()),
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        if options
+            .duration
+            .is_some_and(|limit| start.elapsed() >= limit)
+        {
+            break Ok(());
+        }
+
+        // Change one to three controls per frame, like hands on a pad
+        let mut frame = Vec::new();
+        for _ in 0..=rng.below(3) {
+            let idx = rng.below(controls.len() as u64) as usize;
+            let control = controls[idx];
+            values[idx] = next_value(&mut rng, control, values[idx]);
+            frame.push(control.event(values[idx]));
+        }
+        debug!("Fuzz frame {}: {:?}", frames, frame);
+        if let Err(e) = virtual_dev.emit(&frame) {
+            break Err(e.into());
+        }
+        frames += 1;
+    };
+
+    // Leave nothing held for whatever reads the device next
+    let release: Vec<InputEvent> = controls
+        .iter()
+        .map(|control| control.event(control.rest()))
+        .collect();
+    let _ = virtual_dev.emit(&release);
+    info!("Fuzz: sent {} frames with seed {}", frames, options.seed);
+    println!("Sent {} frames with seed {}", frames, options.seed);
+    result
+}
+
+/// Next valid value of a control
+fn next_value(rng: &mut SplitMix64, control: Control, current: i32) -> i32 {
+    let max = AXIS_MAX as i32;
+    match control {
+        Control::Button(_) => i32::from(current == 0),
+        Control::Axis(_) => match rng.below(4) {
+            0 => control.rest(),
+            1 => 0,
+            2 => max,
+            _ => rng.below(max as u64 + 1) as i32,
+        },
+    }
+}
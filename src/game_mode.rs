@@ -0,0 +1,72 @@
+use ashpd::desktop::game_mode::GameMode;
+use log::{info, warn};
+use std::sync::mpsc;
+use std::thread;
+
+/// Registration of this process with Feral GameMode for a session
+///
+/// Goes through the xdg-desktop-portal, which forwards to `gamemoded` both
+/// inside and outside a sandbox, so the mux gets the same CPU governor and
+/// scheduling tweaks as the game. Unregistered when dropped.
+pub struct GameModeGuard {
+    stop: Option<mpsc::Sender<()>>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl GameModeGuard {
+    /// Register for the lifetime of the guard; failures are only logged, as
+    /// GameMode is an optimization the session runs fine without
+    pub fn register() -> Self {
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        // D-Bus calls need an async runtime, which the mux threads don't have
+        let worker = thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    warn!("GameMode: failed to start runtime: {}", e);
+                    return;
+                }
+            };
+            let pid = std::process::id();
+            runtime.block_on(async move {
+                let portal = match GameMode::new().await {
+                    Ok(portal) => portal,
+                    Err(e) => {
+                        warn!("GameMode unavailable: {}", e);
+                        return;
+                    }
+                };
+                if let Err(e) = portal.register(pid).await {
+                    warn!("GameMode: failed to register: {}", e);
+                    return;
+                }
+                info!("GameMode registered for pid {}", pid);
+
+                // Wait for the session to end, without blocking the runtime
+                let _ = tokio::task::spawn_blocking(move || stop_rx.recv()).await;
+
+                match portal.unregister(pid).await {
+                    Ok(()) => info!("GameMode unregistered"),
+                    Err(e) => warn!("GameMode: failed to unregister: {}", e),
+                }
+            });
+        });
+
+        Self {
+            stop: Some(stop_tx),
+            worker: Some(worker),
+        }
+    }
+}
+
+impl Drop for GameModeGuard {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
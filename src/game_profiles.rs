@@ -0,0 +1,209 @@
+use crate::disconnect_policy::DisconnectPolicy;
+use crate::ff_helpers::{BlockedEffects, FfConfig};
+use crate::mux_modes::{
+    AverageOptions, HandoffButton, ModeOptions, ModeType, PriorityOptions, ToggleOptions,
+    TurnOptions,
+};
+use crate::{HideType, SpoofTarget};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+
+/// Keys accepted in each `[profiles.<name>]` table
+pub const PROFILE_KEYS: &[&str] = &[
+    "games",
+    "hide",
+    "spoof",
+    "mode",
+    "modes",
+    "blocked_effects",
+    "disconnect",
+];
+
+/// Hide, spoof, and mode overrides for a game, e.g. Steam hiding with an Xbox
+/// 360 identity for a native title but System hiding for a Proton one
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct GameProfile {
+    /// Process names that select this profile when running, e.g. "eldenring.exe"
+    pub games: Vec<String>,
+    /// Hide strategy, or the session's default if unset
+    pub hide: Option<HideType>,
+    /// Spoof target, or the session's default if unset
+    pub spoof: Option<SpoofTarget>,
+    /// Mux mode, or the session's default if unset
+    pub mode: Option<ModeType>,
+    /// Parameters of every mode, or the session's defaults if unset
+    pub modes: Option<ModeOptions>,
+    /// Force feedback effect types kept from each controller, or the
+    /// session's if unset
+    pub blocked_effects: Option<BlockedEffects>,
+    /// What happens to a lost controller's inputs, or the session's policy if
+    /// unset
+    pub disconnect: Option<DisconnectPolicy>,
+}
+
+impl GameProfile {
+    /// Override the session's hide strategy and spoof target where set
+    pub fn apply(&self, hide: &mut HideType, spoof: &mut SpoofTarget) {
+        if let Some(profile_hide) = &self.hide {
+            *hide = profile_hide.clone();
+        }
+        if let Some(profile_spoof) = &self.spoof {
+            *spoof = profile_spoof.clone();
+        }
+    }
+
+    /// Override the session's mode and mode parameters where set
+    pub fn apply_mode(&self, mode: &mut ModeType, mode_options: &mut ModeOptions) {
+        if let Some(profile_mode) = &self.mode {
+            *mode = profile_mode.clone();
+        }
+        if let Some(profile_options) = &self.modes {
+            *mode_options = profile_options.clone();
+        }
+    }
+
+    /// Override the session's disconnect policy where set
+    pub fn apply_disconnect(&self, disconnect: &mut DisconnectPolicy) {
+        if let Some(policy) = self.disconnect {
+            *disconnect = policy;
+        }
+    }
+
+    /// Override the session's blocked force feedback effects where set
+    pub fn apply_force_feedback(&self, force_feedback: &mut FfConfig) {
+        if let Some(blocked) = &self.blocked_effects {
+            force_feedback.blocked_effects = blocked.clone();
+        }
+    }
+}
+
+/// Split-duty presets shipped with CtrlAssist, selectable by name without
+/// writing a config file first
+///
+/// They only pick a mode and its tuning, never hiding or spoofing, and have
+/// no games, so they're never selected by detection.
+fn builtin_profiles() -> BTreeMap<String, GameProfile> {
+    let preset = |mode: ModeType, modes: ModeOptions| GameProfile {
+        mode: Some(mode),
+        modes: Some(modes),
+        ..Default::default()
+    };
+    BTreeMap::from([
+        // The assist takes over at the slightest stick movement
+        (
+            "full-copilot".to_string(),
+            preset(
+                ModeType::Priority,
+                ModeOptions {
+                    priority: PriorityOptions { deadzone: 0.05 },
+                    ..Default::default()
+                },
+            ),
+        ),
+        // The primary keeps most of the say, nudged by the assist
+        (
+            "gentle-help".to_string(),
+            preset(
+                ModeType::Average,
+                ModeOptions {
+                    average: AverageOptions {
+                        assist_weight: 0.3,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ),
+        ),
+        // Control passes back and forth with the Mode button
+        (
+            "take-turns".to_string(),
+            preset(
+                ModeType::Turn,
+                ModeOptions {
+                    turn: TurnOptions {
+                        button: HandoffButton::Mode,
+                    },
+                    ..Default::default()
+                },
+            ),
+        ),
+        // Only the assist hands control over or takes it back
+        (
+            "child-safe".to_string(),
+            preset(
+                ModeType::Toggle,
+                ModeOptions {
+                    toggle: ToggleOptions {
+                        button: HandoffButton::Mode,
+                    },
+                    ..Default::default()
+                },
+            ),
+        ),
+    ])
+}
+
+/// Built-in presets merged with the user's profiles, which win on a name clash
+pub fn with_builtins(profiles: &BTreeMap<String, GameProfile>) -> BTreeMap<String, GameProfile> {
+    let mut merged = builtin_profiles();
+    merged.extend(profiles.clone());
+    merged
+}
+
+/// Pick the profile for a session: the selected one, else the first whose
+/// game is running
+pub fn resolve<'a>(
+    profiles: &'a BTreeMap<String, GameProfile>,
+    selected: Option<&str>,
+) -> Result<Option<(&'a str, &'a GameProfile)>, String> {
+    if let Some(name) = selected {
+        return profiles
+            .get_key_value(name)
+            .map(|(name, profile)| Some((name.as_str(), profile)))
+            .ok_or_else(|| format!("Profile '{}' not found in config", name));
+    }
+    if profiles.values().all(|profile| profile.games.is_empty()) {
+        return Ok(None);
+    }
+
+    let running = running_processes();
+    Ok(profiles
+        .iter()
+        .find(|(_, profile)| {
+            profile
+                .games
+                .iter()
+                .any(|game| running.contains(&game.to_lowercase()))
+        })
+        .map(|(name, profile)| (name.as_str(), profile)))
+}
+
+/// Lowercase names of running processes, from both `comm` and the file name
+/// of the first argument, as `comm` is truncated and Wine sets it oddly
+fn running_processes() -> HashSet<String> {
+    let mut names = HashSet::new();
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return names;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if let Ok(comm) = fs::read_to_string(path.join("comm")) {
+            names.insert(comm.trim().to_lowercase());
+        }
+        if let Ok(cmdline) = fs::read(path.join("cmdline"))
+            && let Some(arg0) = cmdline.split(|&b| b == 0).next()
+        {
+            let arg0 = String::from_utf8_lossy(arg0);
+            // Wine paths use backslashes, e.g. Z:\games\eldenring.exe
+            if let Some(file_name) = arg0.rsplit(['/', '\\']).next()
+                && !file_name.is_empty()
+            {
+                names.insert(file_name.to_lowercase());
+            }
+        }
+    }
+    names
+}
@@ -1,18 +1,336 @@
 use evdev::Device;
 use evdev::InputId;
 use evdev::uinput::VirtualDevice;
-use gilrs::{GamepadId, Gilrs};
-use log::error;
+use gilrs::ev::Code;
+use gilrs::ev::filter::{Filter, Jitter, axis_dpad_to_button, deadzone};
+use gilrs::{Axis, Button, Event, EventType, Gamepad, GamepadId, Gilrs, GilrsBuilder};
+use log::{debug, error, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::thread;
 use std::time::{Duration, Instant};
 use uuid::Uuid;
 
-const RETRY_INTERVAL: Duration = Duration::from_millis(50);
-const VIRTUAL_DEV_TIMEOUT: Duration = Duration::from_secs(2);
+use crate::evdev_helpers;
+use crate::quirks::DeviceQuirks;
+use crate::udev_helpers;
+
+/// Attempts at opening a device node before giving up
+const OPEN_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled after each, for at most 150ms in all
+const OPEN_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Options for gilrs's own event filters and SDL mappings
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct GilrsConfig {
+    /// Drop axis changes too small to matter (gilrs jitter filter)
+    pub jitter_filter: bool,
+    /// Apply gilrs's per-device axis deadzones before the mux modes
+    pub deadzone_filter: bool,
+    /// Load SDL mappings from the `SDL_GAMECONTROLLERCONFIG` environment variable
+    pub env_mappings: bool,
+    /// Load the SDL mappings bundled with gilrs
+    pub included_mappings: bool,
+    /// Extra SDL mappings, one per line
+    pub extra_mappings: Option<String>,
+}
+
+impl Default for GilrsConfig {
+    fn default() -> Self {
+        Self {
+            jitter_filter: true,
+            deadzone_filter: true,
+            env_mappings: true,
+            included_mappings: true,
+            extra_mappings: None,
+        }
+    }
+}
+
+/// Build a Gilrs instance honoring the configured mappings
+///
+/// Default filters and state updates are disabled so [`next_event`] can apply
+/// only the configured filters before updating gamepad state.
+pub fn build_gilrs(config: &GilrsConfig) -> Result<Gilrs, Box<dyn Error>> {
+    let mut builder = GilrsBuilder::new()
+        .with_default_filters(false)
+        .set_update_state(false)
+        .add_env_mappings(config.env_mappings)
+        .add_included_mappings(config.included_mappings);
+    if let Some(mappings) = &config.extra_mappings {
+        builder = builder.add_mappings(mappings);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("Failed to init Gilrs: {}", e).into())
+}
+
+/// Fetch the next event with the configured filters applied
+///
+/// Blocks up to `timeout` if given, otherwise returns immediately when no
+/// events are pending. Gamepad state is updated from the filtered event.
+pub fn next_event(
+    gilrs: &mut Gilrs,
+    config: &GilrsConfig,
+    timeout: Option<Duration>,
+) -> Option<Event> {
+    next_event_with(gilrs, config, &DeviceQuirks::default(), timeout, Some)
+}
+
+/// Like [`next_event`], but passes each event through `stage` before gamepad
+/// state is updated, so the mux modes see state consistent with the stage
+///
+/// Device quirks are corrected first, so the filters see events as a
+/// well-behaved controller would report them.
+///
+/// Returns `None` without blocking further once `stage` drops an event and no
+/// others are pending, so callers can service any timers the stage keeps.
+pub fn next_event_with(
+    gilrs: &mut Gilrs,
+    config: &GilrsConfig,
+    quirks: &DeviceQuirks,
+    mut timeout: Option<Duration>,
+    mut stage: impl FnMut(Event) -> Option<Event>,
+) -> Option<Event> {
+    loop {
+        let raw = match timeout {
+            Some(_) => gilrs.next_event_blocking(timeout),
+            None => gilrs.next_event(),
+        };
+        let raw = raw.map(|ev| quirks.translate(ev, gilrs));
+
+        // D-pad hat axes are always mapped to buttons, as the mux modes expect
+        let mut event = raw.filter_ev(&axis_dpad_to_button, gilrs);
+        if config.jitter_filter {
+            event = event.filter_ev(&Jitter::new(), gilrs);
+        }
+        if config.deadzone_filter {
+            event = event.filter_ev(&deadzone, gilrs);
+        }
+
+        match event {
+            Some(ev) if ev.is_dropped() => continue,
+            Some(ev) => match stage(ev) {
+                Some(ev) => {
+                    gilrs.update(&ev);
+                    return Some(ev);
+                }
+                None if timeout.is_some() => timeout = Some(Duration::ZERO),
+                None => {}
+            },
+            None => return None,
+        }
+    }
+}
+
+/// Gamepads paired into one logical controller, such as two Joy-Cons
+///
+/// Each pair has a leader, whose ID stands for the pair, and a partner whose
+/// events are attributed to the leader once gamepad state is updated.
+#[derive(Debug, Clone, Default)]
+pub struct CompositeSources {
+    /// Partner of each leader
+    partners: HashMap<GamepadId, GamepadId>,
+}
+
+impl CompositeSources {
+    /// Pair `partner` with `leader`, so both act as the leader
+    pub fn pair(&mut self, leader: GamepadId, partner: GamepadId) {
+        self.partners.insert(leader, partner);
+    }
+
+    pub fn partner_of(&self, leader: GamepadId) -> Option<GamepadId> {
+        self.partners.get(&leader).copied()
+    }
+
+    /// ID of the logical controller a gamepad belongs to
+    pub fn leader_of(&self, id: GamepadId) -> GamepadId {
+        self.partners
+            .iter()
+            .find_map(|(&leader, &partner)| (partner == id).then_some(leader))
+            .unwrap_or(id)
+    }
+
+    /// The given gamepad and its partner, if any
+    pub fn members(&self, id: GamepadId) -> impl Iterator<Item = GamepadId> {
+        std::iter::once(id).chain(self.partner_of(id))
+    }
+}
+
+/// State lookup of logical controllers, merging composite pairs
+#[derive(Clone, Copy)]
+pub struct Sources<'a> {
+    pub gilrs: &'a Gilrs,
+    pub composites: &'a CompositeSources,
+}
+
+impl<'a> Sources<'a> {
+    pub fn new(gilrs: &'a Gilrs, composites: &'a CompositeSources) -> Self {
+        Self { gilrs, composites }
+    }
+
+    /// State of the logical controller led by `id`
+    pub fn get(&self, id: GamepadId) -> Source<'a> {
+        Source {
+            leader: self.gilrs.gamepad(id),
+            partner: self
+                .composites
+                .partner_of(id)
+                .map(|partner| self.gilrs.gamepad(partner)),
+        }
+    }
+}
+
+/// State of a logical controller, one gamepad or a composite pair
+///
+/// Paired gamepads usually have separate controls, e.g. each Joy-Con has one
+/// stick, so controls read from whichever member is using them.
+#[derive(Clone, Copy)]
+pub struct Source<'a> {
+    leader: Gamepad<'a>,
+    partner: Option<Gamepad<'a>>,
+}
+
+impl<'a> Source<'a> {
+    pub fn members(self) -> impl Iterator<Item = Gamepad<'a>> {
+        std::iter::once(self.leader).chain(self.partner)
+    }
+
+    pub fn is_pressed(&self, btn: Button) -> bool {
+        self.members().any(|gamepad| gamepad.is_pressed(btn))
+    }
+
+    /// Analog value of a button, the highest among members
+    pub fn button_value(&self, btn: Button) -> f32 {
+        self.members()
+            .filter_map(|gamepad| gamepad.button_data(btn).map(|d| d.value()))
+            .fold(0.0, f32::max)
+    }
+
+    /// Value of a stick axis, from the member deflecting it most
+    pub fn axis_value(&self, axis: Axis) -> f32 {
+        self.members()
+            .filter_map(|gamepad| gamepad.axis_data(axis).map(|d| d.value()))
+            .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+            .unwrap_or(0.0)
+    }
+}
+
+/// Buttons gilrs may report, besides Mode which each mode handles itself
+const SEEDED_BUTTONS: &[Button] = &[
+    Button::South,
+    Button::East,
+    Button::North,
+    Button::West,
+    Button::C,
+    Button::Z,
+    Button::LeftTrigger,
+    Button::LeftTrigger2,
+    Button::RightTrigger,
+    Button::RightTrigger2,
+    Button::Select,
+    Button::Start,
+    Button::LeftThumb,
+    Button::RightThumb,
+    Button::DPadUp,
+    Button::DPadDown,
+    Button::DPadLeft,
+    Button::DPadRight,
+];
+
+const SEEDED_AXES: &[Axis] = &[
+    Axis::LeftStickX,
+    Axis::LeftStickY,
+    Axis::LeftZ,
+    Axis::RightStickX,
+    Axis::RightStickY,
+    Axis::RightZ,
+];
+
+/// Events for whatever a gamepad holds right now, read from its evdev node
+///
+/// Gilrs only learns a control's state from its events, so anything held
+/// since before the session started is missing from its cache until moved.
+pub fn held_events(gamepad: Gamepad, device: &Device) -> Vec<EventType> {
+    const EV_KEY: u32 = 0x01;
+    const EV_ABS: u32 = 0x03;
+
+    let keys = device.get_key_state().ok();
+    let abs = device.get_abs_state().ok();
+    let split = |code: Code| {
+        let raw = code.into_u32();
+        (raw >> 16, (raw & 0xffff) as u16)
+    };
+    // Scaled as gilrs does: 0..1 for buttons, -1..1 for axes
+    let abs_value = |code: u16, centered: bool| {
+        let info = abs.as_ref()?.get(usize::from(code))?;
+        let range = (info.maximum - info.minimum) as f32;
+        if range <= 0.0 {
+            return None;
+        }
+        let value = (info.value - info.minimum) as f32 / range;
+        Some(if centered { value * 2.0 - 1.0 } else { value }.clamp(-1.0, 1.0))
+    };
+
+    let mut events = Vec::new();
+    for &btn in SEEDED_BUTTONS {
+        let Some(code) = gamepad.button_code(btn) else {
+            continue;
+        };
+        let value = match split(code) {
+            (EV_KEY, key) => keys
+                .as_ref()
+                .is_some_and(|keys| keys.contains(evdev::KeyCode(key)))
+                .then_some(1.0),
+            (EV_ABS, axis) => abs_value(axis, false).filter(|&value| value > 0.0),
+            _ => None,
+        };
+        if let Some(value) = value {
+            if value >= 0.5 {
+                events.push(EventType::ButtonPressed(btn, code));
+            }
+            events.push(EventType::ButtonChanged(btn, value, code));
+        }
+    }
+    for &axis in SEEDED_AXES {
+        let Some(code) = gamepad.axis_code(axis) else {
+            continue;
+        };
+        if let (EV_ABS, abs_code) = split(code)
+            && let Some(mut value) = abs_value(abs_code, true)
+        {
+            // Up is positive in gilrs, unlike evdev
+            if matches!(axis, Axis::LeftStickY | Axis::RightStickY) {
+                value = -value;
+            }
+            events.push(EventType::AxisChanged(axis, value, code));
+        }
+    }
+    // Hats reach gilrs as D-pad axes, split into buttons by its filter
+    for (axis, negative, positive) in [
+        (Axis::DPadX, Button::DPadLeft, Button::DPadRight),
+        (Axis::DPadY, Button::DPadUp, Button::DPadDown),
+    ] {
+        let Some(code) = gamepad.axis_code(axis) else {
+            continue;
+        };
+        if let (EV_ABS, abs_code) = split(code)
+            && let Some(value) = abs.as_ref().and_then(|abs| abs.get(usize::from(abs_code)))
+            && value.value != 0
+        {
+            let btn = if value.value < 0 { negative } else { positive };
+            events.push(EventType::ButtonPressed(btn, code));
+            events.push(EventType::ButtonChanged(btn, 1.0, code));
+        }
+    }
+    events
+}
 
 /// Represents a physical gamepad and its associated Linux event device.
 pub struct GamepadResource {
@@ -21,18 +339,125 @@ pub struct GamepadResource {
     pub device: Device,
 }
 
-impl Clone for GamepadResource {
-    fn clone(&self) -> Self {
-        GamepadResource {
+impl GamepadResource {
+    /// Connection type derived from the evdev bus type
+    pub fn connection(&self) -> &'static str {
+        match self.device.input_id().bus_type() {
+            evdev::BusType::BUS_USB => "USB",
+            evdev::BusType::BUS_BLUETOOTH => "BT",
+            _ => "Other",
+        }
+    }
+
+    /// Identifier for telling identical controllers apart: the evdev `uniq`
+    /// (serial or MAC) when reported, otherwise the `phys` port path
+    pub fn serial(&self) -> Option<String> {
+        let uniq = self.device.unique_name().filter(|s| !s.is_empty());
+        let phys = self.device.physical_path().filter(|s| !s.is_empty());
+        uniq.or(phys).map(|s| s.to_string())
+    }
+
+    /// Whether this is a virtual gamepad of this or another CtrlAssist
+    /// instance, which would feed back into the mux as a source
+    pub fn is_ctrlassist(&self) -> bool {
+        self.device
+            .physical_path()
+            .is_some_and(|phys| phys.starts_with(evdev_helpers::VIRTUAL_PHYS_PREFIX))
+    }
+
+    /// Process ID of the session that created this CtrlAssist virtual device
+    pub fn ctrlassist_session(&self) -> Option<u32> {
+        self.device
+            .physical_path()?
+            .strip_prefix(evdev_helpers::VIRTUAL_PHYS_PREFIX)?
+            .parse()
+            .ok()
+    }
+
+    /// Name with connection type and a short serial suffix, e.g. "Pad [USB …14.0-2]"
+    pub fn display_name(&self) -> String {
+        match self.serial() {
+            Some(serial) => format!(
+                "{} [{} …{}]",
+                self.name,
+                self.connection(),
+                serial_suffix(&serial)
+            ),
+            None => format!("{} [{}]", self.name, self.connection()),
+        }
+    }
+}
+
+/// Last few characters of a serial, ignoring any "/inputN" interface suffix
+fn serial_suffix(serial: &str) -> &str {
+    const SUFFIX_LEN: usize = 6;
+    let serial = serial.split('/').next().unwrap_or(serial);
+    let start = serial
+        .char_indices()
+        .rev()
+        .nth(SUFFIX_LEN - 1)
+        .map_or(0, |(i, _)| i);
+    &serial[start..]
+}
+
+impl GamepadResource {
+    /// Open another handle to the device, e.g. for force feedback
+    ///
+    /// Fails rather than panics if the node is gone, such as when the
+    /// controller was unplugged, after retrying failures that may be transient.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(GamepadResource {
             name: self.name.clone(),
             path: self.path.clone(),
-            device: Device::open(&self.path).expect("Failed to clone device handle"),
+            device: open_device(&self.path)?,
+        })
+    }
+}
+
+/// Whether a failure to open a device node may clear up on its own
+///
+/// A node known to be a gamepad may also briefly be missing or root-only
+/// while udev recreates it and applies its permissions during a hotplug.
+fn is_transient(e: &io::Error, known_gamepad: bool) -> bool {
+    match e.raw_os_error() {
+        Some(libc::EBUSY | libc::EAGAIN | libc::EINTR) => true,
+        Some(libc::ENOENT | libc::EACCES) => known_gamepad,
+        _ => false,
+    }
+}
+
+/// Open a node known to be a gamepad, retrying transient failures with
+/// exponential backoff
+pub fn open_device(path: &Path) -> io::Result<Device> {
+    open_with_retry(path, true)
+}
+
+fn open_with_retry(path: &Path, known_gamepad: bool) -> io::Result<Device> {
+    let mut delay = OPEN_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match Device::open(path) {
+            Ok(device) => return Ok(device),
+            Err(e) if attempt < OPEN_ATTEMPTS && is_transient(&e, known_gamepad) => {
+                debug!(
+                    "Failed to open {} ({}), retrying in {:?}",
+                    path.display(),
+                    e,
+                    delay
+                );
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
         }
     }
 }
 
 pub fn wait_for_virtual_device(
     v_dev: &mut VirtualDevice,
+    timeout: Duration,
+    retry_interval: Duration,
 ) -> Result<GamepadResource, Box<dyn Error>> {
     let v_path = v_dev
         .enumerate_dev_nodes_blocking()?
@@ -41,7 +466,7 @@ pub fn wait_for_virtual_device(
         .ok_or("Could not find virtual device path")?;
 
     let start = Instant::now();
-    while start.elapsed() < VIRTUAL_DEV_TIMEOUT {
+    while start.elapsed() < timeout {
         if let Ok(dev) = Device::open(&v_path) {
             let resource = GamepadResource {
                 name: dev.name().unwrap().to_string(),
@@ -50,7 +475,7 @@ pub fn wait_for_virtual_device(
             };
             return Ok(resource);
         }
-        thread::sleep(RETRY_INTERVAL);
+        thread::sleep(retry_interval);
     }
     Err("Timed out waiting for virtual device".into())
 }
@@ -79,8 +504,46 @@ pub fn create_uuid(iid: InputId) -> Uuid {
     )
 }
 
+/// What a Gilrs gamepad's event device is matched by, apart from Gilrs so
+/// matching can run on another thread
+#[derive(Debug, Clone)]
+pub struct GamepadIdentity {
+    pub id: GamepadId,
+    pub name: String,
+    os_name: String,
+    uuid: [u8; 16],
+}
+
+impl GamepadIdentity {
+    pub fn new(id: GamepadId, gamepad: Gamepad) -> Self {
+        Self {
+            id,
+            name: gamepad.name().to_string(),
+            os_name: gamepad.os_name().to_string(),
+            uuid: gamepad.uuid(),
+        }
+    }
+}
+
 /// Matches Gilrs gamepads to /dev/input/event* nodes.
+///
+/// Only event nodes on the current seat are considered, so controllers
+/// attached to other seats are never used or hidden.
 pub fn discover_gamepad_resources(gilrs: &Gilrs) -> HashMap<GamepadId, GamepadResource> {
+    let gamepads: Vec<GamepadIdentity> = gilrs
+        .gamepads()
+        .map(|(id, gamepad)| GamepadIdentity::new(id, gamepad))
+        .collect();
+    match_gamepad_resources(&gamepads)
+}
+
+/// Matches gamepads to /dev/input/event* nodes, see [`discover_gamepad_resources`]
+///
+/// Busy nodes are retried, so this may block for a while.
+pub fn match_gamepad_resources(
+    gamepads: &[GamepadIdentity],
+) -> HashMap<GamepadId, GamepadResource> {
+    let seat = udev_helpers::current_seat();
     let mut resources = HashMap::new();
     let mut available_paths: HashSet<PathBuf> = fs::read_dir("/dev/input")
         .into_iter()
@@ -92,21 +555,32 @@ pub fn discover_gamepad_resources(gilrs: &Gilrs) -> HashMap<GamepadId, GamepadRe
                 .and_then(|n| n.to_str())
                 .is_some_and(|s| s.starts_with("event"))
         })
+        .filter(|p| udev_helpers::is_on_seat(p, &seat))
         .collect();
 
-    for (id, gamepad) in gilrs.gamepads() {
+    for gamepad in gamepads {
+        let id = gamepad.id;
         let mut matched_path = None;
 
         for path in &available_paths {
-            if let Ok(device) = Device::open(path) {
-                let input_id = device.input_id();
-                let name_match = device.name().is_some_and(|n| n == gamepad.os_name());
-                let uuid_match = Uuid::from_bytes(gamepad.uuid()) == create_uuid(input_id);
-
-                if name_match && uuid_match {
-                    matched_path = Some((path.clone(), device));
-                    break;
+            // Other input devices are often unreadable, so only a busy node
+            // is retried; anything else is skipped as not this gamepad
+            let device = match open_with_retry(path, false) {
+                Ok(device) => device,
+                Err(e) => {
+                    if is_transient(&e, false) {
+                        warn!("Skipping {}, still busy: {}", path.display(), e);
+                    }
+                    continue;
                 }
+            };
+            let input_id = device.input_id();
+            let name_match = device.name().is_some_and(|n| n == gamepad.os_name);
+            let uuid_match = Uuid::from_bytes(gamepad.uuid) == create_uuid(input_id);
+
+            if name_match && uuid_match {
+                matched_path = Some((path.clone(), device));
+                break;
             }
         }
 
@@ -115,7 +589,7 @@ pub fn discover_gamepad_resources(gilrs: &Gilrs) -> HashMap<GamepadId, GamepadRe
             resources.insert(
                 id,
                 GamepadResource {
-                    name: gamepad.name().to_string(),
+                    name: gamepad.name.clone(),
                     path,
                     device,
                 },
@@ -123,8 +597,7 @@ pub fn discover_gamepad_resources(gilrs: &Gilrs) -> HashMap<GamepadId, GamepadRe
         } else {
             error!(
                 "Failed to match Gilrs gamepad {:?} ('{}') to a Linux event device.",
-                id,
-                gamepad.name()
+                id, gamepad.name
             );
         }
     }
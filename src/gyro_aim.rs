@@ -0,0 +1,226 @@
+use crate::evdev_helpers::{self, AXIS_HALF, AXIS_MAX, READ_POLL_INTERVAL};
+use crate::gilrs_helper::Sources;
+use evdev::{AbsoluteAxisCode, Device, EventSummary, EventType, InputEvent};
+use gilrs::{Button, GamepadId};
+use log::{info, warn};
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the right stick is updated while gyro aiming
+const TICK_INTERVAL: Duration = Duration::from_millis(8);
+
+/// Assist button held to aim with the gyro
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum GyroActivation {
+    /// Aim whenever the mux is running
+    Always,
+    LeftTrigger,
+    #[default]
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    LeftThumb,
+    RightThumb,
+}
+
+impl GyroActivation {
+    fn button(&self) -> Option<Button> {
+        match self {
+            Self::Always => None,
+            Self::LeftTrigger => Some(Button::LeftTrigger),
+            Self::LeftTrigger2 => Some(Button::LeftTrigger2),
+            Self::RightTrigger => Some(Button::RightTrigger),
+            Self::RightTrigger2 => Some(Button::RightTrigger2),
+            Self::LeftThumb => Some(Button::LeftThumb),
+            Self::RightThumb => Some(Button::RightThumb),
+        }
+    }
+}
+
+/// Aiming with the assist controller's gyro on the virtual right stick
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct GyroConfig {
+    pub enabled: bool,
+    /// Stick deflection per degree/second of rotation (0.02 is full at 50°/s)
+    pub sensitivity: f32,
+    pub activation: GyroActivation,
+    pub invert_x: bool,
+    pub invert_y: bool,
+}
+
+impl Default for GyroConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sensitivity: 0.02,
+            activation: GyroActivation::default(),
+            invert_x: false,
+            invert_y: false,
+        }
+    }
+}
+
+/// Latest angular velocity of the motion sensor, in degrees per second
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GyroRates {
+    pub pitch: f32,
+    pub yaw: f32,
+}
+
+/// Read angular velocity from a motion sensor node until shutdown
+pub fn spawn_motion_reader(
+    path: &Path,
+    rates: Arc<Mutex<GyroRates>>,
+    shutdown: Arc<AtomicBool>,
+) -> Result<thread::JoinHandle<()>, Box<dyn std::error::Error>> {
+    let mut device = Device::open(path)?;
+
+    // Gyro axes report in units per degree/second, given by their resolution
+    let resolution = |axis: AbsoluteAxisCode| {
+        device
+            .get_absinfo()
+            .ok()
+            .and_then(|mut infos| infos.find(|(code, _)| *code == axis))
+            .map_or(1.0, |(_, info)| info.resolution().max(1) as f32)
+    };
+    let pitch_res = resolution(AbsoluteAxisCode::ABS_RX);
+    let yaw_res = resolution(AbsoluteAxisCode::ABS_RY);
+
+    info!("Gyro: reading motion sensor {}", path.display());
+    Ok(thread::spawn(move || {
+        while !shutdown.load(Ordering::SeqCst) {
+            let events = match evdev_helpers::wait_readable(&device, READ_POLL_INTERVAL)
+                .and_then(|readable| readable.then(|| device.fetch_events()).transpose())
+            {
+                Ok(Some(events)) => events,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Gyro: motion sensor stopped: {}", e);
+                    *rates.lock() = GyroRates::default();
+                    return;
+                }
+            };
+            for event in events {
+                if let EventSummary::AbsoluteAxis(_, axis, value) = event.destructure() {
+                    match axis {
+                        AbsoluteAxisCode::ABS_RX => rates.lock().pitch = value as f32 / pitch_res,
+                        AbsoluteAxisCode::ABS_RY => rates.lock().yaw = value as f32 / yaw_res,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }))
+}
+
+/// Adds gyro motion onto the virtual right stick while activated
+///
+/// The right stick output of the mux mode is kept as a base, so aiming adds
+/// to whatever the sticks do, and the base is restored once aiming stops.
+pub struct GyroAim {
+    config: GyroConfig,
+    rates: Option<Arc<Mutex<GyroRates>>>,
+    assist_id: GamepadId,
+    /// Last right stick values from the mux mode, in evdev units
+    base: [i32; 2],
+    active: bool,
+    last_tick: Instant,
+}
+
+impl GyroAim {
+    pub fn new(
+        config: GyroConfig,
+        rates: Option<Arc<Mutex<GyroRates>>>,
+        assist_id: GamepadId,
+    ) -> Self {
+        Self {
+            config,
+            rates,
+            assist_id,
+            base: [AXIS_HALF as i32; 2],
+            active: false,
+            last_tick: Instant::now(),
+        }
+    }
+
+    fn is_activated(&self, sources: &Sources) -> bool {
+        if self.rates.is_none() {
+            return false;
+        }
+        match self.config.activation.button() {
+            Some(btn) => sources.get(self.assist_id).is_pressed(btn),
+            None => true,
+        }
+    }
+
+    /// Record right stick output of the mux mode, holding it back while aiming
+    pub fn process(&mut self, events: &mut Vec<InputEvent>) {
+        if self.rates.is_none() {
+            return;
+        }
+        events.retain(|event| {
+            if event.event_type() != EventType::ABSOLUTE {
+                return true;
+            }
+            let index = match AbsoluteAxisCode(event.code()) {
+                AbsoluteAxisCode::ABS_RX => 0,
+                AbsoluteAxisCode::ABS_RY => 1,
+                _ => return true,
+            };
+            self.base[index] = event.value();
+            !self.active
+        });
+    }
+
+    /// Append right stick events for the current gyro motion, if aiming
+    pub fn tick(&mut self, sources: &Sources, now: Instant, out: &mut Vec<InputEvent>) {
+        let Some(rates) = &self.rates else {
+            return;
+        };
+
+        if !self.is_activated(sources) {
+            if self.active {
+                // Restore the mux mode's own right stick output
+                self.active = false;
+                out.push(stick_event(AbsoluteAxisCode::ABS_RX, self.base[0]));
+                out.push(stick_event(AbsoluteAxisCode::ABS_RY, self.base[1]));
+            }
+            return;
+        }
+        if self.active && now < self.last_tick + TICK_INTERVAL {
+            return;
+        }
+        self.active = true;
+        self.last_tick = now;
+
+        let rates = *rates.lock();
+        let sign = |invert: bool| if invert { -1.0 } else { 1.0 };
+        let dx = rates.yaw * self.config.sensitivity * sign(!self.config.invert_x);
+        let dy = rates.pitch * self.config.sensitivity * sign(!self.config.invert_y);
+
+        for (axis, base, delta) in [
+            (AbsoluteAxisCode::ABS_RX, self.base[0], dx),
+            (AbsoluteAxisCode::ABS_RY, self.base[1], dy),
+        ] {
+            let value = (base as f32 + delta * AXIS_HALF).clamp(0.0, AXIS_MAX);
+            out.push(stick_event(axis, value.round() as i32));
+        }
+    }
+
+    /// When the right stick next needs updating, if aiming
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let always = self.config.activation == GyroActivation::Always;
+        (self.rates.is_some() && (self.active || always)).then(|| self.last_tick + TICK_INTERVAL)
+    }
+}
+
+fn stick_event(axis: AbsoluteAxisCode, value: i32) -> InputEvent {
+    InputEvent::new(EventType::ABSOLUTE.0, axis.0, value)
+}
@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// Turns of control kept for the strip, older ones only counting in totals
+const MAX_TURNS: usize = 12;
+
+/// Source role in control, as the assist may be replaced mid-session
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Holder {
+    Primary,
+    Assist,
+}
+
+/// A stretch of time one controller had exclusive control
+#[derive(Debug, Clone)]
+struct Turn {
+    holder: Holder,
+    since: Instant,
+    until: Option<Instant>,
+}
+
+impl Turn {
+    fn length(&self, now: Instant) -> Duration {
+        self.until.unwrap_or(now).duration_since(self.since)
+    }
+}
+
+/// Who held control and for how long, in modes that hand it off, e.g. to
+/// settle who was hogging it or track a player's independence over a session
+#[derive(Debug, Clone, Default)]
+pub struct HandoffHistory {
+    turns: VecDeque<Turn>,
+    /// Time of finished turns per holder, including ones dropped from the strip
+    primary_total: Duration,
+    assist_total: Duration,
+}
+
+impl HandoffHistory {
+    /// Note who is in control as of `now`, or that no one is, e.g. after
+    /// switching to a mode without handoff
+    pub fn record(&mut self, holder: Option<Holder>, now: Instant) {
+        if self.current().map(|turn| turn.holder) == holder {
+            return;
+        }
+        if let Some(turn) = self.turns.back_mut()
+            && turn.until.is_none()
+        {
+            turn.until = Some(now);
+            let length = turn.length(now);
+            match turn.holder {
+                Holder::Primary => self.primary_total += length,
+                Holder::Assist => self.assist_total += length,
+            }
+        }
+        if let Some(holder) = holder {
+            if self.turns.len() == MAX_TURNS {
+                self.turns.pop_front();
+            }
+            self.turns.push_back(Turn {
+                holder,
+                since: now,
+                until: None,
+            });
+        }
+    }
+
+    fn current(&self) -> Option<&Turn> {
+        self.turns.back().filter(|turn| turn.until.is_none())
+    }
+
+    /// Recent turns, oldest first with the current holder marked, and the
+    /// session's totals, e.g. "Primary 2m05s → Assist 35s → ▶ Primary 12s"
+    pub fn describe(&self, now: Instant) -> String {
+        if self.turns.is_empty() {
+            return "Handoffs: none".into();
+        }
+        let strip = self
+            .turns
+            .iter()
+            .map(|turn| {
+                let marker = if turn.until.is_none() { "▶ " } else { "" };
+                format!(
+                    "{}{:?} {}",
+                    marker,
+                    turn.holder,
+                    format_length(turn.length(now))
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" → ");
+
+        let (mut primary, mut assist) = (self.primary_total, self.assist_total);
+        if let Some(turn) = self.current() {
+            match turn.holder {
+                Holder::Primary => primary += turn.length(now),
+                Holder::Assist => assist += turn.length(now),
+            }
+        }
+        let mut out = format!("Handoffs: {}", strip);
+        let _ = write!(
+            out,
+            "\nIn control: primary {}, assist {}",
+            format_length(primary),
+            format_length(assist)
+        );
+        out
+    }
+}
+
+/// Length of a turn, e.g. "45s" or "2m05s"
+fn format_length(length: Duration) -> String {
+    let secs = length.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    }
+}
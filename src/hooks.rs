@@ -0,0 +1,83 @@
+use log::{error, info};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::thread;
+
+/// Session lifecycle events that may trigger a user hook
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookEvent {
+    Start,
+    Stop,
+    Handoff,
+    Disconnect,
+}
+
+impl HookEvent {
+    /// Name exposed to hook commands via `CTRLASSIST_EVENT`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookEvent::Start => "start",
+            HookEvent::Stop => "stop",
+            HookEvent::Handoff => "handoff",
+            HookEvent::Disconnect => "disconnect",
+        }
+    }
+}
+
+/// User shell commands to run on session lifecycle events
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
+pub struct HookConfig {
+    /// Command run after the mux has started
+    pub on_start: Option<String>,
+    /// Command run after the mux has stopped
+    pub on_stop: Option<String>,
+    /// Command run when control is handed off between controllers
+    pub on_handoff: Option<String>,
+    /// Command run when the primary or assist controller disconnects
+    pub on_disconnect: Option<String>,
+}
+
+impl HookConfig {
+    fn command(&self, event: HookEvent) -> Option<&str> {
+        match event {
+            HookEvent::Start => self.on_start.as_deref(),
+            HookEvent::Stop => self.on_stop.as_deref(),
+            HookEvent::Handoff => self.on_handoff.as_deref(),
+            HookEvent::Disconnect => self.on_disconnect.as_deref(),
+        }
+    }
+
+    /// Run the configured command for an event via `sh -c` without blocking
+    ///
+    /// `vars` are exported as extra environment variables alongside
+    /// `CTRLASSIST_EVENT`, so scripts can tell which event fired.
+    pub fn run(&self, event: HookEvent, vars: &[(&str, String)]) {
+        let Some(command) = self.command(event) else {
+            return;
+        };
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(command)
+            .env("CTRLASSIST_EVENT", event.as_str());
+        for (key, value) in vars {
+            cmd.env(key, value);
+        }
+
+        info!("Running {} hook: {}", event.as_str(), command);
+        match cmd.spawn() {
+            Ok(mut child) => {
+                // Reap the child in the background so hooks never stall the mux
+                thread::spawn(move || match child.wait() {
+                    Ok(status) if !status.success() => {
+                        error!("{} hook exited with {}", event.as_str(), status);
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("Failed to wait on {} hook: {}", event.as_str(), e),
+                });
+            }
+            Err(e) => error!("Failed to run {} hook: {}", event.as_str(), e),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use gilrs::{Event, EventType, GamepadId};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Longest delay accepted, beyond which a game is no longer playable
+const MAX_DELAY: Duration = Duration::from_millis(1000);
+
+/// Per-controller artificial input delay, in milliseconds (0 disables)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct DelayConfig {
+    pub primary_ms: u64,
+    pub assist_ms: u64,
+}
+
+/// Holds back a controller's events for a fixed time before they reach the mux
+///
+/// Used to practice under netplay-like latency or to handicap a stronger
+/// player. Events keep their order, and connection changes are never delayed.
+pub struct InputDelay {
+    delays: HashMap<GamepadId, Duration>,
+    /// Held-back events per controller with the instant they become due, oldest first
+    queues: HashMap<GamepadId, VecDeque<(Instant, Event)>>,
+}
+
+impl InputDelay {
+    pub fn new(config: &DelayConfig, primary_id: GamepadId, assist_id: GamepadId) -> Self {
+        let delays = [
+            (primary_id, config.primary_ms),
+            (assist_id, config.assist_ms),
+        ]
+        .into_iter()
+        .filter(|(_, ms)| *ms > 0)
+        .map(|(id, ms)| (id, Duration::from_millis(ms).min(MAX_DELAY)))
+        .collect();
+
+        Self {
+            delays,
+            queues: HashMap::new(),
+        }
+    }
+
+    /// Give a replacement assist the delay of the one it replaces; events
+    /// already held back are still released
+    pub fn replace(&mut self, old_id: GamepadId, new_id: GamepadId) {
+        if let Some(delay) = self.delays.remove(&old_id) {
+            self.delays.insert(new_id, delay);
+        }
+    }
+
+    /// Filter an incoming event of the controller `leader` stands for,
+    /// returning `None` if it is held back
+    pub fn filter(&mut self, event: Event, leader: GamepadId, now: Instant) -> Option<Event> {
+        if matches!(event.event, EventType::Connected | EventType::Disconnected) {
+            return Some(event);
+        }
+        let Some(&delay) = self.delays.get(&leader) else {
+            return Some(event);
+        };
+        self.queues
+            .entry(leader)
+            .or_default()
+            .push_back((now + delay, event));
+        None
+    }
+
+    /// Append events whose delay has passed
+    pub fn expire(&mut self, now: Instant, out: &mut Vec<Event>) {
+        for queue in self.queues.values_mut() {
+            while queue.front().is_some_and(|(due, _)| *due <= now) {
+                out.extend(queue.pop_front().map(|(_, event)| event));
+            }
+        }
+    }
+
+    /// Earliest instant a held-back event becomes due, if any
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.queues
+            .values()
+            .filter_map(|queue| queue.front().map(|(due, _)| *due))
+            .min()
+    }
+}
@@ -0,0 +1,188 @@
+use crate::gilrs_helper::{self, GamepadResource};
+use crate::quirks::Quirk;
+use gilrs::{Axis, Button, Gamepad, MappingSource};
+use std::fmt::Write as _;
+
+/// Buttons gilrs may map, in the order they're listed
+const GILRS_BUTTONS: [Button; 19] = [
+    Button::South,
+    Button::East,
+    Button::North,
+    Button::West,
+    Button::C,
+    Button::Z,
+    Button::LeftTrigger,
+    Button::LeftTrigger2,
+    Button::RightTrigger,
+    Button::RightTrigger2,
+    Button::Select,
+    Button::Start,
+    Button::Mode,
+    Button::LeftThumb,
+    Button::RightThumb,
+    Button::DPadUp,
+    Button::DPadDown,
+    Button::DPadLeft,
+    Button::DPadRight,
+];
+
+/// Axes gilrs may map, in the order they're listed
+const GILRS_AXES: [Axis; 8] = [
+    Axis::LeftStickX,
+    Axis::LeftStickY,
+    Axis::LeftZ,
+    Axis::RightStickX,
+    Axis::RightStickY,
+    Axis::RightZ,
+    Axis::DPadX,
+    Axis::DPadY,
+];
+
+/// Full capability report of one controller, as printed by `inspect`
+///
+/// Covers everything usually needed to debug a misbehaving pad: identity,
+/// every key and axis with its range, force feedback, how gilrs maps it, and
+/// any quirk applied to it.
+pub fn describe(gamepad: Gamepad, resource: &GamepadResource, quirk: Option<Quirk>) -> String {
+    let mut out = String::new();
+    let device = &resource.device;
+    let input_id = device.input_id();
+
+    let _ = writeln!(out, "{}", resource.display_name());
+    let _ = writeln!(out, "  Path:      {}", resource.path.display());
+    let _ = writeln!(out, "  Name:      {}", device.name().unwrap_or("(none)"));
+    let _ = writeln!(
+        out,
+        "  Phys:      {}",
+        device.physical_path().unwrap_or("(none)")
+    );
+    let _ = writeln!(
+        out,
+        "  Uniq:      {}",
+        device.unique_name().unwrap_or("(none)")
+    );
+    let _ = writeln!(
+        out,
+        "  Input ID:  bus {:?}, vendor {:04x}, product {:04x}, version {:04x}",
+        input_id.bus_type(),
+        input_id.vendor(),
+        input_id.product(),
+        input_id.version()
+    );
+    let _ = writeln!(out, "  UUID:      {}", gilrs_helper::create_uuid(input_id));
+    let _ = writeln!(
+        out,
+        "  Driver:    {}.{}.{}",
+        device.driver_version().0,
+        device.driver_version().1,
+        device.driver_version().2
+    );
+    let properties: Vec<String> = device
+        .properties()
+        .iter()
+        .map(|prop| format!("{:?}", prop))
+        .collect();
+    let _ = writeln!(out, "  Props:     {}", list_or_none(&properties));
+
+    let keys: Vec<String> = device
+        .supported_keys()
+        .map(|keys| keys.iter().map(|key| format!("{:?}", key)).collect())
+        .unwrap_or_default();
+    let _ = writeln!(out, "\nKeys ({}):", keys.len());
+    for key in &keys {
+        let _ = writeln!(out, "  {}", key);
+    }
+
+    let axes: Vec<_> = device
+        .get_absinfo()
+        .map(|infos| infos.collect())
+        .unwrap_or_default();
+    let _ = writeln!(out, "\nAbsolute axes ({}):", axes.len());
+    for (axis, info) in &axes {
+        let _ = writeln!(
+            out,
+            "  {:<16} value {:>6}, range {}..={}, fuzz {}, flat {}, resolution {}",
+            format!("{:?}", axis),
+            info.value(),
+            info.minimum(),
+            info.maximum(),
+            info.fuzz(),
+            info.flat(),
+            info.resolution()
+        );
+    }
+
+    let relative: Vec<String> = device
+        .supported_relative_axes()
+        .map(|axes| axes.iter().map(|axis| format!("{:?}", axis)).collect())
+        .unwrap_or_default();
+    if !relative.is_empty() {
+        let _ = writeln!(out, "\nRelative axes: {}", relative.join(", "));
+    }
+
+    let _ = writeln!(out, "\nForce feedback:");
+    match device.supported_ff() {
+        Some(effects) => {
+            let effects: Vec<String> = effects
+                .iter()
+                .map(|effect| format!("{:?}", effect))
+                .collect();
+            let _ = writeln!(out, "  Effects:   {}", list_or_none(&effects));
+            let _ = writeln!(out, "  Slots:     {}", device.max_ff_effects());
+        }
+        None => {
+            let _ = writeln!(out, "  Unsupported");
+        }
+    }
+
+    let source = match gamepad.mapping_source() {
+        MappingSource::SdlMappings => "SDL mapping",
+        MappingSource::Driver => "driver layout",
+        MappingSource::None => "none",
+    };
+    let _ = writeln!(out, "\nGilrs mapping ({}):", source);
+    let _ = writeln!(out, "  Name:      {}", gamepad.name());
+    if let Some(map_name) = gamepad.map_name() {
+        let _ = writeln!(out, "  Mapping:   {}", map_name);
+    }
+    for button in GILRS_BUTTONS {
+        if let Some(code) = gamepad.button_code(button) {
+            let _ = writeln!(out, "  {:<16} <- {}", format!("{:?}", button), code);
+        }
+    }
+    for axis in GILRS_AXES {
+        if let Some(code) = gamepad.axis_code(axis) {
+            let _ = writeln!(out, "  {:<16} <- {}", format!("{:?}", axis), code);
+        }
+    }
+
+    let _ = writeln!(out, "\nQuirks:");
+    match quirk {
+        Some(quirk) => {
+            if !quirk.invert_axes.is_empty() {
+                let _ = writeln!(out, "  Inverted:  {:?}", quirk.invert_axes);
+            }
+            if let Some(dpad) = quirk.dpad {
+                let _ = writeln!(out, "  D-pad:     {:?}", dpad);
+            }
+            if let Some(trigger_max) = quirk.trigger_max {
+                let _ = writeln!(out, "  Triggers:  full at {:.0}%", trigger_max * 100.0);
+            }
+            if let Some(motors) = quirk.motors {
+                let _ = writeln!(out, "  Motors:    {:?}", motors);
+            }
+        }
+        None => {
+            let _ = writeln!(out, "  None");
+        }
+    }
+    out
+}
+
+fn list_or_none(items: &[String]) -> String {
+    if items.is_empty() {
+        "(none)".to_string()
+    } else {
+        items.join(", ")
+    }
+}
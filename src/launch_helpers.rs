@@ -0,0 +1,106 @@
+use crate::sudo_helpers;
+use log::{info, warn};
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitStatus};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+/// A game launched by `ctrlassist run`, once its session is up
+///
+/// The session lives as long as the game: when it exits, `stop` is set so the
+/// session ends and hidden controllers are restored.
+pub struct GameLauncher {
+    command: Vec<String>,
+    stop: Arc<AtomicBool>,
+    exited: Arc<AtomicBool>,
+    game: Option<thread::JoinHandle<io::Result<ExitStatus>>>,
+}
+
+impl GameLauncher {
+    pub fn new(command: Vec<String>, stop: Arc<AtomicBool>) -> Self {
+        Self {
+            command,
+            stop,
+            exited: Arc::new(AtomicBool::new(false)),
+            game: None,
+        }
+    }
+
+    /// Launch the game, unless already running, e.g. when a session restarts
+    /// after sleep
+    ///
+    /// SDL is told to ignore `sources`, by vendor and product ID, and to skip
+    /// hidraw unless `hidapi` is set, as it opens those nodes directly and so
+    /// would still see hidden controllers. Under sudo the game runs as the
+    /// invoking user rather than root.
+    pub fn launch(&mut self, sources: &[(u16, u16)], hidapi: bool) {
+        if self.game.is_some() {
+            return;
+        }
+        let Some((program, args)) = self.command.split_first() else {
+            return;
+        };
+        let mut command = Command::new(program);
+        command.args(args);
+        if !sources.is_empty() {
+            command.env(
+                "SDL_GAMECONTROLLER_IGNORE_DEVICES",
+                sdl_device_list(sources),
+            );
+        }
+        if !hidapi {
+            command.env("SDL_JOYSTICK_HIDAPI", "0");
+        }
+        if let Some((uid, gid)) = sudo_helpers::sudo_user() {
+            // The group goes first, as root is needed to change it
+            command.gid(gid).uid(uid);
+            if let Some(home) = sudo_helpers::user_home(uid) {
+                command.env("HOME", home);
+            }
+            if let Some(runtime) = sudo_helpers::runtime_dir() {
+                command.env("XDG_RUNTIME_DIR", runtime);
+            }
+            if let Ok(user) = std::env::var("SUDO_USER") {
+                command.env("USER", &user).env("LOGNAME", user);
+            }
+        }
+
+        info!("Launching {}", self.command.join(" "));
+        let stop = Arc::clone(&self.stop);
+        let exited = Arc::clone(&self.exited);
+        self.game = Some(thread::spawn(move || {
+            let status = command.spawn().and_then(|mut child| child.wait());
+            // The session exists for the game, so end it along with it
+            exited.store(true, Ordering::SeqCst);
+            stop.store(true, Ordering::SeqCst);
+            status
+        }));
+    }
+
+    /// Exit status of the game once it has exited; None if it never
+    /// launched or is still running, e.g. after Ctrl+C stopped the session
+    pub fn finish(self) -> Option<io::Result<ExitStatus>> {
+        if !self.exited.load(Ordering::SeqCst) {
+            return None;
+        }
+        let game = self.game?;
+        match game.join() {
+            Ok(status) => Some(status),
+            Err(_) => {
+                warn!("Game waiter thread panicked");
+                None
+            }
+        }
+    }
+}
+
+/// Devices in SDL's list format, e.g. "0x045e/0x028e,0x054c/0x09cc"
+fn sdl_device_list(devices: &[(u16, u16)]) -> String {
+    devices
+        .iter()
+        .map(|(vendor, product)| format!("0x{:04x}/0x{:04x}", vendor, product))
+        .collect::<Vec<_>>()
+        .join(",")
+}
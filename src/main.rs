@@ -1,17 +1,65 @@
 use clap::{Parser, Subcommand, ValueEnum};
-use gilrs::Gilrs;
-use log::info;
+use gilrs::GamepadId;
+use log::{info, warn};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::error::Error;
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+mod arcade_input;
+mod audit_log;
+mod button_filters;
+mod cleanup_helpers;
+mod consumers;
+mod control_socket;
+mod crash_helpers;
+mod disconnect_policy;
 mod evdev_helpers;
 mod ff_helpers;
+mod frame_queue;
+mod fuzz_input;
+mod game_mode;
+mod game_profiles;
 mod gilrs_helper;
+mod gyro_aim;
+mod handoff_history;
+mod hooks;
+mod input_delay;
+mod inspect_helpers;
+mod launch_helpers;
+mod mode_button;
+mod mouse_aim;
 mod mux_manager;
 mod mux_modes;
 mod mux_runtime;
+mod notifier;
+mod output_backend;
+mod overlay_pause;
+mod pad_menu;
+mod power_helpers;
+mod quirks;
+mod report_helpers;
+mod rumble_test;
+mod session_timer;
+mod sibling_nodes;
+mod signal_helpers;
+mod sleep_monitor;
+mod source_grab;
+mod state;
+mod steam_backup;
+mod stick_filters;
+mod sudo_helpers;
+mod trace_helpers;
+mod trackpad_input;
 mod tray;
 mod udev_helpers;
+mod uhid_helpers;
 
 /// Multiplex multiple controllers into virtual gamepad.
 #[derive(Parser, Debug)]
@@ -21,113 +69,924 @@ struct Cli {
     command: Commands,
 }
 
+/// How often the CLI mux checks for control signals
+const SIGNAL_POLL: Duration = Duration::from_millis(100);
+
+/// How long `mux --auto` waits before looking for controllers again
+const AUTO_RETRY: Duration = Duration::from_secs(2);
+
+/// Attempts at restarting a session after system sleep, as Bluetooth pads
+/// take a moment to reconnect
+const RESUME_ATTEMPTS: u32 = 5;
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// List all detected controllers and respective IDs.
-    List,
+    List(ListArgs),
+
+    /// Print a controller's full capabilities, for bug reports.
+    Inspect(InspectArgs),
+
+    /// Play test rumble on a controller, to tell FF problems from games'.
+    Rumble(RumbleArgs),
 
     /// Multiplex connected controllers into virtual gamepad.
     Mux(MuxArgs),
 
+    /// Run a game with a mux session that ends when it exits, e.g.
+    /// `run -- %command%` in Steam launch options.
+    Run(RunArgs),
+
     /// Launch system tray app for graphical control.
     Tray,
+
+    /// Combine simple input devices into virtual gamepad via a mapping file.
+    Arcade(ArcadeArgs),
+
+    /// Drive a virtual gamepad with random input to soak-test games.
+    Fuzz(FuzzArgs),
+
+    /// Change a setting of the running mux, e.g. `set mode average`.
+    Set(SetArgs),
+
+    /// Show the running mux's settings and force feedback effects.
+    Status(StatusArgs),
+
+    /// Save a diagnostic report to attach to bug reports.
+    Report(ReportArgs),
+
+    /// Undo system changes a crashed session left behind.
+    Restore(RestoreArgs),
+
+    /// Reap virtual devices and undo system changes of crashed sessions.
+    Cleanup(CleanupArgs),
+
+    /// Show system changes made by past sessions, e.g. hidden devices.
+    Log(LogArgs),
+
+    /// Export, import, or validate the configuration file.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Write the current configuration to a file, or stdout if omitted.
+    Export {
+        path: Option<PathBuf>,
+
+        /// File format, by default from the extension, else TOML.
+        #[arg(long, short, value_enum)]
+        format: Option<ConfigFormat>,
+    },
+
+    /// Validate a configuration file and install it as the current one.
+    Import {
+        path: PathBuf,
+
+        /// File format, by default from the extension, else TOML.
+        #[arg(long, short, value_enum)]
+        format: Option<ConfigFormat>,
+    },
+
+    /// Check a configuration file for errors, or the current one if omitted.
+    Validate {
+        path: Option<PathBuf>,
+
+        /// File format, by default from the extension, else TOML.
+        #[arg(long, short, value_enum)]
+        format: Option<ConfigFormat>,
+    },
+
+    /// Print the JSON Schema of the configuration file, for editors and tools.
+    Schema,
+}
+
+#[derive(clap::Args, Debug)]
+struct ListArgs {
+    /// Show device paths, serials, and battery status.
+    #[arg(long, short)]
+    verbose: bool,
+
+    /// Include CtrlAssist virtual gamepads, e.g. to chain instances.
+    #[arg(long, short)]
+    all: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct InspectArgs {
+    /// Controller ID (see 'list' command).
+    id: usize,
+}
+
+#[derive(clap::Args, Debug)]
+struct RumbleArgs {
+    /// Controller ID (see 'list' command).
+    id: usize,
+
+    /// Test effect to play.
+    #[arg(long, short, value_enum, default_value_t)]
+    pattern: rumble_test::RumblePattern,
+
+    /// How long to play it, in milliseconds.
+    #[arg(long, short, default_value_t = 2000)]
+    duration: u64,
+}
+
+#[derive(clap::Args, Debug)]
+struct ArcadeArgs {
+    /// Mapping file listing devices and their inputs (see README).
+    mapping: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct FuzzArgs {
+    /// Seed of the random input, to reproduce a run; random if omitted.
+    #[arg(long, short)]
+    seed: Option<u64>,
+
+    /// Input frames per second.
+    #[arg(long, short, default_value_t = 20)]
+    rate: u32,
+
+    /// Only change these controls, by evdev name, e.g. BTN_SOUTH,ABS_X.
+    #[arg(long, short, value_delimiter = ',')]
+    controls: Vec<String>,
+
+    /// Stop after this many seconds.
+    #[arg(long, short)]
+    duration: Option<u64>,
+}
+
+#[derive(clap::Args, Debug)]
+#[group(required = true, multiple = true)]
+struct RestoreArgs {
+    /// Remove controller IDs added to Steam's blacklist.
+    #[arg(long)]
+    steam: bool,
+
+    /// Restore device nodes left hidden by system or ACL hiding.
+    #[arg(long)]
+    hide: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct CleanupArgs {
+    /// End other running sessions too, removing their virtual devices.
+    #[arg(long)]
+    kill: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct LogArgs {
+    /// Show only the last N changes.
+    #[arg(long, short)]
+    lines: Option<usize>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ReportArgs {
+    /// Archive to write (.tar.gz), or the state directory if omitted.
+    path: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct StatusArgs {
+    /// Keep refreshing every second until Ctrl+C.
+    #[arg(long, short)]
+    watch: bool,
+
+    /// Output format, e.g. waybar for a bar's custom module.
+    #[arg(long, short, value_enum, default_value_t)]
+    format: StatusFormat,
+
+    /// Also show each stage's full latency histogram.
+    #[arg(long)]
+    timing: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// The format given, else the one of the file's extension
+    fn resolve(format: Option<Self>, path: Option<&Path>) -> Self {
+        format.unwrap_or_else(|| {
+            match path
+                .and_then(|path| path.extension())
+                .and_then(|ext| ext.to_str())
+            {
+                Some(ext) if ext.eq_ignore_ascii_case("json") => Self::Json,
+                _ => Self::Toml,
+            }
+        })
+    }
+
+    fn parse(self, content: &str) -> Result<tray::config::TrayConfig, String> {
+        match self {
+            Self::Toml => tray::config::TrayConfig::validate(content),
+            Self::Json => tray::config::TrayConfig::validate_json(content),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+enum StatusFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per line with text, tooltip, and class
+    Waybar,
+}
+
+#[derive(clap::Args, Debug)]
+struct SetArgs {
+    /// Setting to change.
+    #[arg(value_enum)]
+    setting: control_socket::Setting,
+
+    /// New value, e.g. a mode, rumble target, on/off, or weight.
+    value: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct RunArgs {
+    #[command(flatten)]
+    mux: MuxArgs,
+
+    /// Game command, launched once the virtual gamepad is up.
+    #[arg(last = true, required = true)]
+    command: Vec<String>,
 }
 
 #[derive(clap::Args, Debug)]
 struct MuxArgs {
-    /// Primary controller ID (see 'list' command).
-    #[arg(long, default_value_t = 0)]
-    primary: usize,
+    /// Take the first two controllers, ordered by serial or port, waiting for
+    /// them and restarting after disconnects; for unattended setups.
+    #[arg(long, conflicts_with_all = ["primary", "assist", "primary_partner", "assist_partner"])]
+    auto: bool,
+
+    /// Primary controller ID (see 'list' command) [default: 0].
+    #[arg(long)]
+    primary: Option<usize>,
+
+    /// Assist controller ID (see 'list' command) [default: 1].
+    #[arg(long)]
+    assist: Option<usize>,
+
+    /// Controller ID combined with the primary as one, e.g. the other Joy-Con.
+    #[arg(long)]
+    primary_partner: Option<usize>,
+
+    /// Controller ID combined with the assist as one, e.g. the other Joy-Con.
+    #[arg(long)]
+    assist_partner: Option<usize>,
+
+    /// Hide primary and assist controllers [default: none].
+    #[arg(long, value_enum)]
+    hide: Option<HideType>,
+
+    /// Whether to hide controllers before or after creating the virtual device
+    /// [default: before].
+    #[arg(long, value_enum)]
+    hide_order: Option<HideOrder>,
+
+    /// Delay in milliseconds between hiding and virtual device creation
+    /// [default: 0].
+    #[arg(long)]
+    hide_settle_ms: Option<u64>,
+
+    /// Exclusively grab the controllers' event nodes, so only CtrlAssist
+    /// reads them, whatever the hiding.
+    #[arg(long)]
+    grab_sources: bool,
+
+    /// Spoof target for virtual device [default: none].
+    #[arg(long, value_enum)]
+    spoof: Option<SpoofTarget>,
 
-    /// Assist controller ID (see 'list' command).
-    #[arg(long, default_value_t = 1)]
-    assist: usize,
+    /// Game profile or built-in preset supplying hide, spoof, and mode
+    /// defaults; detected from running games if omitted.
+    #[arg(long)]
+    profile: Option<String>,
 
-    /// Hide primary and assist controllers.
-    #[arg(long, value_enum, default_value_t = HideType::default())]
-    hide: HideType,
+    /// Mode type for combining controllers [default: priority].
+    #[arg(long, value_enum)]
+    mode: Option<mux_modes::ModeType>,
 
-    /// Spoof target for virtual device.
-    #[arg(long, value_enum, default_value_t = SpoofTarget::default())]
-    spoof: SpoofTarget,
+    /// Scale of the primary's sticks and triggers in Blend mode [default: 0.7].
+    #[arg(long, value_parser = mux_modes::parse_weight)]
+    primary_weight: Option<f32>,
 
-    /// Mode type for combining controllers.
-    #[arg(long, value_enum, default_value_t = mux_modes::ModeType::default())]
-    mode: mux_modes::ModeType,
+    /// Scale of the assist's sticks and triggers in Blend mode [default: 0.3].
+    #[arg(long, value_parser = mux_modes::parse_weight)]
+    assist_weight: Option<f32>,
 
-    /// Rumble target for virtual device.
-    #[arg(long, value_enum, default_value_t = RumbleTarget::default())]
-    rumble: RumbleTarget,
+    /// Rumble target for virtual device [default: both].
+    #[arg(long, value_enum)]
+    rumble: Option<RumbleTarget>,
+
+    /// Binding for Steam Deck back paddles (L4/L5/R4/R5) [default: none].
+    #[arg(long, value_enum)]
+    paddles: Option<PaddleBinding>,
+
+    /// Allow CtrlAssist virtual gamepads as sources, e.g. to chain instances.
+    #[arg(long)]
+    allow_virtual: bool,
+
+    /// Skip the confirmation before System hiding changes device permissions.
+    #[arg(long, short)]
+    yes: bool,
 }
 
-#[derive(ValueEnum, Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(ValueEnum, Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
 pub enum HideType {
     #[default]
     None,
     Steam,
     System,
+    Acl,
+}
+
+#[derive(ValueEnum, Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum HideOrder {
+    /// Hide controllers, then create the virtual device
+    #[default]
+    Before,
+    /// Create the virtual device, then hide controllers
+    After,
+    /// Hide controllers only while a game has the virtual device open, so
+    /// menus and launchers keep working with them in between
+    OnUse,
 }
 
-#[derive(ValueEnum, Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(ValueEnum, Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
 pub enum SpoofTarget {
     Primary,
     Assist,
+    SteamDeck,
+    /// Wired Xbox 360 pad, with the exact layout of the xpad driver
+    Xbox360,
+    /// Racing wheel, for games without gamepad support
+    Wheel,
+    /// Flight stick, for games without gamepad support
+    Joystick,
     #[default]
     None,
 }
 
-#[derive(ValueEnum, Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[derive(ValueEnum, Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub enum RumbleTarget {
     Primary,
     Assist,
     #[default]
     Both,
     None,
+    /// Strong (left) motor to the primary, weak (right) motor to the assist
+    Split,
+}
+
+#[derive(ValueEnum, Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum PaddleBinding {
+    /// Ignore paddles, as they have no virtual device equivalent
+    #[default]
+    None,
+    /// Act as the Mode button of the same controller, e.g. for Toggle handoff
+    Mode,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    env_logger::init();
+    crash_helpers::init_logging();
+    let _chrome_trace = trace_helpers::init_chrome_trace();
     let cli = Cli::parse();
+    crash_helpers::install_panic_hook(matches!(cli.command, Commands::Tray));
     match cli.command {
-        Commands::List => list_gamepads(),
-        Commands::Mux(args) => run_mux(args),
+        Commands::List(args) => list_gamepads(args),
+        Commands::Inspect(args) => inspect_gamepad(args),
+        Commands::Rumble(args) => test_rumble(args),
+        Commands::Mux(args) => run_mux(args, None),
+        Commands::Run(args) => run_mux(args.mux, Some(args.command)),
         Commands::Tray => tray::run_tray().await,
+        Commands::Arcade(args) => run_arcade(args),
+        Commands::Fuzz(args) => run_fuzz(args),
+        Commands::Report(args) => run_report(args),
+        Commands::Restore(args) => {
+            if args.steam {
+                println!("{}", steam_backup::restore_steam()?);
+            }
+            if args.hide {
+                println!("{}", udev_helpers::restore_hidden()?);
+            }
+            Ok(())
+        }
+        Commands::Cleanup(args) => {
+            println!("{}", cleanup_helpers::cleanup(args.kill)?);
+            Ok(())
+        }
+        Commands::Log(args) => {
+            let entries = audit_log::read_entries(args.lines)?;
+            if entries.is_empty() {
+                println!("No system changes recorded");
+            }
+            for entry in entries {
+                println!("{}", entry);
+            }
+            Ok(())
+        }
+        Commands::Set(args) => {
+            let msg = control_socket::send_setting(args.setting, &args.value)?;
+            println!("{}", msg);
+            Ok(())
+        }
+        Commands::Status(args) => show_status(args),
+        Commands::Config(cmd) => run_config(cmd),
+    }
+}
+
+fn run_config(cmd: ConfigCommand) -> Result<(), Box<dyn Error>> {
+    use tray::config::TrayConfig;
+
+    match cmd {
+        ConfigCommand::Export { path, format } => {
+            // Fail rather than export defaults in place of an unreadable file
+            let config = TrayConfig::load_strict()?;
+            let content = match ConfigFormat::resolve(format, path.as_deref()) {
+                ConfigFormat::Toml => toml::to_string_pretty(&config)?,
+                ConfigFormat::Json => serde_json::to_string_pretty(&config)? + "\n",
+            };
+            match path {
+                Some(path) => {
+                    fs::write(&path, content)?;
+                    println!("Exported config to {}", path.display());
+                }
+                None => print!("{}", content),
+            }
+        }
+        ConfigCommand::Import { path, format } => {
+            let content = fs::read_to_string(&path)?;
+            let config = ConfigFormat::resolve(format, Some(&path)).parse(&content)?;
+            let current = TrayConfig::config_path()?;
+            if current.exists() {
+                TrayConfig::back_up(&current)?;
+            }
+            config.save()?;
+            println!(
+                "Imported config from {} to {}",
+                path.display(),
+                current.display()
+            );
+        }
+        ConfigCommand::Validate { path, format } => {
+            let path = match path {
+                Some(path) => path,
+                None => TrayConfig::config_path()?,
+            };
+            let content = fs::read_to_string(&path)?;
+            ConfigFormat::resolve(format, Some(&path)).parse(&content)?;
+            println!("{} is valid", path.display());
+        }
+        ConfigCommand::Schema => {
+            println!("{}", serde_json::to_string_pretty(&TrayConfig::schema())?);
+        }
+    }
+    Ok(())
+}
+
+fn list_gamepads(args: ListArgs) -> Result<(), Box<dyn Error>> {
+    print!("{}", describe_gamepads(args.verbose, args.all)?);
+    Ok(())
+}
+
+fn inspect_gamepad(args: InspectArgs) -> Result<(), Box<dyn Error>> {
+    let config = tray::config::TrayConfig::load();
+    let gilrs = gilrs_helper::build_gilrs(&config.gilrs)?;
+    let (id, gamepad) = gilrs
+        .gamepads()
+        .find(|(id, _)| usize::from(*id) == args.id)
+        .ok_or_else(|| format!("No controller with ID {}; see 'list'", args.id))?;
+    let resources = gilrs_helper::discover_gamepad_resources(&gilrs);
+    let resource = resources
+        .get(&id)
+        .ok_or_else(|| format!("No event device found for controller {}", args.id))?;
+    let quirk = quirks::for_resource(&config.quirks, resource);
+    print!("{}", inspect_helpers::describe(gamepad, resource, quirk));
+    Ok(())
+}
+
+fn show_status(args: StatusArgs) -> Result<(), Box<dyn Error>> {
+    loop {
+        match args.format {
+            StatusFormat::Text => {
+                let status = control_socket::request_status()?;
+                if args.watch {
+                    // Clear the screen, so effects appear in place as they change
+                    print!("\x1b[2J\x1b[H");
+                }
+                println!("{}", status.details);
+                if args.timing {
+                    println!("\n{}", status.timing);
+                }
+            }
+            // Bars keep their module running, so a stopped mux is a state
+            // to show rather than an error
+            StatusFormat::Waybar => {
+                println!("{}", waybar_status(control_socket::request_status().ok()));
+            }
+        }
+        if !args.watch {
+            return Ok(());
+        }
+        std::io::stdout().flush()?;
+        std::thread::sleep(Duration::from_secs(1));
     }
 }
 
-fn list_gamepads() -> Result<(), Box<dyn Error>> {
-    let gilrs = Gilrs::new().map_err(|e| format!("Failed to init Gilrs: {e}"))?;
+/// Status as the JSON a waybar custom module reads, classed for styling
+fn waybar_status(status: Option<control_socket::StatusReport>) -> String {
+    let (text, tooltip, class) = match status {
+        Some(status) => {
+            let class = if status.degraded {
+                "degraded"
+            } else {
+                "running"
+            };
+            (format!("🎮 {}", status.mode), status.details, class)
+        }
+        None => (String::new(), "CtrlAssist stopped".to_string(), "stopped"),
+    };
+    serde_json::json!({
+        "text": text,
+        "tooltip": tooltip,
+        "class": class,
+        "alt": class,
+    })
+    .to_string()
+}
+
+fn test_rumble(args: RumbleArgs) -> Result<(), Box<dyn Error>> {
+    let config = tray::config::TrayConfig::load();
+    let gilrs = gilrs_helper::build_gilrs(&config.gilrs)?;
+    let id = gilrs
+        .gamepads()
+        .map(|(id, _)| id)
+        .find(|id| usize::from(*id) == args.id)
+        .ok_or_else(|| format!("No controller with ID {}; see 'list'", args.id))?;
+    let resource = gilrs_helper::discover_gamepad_resources(&gilrs)
+        .remove(&id)
+        .ok_or_else(|| format!("No event device found for controller {}", args.id))?;
+    let motors =
+        ff_helpers::MotorMaps::new(&config.force_feedback, config.quirks).resolve(None, &resource);
+    let report = rumble_test::play(
+        resource,
+        motors,
+        args.pattern,
+        Duration::from_millis(args.duration),
+    )?;
+    println!("{}", report);
+    Ok(())
+}
+
+/// Detected controllers as printed by `list`, with CtrlAssist virtual
+/// gamepads only counted unless `all` is set
+fn describe_gamepads(verbose: bool, all: bool) -> Result<String, Box<dyn Error>> {
+    use std::fmt::Write as _;
+
+    let gilrs = gilrs_helper::build_gilrs(&tray::config::TrayConfig::load().gilrs)?;
+    let resources = gilrs_helper::discover_gamepad_resources(&gilrs);
+    let power = power_helpers::PowerLookup::new();
+    let mut out = String::new();
     let mut found = false;
+    let mut skipped = 0;
     for (id, gamepad) in gilrs.gamepads() {
-        println!("({}) {}", id, gamepad.name());
+        match resources.get(&id) {
+            Some(resource) if resource.is_ctrlassist() && !all => skipped += 1,
+            Some(resource) => {
+                let marker = if let Some(session) = resource.ctrlassist_session() {
+                    format!(" (CtrlAssist virtual, session {})", session)
+                } else if resource.is_ctrlassist() {
+                    " (CtrlAssist virtual)".to_string()
+                } else {
+                    String::new()
+                };
+                writeln!(out, "({}) {}{}", id, resource.display_name(), marker)?;
+                if verbose {
+                    writeln!(out, "    Path:    {}", resource.path.display())?;
+                    if let Some(serial) = resource.serial() {
+                        writeln!(out, "    Serial:  {}", serial)?;
+                    }
+                    match power.power_info(resource) {
+                        Some(power) => writeln!(out, "    Battery: {}", power)?,
+                        None => writeln!(out, "    Battery: Unavailable")?,
+                    }
+                }
+            }
+            None => writeln!(out, "({}) {}", id, gamepad.name())?,
+        }
         found = true;
     }
     if !found {
-        println!("  No controllers found.");
+        writeln!(out, "  No controllers found.")?;
+    }
+    if skipped > 0 {
+        writeln!(
+            out,
+            "  {} CtrlAssist virtual gamepad(s) hidden; show with --all",
+            skipped
+        )?;
+    }
+    Ok(out)
+}
+
+fn run_report(args: ReportArgs) -> Result<(), Box<dyn Error>> {
+    // A broken gilrs setup is worth reporting too, rather than failing
+    let controllers =
+        describe_gamepads(true, true).unwrap_or_else(|e| format!("Unavailable: {}\n", e));
+    let report = report_helpers::build_report(&controllers);
+    let path = match args.path {
+        Some(path) => path,
+        None => {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            state::path(&format!("report-{}.tar.gz", timestamp))?
+        }
+    };
+    report_helpers::write_archive(&report, &path)?;
+    println!("Saved diagnostic report to {}", path.display());
+    println!("Please attach it when filing an issue.");
+    Ok(())
+}
+
+fn run_mux(args: MuxArgs, command: Option<Vec<String>>) -> Result<(), Box<dyn Error>> {
+    if args.auto && command.is_some() {
+        return Err("run can't be combined with --auto, as kiosk sessions outlive games".into());
+    }
+
+    // Ctrl+C and SIGTERM end the session, and in kiosk mode the retrying too
+    let stop = Arc::new(AtomicBool::new(false));
+    // A game, if given, is launched once the session is up and ends it on exit
+    let mut launcher =
+        command.map(|command| launch_helpers::GameLauncher::new(command, Arc::clone(&stop)));
+    let stop_handler = Arc::clone(&stop);
+    ctrlc::set_handler(move || {
+        println!("\nShutting down...");
+        stop_handler.store(true, Ordering::SeqCst);
+    })?;
+
+    // SIGUSR1, SIGUSR2, and, when detached, SIGHUP control the running mux instead
+    signal_helpers::install_control_handlers()?;
+
+    // Sessions load the config themselves, so only report problems once
+    if let (_, Some(report)) = tray::config::TrayConfig::load_checked() {
+        eprintln!(
+            "{}\nUsing defaults for these; the original is kept as config.toml.<time>.bak",
+            report
+        );
+    }
+
+    // Sessions stop before system sleep and start afresh after
+    let sleep = sleep_monitor::spawn();
+    // Restarted sessions count towards the same time limit
+    let time_limit_start = Instant::now();
+
+    if !args.auto {
+        let mut resumed = None;
+        let mut attempts = 0;
+        loop {
+            match run_session(
+                &args,
+                &stop,
+                &sleep,
+                resumed.as_ref(),
+                launcher.as_mut(),
+                time_limit_start,
+            ) {
+                Ok(SessionEnd::Suspended(settings)) => {
+                    println!("Suspended; restarting on resume...");
+                    if !wait_for_resume(&sleep, &stop) {
+                        return Ok(());
+                    }
+                    resumed = Some(settings);
+                    attempts = RESUME_ATTEMPTS;
+                }
+                Ok(_) => break,
+                Err(e) if attempts > 1 && !stop.load(Ordering::SeqCst) => {
+                    warn!("Session failed to restart after resume, retrying: {}", e);
+                    attempts -= 1;
+                    std::thread::sleep(AUTO_RETRY);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if let Some(launcher) = launcher {
+            match launcher.finish() {
+                Some(Ok(status)) if !status.success() => {
+                    return Err(format!("Game exited with {}", status).into());
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(format!("Failed to launch game: {}", e).into()),
+                None => println!("Session stopped before the game exited"),
+            }
+        }
+        return Ok(());
+    }
+
+    // Kiosk mode: keep a session up with whatever controllers are connected
+    let mut waiting = false;
+    let mut resumed = None;
+    while !stop.load(Ordering::SeqCst) {
+        match run_session(
+            &args,
+            &stop,
+            &sleep,
+            resumed.take().as_ref(),
+            None,
+            time_limit_start,
+        ) {
+            Ok(SessionEnd::Stopped) => break,
+            Ok(SessionEnd::Suspended(settings)) => {
+                println!("Suspended; restarting on resume...");
+                if !wait_for_resume(&sleep, &stop) {
+                    break;
+                }
+                resumed = Some(settings);
+            }
+            Ok(SessionEnd::NoControllers) => {
+                if !waiting {
+                    println!("Waiting for two controllers...");
+                    waiting = true;
+                }
+            }
+            Ok(SessionEnd::Disconnected) => {
+                println!("A controller disconnected; waiting to restart...");
+                waiting = true;
+            }
+            Err(e) => {
+                warn!("Session failed, retrying: {}", e);
+                eprintln!("Session failed, retrying: {}", e);
+            }
+        }
+        std::thread::sleep(AUTO_RETRY);
     }
     Ok(())
 }
 
-fn run_mux(args: MuxArgs) -> Result<(), Box<dyn Error>> {
-    if args.primary == args.assist {
-        return Err("Primary and Assist controllers must be separate devices.".into());
+/// Why a mux session ended
+enum SessionEnd {
+    /// Shutdown was requested
+    Stopped,
+    /// A source controller disconnected, ending a kiosk session
+    Disconnected,
+    /// Fewer than two controllers were found for a kiosk session
+    NoControllers,
+    /// The system is going to sleep, with the settings to resume with
+    Suspended(mux_runtime::RuntimeSettings),
+}
+
+/// System sleep events, each with a sender to signal once acted on
+type SleepEvents =
+    std::sync::mpsc::Receiver<(sleep_monitor::SleepEvent, std::sync::mpsc::Sender<()>)>;
+
+/// Block until the system resumes, returning false if shutdown is requested
+/// first
+fn wait_for_resume(sleep: &SleepEvents, stop: &AtomicBool) -> bool {
+    use std::sync::mpsc::RecvTimeoutError;
+
+    while !stop.load(Ordering::SeqCst) {
+        match sleep.recv_timeout(SIGNAL_POLL) {
+            Ok((sleep_monitor::SleepEvent::Resumed, _)) => return true,
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            // Sleep can't be followed anymore, so don't wait forever
+            Err(RecvTimeoutError::Disconnected) => return true,
+        }
     }
+    false
+}
 
-    let gilrs = Gilrs::new().map_err(|e| format!("Failed to init Gilrs: {e}"))?;
+/// Select controllers and run a mux session until shutdown is requested, the
+/// system sleeps, or, in kiosk mode, a source disconnects
+///
+/// Settings changed in a session ended by sleep are taken on from `resumed`,
+/// and a game to run with the session is launched once it's up.
+fn run_session(
+    args: &MuxArgs,
+    stop: &AtomicBool,
+    sleep: &SleepEvents,
+    resumed: Option<&mux_runtime::RuntimeSettings>,
+    mut launcher: Option<&mut launch_helpers::GameLauncher>,
+    time_limit_start: Instant,
+) -> Result<SessionEnd, Box<dyn Error>> {
+    // Hooks, timing, gilrs, mode, and filter options are only configurable via the config file
+    // CTRLASSIST_* variables apply to the session only, never being saved
+    let (file_config, _) = tray::config::TrayConfig::load().with_env_overrides();
+    crash_helpers::set_config_snapshot(&file_config);
+    notifier::configure(&file_config.notify);
+    // Settings the CLI otherwise takes from flags may come from CTRLASSIST_*
+    // variables, between the config file and flags
+    let env = tray::config::env_overrides();
+    let from_env = |key: &str| env.contains_key(key);
+
+    let gilrs = gilrs_helper::build_gilrs(&file_config.gilrs)?;
     let resources = gilrs_helper::discover_gamepad_resources(&gilrs);
 
-    // Identify primary and assist resources
-    let p_id = resources
-        .keys()
-        .find(|&&id| usize::from(id) == args.primary)
-        .copied()
-        .ok_or(format!("Primary ID {} not found", args.primary))?;
-    let a_id = resources
-        .keys()
-        .find(|&&id| usize::from(id) == args.assist)
-        .copied()
-        .ok_or(format!("Assist ID {} not found", args.assist))?;
+    let (p_id, a_id, primary_partner, assist_partner) = if args.auto {
+        // Order by serial or port, then path, so the same pads get the same roles
+        let mut candidates: Vec<_> = resources
+            .iter()
+            .filter(|(_, resource)| !resource.is_ctrlassist())
+            .collect();
+        candidates.sort_by_key(|(_, resource)| (resource.serial(), resource.path.clone()));
+        match candidates[..] {
+            [(&primary, _), (&assist, _), ..] => (primary, assist, None, None),
+            _ => return Ok(SessionEnd::NoControllers),
+        }
+    } else {
+        let primary = args
+            .primary
+            .or_else(|| {
+                env_controller(
+                    &resources,
+                    file_config
+                        .primary_serial
+                        .as_deref()
+                        .filter(|_| from_env("primary_serial")),
+                    file_config
+                        .primary_name
+                        .as_deref()
+                        .filter(|_| from_env("primary_name")),
+                )
+            })
+            .unwrap_or(0);
+        let assist = args
+            .assist
+            .or_else(|| {
+                env_controller(
+                    &resources,
+                    file_config
+                        .assist_serial
+                        .as_deref()
+                        .filter(|_| from_env("assist_serial")),
+                    file_config
+                        .assist_name
+                        .as_deref()
+                        .filter(|_| from_env("assist_name")),
+                )
+            })
+            .unwrap_or(1);
+        let mut selected = vec![primary, assist];
+        selected.extend(args.primary_partner);
+        selected.extend(args.assist_partner);
+        let mut unique = selected.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        if unique.len() != selected.len() {
+            return Err(
+                "Primary, Assist, and partner controllers must be separate devices.".into(),
+            );
+        }
+
+        // Identify primary and assist resources
+        let find_id = |index: usize, role: &str| {
+            resources
+                .keys()
+                .find(|&&id| usize::from(id) == index)
+                .copied()
+                .ok_or(format!("{} ID {} not found", role, index))
+        };
+        let p_id = find_id(primary, "Primary")?;
+        let a_id = find_id(assist, "Assist")?;
+        let primary_partner = args
+            .primary_partner
+            .map(|index| find_id(index, "Primary partner"))
+            .transpose()?;
+        let assist_partner = args
+            .assist_partner
+            .map(|index| find_id(index, "Assist partner"))
+            .transpose()?;
+
+        // Our own virtual gamepad as a source would loop the mux's output back in
+        if !args.allow_virtual {
+            let sources = [Some(p_id), Some(a_id), primary_partner, assist_partner];
+            for id in sources.into_iter().flatten() {
+                if resources.get(&id).is_some_and(|r| r.is_ctrlassist()) {
+                    return Err(format!(
+                        "Controller {} is a CtrlAssist virtual gamepad; pass --allow-virtual to chain instances",
+                        id
+                    )
+                    .into());
+                }
+            }
+        }
+        (p_id, a_id, primary_partner, assist_partner)
+    };
+    let sources: Vec<GamepadId> = [Some(p_id), Some(a_id), primary_partner, assist_partner]
+        .into_iter()
+        .flatten()
+        .collect();
 
     let primary_msg = format!(
         "Primary: ({}) {} @ {}",
@@ -147,38 +1006,403 @@ fn run_mux(args: MuxArgs) -> Result<(), Box<dyn Error>> {
     info!("{}", assist_msg);
     println!("{}", assist_msg);
 
+    for (label, partner) in [("Primary", primary_partner), ("Assist", assist_partner)] {
+        if let Some(id) = partner {
+            let partner_msg = format!(
+                "{} partner: ({}) {} @ {}",
+                label,
+                id,
+                resources[&id].name,
+                resources[&id].path.display()
+            );
+            info!("{}", partner_msg);
+            println!("{}", partner_msg);
+        }
+    }
+
+    // Explicit flags win over the profile, which wins over the defaults
+    let mut hide = HideType::default();
+    let mut spoof = SpoofTarget::default();
+    let mut mode = mux_modes::ModeType::default();
+    if from_env("hide") {
+        hide = file_config.hide.clone();
+    }
+    if from_env("spoof") {
+        spoof = file_config.spoof.clone();
+    }
+    if from_env("mode") {
+        mode = file_config.mode.clone();
+    }
+    let mut mode_options = file_config.modes;
+    let mut force_feedback = file_config.force_feedback;
+    let mut disconnect = file_config.disconnect;
+    let profiles = game_profiles::with_builtins(&file_config.profiles);
+    if let Some((name, profile)) = game_profiles::resolve(&profiles, args.profile.as_deref())? {
+        profile.apply(&mut hide, &mut spoof);
+        profile.apply_mode(&mut mode, &mut mode_options);
+        profile.apply_force_feedback(&mut force_feedback);
+        profile.apply_disconnect(&mut disconnect);
+        let profile_msg = format!("Profile: {}", name);
+        info!("{}", profile_msg);
+        println!("{}", profile_msg);
+    }
+    let hide = args.hide.clone().unwrap_or(hide);
+    let spoof = args.spoof.clone().unwrap_or(spoof);
+    let mode = args.mode.clone().unwrap_or(mode);
+    if let Some(weight) = args.primary_weight {
+        mode_options.blend.primary_weight = weight;
+    }
+    if let Some(weight) = args.assist_weight {
+        mode_options.blend.assist_weight = weight;
+    }
+
+    // Games launched with the session ignore the sources, except any with the
+    // virtual device's IDs, which hiding then has to handle alone
+    let virtual_ids =
+        mux_manager::preset_info(&spoof).map(|info| (info.vendor_id, info.product_id));
+    let sdl_ignored: Vec<(u16, u16)> =
+        if matches!(spoof, SpoofTarget::Primary | SpoofTarget::Assist) {
+            if launcher.is_some() {
+                warn!("Spoofing a source's IDs, so the game can't be told to ignore the sources");
+            }
+            Vec::new()
+        } else {
+            sources
+                .iter()
+                .map(|id| {
+                    let input_id = resources[id].device.input_id();
+                    (input_id.vendor(), input_id.product())
+                })
+                .filter(|&(vendor, product)| virtual_ids != Some((Some(vendor), Some(product))))
+                .collect()
+        };
+    let hidapi = file_config.hidraw;
+
+    // System hiding locks out every node of a controller, so show which first
+    let confirm_hide = matches!(hide, HideType::System);
+    if confirm_hide {
+        println!("System hiding will make these nodes root-only:");
+        for id in &sources {
+            for path in udev_helpers::system_hide_paths(&resources[id])? {
+                println!("  {}", path.display());
+            }
+        }
+        // Kiosk sessions restart unattended, so never ask
+        if !args.yes && !args.auto && std::io::stdin().is_terminal() {
+            print!("Continue? [y/N] ");
+            std::io::stdout().flush()?;
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+            if !answer.trim().eq_ignore_ascii_case("y") {
+                return Err("Cancelled; nothing was hidden".into());
+            }
+        }
+    }
+    let mut hide_order = args
+        .hide_order
+        .clone()
+        .or_else(|| from_env("hide_order").then(|| file_config.hide_order.clone()))
+        .unwrap_or_default();
+    // Hiding only stops later opens, so a game that opened the controllers
+    // before the virtual device would keep them; one launched with the
+    // session is the use to hide for, so hide before it starts
+    if hide_order == HideOrder::OnUse && launcher.is_some() {
+        info!("Launching a game, so hiding controllers before it starts rather than on use");
+        hide_order = HideOrder::Before;
+    }
+    // Nobody is there to keep an unattended kiosk's hiding, so don't roll it
+    // back, and hiding on use already undoes itself once the game closes
+    let hide_confirm_secs = if args.auto || hide_order == HideOrder::OnUse {
+        0
+    } else {
+        file_config.hide_confirm_secs
+    };
+
     // Start mux using the shared helper
     let config = mux_manager::MuxConfig {
         primary_id: p_id,
         assist_id: a_id,
-        mode: args.mode,
-        hide: args.hide,
-        spoof: args.spoof,
-        rumble: args.rumble,
+        primary_partner,
+        assist_partner,
+        mode,
+        mode_options,
+        hide,
+        hide_order,
+        hide_settle_ms: args
+            .hide_settle_ms
+            .or_else(|| from_env("hide_settle_ms").then_some(file_config.hide_settle_ms))
+            .unwrap_or_default(),
+        spoof,
+        rumble: args
+            .rumble
+            .clone()
+            .or_else(|| from_env("rumble").then(|| file_config.rumble.clone()))
+            .unwrap_or_default(),
+        paddles: args
+            .paddles
+            .clone()
+            .or_else(|| from_env("paddles").then(|| file_config.paddles.clone()))
+            .unwrap_or_default(),
+        hooks: file_config.hooks,
+        timing: file_config.timing,
+        gilrs: file_config.gilrs,
+        debounce: file_config.debounce,
+        delay: file_config.delay,
+        hold: file_config.hold,
+        confirm: file_config.confirm,
+        smoothing: file_config.smoothing,
+        gyro: file_config.gyro,
+        mouse: file_config.mouse,
+        trackpad: file_config.trackpad,
+        siblings: file_config.siblings,
+        force_feedback,
+        quirks: file_config.quirks,
+        hidraw: file_config.hidraw,
+        session_name: file_config.session_name,
+        grab_sources: args.grab_sources || file_config.grab_sources,
+        game_mode: file_config.game_mode,
+        hide_confirm_secs,
+        pad_menu: file_config.pad_menu,
+        overlay_pause: file_config.overlay_pause,
+        mode_button: file_config.mode_button,
+        time_limit: file_config.time_limit,
+        time_limit_start,
+        disconnect,
+        profiles,
     };
 
     use std::sync::mpsc;
     let (shutdown_tx, shutdown_rx) = mpsc::channel::<()>();
+    let (settings_tx, settings_rx) = mpsc::channel::<mux_runtime::RuntimeSettings>();
 
     // Spawn mux in a thread, so we can join it in main
-    let mux_thread = std::thread::spawn(move || {
-        let mux_handle = mux_manager::start_mux(gilrs, config).expect("Failed to start mux");
-        // Wait for shutdown signal (blocks efficiently)
-        let _ = shutdown_rx.recv();
-        mux_handle.0.shutdown();
+    let mux_thread = std::thread::spawn(move || -> Result<(), String> {
+        let (mux_handle, runtime_settings) =
+            mux_manager::start_mux(gilrs, config).map_err(|e| {
+                let message = format!("Failed to start mux: {}", e);
+                notifier::post_event_blocking(notifier::SessionEvent::Error, message.clone());
+                message
+            })?;
+        let _ = settings_tx.send(mux_runtime::RuntimeSettings::clone(&runtime_settings));
+        // Wait for shutdown signal or a failed runtime thread
+        let faulted = mux_handle.wait(&shutdown_rx);
+        // Joins runtime threads and restores hidden devices
+        let _ = mux_handle.shutdown();
+        println!("Latency:\n{}", runtime_settings.get_latency());
+        if faulted {
+            return Err("Mux stopped unexpectedly; see log for details".into());
+        }
+        Ok(())
     });
 
-    // Setup Ctrl+C handler to send shutdown signal
+    info!("Mux Active. Press Ctrl+C to exit.");
+    println!("Mux Active. Press Ctrl+C to exit.");
+
+    // Kiosk sessions end when a source goes away, to restart with what's left
+    let watched: Vec<PathBuf> = if args.auto {
+        sources
+            .iter()
+            .map(|id| resources[id].path.clone())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let mut end = SessionEnd::Stopped;
+
+    // Apply control signals until the mux thread finishes; signals received
+    // while the mux starts are kept until its settings arrive
+    let mut runtime_settings = None;
+    // Removes the control socket once the mux stops
+    let mut _control_server = None;
+    let mut last_consumers = Vec::new();
+    // Held until the session stops, delaying sleep until hiding is restored
+    let mut sleep_done = None;
+    let mut pending_sleep = None;
+    while !mux_thread.is_finished() {
+        if stop.load(Ordering::SeqCst) {
+            // Ignore error if already sent
+            let _ = shutdown_tx.send(());
+        } else if watched.iter().any(|path| !path.exists()) {
+            end = SessionEnd::Disconnected;
+            let _ = shutdown_tx.send(());
+        }
+        // Kept until the session is up, as stopping one that's still
+        // starting needs its settings to resume with
+        if pending_sleep.is_none()
+            && let Ok((sleep_monitor::SleepEvent::Suspending, done)) = sleep.try_recv()
+        {
+            pending_sleep = Some(done);
+        }
+        if let Ok(settings) = settings_rx.try_recv() {
+            if let Some(earlier) = resumed {
+                settings.resync_from(earlier);
+            }
+            if let Some(launcher) = launcher.as_deref_mut() {
+                launcher.launch(&sdl_ignored, hidapi);
+            }
+            match control_socket::start_control_server(settings.clone()) {
+                Ok(server) => _control_server = Some(server),
+                Err(e) => warn!("Control socket unavailable, `set` won't work: {}", e),
+            }
+            if confirm_hide && hide_confirm_secs > 0 {
+                confirm_hide_on_enter(&settings, hide_confirm_secs);
+            }
+            runtime_settings = Some(settings);
+        }
+        if let Some(settings) = &runtime_settings
+            && let Some(done) = pending_sleep.take()
+        {
+            end = SessionEnd::Suspended(mux_runtime::RuntimeSettings::clone(settings));
+            sleep_done = Some(done);
+            let _ = shutdown_tx.send(());
+        }
+        if let Some(settings) = &runtime_settings {
+            for signal in signal_helpers::take_pending() {
+                apply_control_signal(signal, settings);
+            }
+            // Tells a game that doesn't see the pad from one ignoring it
+            let in_use = settings.get_consumers();
+            if in_use != last_consumers {
+                if in_use.is_empty() {
+                    println!("Virtual device no longer in use");
+                } else {
+                    println!("Virtual device in use by {}", consumers::describe(&in_use));
+                }
+                last_consumers = in_use;
+            }
+        }
+        std::thread::sleep(SIGNAL_POLL);
+    }
+
+    // Mux thread has finished, failing if it could not start or panicked
+    let result = mux_thread.join().map_err(|_| "Mux thread panicked");
+    // Sleep waits on a session that failed to start, too, until it's gone
+    if let Some(done) = sleep_done.or(pending_sleep) {
+        let _ = done.send(());
+    }
+    result??;
+    Ok(end)
+}
+
+/// ID of the controller matching a serial, else a name, given by the environment
+fn env_controller(
+    resources: &HashMap<GamepadId, gilrs_helper::GamepadResource>,
+    serial: Option<&str>,
+    name: Option<&str>,
+) -> Option<usize> {
+    let mut ids: Vec<GamepadId> = resources.keys().copied().collect();
+    ids.sort_by_key(|&id| usize::from(id));
+    ids.iter()
+        .find(|id| serial.is_some() && resources[id].serial().as_deref() == serial)
+        .or_else(|| {
+            ids.iter()
+                .find(|id| name.is_some() && Some(resources[id].name.as_str()) == name)
+        })
+        .map(|&id| usize::from(id))
+}
+
+/// Keep System hiding once Enter is pressed, before the rollback timer ends
+fn confirm_hide_on_enter(settings: &mux_runtime::RuntimeSettings, secs: u64) {
+    println!(
+        "Press Enter within {}s to keep controllers hidden; otherwise they are restored.",
+        secs
+    );
+    let settings = settings.clone();
+    std::thread::spawn(move || {
+        let mut line = String::new();
+        // EOF, e.g. without a terminal, leaves the rollback to happen
+        if matches!(std::io::stdin().read_line(&mut line), Ok(n) if n > 0)
+            && settings.confirm_hide()
+        {
+            info!("System hiding confirmed");
+            println!("Keeping controllers hidden.");
+        }
+    });
+}
+
+/// The variant after `current`, wrapping around
+fn next_variant<T: ValueEnum + Clone + PartialEq>(current: &T) -> T {
+    let variants = T::value_variants();
+    let index = variants.iter().position(|v| v == current).unwrap_or(0);
+    variants[(index + 1) % variants.len()].clone()
+}
+
+fn apply_control_signal(
+    signal: signal_helpers::ControlSignal,
+    settings: &mux_runtime::RuntimeSettings,
+) {
+    let msg = match signal {
+        signal_helpers::ControlSignal::CycleMode => {
+            let mode = next_variant(&settings.get_mode());
+            settings.update_mode(mode.clone());
+            format!("Mode: {:?}", mode)
+        }
+        signal_helpers::ControlSignal::CycleRumble => {
+            let rumble = next_variant(&settings.get_rumble());
+            settings.update_rumble(rumble.clone());
+            format!("Rumble: {:?}", rumble)
+        }
+        signal_helpers::ControlSignal::Reload => {
+            // An invalid file keeps the running options, rather than defaults
+            let config = tray::config::TrayConfig::config_path()
+                .and_then(|path| Ok(fs::read_to_string(path)?))
+                .map_err(|e| e.to_string())
+                .and_then(|content| tray::config::TrayConfig::validate(&content));
+            match config {
+                Ok(config) => {
+                    settings.update_mode_options(config.modes);
+                    settings.update_smoothing(config.smoothing.enabled);
+                    "Reloaded mode options and smoothing; other options apply on restart".into()
+                }
+                Err(e) => format!("Config not reloaded: {}", e),
+            }
+        }
+    };
+    info!("{}", msg);
+    println!("{}", msg);
+}
+
+fn run_arcade(args: ArcadeArgs) -> Result<(), Box<dyn Error>> {
+    let mapping = arcade_input::ArcadeMapping::load(&args.mapping)
+        .map_err(|e| format!("Invalid mapping {}: {}", args.mapping.display(), e))?;
+    for device in &mapping.devices {
+        println!("Device: {}", device.path.display());
+    }
+
+    use std::sync::mpsc;
+    let (shutdown_tx, shutdown_rx) = mpsc::channel::<()>();
     ctrlc::set_handler(move || {
         println!("\nShutting down...");
-        // Ignore error if already sent
         let _ = shutdown_tx.send(());
     })?;
 
-    info!("Mux Active. Press Ctrl+C to exit.");
-    println!("Mux Active. Press Ctrl+C to exit.");
+    info!("Arcade Active. Press Ctrl+C to exit.");
+    println!("Arcade Active. Press Ctrl+C to exit.");
+    arcade_input::run_arcade(mapping, shutdown_rx)
+}
 
-    // Wait for mux thread to finish
-    let _ = mux_thread.join();
-    Ok(())
+fn run_fuzz(args: FuzzArgs) -> Result<(), Box<dyn Error>> {
+    let seed = args.seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64)
+    });
+    let options = fuzz_input::FuzzOptions {
+        seed,
+        rate: args.rate,
+        controls: args.controls,
+        duration: args.duration.map(Duration::from_secs),
+    };
+
+    use std::sync::mpsc;
+    let (shutdown_tx, shutdown_rx) = mpsc::channel::<()>();
+    ctrlc::set_handler(move || {
+        println!("\nShutting down...");
+        let _ = shutdown_tx.send(());
+    })?;
+
+    let msg = format!("Fuzz Active with seed {}. Press Ctrl+C to exit.", seed);
+    info!("{}", msg);
+    println!("{}", msg);
+    fuzz_input::run_fuzz(options, shutdown_rx)
 }
@@ -0,0 +1,72 @@
+use crate::evdev_helpers::gilrs_button_to_evdev_key;
+use crate::quirks::TargetButton;
+use evdev::{EventType, InputEvent, KeyCode};
+use gilrs::{Button, Event, GamepadId};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// What the virtual gamepad emits when a source presses Mode (Guide)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum ModeOutput {
+    /// BTN_MODE, as pressed
+    #[default]
+    Mode,
+    /// No button, so only CtrlAssist sees it, e.g. as the Toggle trigger
+    Nothing,
+    /// Another button in its place
+    Button(TargetButton),
+}
+
+/// Mode button output of each source, e.g. keeping the assist's Toggle
+/// presses from also opening the Steam overlay
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct ModeButtonConfig {
+    pub primary: ModeOutput,
+    pub assist: ModeOutput,
+}
+
+impl ModeButtonConfig {
+    /// Rewrite the BTN_MODE events in `out`, just produced for `event`, as
+    /// configured for the source of the event
+    ///
+    /// The mux mode still sees the Mode press itself, so handoff keeps working
+    /// whatever the game is sent.
+    pub fn apply(
+        &self,
+        event: &Event,
+        primary_id: GamepadId,
+        assist_id: GamepadId,
+        out: &mut Vec<InputEvent>,
+    ) {
+        let output = match event.id {
+            id if id == primary_id => self.primary,
+            id if id == assist_id => self.assist,
+            _ => return,
+        };
+        let is_mode = matches!(
+            event.event,
+            gilrs::EventType::ButtonPressed(Button::Mode, _)
+                | gilrs::EventType::ButtonReleased(Button::Mode, _)
+                | gilrs::EventType::ButtonChanged(Button::Mode, ..)
+        );
+        if !is_mode {
+            return;
+        }
+
+        let is_mode_key =
+            |e: &InputEvent| e.event_type() == EventType::KEY && e.code() == KeyCode::BTN_MODE.0;
+        match output {
+            ModeOutput::Mode => {}
+            ModeOutput::Nothing => out.retain(|e| !is_mode_key(e)),
+            ModeOutput::Button(target) => {
+                let Some(key) = gilrs_button_to_evdev_key(target.button()) else {
+                    return;
+                };
+                for e in out.iter_mut().filter(|e| is_mode_key(e)) {
+                    *e = InputEvent::new(EventType::KEY.0, key.0, e.value());
+                }
+            }
+        }
+    }
+}
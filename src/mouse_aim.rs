@@ -0,0 +1,194 @@
+use crate::evdev_helpers::{self, AXIS_HALF, AXIS_MAX, READ_POLL_INTERVAL};
+use evdev::{AbsoluteAxisCode, Device, EventSummary, EventType, InputEvent, RelativeAxisCode};
+use log::{info, warn};
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the right stick is updated while the mouse moves
+const TICK_INTERVAL: Duration = Duration::from_millis(8);
+/// How long without motion before the stick returns to the mux mode's output
+const IDLE_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Aiming with a mouse on the virtual right stick
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct MouseConfig {
+    pub enabled: bool,
+    /// Mouse event node, preferably a stable /dev/input/by-id path
+    pub device: Option<PathBuf>,
+    /// Stick deflection per 1000 counts/second of mouse motion
+    pub sensitivity: f32,
+    /// Response curve exponent; below 1 boosts small motions, above 1 damps them
+    pub curve: f32,
+    /// Grab the mouse so it doesn't also move the desktop cursor
+    pub grab: bool,
+    pub invert_y: bool,
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: None,
+            sensitivity: 1.0,
+            curve: 1.0,
+            grab: true,
+            invert_y: false,
+        }
+    }
+}
+
+/// Mouse motion accumulated since the last tick, in counts
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MouseDeltas {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Accumulate relative motion from a mouse node until shutdown
+///
+/// Reads wait with a timeout, so the grab is released soon after shutdown
+/// even while the mouse rests.
+pub fn spawn_mouse_reader(
+    config: &MouseConfig,
+    deltas: Arc<Mutex<MouseDeltas>>,
+    shutdown: Arc<AtomicBool>,
+) -> Result<thread::JoinHandle<()>, Box<dyn std::error::Error>> {
+    let path = config.device.as_ref().ok_or("No mouse device configured")?;
+    let mut device = Device::open(path)?;
+    if config.grab {
+        device.grab()?;
+    }
+
+    info!("Mouse: reading {}", path.display());
+    Ok(thread::spawn(move || {
+        while !shutdown.load(Ordering::SeqCst) {
+            let events = match evdev_helpers::wait_readable(&device, READ_POLL_INTERVAL)
+                .and_then(|readable| readable.then(|| device.fetch_events()).transpose())
+            {
+                Ok(Some(events)) => events,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Mouse: device stopped: {}", e);
+                    return;
+                }
+            };
+            for event in events {
+                if let EventSummary::RelativeAxis(_, axis, value) = event.destructure() {
+                    match axis {
+                        RelativeAxisCode::REL_X => deltas.lock().x += value as f32,
+                        RelativeAxisCode::REL_Y => deltas.lock().y += value as f32,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }))
+}
+
+/// Adds mouse motion onto the virtual right stick
+///
+/// Like gyro aiming, the incoming right stick output is kept as a base that
+/// mouse motion adds to, and it is restored once the mouse comes to rest.
+pub struct MouseAim {
+    config: MouseConfig,
+    deltas: Option<Arc<Mutex<MouseDeltas>>>,
+    /// Last incoming right stick values, in evdev units
+    base: [i32; 2],
+    active: bool,
+    last_tick: Instant,
+    last_motion: Instant,
+}
+
+impl MouseAim {
+    pub fn new(config: MouseConfig, deltas: Option<Arc<Mutex<MouseDeltas>>>) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            deltas,
+            base: [AXIS_HALF as i32; 2],
+            active: false,
+            last_tick: now,
+            last_motion: now,
+        }
+    }
+
+    /// Record incoming right stick output, holding it back while aiming
+    pub fn process(&mut self, events: &mut Vec<InputEvent>) {
+        if self.deltas.is_none() {
+            return;
+        }
+        events.retain(|event| {
+            if event.event_type() != EventType::ABSOLUTE {
+                return true;
+            }
+            let index = match AbsoluteAxisCode(event.code()) {
+                AbsoluteAxisCode::ABS_RX => 0,
+                AbsoluteAxisCode::ABS_RY => 1,
+                _ => return true,
+            };
+            self.base[index] = event.value();
+            !self.active
+        });
+    }
+
+    /// Map a mouse velocity in counts/second onto the response curve
+    fn deflection(&self, velocity: f32) -> f32 {
+        let scaled = (velocity.abs() / 1000.0 * self.config.sensitivity).min(1.0);
+        scaled.powf(self.config.curve.max(0.1)) * velocity.signum()
+    }
+
+    /// Append right stick events for mouse motion since the last tick
+    pub fn tick(&mut self, now: Instant, out: &mut Vec<InputEvent>) {
+        let Some(deltas) = &self.deltas else {
+            return;
+        };
+        if now < self.last_tick + TICK_INTERVAL {
+            return;
+        }
+        let dt = now.duration_since(self.last_tick).as_secs_f32();
+        self.last_tick = now;
+
+        let MouseDeltas { x, y } = std::mem::take(&mut *deltas.lock());
+        if x != 0.0 || y != 0.0 {
+            self.last_motion = now;
+        } else if now >= self.last_motion + IDLE_TIMEOUT {
+            if self.active {
+                // Restore the incoming right stick output
+                self.active = false;
+                out.push(stick_event(AbsoluteAxisCode::ABS_RX, self.base[0]));
+                out.push(stick_event(AbsoluteAxisCode::ABS_RY, self.base[1]));
+            }
+            return;
+        }
+        self.active = true;
+
+        let y_sign = if self.config.invert_y { -1.0 } else { 1.0 };
+        let dx = self.deflection(x / dt);
+        let dy = self.deflection(y / dt) * y_sign;
+
+        for (axis, base, delta) in [
+            (AbsoluteAxisCode::ABS_RX, self.base[0], dx),
+            (AbsoluteAxisCode::ABS_RY, self.base[1], dy),
+        ] {
+            let value = (base as f32 + delta * AXIS_HALF).clamp(0.0, AXIS_MAX);
+            out.push(stick_event(axis, value.round() as i32));
+        }
+    }
+
+    /// When the right stick next needs updating, if a mouse is in use
+    pub fn next_deadline(&self) -> Option<Instant> {
+        // Mouse motion doesn't wake the input loop, so poll while a mouse is set
+        self.deltas.as_ref().map(|_| self.last_tick + TICK_INTERVAL)
+    }
+}
+
+fn stick_event(axis: AbsoluteAxisCode, value: i32) -> InputEvent {
+    InputEvent::new(EventType::ABSOLUTE.0, axis.0, value)
+}
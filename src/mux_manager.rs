@@ -1,41 +1,159 @@
-use crate::evdev_helpers::{self, VirtualGamepadInfo};
-use crate::gilrs_helper::{self};
-use crate::mux_modes::ModeType;
-use crate::mux_runtime::RuntimeSettings;
-use crate::udev_helpers::ScopedDeviceHider;
-use crate::{HideType, RumbleTarget, SpoofTarget};
+use crate::button_filters::{ConfirmConfig, DebounceConfig, HoldTimingConfig};
+use crate::consumers;
+use crate::disconnect_policy::{DisconnectPolicy, DisconnectWatch};
+use crate::evdev_helpers::{self, VirtualGamepadInfo, VirtualLayout};
+use crate::ff_helpers::{FfCapabilities, FfConfig, MotorMaps};
+use crate::game_mode::GameModeGuard;
+use crate::game_profiles::GameProfile;
+use crate::gilrs_helper::{self, CompositeSources, GamepadResource, GilrsConfig};
+use crate::gyro_aim::{self, GyroAim, GyroConfig, GyroRates};
+use crate::hooks::{HookConfig, HookEvent};
+use crate::input_delay::DelayConfig;
+use crate::mode_button::ModeButtonConfig;
+use crate::mouse_aim::{self, MouseAim, MouseConfig, MouseDeltas};
+use crate::mux_modes::{ModeOptions, ModeType};
+use crate::mux_runtime::{FfOptions, InputOptions, RuntimeSettings, TimingConfig};
+use crate::notifier::{self, SessionEvent};
+use crate::output_backend;
+use crate::overlay_pause::{OverlayPause, OverlayPauseConfig};
+use crate::pad_menu::{PadMenu, PadMenuConfig};
+use crate::power_helpers::{ChargeState, PowerLookup};
+use crate::quirks::{DeviceQuirks, Quirk};
+use crate::session_timer::{SessionTimer, TimeLimitConfig};
+use crate::sibling_nodes::{self, KeyChange, SiblingConfig, SiblingInput};
+use crate::source_grab::SourceGrabs;
+use crate::stick_filters::SmoothingConfig;
+use crate::trackpad_input::{self, TrackpadConfig, TrackpadInput, TrackpadPosition};
+use crate::udev_helpers::{self, ScopedDeviceHider};
+use crate::{HideOrder, HideType, PaddleBinding, RumbleTarget, SpoofTarget};
 use evdev::Device;
 use gilrs::{GamepadId, Gilrs};
-use log::info;
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
 use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a supervisor checks whether the runtime threads are still healthy
+const SUPERVISE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often controller batteries are checked for the low battery event
+const BATTERY_INTERVAL: Duration = Duration::from_secs(60);
 
 /// Configuration for starting a mux session
 pub struct MuxConfig {
     pub primary_id: GamepadId,
     pub assist_id: GamepadId,
+    /// Second gamepad forming one logical primary, e.g. the other Joy-Con
+    pub primary_partner: Option<GamepadId>,
+    /// Second gamepad forming one logical assist
+    pub assist_partner: Option<GamepadId>,
     pub mode: ModeType,
+    pub mode_options: ModeOptions,
     pub hide: HideType,
+    pub hide_order: HideOrder,
+    pub hide_settle_ms: u64,
     pub spoof: SpoofTarget,
     pub rumble: RumbleTarget,
+    pub paddles: PaddleBinding,
+    pub hooks: HookConfig,
+    pub timing: TimingConfig,
+    pub gilrs: GilrsConfig,
+    pub debounce: DebounceConfig,
+    pub delay: DelayConfig,
+    pub hold: HoldTimingConfig,
+    pub confirm: ConfirmConfig,
+    pub smoothing: SmoothingConfig,
+    pub gyro: GyroConfig,
+    pub mouse: MouseConfig,
+    pub trackpad: TrackpadConfig,
+    pub siblings: SiblingConfig,
+    pub force_feedback: FfConfig,
+    /// Controller model oddities, on top of the built-in ones
+    pub quirks: Vec<Quirk>,
+    /// Present the virtual device over uhid too, for games reading hidraw
+    pub hidraw: bool,
+    /// Append the session to the virtual device's name, to tell sessions apart
+    pub session_name: bool,
+    /// Exclusively grab the sources' event nodes for the session
+    pub grab_sources: bool,
+    /// Register with Feral GameMode while the session runs
+    pub game_mode: bool,
+    /// Seconds to confirm System hiding before it's rolled back, 0 to keep it
+    pub hide_confirm_secs: u64,
+    /// Menu opened by a chord on either controller
+    pub pad_menu: PadMenuConfig,
+    /// Freezing of output while a system chord is held
+    pub overlay_pause: OverlayPauseConfig,
+    /// What each source's Mode button emits
+    pub mode_button: ModeButtonConfig,
+    /// Session length limit and what happens once reached
+    pub time_limit: TimeLimitConfig,
+    /// When the time limit started counting, earlier than now for a restart
+    pub time_limit_start: Instant,
+    /// What happens to the inputs of a source that disconnects
+    pub disconnect: DisconnectPolicy,
+    /// Profiles the controller menu can switch to, built-ins included
+    pub profiles: BTreeMap<String, GameProfile>,
 }
 
 /// Handle to a running mux session
 pub struct MuxHandle {
-    pub input_handle: thread::JoinHandle<()>,
+    pub input_handle: thread::JoinHandle<Gilrs>,
     pub ff_handle: thread::JoinHandle<()>,
+    /// Threads reading side devices, e.g. the gyro, joined so grabs are
+    /// released before a new session opens the same devices
+    pub readers: Vec<thread::JoinHandle<()>>,
     pub shutdown: Arc<AtomicBool>,
     pub virtual_device_path: PathBuf,
+    pub hooks: HookConfig,
+    pub hook_vars: Vec<(&'static str, String)>,
+    /// Keeps devices hidden for the session, shared with the rollback timer
+    pub hider: Arc<Mutex<ScopedDeviceHider>>,
+    /// Keeps GameMode active for the session, if enabled
+    pub game_mode: Option<GameModeGuard>,
+    /// Exclusive grabs of the sources, if enabled
+    pub grabs: SourceGrabs,
+    /// Set when the session stopped itself, e.g. from the controller menu
+    pub stop_requested: Arc<AtomicBool>,
 }
 
 impl MuxHandle {
-    /// Request shutdown and wait for threads to complete
-    pub fn shutdown(self) {
-        use std::sync::atomic::Ordering;
+    /// Block until shutdown is requested or a runtime thread stops on its own
+    ///
+    /// Returns `true` if the session ended abnormally, e.g. a thread panicked.
+    pub fn wait(&self, shutdown_rx: &Receiver<()>) -> bool {
+        loop {
+            match shutdown_rx.recv_timeout(SUPERVISE_INTERVAL) {
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return false,
+                Err(RecvTimeoutError::Timeout) => {
+                    if self.stop_requested.load(Ordering::SeqCst) {
+                        info!("Session stopped from within, shutting down");
+                        return false;
+                    }
+                    if self.shutdown.load(Ordering::SeqCst) {
+                        error!("Mux runtime stopped unexpectedly, shutting down session");
+                        notifier::post_event(
+                            SessionEvent::Error,
+                            "The session stopped unexpectedly; check the logs",
+                        );
+                        return true;
+                    }
+                }
+            }
+        }
+    }
 
+    /// Request shutdown and wait for threads to complete
+    ///
+    /// Returns the Gilrs instance the session was started with, so callers
+    /// can keep using it for controller discovery.
+    pub fn shutdown(mut self) -> Option<Gilrs> {
         self.shutdown.store(true, Ordering::SeqCst);
 
         // Unblock FF thread by sending no-op event
@@ -47,15 +165,284 @@ impl MuxHandle {
             ]);
         }
 
-        let _ = self.input_handle.join();
+        let gilrs = self.input_handle.join().ok();
         let _ = self.ff_handle.join();
+        for reader in self.readers.drain(..) {
+            let _ = reader.join();
+        }
+
+        // Restore hidden devices once nothing reads from them anymore
+        self.grabs.release();
+        self.hider.lock().restore();
+
+        // GameMode ends with the session rather than the tray or process
+        drop(self.game_mode.take());
+
+        self.hooks.run(HookEvent::Stop, &self.hook_vars);
+        notifier::post_event_blocking(SessionEvent::Stop, "Session stopped");
+        crate::crash_helpers::set_session_summary(None);
+        gilrs
+    }
+}
+
+/// Flags the session for shutdown if the owning runtime thread panics,
+/// so the supervisor still tears down the session and restores devices
+struct PanicGuard {
+    name: &'static str,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl Drop for PanicGuard {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            error!("Mux {} thread panicked", self.name);
+            self.shutdown.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Hide the devices of the given controllers, if found
+fn hide_sources(
+    hider: &mut ScopedDeviceHider,
+    resources: &HashMap<GamepadId, GamepadResource>,
+    ids: &[GamepadId],
+) -> Result<(), Box<dyn Error>> {
+    for id in ids {
+        if let Some(resource) = resources.get(id) {
+            hider.hide_gamepad_devices(resource)?;
+        }
+    }
+    Ok(())
+}
+
+/// Restore System-hidden devices unless the session is confirmed working in
+/// time, like a display mode change that reverts on its own
+fn spawn_hide_rollback(
+    hider: Arc<Mutex<ScopedDeviceHider>>,
+    settings: Arc<RuntimeSettings>,
+    timeout: Duration,
+    shutdown: Arc<AtomicBool>,
+) {
+    settings.hide_pending.store(true, Ordering::SeqCst);
+    thread::spawn(move || {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if !settings.is_hide_pending() || shutdown.load(Ordering::SeqCst) {
+                return;
+            }
+            thread::sleep(SUPERVISE_INTERVAL);
+        }
+        if settings.hide_pending.swap(false, Ordering::SeqCst) {
+            warn!(
+                "System hiding not confirmed within {}s, restoring devices",
+                timeout.as_secs()
+            );
+            hider.lock().restore();
+        }
+    });
+}
+
+/// Hide controllers while another process has the virtual device open, and
+/// restore them once it's closed, so they stay usable outside of games
+fn spawn_hide_on_use(
+    hider: Arc<Mutex<ScopedDeviceHider>>,
+    sources: Vec<GamepadResource>,
+    settings: Arc<RuntimeSettings>,
+    shutdown: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut hidden = false;
+        while !shutdown.load(Ordering::SeqCst) {
+            let in_use = !settings.consumers.read().is_empty();
+            if in_use && !hidden {
+                info!("Virtual device opened, hiding controllers");
+                let mut hider = hider.lock();
+                for resource in &sources {
+                    if let Err(e) = hider.hide_gamepad_devices(resource) {
+                        warn!("Failed to hide {}: {}", resource.name, e);
+                    }
+                }
+                hidden = true;
+            } else if !in_use && hidden {
+                info!("Virtual device closed, restoring controllers");
+                hider.lock().restore();
+                hidden = false;
+            }
+            thread::sleep(SUPERVISE_INTERVAL);
+        }
+    })
+}
+
+/// Post the low battery event once for each controller whose battery runs
+/// down to `percent` while discharging
+fn spawn_battery_watch(sources: Vec<GamepadResource>, percent: u8, shutdown: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        let mut warned = vec![false; sources.len()];
+        let mut next_check = Instant::now();
+        while !shutdown.load(Ordering::SeqCst) {
+            if Instant::now() >= next_check {
+                next_check += BATTERY_INTERVAL;
+                let lookup = PowerLookup::new();
+                for (resource, warned) in sources.iter().zip(&mut warned) {
+                    let Some(power) = lookup.power_info(resource) else {
+                        continue;
+                    };
+                    let low = power.state == ChargeState::Discharging
+                        && power.level.is_some_and(|level| level <= percent);
+                    if low && !*warned {
+                        warn!("{} battery low: {}", resource.name, power);
+                        notifier::post_event(
+                            SessionEvent::LowBattery,
+                            format!("{} battery low: {}", resource.name, power),
+                        );
+                    }
+                    // Warn again after charging up past the threshold
+                    *warned = low || (*warned && power.state != ChargeState::Charging);
+                }
+            }
+            thread::sleep(SUPERVISE_INTERVAL);
+        }
+    });
+}
+
+/// Start reading the assist controller's motion sensor, if it has one
+fn start_gyro(
+    resources: &HashMap<GamepadId, GamepadResource>,
+    assist_id: GamepadId,
+    shutdown: &Arc<AtomicBool>,
+    readers: &mut Vec<thread::JoinHandle<()>>,
+) -> Option<Arc<Mutex<GyroRates>>> {
+    let resource = resources.get(&assist_id)?;
+    let path = match udev_helpers::find_motion_devnode(resource) {
+        Ok(Some(path)) => path,
+        Ok(None) => {
+            warn!("Gyro: assist controller has no motion sensor, aiming disabled");
+            return None;
+        }
+        Err(e) => {
+            warn!("Gyro: failed to find motion sensor: {}", e);
+            return None;
+        }
+    };
+
+    // The reader exits once shutdown is flagged or the device is gone
+    let rates = Arc::new(Mutex::new(GyroRates::default()));
+    match gyro_aim::spawn_motion_reader(&path, Arc::clone(&rates), Arc::clone(shutdown)) {
+        Ok(reader) => {
+            readers.push(reader);
+            Some(rates)
+        }
+        Err(e) => {
+            warn!("Gyro: failed to open {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Start reading the assist controller's trackpad
+fn start_trackpad(
+    resources: &HashMap<GamepadId, GamepadResource>,
+    assist_id: GamepadId,
+    config: &TrackpadConfig,
+    shutdown: &Arc<AtomicBool>,
+    readers: &mut Vec<thread::JoinHandle<()>>,
+) -> Option<Arc<Mutex<TrackpadPosition>>> {
+    let resource = resources.get(&assist_id)?;
+
+    // The reader exits once shutdown is flagged or the device is gone
+    let position = Arc::new(Mutex::new(TrackpadPosition::default()));
+    match trackpad_input::spawn_trackpad_reader(
+        &resource.path,
+        config,
+        Arc::clone(&position),
+        Arc::clone(shutdown),
+    ) {
+        Ok(reader) => {
+            readers.push(reader);
+            Some(position)
+        }
+        Err(e) => {
+            warn!(
+                "Trackpad: failed to open {}: {}",
+                resource.path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Start reading keys from the other event nodes of the given controllers
+fn start_siblings(
+    resources: &HashMap<GamepadId, GamepadResource>,
+    ids: &[GamepadId],
+    config: &SiblingConfig,
+    shutdown: &Arc<AtomicBool>,
+    readers: &mut Vec<thread::JoinHandle<()>>,
+) -> Option<Arc<Mutex<Vec<KeyChange>>>> {
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let key_map = Arc::new(config.key_map());
+    let sources: Vec<PathBuf> = ids
+        .iter()
+        .filter_map(|id| resources.get(id))
+        .map(|resource| resource.path.clone())
+        .collect();
+    let mut reading = false;
+
+    for id in ids {
+        let Some(resource) = resources.get(id) else {
+            continue;
+        };
+        let paths = match udev_helpers::find_sibling_devnodes(resource, &sources) {
+            Ok(paths) => paths,
+            Err(e) => {
+                warn!("Siblings: failed to find nodes of {}: {}", resource.name, e);
+                continue;
+            }
+        };
+        // Readers exit once shutdown is flagged or the node is gone
+        for path in paths {
+            match sibling_nodes::spawn_sibling_reader(
+                &path,
+                *id,
+                Arc::clone(&key_map),
+                Arc::clone(&changes),
+                Arc::clone(shutdown),
+            ) {
+                Ok(reader) => {
+                    readers.push(reader);
+                    reading = true;
+                }
+                Err(e) => warn!("Siblings: failed to open {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    reading.then_some(changes)
+}
+
+/// The virtual device of a spoof with fixed IDs; None for those copying a
+/// source, which are only known once the session has its controllers
+pub fn preset_info(spoof: &SpoofTarget) -> Option<VirtualGamepadInfo> {
+    match spoof {
+        SpoofTarget::Primary | SpoofTarget::Assist => None,
+        SpoofTarget::SteamDeck => Some(VirtualGamepadInfo::steam_deck()),
+        SpoofTarget::Xbox360 => Some(VirtualGamepadInfo::xbox_360()),
+        SpoofTarget::Wheel => Some(VirtualGamepadInfo::wheel()),
+        SpoofTarget::Joystick => Some(VirtualGamepadInfo::joystick()),
+        SpoofTarget::None => Some(VirtualGamepadInfo {
+            name: "CtrlAssist Virtual Gamepad".into(),
+            vendor_id: None,
+            product_id: None,
+            layout: VirtualLayout::Default,
+        }),
     }
 }
 
 /// Start a mux session with the given configuration
 ///
 /// This function:
-/// 1. Sets up device hiding
+/// 1. Sets up device hiding (or after step 2, or once in use, per `hide_order`)
 /// 2. Creates the virtual gamepad
 /// 3. Prepares FF targets
 /// 4. Spawns input and FF threads
@@ -66,63 +453,347 @@ pub fn start_mux(
 ) -> Result<(MuxHandle, Arc<RuntimeSettings>), Box<dyn Error>> {
     let resources = gilrs_helper::discover_gamepad_resources(&gilrs);
 
-    // Setup hiding
-    let mut _hider = ScopedDeviceHider::new(config.hide.clone());
-    if let Some(primary_res) = resources.get(&config.primary_id) {
-        _hider.hide_gamepad_devices(primary_res)?;
+    // Pair partners with the controller they complete, e.g. two Joy-Cons
+    let mut composites = CompositeSources::default();
+    for (leader, partner) in [
+        (config.primary_id, config.primary_partner),
+        (config.assist_id, config.assist_partner),
+    ] {
+        if let Some(partner) = partner {
+            composites.pair(leader, partner);
+        }
+    }
+    let source_ids: Vec<GamepadId> = [config.primary_id, config.assist_id]
+        .into_iter()
+        .flat_map(|id| composites.members(id))
+        .collect();
+
+    // Read what's held before the session starts, which gilrs doesn't know
+    let held = source_ids
+        .iter()
+        .filter_map(|&id| {
+            let resource = resources.get(&id)?;
+            Some((
+                id,
+                gilrs_helper::held_events(gilrs.gamepad(id), &resource.device),
+            ))
+        })
+        .collect();
+
+    // Grabbed for the whole session, independent of hiding
+    let mut grabs = SourceGrabs::default();
+    if config.grab_sources {
+        for id in &source_ids {
+            if let Some(resource) = resources.get(id) {
+                grabs.grab(resource);
+            }
+        }
     }
-    if let Some(assist_res) = resources.get(&config.assist_id) {
-        _hider.hide_gamepad_devices(assist_res)?;
+
+    // Setup hiding, before or after the virtual device as the launcher needs
+    let mut hider = ScopedDeviceHider::new(config.hide.clone());
+    let settle = Duration::from_millis(config.hide_settle_ms);
+    if config.hide_order == HideOrder::Before {
+        hide_sources(&mut hider, &resources, &source_ids)?;
+        thread::sleep(settle);
     }
 
     // Setup virtual device
     let virtual_info = match config.spoof {
         SpoofTarget::Primary => VirtualGamepadInfo::from(&gilrs.gamepad(config.primary_id)),
         SpoofTarget::Assist => VirtualGamepadInfo::from(&gilrs.gamepad(config.assist_id)),
-        SpoofTarget::None => VirtualGamepadInfo {
-            name: "CtrlAssist Virtual Gamepad".into(),
-            vendor_id: None,
-            product_id: None,
-        },
+        _ => preset_info(&config.spoof).expect("spoof of a fixed device"),
+    };
+    let virtual_info = if config.session_name {
+        virtual_info.with_session_suffix()
+    } else {
+        virtual_info
     };
 
-    let mut v_uinput = evdev_helpers::create_virtual_gamepad(&virtual_info)?;
-    let v_resource = gilrs_helper::wait_for_virtual_device(&mut v_uinput)?;
+    // Any controller may become a rumble target, as the target can change live
+    let ff = FfCapabilities::resolve(
+        &config.force_feedback,
+        source_ids
+            .iter()
+            .filter_map(|id| resources.get(id))
+            .map(|resource| &resource.device),
+    );
+    let mut v_uinput = evdev_helpers::create_virtual_gamepad(&virtual_info, &ff)?;
+    let v_resource = gilrs_helper::wait_for_virtual_device(
+        &mut v_uinput,
+        config.timing.virtual_device_timeout(),
+        config.timing.retry_interval(),
+    )?;
     let virtual_device_path = v_resource.path.clone();
 
+    // uinput devices live under /devices/virtual, which logind assigns to seat0
+    let seat = udev_helpers::current_seat();
+    if !udev_helpers::is_on_seat(&virtual_device_path, &seat) {
+        warn!(
+            "Virtual device is not on seat {}; add a udev rule setting ID_SEAT for it",
+            seat
+        );
+    }
+
+    if config.hide_order == HideOrder::After {
+        thread::sleep(settle);
+        hide_sources(&mut hider, &resources, &source_ids)?;
+    }
+
     info!(
         "Virtual: {} @ {}",
         v_resource.name,
         v_resource.path.display()
     );
 
+    // Describe the session for crash reports
+    let describe = |leader: GamepadId| {
+        let members: Vec<String> = composites
+            .members(leader)
+            .map(|id| {
+                resources.get(&id).map_or_else(
+                    || id.to_string(),
+                    |r| format!("({}) {} @ {}", id, r.display_name(), r.path.display()),
+                )
+            })
+            .collect();
+        members.join(" + ")
+    };
+    crate::crash_helpers::set_session_summary(Some(format!(
+        "Primary: {}\nAssist:  {}\nVirtual: {} @ {}\nMode: {:?}, Hide: {:?}, Spoof: {:?}, Rumble: {:?}",
+        describe(config.primary_id),
+        describe(config.assist_id),
+        v_resource.name,
+        v_resource.path.display(),
+        config.mode,
+        config.hide,
+        config.spoof,
+        config.rumble
+    )));
+
+    // Describe the session to user hooks
+    let hook_vars = vec![
+        ("CTRLASSIST_PRIMARY", config.primary_id.to_string()),
+        ("CTRLASSIST_ASSIST", config.assist_id.to_string()),
+        ("CTRLASSIST_MODE", format!("{:?}", config.mode)),
+        (
+            "CTRLASSIST_VIRTUAL_DEVICE",
+            virtual_device_path.display().to_string(),
+        ),
+    ];
+
     // Create runtime settings
-    let runtime_settings = Arc::new(RuntimeSettings::new(config.mode, config.rumble));
+    let runtime_settings = Arc::new(RuntimeSettings::new(
+        config.mode,
+        config.mode_options,
+        config.rumble,
+        config.smoothing.enabled,
+        config.assist_id,
+    ));
 
     // Setup shutdown signal
     let shutdown = Arc::new(AtomicBool::new(false));
 
-    // Clone resources for FF thread (don't remove from map)
-    let all_resources = resources.clone();
+    // Roll back System hiding unless confirmed in time, if enabled
+    let hider = Arc::new(Mutex::new(hider));
+    if matches!(config.hide, HideType::System)
+        && config.hide_confirm_secs > 0
+        && config.hide_order != HideOrder::OnUse
+    {
+        spawn_hide_rollback(
+            Arc::clone(&hider),
+            Arc::clone(&runtime_settings),
+            Duration::from_secs(config.hide_confirm_secs),
+            Arc::clone(&shutdown),
+        );
+    }
+
+    // Side device readers and watchers, joined at shutdown
+    let mut readers = Vec::new();
+
+    // Watch which processes open the virtual device, to tell a game that
+    // doesn't see it apart from one that ignores it
+    readers.push(consumers::spawn_watcher(
+        virtual_device_path.clone(),
+        Arc::clone(&runtime_settings.consumers),
+        Arc::clone(&shutdown),
+    ));
+
+    // Hide controllers only while the virtual device is in use, if enabled
+    if config.hide_order == HideOrder::OnUse {
+        let sources = source_ids
+            .iter()
+            .filter_map(|id| resources.get(id))
+            .filter_map(|resource| match resource.try_clone() {
+                Ok(resource) => Some(resource),
+                Err(e) => {
+                    warn!(
+                        "Failed to reopen {} for hiding on use: {}",
+                        resource.path.display(),
+                        e
+                    );
+                    None
+                }
+            })
+            .collect();
+        readers.push(spawn_hide_on_use(
+            Arc::clone(&hider),
+            sources,
+            Arc::clone(&runtime_settings),
+            Arc::clone(&shutdown),
+        ));
+    }
+
+    // Watch batteries only if running low is posted anywhere
+    if let Some(percent) = notifier::low_battery_percent() {
+        let sources = source_ids
+            .iter()
+            .filter_map(|id| resources.get(id))
+            .filter_map(|resource| resource.try_clone().ok())
+            .collect();
+        spawn_battery_watch(sources, percent, Arc::clone(&shutdown));
+    }
+
+    // Read the assist's motion sensor for gyro aiming, if it has one
+    let gyro_rates = if config.gyro.enabled {
+        start_gyro(&resources, config.assist_id, &shutdown, &mut readers)
+    } else {
+        None
+    };
+
+    // Read the mouse for aiming, if one is configured
+    let mouse_deltas = if config.mouse.enabled {
+        let deltas = Arc::new(Mutex::new(MouseDeltas::default()));
+        match mouse_aim::spawn_mouse_reader(
+            &config.mouse,
+            Arc::clone(&deltas),
+            Arc::clone(&shutdown),
+        ) {
+            Ok(reader) => {
+                readers.push(reader);
+                Some(deltas)
+            }
+            Err(e) => {
+                warn!("Mouse: failed to start, aiming disabled: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Read the assist's trackpad as a stick or D-pad, if enabled
+    let trackpad_position = if config.trackpad.enabled {
+        start_trackpad(
+            &resources,
+            config.assist_id,
+            &config.trackpad,
+            &shutdown,
+            &mut readers,
+        )
+    } else {
+        None
+    };
+
+    // Read keys from the controllers' other event nodes, if enabled
+    let sibling_changes = if config.siblings.enabled {
+        start_siblings(
+            &resources,
+            &source_ids,
+            &config.siblings,
+            &shutdown,
+            &mut readers,
+        )
+    } else {
+        None
+    };
+
+    // Correct known oddities of the source controllers
+    let mut device_quirks = DeviceQuirks::new(config.quirks.clone());
+    for id in &source_ids {
+        if let Some(resource) = resources.get(id) {
+            device_quirks.insert(*id, resource);
+        }
+    }
+
+    // Present the virtual device through uinput, and over uhid too if enabled
+    let outputs = output_backend::select(v_resource.device, &virtual_info, config.hidraw);
+
+    // Clone resources for FF thread (don't remove from map), leaving out any
+    // that can't be reopened, which then only lose rumble
+    let all_resources = resources
+        .iter()
+        .filter_map(|(&id, resource)| match resource.try_clone() {
+            Ok(resource) => Some((id, resource)),
+            Err(e) => {
+                warn!(
+                    "Failed to reopen {} for force feedback: {}",
+                    resource.path.display(),
+                    e
+                );
+                None
+            }
+        })
+        .collect();
 
     // Spawn input thread
     let shutdown_input = Arc::clone(&shutdown);
     let runtime_settings_input = Arc::clone(&runtime_settings);
+    let input_options = InputOptions {
+        hooks: config.hooks.clone(),
+        event_timeout: config.timing.event_timeout(),
+        gilrs: config.gilrs,
+        paddles: config.paddles,
+        debounce: config.debounce,
+        delay: config.delay,
+        hold: config.hold,
+        confirm: config.confirm,
+        smoothing: config.smoothing,
+        gyro: GyroAim::new(config.gyro, gyro_rates, config.assist_id),
+        mouse: MouseAim::new(config.mouse, mouse_deltas),
+        trackpad: TrackpadInput::new(config.trackpad, trackpad_position),
+        siblings: SiblingInput::new(sibling_changes),
+        composites: composites.clone(),
+        held,
+        quirks: device_quirks,
+        outputs,
+        timer: SessionTimer::new(
+            &config.time_limit,
+            &config.profiles,
+            config.time_limit_start,
+        ),
+        disconnects: DisconnectWatch::new(config.disconnect),
+        menu: PadMenu::new(&config.pad_menu, config.profiles),
+        pause: OverlayPause::new(&config.overlay_pause),
+        mode_button: config.mode_button,
+    };
     let input_handle = thread::spawn(move || {
+        let _guard = PanicGuard {
+            name: "input",
+            shutdown: Arc::clone(&shutdown_input),
+        };
         crate::mux_runtime::run_input_loop(
             gilrs,
-            v_resource.device,
             runtime_settings_input,
             config.primary_id,
             config.assist_id,
             shutdown_input,
-        );
+            input_options,
+        )
     });
 
     // Spawn FF thread
+    let ff_options = FfOptions {
+        composites,
+        motors: MotorMaps::new(&config.force_feedback, config.quirks),
+    };
     let shutdown_ff = Arc::clone(&shutdown);
     let runtime_settings_ff = Arc::clone(&runtime_settings);
     let ff_handle = thread::spawn(move || {
+        let _guard = PanicGuard {
+            name: "FF",
+            shutdown: Arc::clone(&shutdown_ff),
+        };
         crate::mux_runtime::run_ff_loop(
             &mut v_uinput,
             all_resources,
@@ -130,15 +801,34 @@ pub fn start_mux(
             config.primary_id,
             config.assist_id,
             shutdown_ff,
+            ff_options,
         );
     });
 
+    config.hooks.run(HookEvent::Start, &hook_vars);
+    notifier::post_event(
+        SessionEvent::Start,
+        format!(
+            "Session started: primary {}, assist {}, mode {:?}",
+            config.primary_id,
+            config.assist_id,
+            runtime_settings.get_mode()
+        ),
+    );
+
     Ok((
         MuxHandle {
             input_handle,
             ff_handle,
+            readers,
             shutdown,
             virtual_device_path,
+            hooks: config.hooks,
+            hook_vars,
+            hider,
+            grabs,
+            game_mode: config.game_mode.then(GameModeGuard::register),
+            stop_requested: Arc::clone(&runtime_settings.stop_requested),
         },
         runtime_settings,
     ))
@@ -1,10 +1,24 @@
-use super::{MuxMode, helpers};
+use super::{AverageOptions, MuxMode, helpers};
 use crate::evdev_helpers;
+use crate::gilrs_helper::Sources;
 use evdev::InputEvent;
-use gilrs::{Button, Event, EventType, GamepadId, Gilrs};
+use gilrs::{Button, Event, EventType, GamepadId};
 
-#[derive(Default)]
-pub struct AverageMode;
+pub struct AverageMode {
+    options: AverageOptions,
+}
+
+impl AverageMode {
+    pub fn new(options: AverageOptions) -> Self {
+        Self { options }
+    }
+
+    /// Blend two active values by the assist weight
+    fn blend(&self, primary: f32, assist: f32) -> f32 {
+        let weight = self.options.assist_weight.clamp(0.0, 1.0);
+        primary * (1.0 - weight) + assist * weight
+    }
+}
 
 impl MuxMode for AverageMode {
     fn handle_event(
@@ -12,21 +26,22 @@ impl MuxMode for AverageMode {
         event: &Event,
         primary_id: GamepadId,
         assist_id: GamepadId,
-        gilrs: &Gilrs,
-    ) -> Option<Vec<InputEvent>> {
+        sources: &Sources,
+        out: &mut Vec<InputEvent>,
+    ) {
         // Filter out irrelevant devices
         if event.id != primary_id && event.id != assist_id {
-            return None;
+            return;
         }
 
-        let primary = gilrs.gamepad(primary_id);
-        let assist = gilrs.gamepad(assist_id);
+        let primary = sources.get(primary_id);
+        let assist = sources.get(assist_id);
 
         match event.event {
             EventType::ButtonPressed(btn, _) | EventType::ButtonReleased(btn, _) => {
                 // Skip unknown buttons - they may be mapped to axes instead
                 if btn == Button::Unknown {
-                    return None;
+                    return;
                 }
 
                 let is_pressed = matches!(event.event, EventType::ButtonPressed(..));
@@ -40,40 +55,39 @@ impl MuxMode for AverageMode {
 
                 // If either is still holding, block this event (OR logic)
                 if other_holding {
-                    return None;
+                    return;
                 }
 
-                helpers::create_button_key_event(btn, is_pressed).map(|e| vec![e])
+                out.extend(helpers::create_button_key_event(btn, is_pressed));
             }
 
             EventType::ButtonChanged(btn, _, _) => {
-                let abs_axis = evdev_helpers::gilrs_button_to_evdev_axis(btn)?;
+                let Some(abs_axis) = evdev_helpers::gilrs_button_to_evdev_axis(btn) else {
+                    return;
+                };
 
                 let event = if let Some([neg_btn, pos_btn]) = evdev_helpers::dpad_axis_pair(btn) {
                     // D-pad: Average active values
                     let assist_net = helpers::calculate_dpad_net_value(&assist, neg_btn, pos_btn);
                     let primary_net = helpers::calculate_dpad_net_value(&primary, neg_btn, pos_btn);
 
-                    let final_value = match (
-                        assist_net.abs() > helpers::DEADZONE,
-                        primary_net.abs() > helpers::DEADZONE,
-                    ) {
-                        (true, true) => primary_net + assist_net,
-                        (true, false) => assist_net,
-                        (false, _) => primary_net,
-                    };
+                    let deadzone = self.options.deadzone;
+                    let final_value =
+                        match (assist_net.abs() > deadzone, primary_net.abs() > deadzone) {
+                            (true, true) => primary_net + assist_net,
+                            (true, false) => assist_net,
+                            (false, _) => primary_net,
+                        };
 
                     helpers::create_dpad_event(final_value, neg_btn, pos_btn, abs_axis)
                 } else {
                     // Trigger: Average active values
-                    let primary_val = primary.button_data(btn).map_or(0.0, |d| d.value());
-                    let assist_val = assist.button_data(btn).map_or(0.0, |d| d.value());
-
-                    let final_value = match (
-                        assist_val > helpers::DEADZONE,
-                        primary_val > helpers::DEADZONE,
-                    ) {
-                        (true, true) => (primary_val + assist_val) / 2.0,
+                    let primary_val = primary.button_value(btn);
+                    let assist_val = assist.button_value(btn);
+
+                    let deadzone = self.options.deadzone;
+                    let final_value = match (assist_val > deadzone, primary_val > deadzone) {
+                        (true, true) => self.blend(primary_val, assist_val),
                         (true, false) => assist_val,
                         (false, _) => primary_val,
                     };
@@ -81,27 +95,46 @@ impl MuxMode for AverageMode {
                     helpers::create_trigger_event(final_value, abs_axis)
                 };
 
-                Some(vec![event])
+                out.push(event);
+            }
+
+            EventType::AxisChanged(axis, _, _) if helpers::is_trigger_axis(axis) => {
+                // Trigger axis: Average active values, as for trigger buttons
+                let primary_val = helpers::read_trigger_axis(&primary, axis);
+                let assist_val = helpers::read_trigger_axis(&assist, axis);
+
+                let deadzone = self.options.deadzone;
+                let final_value = match (assist_val > deadzone, primary_val > deadzone) {
+                    (true, true) => self.blend(primary_val, assist_val),
+                    (true, false) => assist_val,
+                    (false, _) => primary_val,
+                };
+
+                out.extend(helpers::create_trigger_axis_event(axis, final_value));
             }
 
             EventType::AxisChanged(axis, _, _) => {
-                let (x_axis, y_axis) = helpers::map_to_stick_pair(axis)?;
+                let Some((x_axis, y_axis)) = helpers::map_to_stick_pair(axis) else {
+                    return;
+                };
 
                 // Check activity on both sticks
-                let assist_active = helpers::is_stick_active(&assist, x_axis, y_axis);
-                let primary_active = helpers::is_stick_active(&primary, x_axis, y_axis);
+                let deadzone = self.options.deadzone;
+                let assist_active = helpers::is_stick_active(&assist, x_axis, y_axis, deadzone);
+                let primary_active = helpers::is_stick_active(&primary, x_axis, y_axis, deadzone);
 
                 // Calculate final values
                 let (final_x, final_y) = {
-                    let assist_x = assist.axis_data(x_axis).map_or(0.0, |d| d.value());
-                    let assist_y = assist.axis_data(y_axis).map_or(0.0, |d| d.value());
-                    let primary_x = primary.axis_data(x_axis).map_or(0.0, |d| d.value());
-                    let primary_y = primary.axis_data(y_axis).map_or(0.0, |d| d.value());
+                    let assist_x = assist.axis_value(x_axis);
+                    let assist_y = assist.axis_value(y_axis);
+                    let primary_x = primary.axis_value(x_axis);
+                    let primary_y = primary.axis_value(y_axis);
 
                     match (assist_active, primary_active) {
-                        (true, true) => {
-                            ((primary_x + assist_x) / 2.0, (primary_y + assist_y) / 2.0)
-                        }
+                        (true, true) => (
+                            self.blend(primary_x, assist_x),
+                            self.blend(primary_y, assist_y),
+                        ),
                         (true, false) => (assist_x, assist_y),
                         (false, _) => (primary_x, primary_y),
                     }
@@ -110,13 +143,12 @@ impl MuxMode for AverageMode {
                 // Emit events for both axes
                 let events = [(x_axis, final_x), (y_axis, final_y)]
                     .into_iter()
-                    .filter_map(|(ax, val)| helpers::create_stick_event(ax, val))
-                    .collect::<Vec<_>>();
+                    .filter_map(|(ax, val)| helpers::create_stick_event(ax, val));
 
-                (!events.is_empty()).then_some(events)
+                out.extend(events);
             }
 
-            _ => None,
+            _ => {}
         }
     }
 }
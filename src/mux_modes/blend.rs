@@ -0,0 +1,124 @@
+use super::{BlendOptions, MuxMode, helpers};
+use crate::evdev_helpers;
+use crate::gilrs_helper::Sources;
+use evdev::InputEvent;
+use gilrs::{Button, Event, EventType, GamepadId};
+
+pub struct BlendMode {
+    options: BlendOptions,
+}
+
+impl BlendMode {
+    pub fn new(options: BlendOptions) -> Self {
+        Self { options }
+    }
+
+    /// Mix two values by their weights, whether or not either is active
+    fn mix(&self, primary: f32, assist: f32) -> f32 {
+        primary * self.options.primary_weight.max(0.0)
+            + assist * self.options.assist_weight.max(0.0)
+    }
+}
+
+impl MuxMode for BlendMode {
+    fn handle_event(
+        &mut self,
+        event: &Event,
+        primary_id: GamepadId,
+        assist_id: GamepadId,
+        sources: &Sources,
+        out: &mut Vec<InputEvent>,
+    ) {
+        // Filter out irrelevant devices
+        if event.id != primary_id && event.id != assist_id {
+            return;
+        }
+
+        let primary = sources.get(primary_id);
+        let assist = sources.get(assist_id);
+
+        match event.event {
+            EventType::ButtonPressed(btn, _) | EventType::ButtonReleased(btn, _) => {
+                // Skip unknown buttons - they may be mapped to axes instead
+                if btn == Button::Unknown {
+                    return;
+                }
+
+                let is_pressed = matches!(event.event, EventType::ButtonPressed(..));
+
+                // Buttons can't be weighted, so they're OR'ed as in Average
+                let other_holding = if event.id == primary_id {
+                    assist.is_pressed(btn)
+                } else {
+                    primary.is_pressed(btn)
+                };
+                if other_holding {
+                    return;
+                }
+
+                out.extend(helpers::create_button_key_event(btn, is_pressed));
+            }
+
+            EventType::ButtonChanged(btn, _, _) => {
+                let Some(abs_axis) = evdev_helpers::gilrs_button_to_evdev_axis(btn) else {
+                    return;
+                };
+
+                let event = if let Some([neg_btn, pos_btn]) = evdev_helpers::dpad_axis_pair(btn) {
+                    // D-pad: Digital, so summed unweighted
+                    let assist_net = helpers::calculate_dpad_net_value(&assist, neg_btn, pos_btn);
+                    let primary_net = helpers::calculate_dpad_net_value(&primary, neg_btn, pos_btn);
+                    let final_value = (primary_net + assist_net).clamp(-1.0, 1.0);
+
+                    helpers::create_dpad_event(final_value, neg_btn, pos_btn, abs_axis)
+                } else {
+                    // Trigger: Weighted sum
+                    let final_value = self
+                        .mix(primary.button_value(btn), assist.button_value(btn))
+                        .clamp(0.0, 1.0);
+
+                    helpers::create_trigger_event(final_value, abs_axis)
+                };
+
+                out.push(event);
+            }
+
+            EventType::AxisChanged(axis, _, _) if helpers::is_trigger_axis(axis) => {
+                // Trigger axis: Weighted sum, as for trigger buttons
+                let final_value = self
+                    .mix(
+                        helpers::read_trigger_axis(&primary, axis),
+                        helpers::read_trigger_axis(&assist, axis),
+                    )
+                    .clamp(0.0, 1.0);
+
+                out.extend(helpers::create_trigger_axis_event(axis, final_value));
+            }
+
+            EventType::AxisChanged(axis, _, _) => {
+                let Some((x_axis, y_axis)) = helpers::map_to_stick_pair(axis) else {
+                    return;
+                };
+
+                // Weighted sum of both sticks, kept within the unit circle so
+                // the direction survives when both push the same way
+                let mut x = self.mix(primary.axis_value(x_axis), assist.axis_value(x_axis));
+                let mut y = self.mix(primary.axis_value(y_axis), assist.axis_value(y_axis));
+                let magnitude = (x * x + y * y).sqrt();
+                if magnitude > 1.0 {
+                    x /= magnitude;
+                    y /= magnitude;
+                }
+
+                // Emit events for both axes
+                let events = [(x_axis, x), (y_axis, y)]
+                    .into_iter()
+                    .filter_map(|(ax, val)| helpers::create_stick_event(ax, val));
+
+                out.extend(events);
+            }
+
+            _ => {}
+        }
+    }
+}
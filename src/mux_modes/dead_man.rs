@@ -0,0 +1,52 @@
+use super::{DeadManOptions, MuxMode, helpers};
+use crate::gilrs_helper::Sources;
+use evdev::InputEvent;
+use gilrs::{Event, EventType, GamepadId};
+
+pub struct DeadManMode {
+    options: DeadManOptions,
+}
+
+impl DeadManMode {
+    pub fn new(options: DeadManOptions) -> Self {
+        Self { options }
+    }
+}
+
+impl MuxMode for DeadManMode {
+    fn handle_event(
+        &mut self,
+        event: &Event,
+        primary_id: GamepadId,
+        assist_id: GamepadId,
+        sources: &Sources,
+        out: &mut Vec<InputEvent>,
+    ) {
+        let enable_button = self.options.button.button();
+
+        // Assist enables output, jumping straight to what the primary holds,
+        // and resets everything to neutral the instant it lets go
+        if event.id == assist_id {
+            match event.event {
+                EventType::ButtonPressed(btn, _) if btn == enable_button => {
+                    let primary = sources.get(primary_id);
+                    helpers::sync_controller_state(&primary, &[], out);
+                    helpers::release_unset(out);
+                }
+                EventType::ButtonReleased(btn, _) if btn == enable_button => {
+                    helpers::release_unset(out);
+                }
+                EventType::Disconnected => helpers::release_unset(out),
+                _ => {}
+            }
+            return;
+        }
+
+        // Only forward primary events while the assist holds the button
+        if event.id != primary_id || !sources.get(assist_id).is_pressed(enable_button) {
+            return;
+        }
+        let primary = sources.get(primary_id);
+        helpers::convert_event(event, &primary, out);
+    }
+}
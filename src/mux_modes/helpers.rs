@@ -1,22 +1,43 @@
 use evdev::InputEvent;
-use gilrs::{Axis, Button, Gamepad};
+use gilrs::{Axis, Button, Event, EventType};
+use std::collections::HashMap;
 
 use crate::evdev_helpers;
+use crate::gilrs_helper::Source;
 
 pub const DEADZONE: f32 = 0.1;
 
+/// Last value emitted per virtual axis, shared by all modes
+///
+/// Modes recompute whole stick pairs and triggers on every axis event, so
+/// most of their axis output repeats what the virtual device already holds.
+#[derive(Default)]
+pub struct AxisCache {
+    last: HashMap<u16, i32>,
+}
+
+impl AxisCache {
+    /// Drop axis events whose value matches the last one emitted on that axis
+    pub fn retain_changed(&mut self, events: &mut Vec<InputEvent>) {
+        events.retain(|event| {
+            event.event_type() != evdev::EventType::ABSOLUTE
+                || self.last.insert(event.code(), event.value()) != Some(event.value())
+        });
+    }
+}
+
 /// Calculate net axis value for D-pad from button states (-1.0 to 1.0)
-pub fn calculate_dpad_net_value(gamepad: &Gamepad, neg_btn: Button, pos_btn: Button) -> f32 {
-    let neg = gamepad.button_data(neg_btn).map_or(0.0, |d| d.value());
-    let pos = gamepad.button_data(pos_btn).map_or(0.0, |d| d.value());
+pub fn calculate_dpad_net_value(source: &Source, neg_btn: Button, pos_btn: Button) -> f32 {
+    let neg = source.button_value(neg_btn);
+    let pos = source.button_value(pos_btn);
     pos - neg
 }
 
 /// Check if a stick is active using circular deadzone
-pub fn is_stick_active(gamepad: &Gamepad, x_axis: Axis, y_axis: Axis) -> bool {
-    let x = gamepad.axis_data(x_axis).map_or(0.0, |d| d.value());
-    let y = gamepad.axis_data(y_axis).map_or(0.0, |d| d.value());
-    (x * x + y * y).sqrt() > DEADZONE
+pub fn is_stick_active(source: &Source, x_axis: Axis, y_axis: Axis, deadzone: f32) -> bool {
+    let x = source.axis_value(x_axis);
+    let y = source.axis_value(y_axis);
+    (x * x + y * y).sqrt() > deadzone
 }
 
 /// Map an axis to its stick pair (X and Y)
@@ -28,6 +49,43 @@ pub fn map_to_stick_pair(axis: Axis) -> Option<(Axis, Axis)> {
     }
 }
 
+/// Check if an axis is a trigger, as some controllers report L2/R2 as axes
+pub fn is_trigger_axis(axis: Axis) -> bool {
+    matches!(axis, Axis::LeftZ | Axis::RightZ)
+}
+
+/// Convert a trigger axis value from gilrs' -1.0..1.0 (rest at -1.0) to 0.0..1.0
+pub fn trigger_axis_value(value: f32) -> f32 {
+    ((value + 1.0) / 2.0).clamp(0.0, 1.0)
+}
+
+/// Read a trigger axis of a controller as 0.0..1.0
+pub fn read_trigger_axis(source: &Source, axis: Axis) -> f32 {
+    source
+        .members()
+        .filter_map(|gamepad| {
+            gamepad
+                .axis_data(axis)
+                .map(|d| trigger_axis_value(d.value()))
+        })
+        .fold(0.0, f32::max)
+}
+
+/// Create an InputEvent for a trigger reported as an axis, from 0.0..1.0
+pub fn create_trigger_axis_event(axis: Axis, value: f32) -> Option<InputEvent> {
+    let ev_axis = evdev_helpers::gilrs_axis_to_evdev_axis(axis)?;
+    Some(create_trigger_event(value, ev_axis))
+}
+
+/// Create an InputEvent for any axis, scaling triggers and sticks accordingly
+pub fn create_axis_event(axis: Axis, value: f32) -> Option<InputEvent> {
+    if is_trigger_axis(axis) {
+        create_trigger_axis_event(axis, trigger_axis_value(value))
+    } else {
+        create_stick_event(axis, value)
+    }
+}
+
 /// Create an InputEvent for a button key press/release
 pub fn create_button_key_event(btn: Button, is_pressed: bool) -> Option<InputEvent> {
     let key = evdev_helpers::gilrs_button_to_evdev_key(btn)?;
@@ -79,14 +137,120 @@ pub fn create_stick_event(axis: Axis, value: f32) -> Option<InputEvent> {
 /// Process a button that maps to an axis (D-pad or trigger)
 pub fn process_button_axis(
     btn: Button,
-    gamepad: &Gamepad,
+    source: &Source,
     abs_axis: evdev::AbsoluteAxisCode,
 ) -> InputEvent {
     if let Some([neg_btn, pos_btn]) = evdev_helpers::dpad_axis_pair(btn) {
-        let net_value = calculate_dpad_net_value(gamepad, neg_btn, pos_btn);
+        let net_value = calculate_dpad_net_value(source, neg_btn, pos_btn);
         create_dpad_event(net_value, neg_btn, pos_btn, abs_axis)
     } else {
-        let value = gamepad.button_data(btn).map_or(0.0, |d| d.value());
+        let value = source.button_value(btn);
         create_trigger_event(value, abs_axis)
     }
 }
+
+/// Synchronize all input states from the given controller
+///
+/// Buttons listed in `skip_buttons` are left out of the snapshot, e.g. when
+/// they are reserved for mode control rather than forwarded to the game.
+pub fn sync_controller_state(active: &Source, skip_buttons: &[Button], out: &mut Vec<InputEvent>) {
+    for gamepad in active.members() {
+        let state = gamepad.state();
+
+        // Synchronize button states, merged across composite members
+        for (code, _) in state.buttons() {
+            let Some(gilrs::ev::AxisOrBtn::Btn(btn)) = gamepad.axis_or_btn_name(code) else {
+                continue;
+            };
+
+            if skip_buttons.contains(&btn) {
+                continue;
+            }
+
+            // Handle buttons mapped to keys
+            if let Some(event) = create_button_key_event(btn, active.is_pressed(btn)) {
+                out.push(event);
+            }
+
+            // Handle buttons mapped to axes (triggers, D-pad)
+            if let Some(abs_axis) = evdev_helpers::gilrs_button_to_evdev_axis(btn) {
+                out.push(process_button_axis(btn, active, abs_axis));
+            }
+        }
+
+        // Synchronize axis states
+        for (code, axis_data) in state.axes() {
+            let Some(gilrs::ev::AxisOrBtn::Axis(axis)) = gamepad.axis_or_btn_name(code) else {
+                continue;
+            };
+
+            if let Some(event) = create_axis_event(axis, axis_data.value()) {
+                out.push(event);
+            }
+        }
+    }
+}
+
+/// Convert a gilrs event from a single controller to evdev events
+pub fn convert_event(event: &Event, active: &Source, out: &mut Vec<InputEvent>) {
+    let converted = match event.event {
+        EventType::ButtonPressed(btn, _) | EventType::ButtonReleased(btn, _) => {
+            let is_pressed = matches!(event.event, EventType::ButtonPressed(..));
+            create_button_key_event(btn, is_pressed)
+        }
+
+        EventType::ButtonChanged(btn, _, _) => evdev_helpers::gilrs_button_to_evdev_axis(btn)
+            .map(|abs_axis| process_button_axis(btn, active, abs_axis)),
+
+        EventType::AxisChanged(axis, raw_val, _) => create_axis_event(axis, raw_val),
+
+        _ => None,
+    };
+    out.extend(converted);
+}
+
+/// Release buttons and center axes a sync frame leaves unset, so nothing a
+/// replaced controller held stays pressed
+pub fn release_unset(out: &mut Vec<InputEvent>) {
+    let is_set = |out: &[InputEvent], kind: evdev::EventType, code: u16| {
+        out.iter()
+            .any(|event| event.event_type() == kind && event.code() == code)
+    };
+    for key in evdev_helpers::VIRTUAL_KEYS {
+        if !is_set(out, evdev::EventType::KEY, key.0) {
+            out.push(InputEvent::new(evdev::EventType::KEY.0, key.0, 0));
+        }
+    }
+    for (axis, rest) in [
+        (
+            evdev::AbsoluteAxisCode::ABS_X,
+            evdev_helpers::AXIS_HALF as i32,
+        ),
+        (
+            evdev::AbsoluteAxisCode::ABS_Y,
+            evdev_helpers::AXIS_HALF as i32,
+        ),
+        (
+            evdev::AbsoluteAxisCode::ABS_RX,
+            evdev_helpers::AXIS_HALF as i32,
+        ),
+        (
+            evdev::AbsoluteAxisCode::ABS_RY,
+            evdev_helpers::AXIS_HALF as i32,
+        ),
+        (
+            evdev::AbsoluteAxisCode::ABS_HAT0X,
+            evdev_helpers::AXIS_HALF as i32,
+        ),
+        (
+            evdev::AbsoluteAxisCode::ABS_HAT0Y,
+            evdev_helpers::AXIS_HALF as i32,
+        ),
+        (evdev::AbsoluteAxisCode::ABS_Z, 0),
+        (evdev::AbsoluteAxisCode::ABS_RZ, 0),
+    ] {
+        if !is_set(out, evdev::EventType::ABSOLUTE, axis.0) {
+            out.push(InputEvent::new(evdev::EventType::ABSOLUTE.0, axis.0, rest));
+        }
+    }
+}
@@ -1,37 +1,263 @@
 pub mod average;
+pub mod blend;
+pub mod dead_man;
 pub mod helpers;
 pub mod priority;
 pub mod toggle;
+pub mod turn;
 
 use evdev::InputEvent;
-use gilrs::{Event, GamepadId};
-use serde::{Deserialize, Serialize};
+use gilrs::{Button, Event, GamepadId};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::gilrs_helper::Sources;
 
 // Enum for all muxing modes
-#[derive(clap::ValueEnum, Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+//
+// Adding a mode takes a variant here, a module implementing `MuxMode`, and an
+// arm in `create_mux_mode`; the CLI, config file, and tray pick it up from
+// this enum. Modes can't be registered from outside the crate, as ctrlassist
+// is built as a binary only, without a library API to register against.
+#[derive(clap::ValueEnum, Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub enum ModeType {
     Average,
+    Blend,
     #[default]
     Priority,
     Toggle,
+    Turn,
+    DeadMan,
+}
+
+/// Button reserved for handing off control in Toggle and Turn modes
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum HandoffButton {
+    #[default]
+    Mode,
+    Start,
+    Select,
+    LeftThumb,
+    RightThumb,
+}
+
+impl HandoffButton {
+    pub fn button(self) -> Button {
+        match self {
+            Self::Mode => Button::Mode,
+            Self::Start => Button::Start,
+            Self::Select => Button::Select,
+            Self::LeftThumb => Button::LeftThumb,
+            Self::RightThumb => Button::RightThumb,
+        }
+    }
+}
+
+/// Read a mode parameter, rejecting NaN and infinities, which TOML allows
+/// but would poison every mixed value
+fn finite<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let value = f32::deserialize(deserializer)?;
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "expected a finite number, found {}",
+            value
+        )))
+    }
+}
+
+/// A Blend weight, which may exceed 1.0 to boost a controller but must be
+/// finite and not negative
+pub fn check_weight(value: f32) -> Result<f32, String> {
+    if value.is_finite() && value >= 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("expected a weight of 0.0 or more, found {}", value))
+    }
+}
+
+/// Read a Blend weight, rejecting what `check_weight` does
+fn weight<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    check_weight(f32::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+/// A Blend weight given as text, e.g. by a flag
+pub fn parse_weight(value: &str) -> Result<f32, String> {
+    check_weight(parse_number(value)?)
+}
+
+/// Average mode's share of the assist, from 0.0 to 1.0
+pub fn check_share(value: f32) -> Result<f32, String> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!(
+            "expected a weight from 0.0 to 1.0, found {}",
+            value
+        ))
+    }
+}
+
+/// Read Average mode's share, rejecting what `check_share` does
+fn share<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    check_share(f32::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+/// Average mode's share given as text, e.g. by `ctrlassist set weight`
+pub fn parse_share(value: &str) -> Result<f32, String> {
+    check_share(parse_number(value)?)
+}
+
+fn parse_number(value: &str) -> Result<f32, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("expected a number, found '{}'", value))
+}
+
+/// Parameters for Average mode
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(default)]
+pub struct AverageOptions {
+    /// Share of the assist in blended sticks and triggers (0.0-1.0)
+    #[serde(deserialize_with = "share")]
+    pub assist_weight: f32,
+    /// Stick and trigger magnitude below which a controller counts as idle
+    #[serde(deserialize_with = "finite")]
+    pub deadzone: f32,
+}
+
+impl Default for AverageOptions {
+    fn default() -> Self {
+        Self {
+            assist_weight: 0.5,
+            deadzone: helpers::DEADZONE,
+        }
+    }
+}
+
+/// Parameters for Blend mode
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(default)]
+pub struct BlendOptions {
+    /// Scale of the primary's sticks and triggers in the mix
+    #[serde(deserialize_with = "weight")]
+    pub primary_weight: f32,
+    /// Scale of the assist's sticks and triggers in the mix
+    #[serde(deserialize_with = "weight")]
+    pub assist_weight: f32,
+}
+
+impl Default for BlendOptions {
+    fn default() -> Self {
+        Self {
+            primary_weight: 0.7,
+            assist_weight: 0.3,
+        }
+    }
+}
+
+/// Parameters for Priority mode
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(default)]
+pub struct PriorityOptions {
+    /// Assist stick and D-pad magnitude above which the assist takes over
+    #[serde(deserialize_with = "finite")]
+    pub deadzone: f32,
+}
+
+impl Default for PriorityOptions {
+    fn default() -> Self {
+        Self {
+            deadzone: helpers::DEADZONE,
+        }
+    }
+}
+
+/// Parameters for Toggle mode
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(default)]
+pub struct ToggleOptions {
+    /// Assist button that toggles control
+    pub button: HandoffButton,
+}
+
+/// Parameters for Turn mode
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(default)]
+pub struct TurnOptions {
+    /// Button the current holder presses to pass control
+    pub button: HandoffButton,
+}
+
+/// Parameters for DeadMan mode
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(default)]
+pub struct DeadManOptions {
+    /// Assist button held to let the primary's input through
+    pub button: HandoffButton,
+}
+
+/// Parameters for every mode, so switching modes live keeps each one's tuning
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(default)]
+pub struct ModeOptions {
+    pub average: AverageOptions,
+    pub blend: BlendOptions,
+    pub priority: PriorityOptions,
+    pub toggle: ToggleOptions,
+    pub turn: TurnOptions,
+    pub dead_man: DeadManOptions,
+}
+
+impl ModeOptions {
+    /// Whether the parameters of `mode` match, ignoring those of other modes
+    pub fn same_for(&self, other: &Self, mode: &ModeType) -> bool {
+        match mode {
+            ModeType::Average => self.average == other.average,
+            ModeType::Blend => self.blend == other.blend,
+            ModeType::Priority => self.priority == other.priority,
+            ModeType::Toggle => self.toggle == other.toggle,
+            ModeType::Turn => self.turn == other.turn,
+            ModeType::DeadMan => self.dead_man == other.dead_man,
+        }
+    }
 }
 
 /// The trait all muxing modes must implement
 pub trait MuxMode {
+    /// Handle an event, appending any resulting events to the `out` buffer
+    ///
+    /// Events from composite partners arrive attributed to their leader, and
+    /// `sources` reads controller state merged across each pair.
     fn handle_event(
         &mut self,
         event: &Event,
         primary_id: GamepadId,
         assist_id: GamepadId,
-        gilrs: &gilrs::Gilrs,
-    ) -> Option<Vec<InputEvent>>;
+        sources: &Sources,
+        out: &mut Vec<InputEvent>,
+    );
+
+    /// Controller currently in exclusive control, for modes that hand off
+    fn active_id(&self) -> Option<GamepadId> {
+        None
+    }
+
+    /// Give exclusive control to `id`, syncing to what it holds, e.g. when
+    /// the holder's controller is lost; modes without a holder ignore this
+    fn hand_to(&mut self, _id: GamepadId, _sources: &Sources, _out: &mut Vec<InputEvent>) {}
 }
 
-/// Factory function to create the correct mux mode
-pub fn create_mux_mode(mode: ModeType) -> Box<dyn MuxMode> {
+/// Factory function to create the correct mux mode with its parameters
+pub fn create_mux_mode(mode: ModeType, options: &ModeOptions) -> Box<dyn MuxMode> {
     match mode {
-        ModeType::Average => Box::new(average::AverageMode),
-        ModeType::Priority => Box::new(priority::PriorityMode),
-        ModeType::Toggle => Box::new(toggle::ToggleMode::default()),
+        ModeType::Average => Box::new(average::AverageMode::new(options.average.clone())),
+        ModeType::Blend => Box::new(blend::BlendMode::new(options.blend.clone())),
+        ModeType::Priority => Box::new(priority::PriorityMode::new(options.priority.clone())),
+        ModeType::Toggle => Box::new(toggle::ToggleMode::new(options.toggle.clone())),
+        ModeType::Turn => Box::new(turn::TurnMode::new(options.turn.clone())),
+        ModeType::DeadMan => Box::new(dead_man::DeadManMode::new(options.dead_man.clone())),
     }
 }
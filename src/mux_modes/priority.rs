@@ -1,10 +1,18 @@
-use super::{MuxMode, helpers};
+use super::{MuxMode, PriorityOptions, helpers};
 use crate::evdev_helpers;
+use crate::gilrs_helper::Sources;
 use evdev::InputEvent;
-use gilrs::{Button, Event, EventType, GamepadId, Gilrs};
+use gilrs::{Button, Event, EventType, GamepadId};
 
-#[derive(Default)]
-pub struct PriorityMode;
+pub struct PriorityMode {
+    options: PriorityOptions,
+}
+
+impl PriorityMode {
+    pub fn new(options: PriorityOptions) -> Self {
+        Self { options }
+    }
+}
 
 impl MuxMode for PriorityMode {
     fn handle_event(
@@ -12,21 +20,22 @@ impl MuxMode for PriorityMode {
         event: &Event,
         primary_id: GamepadId,
         assist_id: GamepadId,
-        gilrs: &Gilrs,
-    ) -> Option<Vec<InputEvent>> {
+        sources: &Sources,
+        out: &mut Vec<InputEvent>,
+    ) {
         // Filter out irrelevant devices
         if event.id != primary_id && event.id != assist_id {
-            return None;
+            return;
         }
 
-        let primary = gilrs.gamepad(primary_id);
-        let assist = gilrs.gamepad(assist_id);
+        let primary = sources.get(primary_id);
+        let assist = sources.get(assist_id);
 
         match event.event {
             EventType::ButtonPressed(btn, _) | EventType::ButtonReleased(btn, _) => {
                 // Skip unknown buttons - they may be mapped to axes instead
                 if btn == Button::Unknown {
-                    return None;
+                    return;
                 }
 
                 let is_pressed = matches!(event.event, EventType::ButtonPressed(..));
@@ -36,21 +45,23 @@ impl MuxMode for PriorityMode {
 
                 // Block primary's event if assist is holding
                 if assist_holding && event.id == primary_id {
-                    return None;
+                    return;
                 }
 
-                helpers::create_button_key_event(btn, is_pressed).map(|e| vec![e])
+                out.extend(helpers::create_button_key_event(btn, is_pressed));
             }
 
             EventType::ButtonChanged(btn, _, _) => {
-                let abs_axis = evdev_helpers::gilrs_button_to_evdev_axis(btn)?;
+                let Some(abs_axis) = evdev_helpers::gilrs_button_to_evdev_axis(btn) else {
+                    return;
+                };
 
                 let event = if let Some([neg_btn, pos_btn]) = evdev_helpers::dpad_axis_pair(btn) {
                     // D-pad: Assist priority
                     let assist_net = helpers::calculate_dpad_net_value(&assist, neg_btn, pos_btn);
                     let primary_net = helpers::calculate_dpad_net_value(&primary, neg_btn, pos_btn);
 
-                    let final_value = if assist_net.abs() > helpers::DEADZONE {
+                    let final_value = if assist_net.abs() > self.options.deadzone {
                         assist_net
                     } else {
                         primary_net
@@ -59,42 +70,53 @@ impl MuxMode for PriorityMode {
                     helpers::create_dpad_event(final_value, neg_btn, pos_btn, abs_axis)
                 } else {
                     // Trigger: Highest value wins
-                    let primary_val = primary.button_data(btn).map_or(0.0, |d| d.value());
-                    let assist_val = assist.button_data(btn).map_or(0.0, |d| d.value());
+                    let primary_val = primary.button_value(btn);
+                    let assist_val = assist.button_value(btn);
                     let max_val = primary_val.max(assist_val);
 
                     helpers::create_trigger_event(max_val, abs_axis)
                 };
 
-                Some(vec![event])
+                out.push(event);
+            }
+
+            EventType::AxisChanged(axis, _, _) if helpers::is_trigger_axis(axis) => {
+                // Trigger axis: Highest value wins, as for trigger buttons
+                let primary_val = helpers::read_trigger_axis(&primary, axis);
+                let assist_val = helpers::read_trigger_axis(&assist, axis);
+
+                out.extend(helpers::create_trigger_axis_event(
+                    axis,
+                    primary_val.max(assist_val),
+                ));
             }
 
             EventType::AxisChanged(axis, _, _) => {
-                let (x_axis, y_axis) = helpers::map_to_stick_pair(axis)?;
+                let Some((x_axis, y_axis)) = helpers::map_to_stick_pair(axis) else {
+                    return;
+                };
 
                 // Check if assist is active on this stick
-                let assist_active = helpers::is_stick_active(&assist, x_axis, y_axis);
+                let assist_active =
+                    helpers::is_stick_active(&assist, x_axis, y_axis, self.options.deadzone);
 
                 // If primary moved but assist is active, ignore
                 if event.id == primary_id && assist_active {
-                    return None;
+                    return;
                 }
 
                 // Determine owner and emit events for both axes
                 let owner = if assist_active { assist } else { primary };
 
-                let events = [x_axis, y_axis]
-                    .into_iter()
-                    .filter_map(|ax| {
-                        let value = owner.axis_data(ax).map_or(0.0, |d| d.value());
-                        helpers::create_stick_event(ax, value)
-                    })
-                    .collect::<Vec<_>>();
+                let events = [x_axis, y_axis].into_iter().filter_map(|ax| {
+                    let value = owner.axis_value(ax);
+                    helpers::create_stick_event(ax, value)
+                });
 
-                (!events.is_empty()).then_some(events)
+                out.extend(events);
             }
 
-            _ => None,
+            _ => {}
         }
     }
 }
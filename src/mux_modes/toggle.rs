@@ -1,78 +1,18 @@
-use super::{MuxMode, helpers};
+use super::{MuxMode, ToggleOptions, helpers};
+use crate::gilrs_helper::Sources;
 use evdev::InputEvent;
-use gilrs::{Event, EventType, GamepadId, Gilrs};
+use gilrs::{Event, EventType, GamepadId};
 
-use crate::evdev_helpers;
-
-#[derive(Default)]
 pub struct ToggleMode {
     active_id: Option<GamepadId>,
+    options: ToggleOptions,
 }
 
 impl ToggleMode {
-    /// Synchronize all input states from the newly active controller
-    fn sync_controller_state(
-        active: gilrs::Gamepad,
-        active_id: GamepadId,
-        assist_id: GamepadId,
-    ) -> Vec<InputEvent> {
-        let state = active.state();
-        let mut events = Vec::new();
-
-        // Synchronize button states
-        for (code, button_data) in state.buttons() {
-            let Some(gilrs::ev::AxisOrBtn::Btn(btn)) = active.axis_or_btn_name(code) else {
-                continue;
-            };
-
-            // Skip Mode button on assist controller for exclusive binding
-            if active_id == assist_id && btn == gilrs::Button::Mode {
-                continue;
-            }
-
-            // Handle buttons mapped to keys
-            if let Some(event) = helpers::create_button_key_event(btn, button_data.is_pressed()) {
-                events.push(event);
-            }
-
-            // Handle buttons mapped to axes (triggers, D-pad)
-            if let Some(abs_axis) = evdev_helpers::gilrs_button_to_evdev_axis(btn) {
-                events.push(helpers::process_button_axis(btn, &active, abs_axis));
-            }
-        }
-
-        // Synchronize axis states
-        for (code, axis_data) in state.axes() {
-            let Some(gilrs::ev::AxisOrBtn::Axis(axis)) = active.axis_or_btn_name(code) else {
-                continue;
-            };
-
-            if let Some(event) = helpers::create_stick_event(axis, axis_data.value()) {
-                events.push(event);
-            }
-        }
-
-        events
-    }
-
-    /// Convert a gilrs event to evdev events
-    fn convert_event(event: &Event, active: gilrs::Gamepad) -> Option<Vec<InputEvent>> {
-        match event.event {
-            EventType::ButtonPressed(btn, _) | EventType::ButtonReleased(btn, _) => {
-                let is_pressed = matches!(event.event, EventType::ButtonPressed(..));
-                helpers::create_button_key_event(btn, is_pressed).map(|e| vec![e])
-            }
-
-            EventType::ButtonChanged(btn, _, _) => {
-                let abs_axis = evdev_helpers::gilrs_button_to_evdev_axis(btn)?;
-                Some(vec![helpers::process_button_axis(btn, &active, abs_axis)])
-            }
-
-            EventType::AxisChanged(axis, raw_val, _) => {
-                helpers::create_stick_event(axis, raw_val).map(|e| vec![e])
-            }
-
-            _ => None,
+    pub fn new(options: ToggleOptions) -> Self {
+        Self {
+            active_id: None,
+            options,
         }
     }
 }
@@ -83,14 +23,16 @@ impl MuxMode for ToggleMode {
         event: &Event,
         primary_id: GamepadId,
         assist_id: GamepadId,
-        gilrs: &Gilrs,
-    ) -> Option<Vec<InputEvent>> {
+        sources: &Sources,
+        out: &mut Vec<InputEvent>,
+    ) {
+        let toggle_button = self.options.button.button();
         let active_id = self.active_id.get_or_insert(primary_id);
 
         // Handle toggle logic
         if matches!(
             (event.id, event.event),
-            (id, EventType::ButtonPressed(gilrs::Button::Mode, _)) if id == assist_id
+            (id, EventType::ButtonPressed(btn, _)) if id == assist_id && btn == toggle_button
         ) {
             *active_id = if *active_id == primary_id {
                 assist_id
@@ -98,16 +40,33 @@ impl MuxMode for ToggleMode {
                 primary_id
             };
 
-            let active = gilrs.gamepad(*active_id);
-            return Some(Self::sync_controller_state(active, *active_id, assist_id));
+            // Skip toggle button on assist controller for exclusive binding
+            let skip_buttons: &[gilrs::Button] = if *active_id == assist_id {
+                &[toggle_button]
+            } else {
+                &[]
+            };
+            let active = sources.get(*active_id);
+            helpers::sync_controller_state(&active, skip_buttons, out);
+            return;
         }
 
         // Only forward events from the active controller
         if event.id != *active_id {
-            return None;
+            return;
         }
 
-        let active = gilrs.gamepad(*active_id);
-        Self::convert_event(event, active)
+        let active = sources.get(*active_id);
+        helpers::convert_event(event, &active, out);
+    }
+
+    fn active_id(&self) -> Option<GamepadId> {
+        self.active_id
+    }
+
+    fn hand_to(&mut self, id: GamepadId, sources: &Sources, out: &mut Vec<InputEvent>) {
+        self.active_id = Some(id);
+        let toggle_button = self.options.button.button();
+        helpers::sync_controller_state(&sources.get(id), &[toggle_button], out);
     }
 }
@@ -0,0 +1,71 @@
+use super::{MuxMode, TurnOptions, helpers};
+use crate::gilrs_helper::Sources;
+use evdev::InputEvent;
+use gilrs::{Event, EventType, GamepadId};
+
+pub struct TurnMode {
+    holder_id: Option<GamepadId>,
+    options: TurnOptions,
+}
+
+impl TurnMode {
+    pub fn new(options: TurnOptions) -> Self {
+        Self {
+            holder_id: None,
+            options,
+        }
+    }
+}
+
+impl MuxMode for TurnMode {
+    fn handle_event(
+        &mut self,
+        event: &Event,
+        primary_id: GamepadId,
+        assist_id: GamepadId,
+        sources: &Sources,
+        out: &mut Vec<InputEvent>,
+    ) {
+        let pass_button = self.options.button.button();
+        let holder_id = self.holder_id.get_or_insert(primary_id);
+
+        // Only the current holder may act, including passing control
+        if event.id != *holder_id {
+            return;
+        }
+
+        // Handle pass logic
+        if matches!(event.event, EventType::ButtonPressed(btn, _) if btn == pass_button) {
+            *holder_id = if *holder_id == primary_id {
+                assist_id
+            } else {
+                primary_id
+            };
+
+            let holder = sources.get(*holder_id);
+            helpers::sync_controller_state(&holder, &[pass_button], out);
+            return;
+        }
+
+        // Pass button is reserved for handoff on either controller
+        if matches!(
+            event.event,
+            EventType::ButtonReleased(btn, _) | EventType::ButtonChanged(btn, ..) if btn == pass_button
+        ) {
+            return;
+        }
+
+        let holder = sources.get(*holder_id);
+        helpers::convert_event(event, &holder, out);
+    }
+
+    fn active_id(&self) -> Option<GamepadId> {
+        self.holder_id
+    }
+
+    fn hand_to(&mut self, id: GamepadId, sources: &Sources, out: &mut Vec<InputEvent>) {
+        self.holder_id = Some(id);
+        let pass_button = self.options.button.button();
+        helpers::sync_controller_state(&sources.get(id), &[pass_button], out);
+    }
+}
@@ -1,31 +1,203 @@
-use crate::RumbleTarget;
-use crate::ff_helpers::PhysicalFFDev;
-use crate::gilrs_helper::GamepadResource;
+use crate::button_filters::{ButtonFilters, ConfirmConfig, DebounceConfig, HoldTimingConfig};
+use crate::consumers::Consumer;
+use crate::disconnect_policy::{self, DisconnectWatch};
+use crate::evdev_helpers;
+use crate::ff_helpers::{FfHealth, FfWaker, MotorMaps, PhysicalFFDev, RumbleChannel};
+use crate::frame_queue::{self, FrameReceiver};
+use crate::gilrs_helper::{
+    self, CompositeSources, GamepadIdentity, GamepadResource, GilrsConfig, Sources,
+};
+use crate::gyro_aim::GyroAim;
+use crate::handoff_history::{HandoffHistory, Holder};
+use crate::hooks::{HookConfig, HookEvent};
+use crate::input_delay::{DelayConfig, InputDelay};
+use crate::mode_button::ModeButtonConfig;
+use crate::mouse_aim::MouseAim;
 use crate::mux_modes;
-use crate::mux_modes::ModeType;
+use crate::mux_modes::helpers::{self, AxisCache};
+use crate::mux_modes::{ModeOptions, ModeType};
+use crate::notifier::{self, SessionEvent};
+use crate::output_backend::OutputBackend;
+use crate::overlay_pause::{OverlayPause, PauseChange, PauseOutput};
+use crate::pad_menu::{MenuAction, PadMenu};
+use crate::quirks::DeviceQuirks;
+use crate::session_timer::SessionTimer;
+use crate::sibling_nodes::SiblingInput;
+use crate::stick_filters::{SmoothingConfig, StickSmoother};
+use crate::trace_helpers::{LatencyRecorder, LatencyStats, Span, Tracer};
+use crate::trackpad_input::TrackpadInput;
+use crate::{PaddleBinding, RumbleTarget};
+use evdev::InputEvent;
 use evdev::uinput::VirtualDevice;
-use evdev::{Device, EventType, InputEvent};
 use gilrs::{GamepadId, Gilrs};
 use log::{debug, error, info, warn};
-use parking_lot::RwLock;
-use std::collections::HashMap;
-use std::sync::Arc;
+use parking_lot::{Mutex, RwLock};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
 
 const NEXT_EVENT_TIMEOUT: Duration = Duration::from_millis(1000);
+const VIRTUAL_DEV_TIMEOUT: Duration = Duration::from_secs(2);
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+/// Initial capacity for reusable event buffers, enough for a full state sync
+const EVENT_BUFFER_CAPACITY: usize = 64;
+/// How soon the intake retries queuing frames while the writer is behind
+const BACKLOG_RETRY: Duration = Duration::from_millis(1);
+
+/// Event loop timing parameters, in milliseconds, clamped to sane bounds on use
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct TimingConfig {
+    /// How long the input loop blocks for events before checking for shutdown
+    pub event_timeout_ms: u64,
+    /// How long to wait for the virtual device node to appear
+    pub virtual_device_timeout_ms: u64,
+    /// Delay between attempts to open the virtual device node
+    pub retry_interval_ms: u64,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            event_timeout_ms: NEXT_EVENT_TIMEOUT.as_millis() as u64,
+            virtual_device_timeout_ms: VIRTUAL_DEV_TIMEOUT.as_millis() as u64,
+            retry_interval_ms: RETRY_INTERVAL.as_millis() as u64,
+        }
+    }
+}
+
+impl TimingConfig {
+    pub fn event_timeout(&self) -> Duration {
+        Duration::from_millis(self.event_timeout_ms.clamp(10, 5_000))
+    }
+
+    pub fn virtual_device_timeout(&self) -> Duration {
+        Duration::from_millis(self.virtual_device_timeout_ms.clamp(500, 30_000))
+    }
+
+    pub fn retry_interval(&self) -> Duration {
+        Duration::from_millis(self.retry_interval_ms.clamp(5, 1_000))
+    }
+}
+
+/// Session options for the input loop that don't change while running
+pub struct InputOptions {
+    pub hooks: HookConfig,
+    pub event_timeout: Duration,
+    pub gilrs: GilrsConfig,
+    pub paddles: PaddleBinding,
+    pub debounce: DebounceConfig,
+    pub delay: DelayConfig,
+    pub hold: HoldTimingConfig,
+    /// Buttons needing a second press to go through
+    pub confirm: ConfirmConfig,
+    pub smoothing: SmoothingConfig,
+    pub gyro: GyroAim,
+    pub mouse: MouseAim,
+    pub trackpad: TrackpadInput,
+    pub siblings: SiblingInput,
+    pub composites: CompositeSources,
+    /// What each source member held at startup, read from evdev
+    pub held: HashMap<GamepadId, Vec<gilrs::EventType>>,
+    /// Oddities of the source controllers, corrected as events arrive
+    pub quirks: DeviceQuirks,
+    /// Where output frames are presented, e.g. the uinput device
+    pub outputs: Vec<Box<dyn OutputBackend>>,
+    /// Menu opened by a chord on either controller, if enabled
+    pub menu: Option<PadMenu>,
+    /// Freezing of output while a system chord is held, if enabled
+    pub pause: Option<OverlayPause>,
+    /// What each source's Mode button emits
+    pub mode_button: ModeButtonConfig,
+    /// Countdown to the session's time limit, if set
+    pub timer: Option<SessionTimer>,
+    /// Lost source controllers whose inputs are yet to be let go
+    pub disconnects: DisconnectWatch,
+}
+
+/// Session options for the FF loop that don't change while running
+pub struct FfOptions {
+    /// Partners rumbling along with their leader, e.g. the other Joy-Con
+    pub composites: CompositeSources,
+    /// How each rumble target's motors are driven
+    pub motors: MotorMaps,
+}
 
 /// Runtime-updatable mux settings
+#[derive(Clone)]
 pub struct RuntimeSettings {
     pub mode: Arc<RwLock<ModeType>>,
     pub rumble: Arc<RwLock<RumbleTarget>>,
+    pub smoothing: Arc<RwLock<bool>>,
+    pub mode_options: Arc<RwLock<ModeOptions>>,
+    /// Per-stage latency histograms, collected for the whole session
+    pub latency: Arc<LatencyRecorder>,
+    /// Force feedback health of each rumble target
+    pub ff_health: Arc<Mutex<FfHealth>>,
+    /// System hiding awaiting confirmation before it's rolled back
+    pub hide_pending: Arc<AtomicBool>,
+    /// Current assist, replaced by the input loop when requested
+    pub assist: Arc<RwLock<AssistSource>>,
+    /// Replacement assist requested while running, taken by the input loop
+    pub assist_request: Arc<Mutex<Option<GamepadId>>>,
+    /// Connected controllers the assist may be replaced with, by name
+    pub assist_candidates: Arc<RwLock<Vec<(GamepadId, String)>>>,
+    /// Controller in exclusive control, for modes that hand off
+    pub active: Arc<RwLock<Option<GamepadId>>>,
+    /// Who held exclusive control over the session, and for how long
+    pub handoffs: Arc<Mutex<HandoffHistory>>,
+    /// Source controllers, or their partners, currently disconnected
+    pub disconnected: Arc<Mutex<HashSet<GamepadId>>>,
+    /// Other processes with the virtual device open, e.g. Steam or a game
+    pub consumers: Arc<RwLock<Vec<Consumer>>>,
+    /// Stop asked for from within the session, e.g. the controller menu
+    pub stop_requested: Arc<AtomicBool>,
+    /// Rumble pulse asked for on the rumble targets, e.g. a time limit warning
+    pub alert_requested: Arc<AtomicBool>,
+    /// Wakes the FF loop to act on requests without waiting on the game
+    pub ff_waker: Arc<FfWaker>,
+}
+
+/// The assist controller, with its device for rumble once replaced
+pub struct AssistSource {
+    pub id: GamepadId,
+    /// Set for replacements, which may have connected after the session started
+    pub resource: Option<GamepadResource>,
 }
 
 impl RuntimeSettings {
-    pub fn new(mode: ModeType, rumble: RumbleTarget) -> Self {
+    pub fn new(
+        mode: ModeType,
+        mode_options: ModeOptions,
+        rumble: RumbleTarget,
+        smoothing: bool,
+        assist_id: GamepadId,
+    ) -> Self {
         Self {
+            assist: Arc::new(RwLock::new(AssistSource {
+                id: assist_id,
+                resource: None,
+            })),
+            assist_request: Arc::new(Mutex::new(None)),
+            assist_candidates: Arc::new(RwLock::new(Vec::new())),
+            active: Arc::new(RwLock::new(None)),
+            handoffs: Arc::new(Mutex::new(HandoffHistory::default())),
+            disconnected: Arc::new(Mutex::new(HashSet::new())),
+            consumers: Arc::new(RwLock::new(Vec::new())),
+            stop_requested: Arc::new(AtomicBool::new(false)),
+            alert_requested: Arc::new(AtomicBool::new(false)),
+            ff_waker: Arc::new(FfWaker::new()),
             mode: Arc::new(RwLock::new(mode)),
+            mode_options: Arc::new(RwLock::new(mode_options)),
+            latency: Arc::new(LatencyRecorder::default()),
+            ff_health: Arc::new(Mutex::new(FfHealth::default())),
+            hide_pending: Arc::new(AtomicBool::new(false)),
             rumble: Arc::new(RwLock::new(rumble)),
+            smoothing: Arc::new(RwLock::new(smoothing)),
         }
     }
 
@@ -34,93 +206,775 @@ impl RuntimeSettings {
         *mode = new_mode;
     }
 
+    pub fn update_mode_options(&self, new_options: ModeOptions) {
+        let mut mode_options = self.mode_options.write();
+        *mode_options = new_options;
+    }
+
+    /// Change Blend mode's weights, applied from the next event
+    pub fn update_blend_weights(&self, primary: f32, assist: f32) -> Result<(), String> {
+        let primary = mux_modes::check_weight(primary)?;
+        let assist = mux_modes::check_weight(assist)?;
+        let mut mode_options = self.mode_options.write();
+        mode_options.blend.primary_weight = primary;
+        mode_options.blend.assist_weight = assist;
+        Ok(())
+    }
+
     pub fn update_rumble(&self, new_rumble: RumbleTarget) {
         let mut rumble = self.rumble.write();
         *rumble = new_rumble;
+        self.ff_waker.wake();
+    }
+
+    pub fn update_smoothing(&self, enabled: bool) {
+        let mut smoothing = self.smoothing.write();
+        *smoothing = enabled;
     }
 
     pub fn get_mode(&self) -> ModeType {
         self.mode.read().clone()
     }
 
+    pub fn get_mode_options(&self) -> ModeOptions {
+        self.mode_options.read().clone()
+    }
+
     pub fn get_rumble(&self) -> RumbleTarget {
         self.rumble.read().clone()
     }
+
+    pub fn get_smoothing(&self) -> bool {
+        *self.smoothing.read()
+    }
+
+    /// Take on the settings changed while an earlier session ran, e.g. one
+    /// restarted after system sleep
+    pub fn resync_from(&self, earlier: &RuntimeSettings) {
+        self.update_mode(earlier.get_mode());
+        self.update_mode_options(earlier.get_mode_options());
+        self.update_rumble(earlier.get_rumble());
+        self.update_smoothing(earlier.get_smoothing());
+    }
+
+    pub fn get_latency(&self) -> LatencyStats {
+        self.latency.snapshot()
+    }
+
+    pub fn get_ff_health(&self) -> FfHealth {
+        self.ff_health.lock().clone()
+    }
+
+    /// Keep System hiding for the session, returning whether it was pending
+    pub fn confirm_hide(&self) -> bool {
+        self.hide_pending.swap(false, Ordering::SeqCst)
+    }
+
+    pub fn is_hide_pending(&self) -> bool {
+        self.hide_pending.load(Ordering::SeqCst)
+    }
+
+    /// Ask the input loop to replace the assist, keeping the virtual device
+    pub fn request_assist(&self, id: GamepadId) {
+        *self.assist_request.lock() = Some(id);
+    }
+
+    pub fn get_assist_id(&self) -> GamepadId {
+        self.assist.read().id
+    }
+
+    pub fn get_assist_candidates(&self) -> Vec<(GamepadId, String)> {
+        self.assist_candidates.read().clone()
+    }
+
+    pub fn get_active_id(&self) -> Option<GamepadId> {
+        *self.active.read()
+    }
+
+    /// Publish the controller in exclusive control, noting the handoff
+    pub fn set_active(&self, active: Option<GamepadId>) {
+        *self.active.write() = active;
+        let holder = active.map(|id| {
+            if id == self.get_assist_id() {
+                Holder::Assist
+            } else {
+                Holder::Primary
+            }
+        });
+        self.handoffs.lock().record(holder, Instant::now());
+    }
+
+    /// Recent handoffs and time in control, for display
+    pub fn describe_handoffs(&self) -> String {
+        self.handoffs.lock().describe(Instant::now())
+    }
+
+    /// Whether a source controller is disconnected
+    pub fn has_disconnected(&self) -> bool {
+        !self.disconnected.lock().is_empty()
+    }
+
+    /// Whether a source controller is disconnected or rumble is failing
+    pub fn is_degraded(&self) -> bool {
+        self.has_disconnected()
+            || self
+                .ff_health
+                .lock()
+                .targets
+                .values()
+                .any(|target| !target.is_healthy())
+    }
+
+    pub fn get_consumers(&self) -> Vec<Consumer> {
+        self.consumers.read().clone()
+    }
+
+    /// End the session from within, as a stop rather than a failure
+    pub fn request_stop(&self) {
+        self.stop_requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Briefly rumble the rumble targets, to get the players' attention
+    pub fn request_alert(&self) {
+        self.alert_requested.store(true, Ordering::SeqCst);
+        self.ff_waker.wake();
+    }
 }
 
+/// Run the mux: an intake stage here, polling gilrs and running filters and
+/// the mux mode, and a writer thread it queues output frames to
+///
+/// Slow uinput or uhid writes only grow the queue, so gilrs keeps being
+/// drained even under burst load.
 pub fn run_input_loop(
     mut gilrs: Gilrs,
-    mut v_dev: Device,
     runtime_settings: Arc<RuntimeSettings>,
     p_id: GamepadId,
-    a_id: GamepadId,
+    mut a_id: GamepadId,
     shutdown: Arc<AtomicBool>,
-) {
-    let mut mux_mode = mux_modes::create_mux_mode(runtime_settings.get_mode());
+    options: InputOptions,
+) -> Gilrs {
+    let InputOptions {
+        hooks,
+        event_timeout,
+        gilrs: gilrs_config,
+        paddles,
+        debounce,
+        delay,
+        hold,
+        confirm,
+        smoothing,
+        mut gyro,
+        mut mouse,
+        mut trackpad,
+        mut siblings,
+        composites,
+        held,
+        mut quirks,
+        outputs,
+        mut menu,
+        mut pause,
+        mode_button,
+        mut timer,
+        mut disconnects,
+    } = options;
+
+    let tracer = Tracer::new(Arc::clone(&runtime_settings.latency));
+    let (mut frames, frames_rx) = frame_queue::frame_queue();
+    let writer_tracer = tracer.clone();
+    let writer = std::thread::spawn(move || {
+        run_writer(outputs, frames_rx, writer_tracer);
+    });
+
+    let mut input_delay = InputDelay::new(&delay, p_id, a_id);
+    let mut button_filters = ButtonFilters::new(&debounce, &hold, &confirm, p_id, a_id);
+    // Events released by the input delay, before the button filters
+    let mut delayed = Vec::new();
+    let mut smoother = StickSmoother::new(&smoothing, p_id, a_id);
+    // Events ready for the mux mode, either fresh or released by a filter timer
+    let mut ready = Vec::new();
+    // Lost controllers whose inputs are let go, refilled each iteration
+    let mut released = Vec::new();
+
     let mut last_mode = runtime_settings.get_mode();
+    let mut last_options = runtime_settings.get_mode_options();
+    let mut mux_mode = mux_modes::create_mux_mode(last_mode.clone(), &last_options);
+    // Kept across mode switches, as it mirrors the virtual device's axes
+    let mut axis_cache = AxisCache::default();
+
+    // Scratch buffer reused across events to avoid per-event allocations
+    let mut out_events = Vec::with_capacity(EVENT_BUFFER_CAPACITY);
+
+    // Start the virtual device in sync with anything already held
+    initial_sync_events(
+        mux_mode.as_mut(),
+        &Sources::new(&gilrs, &composites),
+        &held,
+        p_id,
+        a_id,
+        &mut out_events,
+    );
+    axis_cache.retain_changed(&mut out_events);
+    debug!("Sending initial sync frame of {} events", out_events.len());
+    frames.send(&mut out_events);
+    let discovery = Discovery::spawn(Arc::clone(&runtime_settings));
+    discovery.publish_candidates(&gilrs, &composites, p_id);
 
     while !shutdown.load(Ordering::SeqCst) {
-        // Check for mode changes
+        // Check for mode or mode parameter changes
         let current_mode = runtime_settings.get_mode();
-        if current_mode != last_mode {
+        let current_options = runtime_settings.get_mode_options();
+        // Other modes' parameters, e.g. tuned from the tray, leave it be
+        if current_mode != last_mode || !current_options.same_for(&last_options, &current_mode) {
             info!(
                 "Switching mux mode from {:?} to {:?}",
                 last_mode, current_mode
             );
-            mux_mode = mux_modes::create_mux_mode(current_mode.clone());
+            mux_mode = mux_modes::create_mux_mode(current_mode.clone(), &current_options);
+            runtime_settings.set_active(mux_mode.active_id());
             last_mode = current_mode;
+
+            // The new mode starts from what's held now, not from rest
+            out_events.clear();
+            initial_sync_events(
+                mux_mode.as_mut(),
+                &Sources::new(&gilrs, &composites),
+                &held,
+                p_id,
+                a_id,
+                &mut out_events,
+            );
+            helpers::release_unset(&mut out_events);
+            axis_cache.retain_changed(&mut out_events);
+            frames.send(&mut out_events);
+        }
+        last_options = current_options;
+
+        // Replace the assist if requested, keeping the virtual device the
+        // game is attached to
+        let requested = runtime_settings.assist_request.lock().take();
+        if let Some(new_assist) = requested.filter(|&id| id != a_id && id != p_id) {
+            discovery.find(&gilrs, new_assist);
+        }
+        // Its event device is matched off this thread, so the replacement
+        // happens once it's found
+        let found = discovery.try_found();
+        if let Some((new_assist, resource)) = found.filter(|&(id, _)| id != a_id && id != p_id) {
+            info!("Replacing assist {} with {}", a_id, new_assist);
+            if let Some(resource) = &resource {
+                quirks.insert(new_assist, resource);
+            }
+            *runtime_settings.assist.write() = AssistSource {
+                id: new_assist,
+                resource,
+            };
+            // Moves rumble, playing effects included, over to it right away
+            runtime_settings.ff_waker.wake();
+            input_delay.replace(a_id, new_assist);
+            // A lost assist no longer degrades the session once replaced
+            runtime_settings.disconnected.lock().remove(&a_id);
+            a_id = new_assist;
+            button_filters = ButtonFilters::new(&debounce, &hold, &confirm, p_id, a_id);
+            smoother = StickSmoother::new(&smoothing, p_id, a_id);
+            mux_mode = mux_modes::create_mux_mode(last_mode.clone(), &last_options);
+            runtime_settings.set_active(mux_mode.active_id());
+
+            // Resync to what's held now, releasing whatever the old assist held
+            out_events.clear();
+            initial_sync_events(
+                mux_mode.as_mut(),
+                &Sources::new(&gilrs, &composites),
+                &held,
+                p_id,
+                a_id,
+                &mut out_events,
+            );
+            helpers::release_unset(&mut out_events);
+            axis_cache.retain_changed(&mut out_events);
+            frames.send(&mut out_events);
+        }
+
+        // Aim with the gyro, mouse, and trackpad, then advance smoothed sticks, snapping
+        // them into place if smoothing was just disabled
+        let now = Instant::now();
+        out_events.clear();
+        gyro.tick(&Sources::new(&gilrs, &composites), now, &mut out_events);
+        mouse.process(&mut out_events);
+        mouse.tick(now, &mut out_events);
+        trackpad.process(&mut out_events);
+        trackpad.tick(now, &mut out_events);
+        smoother.process(a_id, &mut out_events, now);
+        smoother.set_enabled(runtime_settings.get_smoothing(), &mut out_events);
+        smoother.tick(now, &mut out_events);
+        if !frames.send(&mut out_events) {
+            error!("Virtual device writer stopped, ending session");
+            shutdown.store(true, Ordering::SeqCst);
+            break;
+        }
+        if let Some(timer) = &mut timer
+            && timer.tick(now, &runtime_settings)
+        {
+            shutdown.store(true, Ordering::SeqCst);
+            break;
+        }
+
+        // Release events held back by the input delay, then transitions held
+        // back by the button filters, once due
+        input_delay.expire(now, &mut delayed);
+        for event in delayed.drain(..) {
+            ready.extend(button_filters.filter(event, now));
+        }
+        button_filters.expire(now, &mut ready);
+        siblings.tick(&gilrs, now, &mut ready);
+
+        // Let go of what lost controllers held, once any hold runs out
+        released.clear();
+        disconnects.expire(now, &mut released);
+        for &id in &released {
+            let leader = composites.leader_of(id);
+            if leader != p_id && leader != a_id {
+                continue;
+            }
+            info!("Releasing inputs of lost controller {}", id);
+            disconnect_policy::release_events(&gilrs, id, &mut ready);
+            if disconnects.transfers() && mux_mode.active_id() == Some(leader) {
+                let survivor = if leader == p_id { a_id } else { p_id };
+                info!("Transferring control to controller {}", survivor);
+                out_events.clear();
+                mux_mode.hand_to(
+                    survivor,
+                    &Sources::new(&gilrs, &composites),
+                    &mut out_events,
+                );
+                helpers::release_unset(&mut out_events);
+                axis_cache.retain_changed(&mut out_events);
+                frames.send(&mut out_events);
+                runtime_settings.set_active(mux_mode.active_id());
+                hooks.run(
+                    HookEvent::Handoff,
+                    &[("CTRLASSIST_ACTIVE", survivor.to_string())],
+                );
+            }
+        }
+        for event in &ready {
+            gilrs.update(event);
+        }
+
+        if ready.is_empty() {
+            // Wake up in time for the next held-back transition or stick update
+            let timeout = [
+                input_delay.next_deadline(),
+                button_filters.next_deadline(),
+                smoother.next_deadline(),
+                gyro.next_deadline(),
+                mouse.next_deadline(),
+                trackpad.next_deadline(),
+                siblings.next_deadline(),
+                timer.as_ref().and_then(SessionTimer::next_deadline),
+                disconnects.next_deadline(),
+                // Retry queuing frames the writer had no room for
+                frames.has_backlog().then(|| now + BACKLOG_RETRY),
+            ]
+            .into_iter()
+            .flatten()
+            .min()
+            .map_or(event_timeout, |deadline| {
+                deadline.saturating_duration_since(now).min(event_timeout)
+            });
+            let next = gilrs_helper::next_event_with(
+                &mut gilrs,
+                &gilrs_config,
+                &quirks,
+                Some(timeout),
+                |event| {
+                    let now = Instant::now();
+                    let leader = composites.leader_of(event.id);
+                    let event = input_delay.filter(event, leader, now)?;
+                    button_filters.filter(event, now)
+                },
+            );
+            match next {
+                Some(event) => ready.push(event),
+                None => continue,
+            }
         }
 
-        while let Some(event) = gilrs.next_event_blocking(Some(NEXT_EVENT_TIMEOUT)) {
+        for mut event in ready.drain(..) {
             if shutdown.load(Ordering::SeqCst) {
                 break;
             }
-            if event.id != p_id && event.id != a_id {
+            if matches!(
+                event.event,
+                gilrs::EventType::Connected | gilrs::EventType::Disconnected
+            ) {
+                discovery.publish_candidates(&gilrs, &composites, p_id);
+            }
+            let leader = composites.leader_of(event.id);
+            if leader != p_id && leader != a_id {
                 continue;
             }
-            if let Some(mut out_events) = mux_mode.handle_event(&event, p_id, a_id, &gilrs)
-                && !out_events.is_empty()
+            if event.event == gilrs::EventType::Disconnected {
+                warn!("Controller {} disconnected", event.id);
+                runtime_settings.disconnected.lock().insert(event.id);
+                disconnects.lost(event.id, Instant::now());
+                hooks.run(
+                    HookEvent::Disconnect,
+                    &[("CTRLASSIST_CONTROLLER", event.id.to_string())],
+                );
+                notifier::post_event(
+                    SessionEvent::Disconnect,
+                    format!("Controller {} disconnected", event.id),
+                );
+            } else if event.event == gilrs::EventType::Connected {
+                runtime_settings.disconnected.lock().remove(&event.id);
+                disconnects.reconnected(event.id);
+            }
+            // Composite partners act as their leader from here on
+            event.id = leader;
+            if paddles == PaddleBinding::Mode {
+                bind_paddle_to_mode(&mut event);
+            }
+
+            // The menu takes over both controllers while open
+            if let Some(menu) = &mut menu {
+                match menu.handle(&event, &runtime_settings) {
+                    MenuAction::Pass => {}
+                    MenuAction::Opened => {
+                        out_events.clear();
+                        helpers::release_unset(&mut out_events);
+                        axis_cache.retain_changed(&mut out_events);
+                        frames.send(&mut out_events);
+                        continue;
+                    }
+                    MenuAction::Consumed | MenuAction::Closed => {
+                        if runtime_settings.stop_requested.load(Ordering::SeqCst) {
+                            shutdown.store(true, Ordering::SeqCst);
+                        }
+                        continue;
+                    }
+                }
+            }
+
+            // Freeze output while the system chord is held, so nothing leaks
+            // into an overlay opened with it
+            if let Some(pause) = &mut pause {
+                match pause.update(&event) {
+                    PauseChange::Paused if pause.output == PauseOutput::Release => {
+                        out_events.clear();
+                        helpers::release_unset(&mut out_events);
+                        axis_cache.retain_changed(&mut out_events);
+                        frames.send(&mut out_events);
+                    }
+                    PauseChange::Resumed => {
+                        // Catch up with what changed while paused
+                        out_events.clear();
+                        initial_sync_events(
+                            mux_mode.as_mut(),
+                            &Sources::new(&gilrs, &composites),
+                            &held,
+                            p_id,
+                            a_id,
+                            &mut out_events,
+                        );
+                        helpers::release_unset(&mut out_events);
+                        axis_cache.retain_changed(&mut out_events);
+                        frames.send(&mut out_events);
+                    }
+                    _ => {}
+                }
+                if pause.holds_back(&event) {
+                    continue;
+                }
+            }
+
+            tracer.record_receive(event.time);
+            let mode_span = tracing::trace_span!("mode", controller = %event.id).entered();
+            let mode_start = Instant::now();
+
+            let active_before = mux_mode.active_id();
+            out_events.clear();
+            let sources = Sources::new(&gilrs, &composites);
+            mux_mode.handle_event(&event, p_id, a_id, &sources, &mut out_events);
+            mode_button.apply(&event, p_id, a_id, &mut out_events);
+            axis_cache.retain_changed(&mut out_events);
+            gyro.process(&mut out_events);
+            mouse.process(&mut out_events);
+            trackpad.process(&mut out_events);
+            smoother.process(event.id, &mut out_events, mode_start);
+
+            frames.send(&mut out_events);
+            tracer.record(Span::Mode, mode_start.elapsed());
+            drop(mode_span);
+
+            // Report handoffs from modes with an exclusive active controller
+            if mux_mode.active_id() != active_before {
+                runtime_settings.set_active(mux_mode.active_id());
+            }
+            if let Some(active) = mux_mode.active_id()
+                && active_before.is_some_and(|before| before != active)
             {
-                out_events.push(InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0));
-                if let Err(e) = v_dev.send_events(&out_events) {
-                    error!("Failed to write input events: {}", e);
+                hooks.run(
+                    HookEvent::Handoff,
+                    &[("CTRLASSIST_ACTIVE", active.to_string())],
+                );
+            }
+        }
+    }
+
+    // Let the writer drain what's queued and backlogged, then end with the intake
+    frames.finish();
+    if let Err(panic) = writer.join() {
+        std::panic::resume_unwind(panic);
+    }
+    gilrs
+}
+
+/// Write each queued frame to the virtual device until the intake stops
+fn run_writer(mut outputs: Vec<Box<dyn OutputBackend>>, frames: FrameReceiver, tracer: Tracer) {
+    while let Some(frame) = frames.recv() {
+        let _span = tracing::trace_span!("write", events = frame.len()).entered();
+        let start = Instant::now();
+        for output in &mut outputs {
+            if let Err(e) = output.write_frame(&frame) {
+                error!("Failed to write input events to {}: {}", output.name(), e);
+            }
+        }
+        tracer.record(Span::Write, start.elapsed());
+        frames.recycle(frame);
+    }
+}
+
+/// Matches event devices for the input loop on a thread of its own, as
+/// opening every node can block for a while on busy ones
+struct Discovery {
+    requests: Option<mpsc::Sender<DiscoveryRequest>>,
+    found: mpsc::Receiver<(GamepadId, Option<GamepadResource>)>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+enum DiscoveryRequest {
+    /// Share these gamepads as assist candidates, less virtual ones
+    Candidates(Vec<GamepadIdentity>),
+    /// Find the event device of a replacement assist among all gamepads, so
+    /// identical controllers are told apart as they were at startup
+    Assist(GamepadId, Vec<GamepadIdentity>),
+}
+
+impl Discovery {
+    fn spawn(settings: Arc<RuntimeSettings>) -> Self {
+        let (requests, requests_rx) = mpsc::channel();
+        let (found_tx, found) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            for request in requests_rx {
+                match request {
+                    DiscoveryRequest::Candidates(gamepads) => {
+                        let resources = gilrs_helper::match_gamepad_resources(&gamepads);
+                        let candidates = gamepads
+                            .into_iter()
+                            .filter(|g| !resources.get(&g.id).is_some_and(|r| r.is_ctrlassist()))
+                            .map(|g| (g.id, g.name))
+                            .collect();
+                        *settings.assist_candidates.write() = candidates;
+                    }
+                    DiscoveryRequest::Assist(id, gamepads) => {
+                        let resource = gilrs_helper::match_gamepad_resources(&gamepads).remove(&id);
+                        if found_tx.send((id, resource)).is_err() {
+                            break;
+                        }
+                    }
                 }
             }
+        });
+        Self {
+            requests: Some(requests),
+            found,
+            thread: Some(thread),
+        }
+    }
+
+    fn send(&self, request: DiscoveryRequest) {
+        if let Some(requests) = &self.requests {
+            let _ = requests.send(request);
+        }
+    }
+
+    /// Share the controllers the assist may be replaced with, leaving out the
+    /// primary, partners, and CtrlAssist's own virtual gamepads
+    fn publish_candidates(&self, gilrs: &Gilrs, composites: &CompositeSources, p_id: GamepadId) {
+        let gamepads = gilrs
+            .gamepads()
+            .filter(|(id, _)| *id != p_id && composites.leader_of(*id) == *id)
+            .map(|(id, gamepad)| GamepadIdentity::new(id, gamepad))
+            .collect();
+        self.send(DiscoveryRequest::Candidates(gamepads));
+    }
+
+    /// Start looking for the event device of a replacement assist
+    fn find(&self, gilrs: &Gilrs, id: GamepadId) {
+        let gamepads = gilrs
+            .gamepads()
+            .map(|(id, gamepad)| GamepadIdentity::new(id, gamepad))
+            .collect();
+        self.send(DiscoveryRequest::Assist(id, gamepads));
+    }
+
+    /// A replacement assist whose search finished, with its event device if found
+    fn try_found(&self) -> Option<(GamepadId, Option<GamepadResource>)> {
+        self.found.try_recv().ok()
+    }
+}
+
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        // Closing the channel ends the thread once its current search is done
+        self.requests.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Rewrite Steam Deck back paddle presses as Mode button presses
+fn bind_paddle_to_mode(event: &mut gilrs::Event) {
+    use gilrs::{Button, EventType};
+
+    event.event = match event.event {
+        EventType::ButtonPressed(Button::Unknown, code)
+            if evdev_helpers::is_steam_deck_paddle(code) =>
+        {
+            EventType::ButtonPressed(Button::Mode, code)
+        }
+        EventType::ButtonReleased(Button::Unknown, code)
+            if evdev_helpers::is_steam_deck_paddle(code) =>
+        {
+            EventType::ButtonReleased(Button::Mode, code)
+        }
+        other => other,
+    };
+}
+
+/// Replay the current state of both controllers through the mux mode
+///
+/// The Mode button is skipped so toggle-style modes don't hand off at startup.
+fn initial_sync_events(
+    mux_mode: &mut dyn mux_modes::MuxMode,
+    sources: &Sources,
+    held: &HashMap<GamepadId, Vec<gilrs::EventType>>,
+    p_id: GamepadId,
+    a_id: GamepadId,
+    out: &mut Vec<InputEvent>,
+) {
+    use gilrs::ev::AxisOrBtn;
+
+    let members = [p_id, a_id]
+        .into_iter()
+        .flat_map(|id| sources.get(id).members().map(move |gamepad| (id, gamepad)));
+    for (id, gamepad) in members {
+        let state = gamepad.state();
+        let mut snapshot = Vec::new();
+
+        // A gamepad without a single event yet holds what it did at startup
+        if state.buttons().next().is_none() && state.axes().next().is_none() {
+            snapshot.extend(held.get(&gamepad.id()).into_iter().flatten().copied());
+        }
+
+        for (code, data) in state.buttons() {
+            let Some(AxisOrBtn::Btn(btn)) = gamepad.axis_or_btn_name(code) else {
+                continue;
+            };
+            if btn == gilrs::Button::Mode {
+                continue;
+            }
+            if data.is_pressed() {
+                snapshot.push(gilrs::EventType::ButtonPressed(btn, code));
+            }
+            snapshot.push(gilrs::EventType::ButtonChanged(btn, data.value(), code));
+        }
+
+        for (code, data) in state.axes() {
+            let Some(AxisOrBtn::Axis(axis)) = gamepad.axis_or_btn_name(code) else {
+                continue;
+            };
+            snapshot.push(gilrs::EventType::AxisChanged(axis, data.value(), code));
+        }
+
+        for ev in snapshot {
+            let event = gilrs::Event::new(id, ev);
+            mux_mode.handle_event(&event, p_id, a_id, sources, out);
         }
     }
 }
 
 pub fn run_ff_loop(
     v_uinput: &mut VirtualDevice,
-    all_resources: HashMap<GamepadId, GamepadResource>,
+    mut all_resources: HashMap<GamepadId, GamepadResource>,
     runtime_settings: Arc<RuntimeSettings>,
     p_id: GamepadId,
-    a_id: GamepadId,
+    mut a_id: GamepadId,
     shutdown: Arc<AtomicBool>,
+    options: FfOptions,
 ) {
     use crate::ff_helpers::EffectManager;
 
+    let FfOptions { composites, motors } = options;
+
     // Centralized effect state
     let mut effect_manager = EffectManager::new();
 
     // Current physical devices
-    let mut phys_devs = build_ff_targets(&all_resources, runtime_settings.get_rumble(), p_id, a_id);
+    let mut phys_devs = build_ff_targets(
+        &all_resources,
+        &runtime_settings.ff_health,
+        runtime_settings.get_rumble(),
+        &composites,
+        &motors,
+        p_id,
+        a_id,
+    );
     let mut last_rumble = runtime_settings.get_rumble();
 
+    // Scratch buffer reused across reads to avoid per-iteration allocations
+    let mut ff_events = Vec::with_capacity(EVENT_BUFFER_CAPACITY);
+
     info!("FF Thread started.");
 
     while !shutdown.load(Ordering::SeqCst) {
+        // Check for a replaced assist, whose device may be new to the session
+        let assist_changed = {
+            let assist = runtime_settings.assist.read();
+            if assist.id != a_id {
+                match assist.resource.as_ref().map(GamepadResource::try_clone) {
+                    Some(Ok(resource)) => {
+                        all_resources.insert(assist.id, resource);
+                    }
+                    Some(Err(e)) => warn!("Failed to reopen replacement assist for rumble: {}", e),
+                    None => {}
+                }
+                a_id = assist.id;
+                true
+            } else {
+                false
+            }
+        };
+
         // Check for rumble target changes
         let current_rumble = runtime_settings.get_rumble();
-        if current_rumble != last_rumble {
+        if current_rumble != last_rumble || assist_changed {
             info!(
                 "Switching rumble target from {:?} to {:?}",
                 last_rumble, current_rumble
             );
 
             // Build new device set
-            let mut new_phys_devs =
-                build_ff_targets(&all_resources, current_rumble.clone(), p_id, a_id);
+            let mut new_phys_devs = build_ff_targets(
+                &all_resources,
+                &runtime_settings.ff_health,
+                current_rumble.clone(),
+                &composites,
+                &motors,
+                p_id,
+                a_id,
+            );
 
             // Synchronize all effects to new devices
             for dev in &mut new_phys_devs {
@@ -146,17 +1000,44 @@ pub fn run_ff_loop(
             last_rumble = current_rumble;
         }
 
-        // Process events
-        let events: Vec<_> = match v_uinput.fetch_events() {
-            Ok(iter) => iter.collect(),
-            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => vec![],
+        if runtime_settings
+            .alert_requested
+            .swap(false, Ordering::SeqCst)
+        {
+            for dev in &mut phys_devs {
+                if let Err(e) = dev.alert() {
+                    warn!("Failed to rumble {}: {}", dev.resource.path.display(), e);
+                }
+            }
+        }
+
+        // Process events, waiting for them until woken for a request or
+        // it's time to check for shutdown
+        //
+        // The kernel queues these in a 16-event ring that overwrites itself
+        // when full, without SYN_DROPPED, so an overrun can't be detected and
+        // lost play or stop events stay lost. Uploads and erases are safe, as
+        // the game's request fails if never answered; reading as soon as
+        // events arrive is the only defence for the rest.
+        ff_events.clear();
+        let fetched = match runtime_settings
+            .ff_waker
+            .wait(v_uinput, evdev_helpers::READ_POLL_INTERVAL)
+        {
+            Ok(true) => v_uinput.fetch_events().map(|iter| ff_events.extend(iter)),
+            Ok(false) => Ok(()),
+            Err(e) => Err(e),
+        };
+        match fetched {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
             Err(e) => {
                 error!("Error fetching FF events: {}", e);
-                vec![]
             }
-        };
+        }
 
-        for event in events {
+        let had_events = !ff_events.is_empty();
+        for event in ff_events.drain(..) {
             match event.destructure() {
                 evdev::EventSummary::UInput(ev, evdev::UInputCode::UI_FF_UPLOAD, ..) => {
                     if let Ok(upload_ev) = v_uinput.process_ff_upload(ev) {
@@ -266,29 +1147,64 @@ pub fn run_ff_loop(
                 }
             }
         }
+        if had_events {
+            runtime_settings.ff_health.lock().effects = effect_manager.snapshot();
+        }
     }
 }
 
 // Helper function to build FF targets based on rumble setting
 fn build_ff_targets(
     all_resources: &HashMap<GamepadId, GamepadResource>,
+    health: &Arc<Mutex<FfHealth>>,
     rumble: RumbleTarget,
+    composites: &CompositeSources,
+    motors: &MotorMaps,
     p_id: GamepadId,
     a_id: GamepadId,
 ) -> Vec<PhysicalFFDev> {
     let rumble_ids = match rumble {
-        RumbleTarget::Primary => vec![p_id],
-        RumbleTarget::Assist => vec![a_id],
-        RumbleTarget::Both => vec![p_id, a_id],
+        RumbleTarget::Primary => vec![(p_id, RumbleChannel::Both)],
+        RumbleTarget::Assist => vec![(a_id, RumbleChannel::Both)],
+        RumbleTarget::Both => vec![(p_id, RumbleChannel::Both), (a_id, RumbleChannel::Both)],
         RumbleTarget::None => vec![],
+        RumbleTarget::Split => vec![(p_id, RumbleChannel::Strong), (a_id, RumbleChannel::Weak)],
     };
 
+    // Composite partners rumble along with their leader, mapped alike
     rumble_ids
         .into_iter()
-        .filter_map(|id| {
+        .flat_map(|(id, channel)| {
+            let (configured, blocked) = if id == p_id {
+                (motors.primary, &motors.primary_blocked)
+            } else {
+                (motors.assist, &motors.assist_blocked)
+            };
+            composites
+                .members(id)
+                .map(move |member| (member, channel, configured, blocked))
+        })
+        .filter_map(|(id, channel, configured, blocked)| {
             all_resources.get(&id).and_then(|res| {
                 if res.device.supported_ff().is_some() {
-                    Some(PhysicalFFDev::new(res.clone()))
+                    let map = motors.resolve(configured, res);
+                    match res.try_clone() {
+                        Ok(res) => Some(PhysicalFFDev::new(
+                            res,
+                            Arc::clone(health),
+                            channel,
+                            map,
+                            blocked.clone(),
+                        )),
+                        Err(e) => {
+                            warn!(
+                                "Failed to open {} for force feedback: {}",
+                                res.path.display(),
+                                e
+                            );
+                            None
+                        }
+                    }
                 } else {
                     warn!(
                         "Device {} ({}) does not support force feedback (rumble setting: {:?})",
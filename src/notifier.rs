@@ -0,0 +1,405 @@
+use log::{error, info};
+use notify_rust::{Notification, Timeout};
+use parking_lot::RwLock;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Where session notifications go
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum NotifyBackend {
+    /// Desktop notification server, via D-Bus
+    Desktop,
+    /// Standard output, e.g. for the journal of a headless daemon
+    Stdout,
+    /// On-screen display of the desktop shell, e.g. KDE Plasma's
+    Osd,
+    /// HTTP POST to `webhook_url`, e.g. an ntfy topic
+    Webhook,
+}
+
+/// Session events posted to the webhook, e.g. for a phone ping in another room
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum SessionEvent {
+    Start,
+    Stop,
+    Disconnect,
+    LowBattery,
+    Error,
+}
+
+impl SessionEvent {
+    /// Name given to webhook templates as `{event}`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Stop => "stop",
+            Self::Disconnect => "disconnect",
+            Self::LowBattery => "low_battery",
+            Self::Error => "error",
+        }
+    }
+
+    const ALL: [Self; 5] = [
+        Self::Start,
+        Self::Stop,
+        Self::Disconnect,
+        Self::LowBattery,
+        Self::Error,
+    ];
+}
+
+/// Notification backends, all of which get every notification, and the
+/// webhook session events are posted to
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub backends: Vec<NotifyBackend>,
+    /// URL session events, and notifications with the Webhook backend, are
+    /// posted to
+    pub webhook_url: Option<String>,
+    /// Request body with `{event}`, `{summary}`, and `{body}` filled in, sent
+    /// as JSON if it starts with `{`
+    pub webhook_template: String,
+    /// Session events posted to the webhook
+    pub webhook_events: Vec<SessionEvent>,
+    /// Battery level in percent at or below which a discharging controller
+    /// counts as low
+    pub low_battery_percent: u8,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            backends: vec![NotifyBackend::Desktop],
+            webhook_url: None,
+            webhook_template: "{body}".into(),
+            webhook_events: SessionEvent::ALL.to_vec(),
+            low_battery_percent: 15,
+        }
+    }
+}
+
+/// A notification for the players, or whoever watches over the session
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub summary: String,
+    pub body: String,
+    /// How long it stays up where that applies, or the backend's default
+    pub timeout: Option<Duration>,
+    /// Session event it reports, if any
+    pub event: Option<SessionEvent>,
+}
+
+impl Note {
+    pub fn new(summary: impl Into<String>, body: impl Into<String>) -> Self {
+        Self {
+            summary: summary.into(),
+            body: body.into(),
+            timeout: None,
+            event: None,
+        }
+    }
+}
+
+/// A way of delivering notifications
+///
+/// Interactive notifications, such as the controller menu or confirming
+/// System hiding, stay desktop-only, as other backends can't be answered.
+pub trait Notifier: Send + Sync {
+    /// Short name for logs, e.g. "desktop"
+    fn name(&self) -> &'static str;
+
+    /// Deliver a note, blocking until it's handed off
+    fn send(&self, note: &Note) -> Result<(), Box<dyn Error>>;
+}
+
+pub struct DesktopNotifier;
+
+impl Notifier for DesktopNotifier {
+    fn name(&self) -> &'static str {
+        "desktop"
+    }
+
+    fn send(&self, note: &Note) -> Result<(), Box<dyn Error>> {
+        let mut notification = Notification::new();
+        notification
+            .summary(&note.summary)
+            .body(&note.body)
+            .appname("CtrlAssist");
+        if let Some(timeout) = note.timeout {
+            notification.timeout(Timeout::Milliseconds(timeout.as_millis() as u32));
+        }
+        notification.show()?;
+        Ok(())
+    }
+}
+
+pub struct StdoutNotifier;
+
+impl Notifier for StdoutNotifier {
+    fn name(&self) -> &'static str {
+        "stdout"
+    }
+
+    fn send(&self, note: &Note) -> Result<(), Box<dyn Error>> {
+        println!("{}: {}", note.summary, note.body.replace('\n', "; "));
+        Ok(())
+    }
+}
+
+#[zbus::proxy(
+    interface = "org.kde.osdService",
+    default_service = "org.kde.plasmashell",
+    default_path = "/org/kde/osdService"
+)]
+trait OsdService {
+    #[zbus(name = "showText")]
+    fn show_text(&self, icon: &str, text: &str) -> zbus::Result<()>;
+}
+
+/// Brief text over whatever is on screen, including fullscreen games, shown
+/// by the Plasma shell
+pub struct OsdNotifier;
+
+impl Notifier for OsdNotifier {
+    fn name(&self) -> &'static str {
+        "osd"
+    }
+
+    fn send(&self, note: &Note) -> Result<(), Box<dyn Error>> {
+        let connection = zbus::blocking::Connection::session()?;
+        let osd = OsdServiceProxyBlocking::new(&connection)?;
+        // The OSD fits a line, so the summary is left out
+        osd.show_text("input-gaming", &note.body.replace('\n', " · "))?;
+        Ok(())
+    }
+}
+
+/// Posts the templated note with the summary as its title, as ntfy reads it,
+/// via curl so no HTTP stack is linked in
+pub struct WebhookNotifier {
+    pub url: String,
+    pub template: String,
+}
+
+impl WebhookNotifier {
+    /// Fill in the template, escaping values to fit in JSON strings if it's
+    /// JSON, e.g. `{"text": "{summary}: {body}"}` for a Matrix or Slack hook
+    fn render(&self, note: &Note) -> (String, bool) {
+        let json = self.template.trim_start().starts_with('{');
+        let escape = |value: &str| {
+            if json {
+                let quoted = serde_json::Value::from(value).to_string();
+                quoted[1..quoted.len() - 1].to_string()
+            } else {
+                value.to_string()
+            }
+        };
+        let event = note.event.map_or("notification", SessionEvent::as_str);
+        let values = [
+            ("{event}", event),
+            ("{summary}", note.summary.as_str()),
+            ("{body}", note.body.as_str()),
+        ];
+        // One pass, so placeholders within the values are left as they are
+        let mut body = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find('{') {
+            body.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match values.iter().find(|(key, _)| rest.starts_with(key)) {
+                Some((key, value)) => {
+                    body.push_str(&escape(value));
+                    rest = &rest[key.len()..];
+                }
+                None => {
+                    body.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        body.push_str(rest);
+        (body, json)
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn send(&self, note: &Note) -> Result<(), Box<dyn Error>> {
+        let (body, json) = self.render(note);
+        let mut curl = Command::new("curl");
+        curl.args(["--silent", "--show-error", "--fail", "--max-time", "10"])
+            .arg("--header")
+            .arg(format!(
+                "Title: {}",
+                note.summary.replace(['\r', '\n'], " ")
+            ));
+        if json {
+            curl.args(["--header", "Content-Type: application/json"]);
+        }
+        // The URL is given on stdin, as it may hold a token that anyone could
+        // otherwise read from the process list. The body is sent as is, as
+        // --data-binary would read a file for one starting with @, e.g. from
+        // a device name.
+        let mut child = curl
+            .arg("--data-raw")
+            .arg(body)
+            .args(["--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            writeln!(stdin, "url = \"{}\"", curl_quote(&self.url))?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().into());
+        }
+        Ok(())
+    }
+}
+
+/// A value escaped to go within double quotes in a curl config file
+fn curl_quote(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Notifiers set from config, or None for the desktop alone
+static NOTIFIERS: RwLock<Option<Vec<Arc<dyn Notifier>>>> = RwLock::new(None);
+
+/// Webhook session events are posted to
+struct EventWebhook {
+    notifier: Arc<WebhookNotifier>,
+    events: Vec<SessionEvent>,
+    low_battery_percent: u8,
+}
+
+static WEBHOOK: RwLock<Option<EventWebhook>> = RwLock::new(None);
+
+/// Set up the configured backends for every notification from here on
+pub fn configure(config: &NotifyConfig) {
+    let webhook = config.webhook_url.as_ref().map(|url| {
+        Arc::new(WebhookNotifier {
+            url: url.clone(),
+            template: config.webhook_template.clone(),
+        })
+    });
+    let notifiers = config
+        .backends
+        .iter()
+        .filter_map(|backend| -> Option<Arc<dyn Notifier>> {
+            match backend {
+                NotifyBackend::Desktop => Some(Arc::new(DesktopNotifier)),
+                NotifyBackend::Stdout => Some(Arc::new(StdoutNotifier)),
+                NotifyBackend::Osd => Some(Arc::new(OsdNotifier)),
+                NotifyBackend::Webhook => match &webhook {
+                    Some(webhook) => Some(Arc::clone(webhook) as Arc<dyn Notifier>),
+                    None => {
+                        error!("Webhook notifications need a webhook_url; skipping");
+                        None
+                    }
+                },
+            }
+        })
+        .collect::<Vec<_>>();
+    info!(
+        "Notifications: {:?}",
+        notifiers.iter().map(|n| n.name()).collect::<Vec<_>>()
+    );
+    *NOTIFIERS.write() = Some(notifiers);
+    *WEBHOOK.write() = webhook.map(|notifier| EventWebhook {
+        notifier,
+        events: config.webhook_events.clone(),
+        low_battery_percent: config.low_battery_percent,
+    });
+}
+
+fn notifiers() -> Vec<Arc<dyn Notifier>> {
+    NOTIFIERS
+        .read()
+        .clone()
+        .unwrap_or_else(|| vec![Arc::new(DesktopNotifier)])
+}
+
+/// Battery level counting as low, if low battery is posted at all, to skip
+/// watching batteries otherwise
+pub fn low_battery_percent() -> Option<u8> {
+    WEBHOOK
+        .read()
+        .as_ref()
+        .filter(|webhook| webhook.events.contains(&SessionEvent::LowBattery))
+        .map(|webhook| webhook.low_battery_percent)
+}
+
+/// Post a session event to the webhook, if configured for it, off the
+/// calling thread
+///
+/// Desktop backends aren't sent these, as the tray already shows its own.
+pub fn post_event(event: SessionEvent, body: impl Into<String>) {
+    let body = body.into();
+    thread::spawn(move || post_event_blocking(event, body));
+}
+
+/// Post a session event and wait for it, e.g. as the process exits
+pub fn post_event_blocking(event: SessionEvent, body: impl Into<String>) {
+    let webhook = match WEBHOOK.read().as_ref() {
+        Some(webhook) if webhook.events.contains(&event) => Arc::clone(&webhook.notifier),
+        _ => return,
+    };
+    let note = Note {
+        event: Some(event),
+        ..Note::new(format!("CtrlAssist: {}", event.as_str()), body)
+    };
+    if let Err(e) = webhook.send(&note) {
+        error!("Failed to post {} event: {}", event.as_str(), e);
+    }
+}
+
+/// Deliver a note through every backend, off the calling thread as they
+/// block, e.g. on the notification server or network
+pub fn send(note: Note) {
+    for notifier in notifiers() {
+        let note = note.clone();
+        thread::spawn(move || {
+            if let Err(e) = notifier.send(&note) {
+                error!("Failed to send {} notification: {}", notifier.name(), e);
+            }
+        });
+    }
+}
+
+/// Deliver a note through every backend and wait for them, e.g. while the
+/// process is going down
+///
+/// Falls back to the desktop if the backends are being reconfigured, as
+/// waiting on them could hang a panicking process.
+pub fn send_blocking(note: Note) {
+    let notifiers = NOTIFIERS
+        .try_read()
+        .and_then(|notifiers| notifiers.clone())
+        .unwrap_or_else(|| vec![Arc::new(DesktopNotifier)]);
+    for notifier in notifiers {
+        if let Err(e) = notifier.send(&note) {
+            error!("Failed to send {} notification: {}", notifier.name(), e);
+        }
+    }
+}
+
+/// Deliver a plain notification, see [`send`]
+pub fn notify(summary: impl Into<String>, body: impl Into<String>) {
+    send(Note::new(summary, body));
+}
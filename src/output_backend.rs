@@ -0,0 +1,88 @@
+use crate::evdev_helpers::{VirtualGamepadInfo, VirtualLayout};
+use crate::uhid_helpers::UhidGamepad;
+use evdev::{Device, EventType, InputEvent};
+use log::{info, warn};
+use std::io;
+
+/// A sink presenting the virtual gamepad's output to games
+///
+/// Frames arrive in the default layout, i.e. the codes and ranges the mux
+/// modes produce, and each backend converts them to what it presents, so new
+/// emulation targets are added here without touching the modes or runtime.
+pub trait OutputBackend: Send {
+    /// Short name for logs, e.g. "uinput"
+    fn name(&self) -> &'static str;
+
+    /// Present one frame of events, given without a trailing SYN_REPORT
+    fn write_frame(&mut self, events: &[InputEvent]) -> io::Result<()>;
+}
+
+/// The uinput virtual gamepad, written through its evdev node
+pub struct UinputOutput {
+    device: Device,
+    layout: VirtualLayout,
+    /// Reused for layout conversion to avoid per-frame allocations
+    scratch: Vec<InputEvent>,
+}
+
+impl UinputOutput {
+    pub fn new(device: Device, layout: VirtualLayout) -> Self {
+        Self {
+            device,
+            layout,
+            scratch: Vec::new(),
+        }
+    }
+}
+
+impl OutputBackend for UinputOutput {
+    fn name(&self) -> &'static str {
+        "uinput"
+    }
+
+    fn write_frame(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        self.scratch.clear();
+        self.scratch.extend_from_slice(events);
+        self.layout.convert(&mut self.scratch);
+        if self.scratch.is_empty() {
+            return Ok(());
+        }
+        self.scratch
+            .push(InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0));
+        self.device.send_events(&self.scratch)
+    }
+}
+
+impl OutputBackend for UhidGamepad {
+    fn name(&self) -> &'static str {
+        "uhid"
+    }
+
+    fn write_frame(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        self.update(events)
+    }
+}
+
+/// Backends for a session: always the uinput device, which `device` is the
+/// evdev node of, and a uhid device too if `hidraw` is set
+///
+/// A backend that fails to start is left out with a warning, as the session
+/// still works through the others.
+pub fn select(
+    device: Device,
+    info: &VirtualGamepadInfo,
+    hidraw: bool,
+) -> Vec<Box<dyn OutputBackend>> {
+    let mut outputs: Vec<Box<dyn OutputBackend>> =
+        vec![Box::new(UinputOutput::new(device, info.layout))];
+    if hidraw {
+        match UhidGamepad::create(info) {
+            Ok(hid) => {
+                info!("Virtual device also presented over uhid");
+                outputs.push(Box::new(hid));
+            }
+            Err(e) => warn!("uhid: failed to create device, hidraw disabled: {}", e),
+        }
+    }
+    outputs
+}
@@ -0,0 +1,120 @@
+use crate::mux_modes::HandoffButton;
+use gilrs::{Button, Event, EventType, GamepadId};
+use log::info;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// What the virtual gamepad does while the system chord is held
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum PauseOutput {
+    /// Keep the last state, as if both controllers froze
+    #[default]
+    Hold,
+    /// Let go of everything, as if both controllers were put down
+    Release,
+}
+
+/// Freezing of the virtual gamepad while a system chord is held, e.g. the
+/// Guide button opening the Steam overlay
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct OverlayPauseConfig {
+    pub enabled: bool,
+    /// Buttons held together on one controller to pause output
+    pub chord: Vec<HandoffButton>,
+    pub output: PauseOutput,
+}
+
+impl Default for OverlayPauseConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            chord: vec![HandoffButton::Mode],
+            output: PauseOutput::Hold,
+        }
+    }
+}
+
+/// Change of pause state on an event
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseChange {
+    Unchanged,
+    Paused,
+    Resumed,
+}
+
+/// Tracks the system chord on each controller, pausing output while any
+/// controller holds it
+///
+/// The chord's own buttons keep reaching the virtual gamepad, so an overlay
+/// reading it still opens; everything else is held back while paused.
+pub struct OverlayPause {
+    chord: Vec<Button>,
+    pub output: PauseOutput,
+    held: HashSet<(GamepadId, Button)>,
+    paused: bool,
+}
+
+impl OverlayPause {
+    /// The pause, or None if disabled
+    pub fn new(config: &OverlayPauseConfig) -> Option<Self> {
+        if !config.enabled || config.chord.is_empty() {
+            return None;
+        }
+        Some(Self {
+            chord: config.chord.iter().map(|button| button.button()).collect(),
+            output: config.output,
+            held: HashSet::new(),
+            paused: false,
+        })
+    }
+
+    /// Follow the chord through an event of controller `event.id`, which
+    /// should already be that of its composite leader
+    pub fn update(&mut self, event: &Event) -> PauseChange {
+        match event.event {
+            EventType::ButtonPressed(button, _) => {
+                self.held.insert((event.id, button));
+            }
+            EventType::ButtonReleased(button, _) => {
+                self.held.remove(&(event.id, button));
+            }
+            EventType::Disconnected => self.held.retain(|(id, _)| *id != event.id),
+            _ => return PauseChange::Unchanged,
+        }
+
+        let chord_held = self.held.iter().any(|&(id, _)| {
+            self.chord
+                .iter()
+                .all(|&button| self.held.contains(&(id, button)))
+        });
+        match (self.paused, chord_held) {
+            (false, true) => {
+                info!("System chord held, pausing output");
+                self.paused = true;
+                PauseChange::Paused
+            }
+            (true, false) => {
+                info!("System chord released, resuming output");
+                self.paused = false;
+                PauseChange::Resumed
+            }
+            _ => PauseChange::Unchanged,
+        }
+    }
+
+    /// Whether `event` is held back, being paused and not of the chord
+    pub fn holds_back(&self, event: &Event) -> bool {
+        if !self.paused {
+            return false;
+        }
+        match event.event {
+            EventType::ButtonPressed(button, _)
+            | EventType::ButtonReleased(button, _)
+            | EventType::ButtonChanged(button, ..) => !self.chord.contains(&button),
+            EventType::AxisChanged(..) | EventType::ButtonRepeated(..) => true,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use crate::game_profiles::GameProfile;
+use crate::mux_modes::HandoffButton;
+use crate::mux_runtime::RuntimeSettings;
+use clap::ValueEnum;
+use gilrs::{Button, Event, EventType, GamepadId};
+use log::{error, info};
+use notify_rust::{Notification, Timeout};
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::thread;
+
+/// How long the menu notification stays up without input
+const NOTIFICATION_TIMEOUT_MS: u32 = 10_000;
+
+/// Menu opened by a button chord on either controller, to change the
+/// session without reaching for the keyboard or tray
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct PadMenuConfig {
+    pub enabled: bool,
+    /// Buttons held together on one controller to open or close the menu
+    pub chord: Vec<HandoffButton>,
+}
+
+impl Default for PadMenuConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            chord: vec![HandoffButton::Select, HandoffButton::Start],
+        }
+    }
+}
+
+/// Entries of the menu, in order
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Entry {
+    Mode,
+    Rumble,
+    Profile,
+    Stop,
+}
+
+const ENTRIES: [Entry; 4] = [Entry::Mode, Entry::Rumble, Entry::Profile, Entry::Stop];
+
+/// What the menu made of an event, for the input loop to act on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuAction {
+    /// The event isn't the menu's; pass it on
+    Pass,
+    /// The menu opened, taking over both controllers; the virtual device
+    /// should let go of what they held
+    Opened,
+    /// The menu used the event
+    Consumed,
+    /// The menu closed, handing the controllers back as they're released
+    Closed,
+}
+
+/// Menu navigated with the D-pad, shown as a desktop notification
+///
+/// While open, button and stick input of both controllers goes to the menu
+/// rather than the game: Up and Down pick an entry, Left and Right change it,
+/// South applies a profile or stops the session, and East or the chord
+/// closes the menu.
+pub struct PadMenu {
+    chord: Vec<Button>,
+    profiles: Vec<(String, GameProfile)>,
+    /// Buttons held on each controller, to spot the chord
+    held: HashSet<(GamepadId, Button)>,
+    /// Buttons still held when the menu closed, whose releases it keeps
+    swallow: HashSet<(GamepadId, Button)>,
+    /// Selected entry while open
+    open: Option<usize>,
+    /// Profile picked in the menu, applied with South
+    profile: usize,
+    /// Notification shown, replaced as the menu changes
+    notification: Arc<Mutex<Option<u32>>>,
+}
+
+impl PadMenu {
+    /// The menu, or None if disabled
+    pub fn new(config: &PadMenuConfig, profiles: BTreeMap<String, GameProfile>) -> Option<Self> {
+        if !config.enabled || config.chord.is_empty() {
+            return None;
+        }
+        Some(Self {
+            chord: config.chord.iter().map(|button| button.button()).collect(),
+            profiles: profiles.into_iter().collect(),
+            held: HashSet::new(),
+            swallow: HashSet::new(),
+            open: None,
+            profile: 0,
+            notification: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Act on an event of controller `event.id`, which should already be
+    /// that of its composite leader
+    pub fn handle(&mut self, event: &Event, settings: &RuntimeSettings) -> MenuAction {
+        let (button, pressed) = match event.event {
+            EventType::ButtonPressed(button, _) => (button, true),
+            EventType::ButtonReleased(button, _) => (button, false),
+            EventType::ButtonChanged(..) | EventType::AxisChanged(..) => {
+                return if self.open.is_some() {
+                    MenuAction::Consumed
+                } else {
+                    MenuAction::Pass
+                };
+            }
+            _ => return MenuAction::Pass,
+        };
+        if pressed {
+            self.held.insert((event.id, button));
+        } else {
+            self.held.remove(&(event.id, button));
+            if self.swallow.remove(&(event.id, button)) {
+                return MenuAction::Consumed;
+            }
+        }
+
+        let chord_held = pressed
+            && self.chord.contains(&button)
+            && self
+                .chord
+                .iter()
+                .all(|&chord_button| self.held.contains(&(event.id, chord_button)));
+
+        let Some(selected) = self.open else {
+            if !chord_held {
+                return MenuAction::Pass;
+            }
+            info!("Controller menu opened by {}", event.id);
+            self.open = Some(0);
+            self.show(settings);
+            return MenuAction::Opened;
+        };
+        if !pressed {
+            return MenuAction::Consumed;
+        }
+        if chord_held || button == Button::East {
+            return self.close();
+        }
+
+        match (button, ENTRIES[selected]) {
+            (Button::DPadUp, _) => {
+                self.open = Some((selected + ENTRIES.len() - 1) % ENTRIES.len());
+            }
+            (Button::DPadDown, _) => self.open = Some((selected + 1) % ENTRIES.len()),
+            (Button::DPadLeft | Button::DPadRight, entry) => {
+                let forward = button == Button::DPadRight;
+                match entry {
+                    Entry::Mode => settings.update_mode(cycle(&settings.get_mode(), forward)),
+                    Entry::Rumble => {
+                        settings.update_rumble(cycle(&settings.get_rumble(), forward));
+                    }
+                    Entry::Profile if !self.profiles.is_empty() => {
+                        let count = self.profiles.len();
+                        self.profile = if forward {
+                            (self.profile + 1) % count
+                        } else {
+                            (self.profile + count - 1) % count
+                        };
+                    }
+                    Entry::Profile | Entry::Stop => {}
+                }
+            }
+            (Button::South, Entry::Profile) => {
+                if let Some((name, profile)) = self.profiles.get(self.profile) {
+                    info!("Controller menu: applying profile {}", name);
+                    let mut mode = settings.get_mode();
+                    let mut mode_options = settings.get_mode_options();
+                    profile.apply_mode(&mut mode, &mut mode_options);
+                    settings.update_mode(mode);
+                    settings.update_mode_options(mode_options);
+                }
+                return self.close();
+            }
+            (Button::South, Entry::Stop) => {
+                info!("Controller menu: stopping session");
+                settings.request_stop();
+                return self.close();
+            }
+            (Button::South, _) => return self.close(),
+            _ => return MenuAction::Consumed,
+        }
+        self.show(settings);
+        MenuAction::Consumed
+    }
+
+    fn close(&mut self) -> MenuAction {
+        info!("Controller menu closed");
+        self.open = None;
+        // The game saw these released when the menu opened
+        self.swallow = std::mem::take(&mut self.held);
+        self.notify("CtrlAssist".into(), "Menu closed".into(), 1_000);
+        MenuAction::Closed
+    }
+
+    /// Show the menu with its current values, the selected entry marked
+    fn show(&self, settings: &RuntimeSettings) {
+        let Some(selected) = self.open else {
+            return;
+        };
+        let profile = self
+            .profiles
+            .get(self.profile)
+            .map_or("none", |(name, _)| name.as_str());
+        let body = ENTRIES
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let marker = if i == selected { "▶" } else { "  " };
+                let line = match entry {
+                    Entry::Mode => format!("Mode: ◀ {:?} ▶", settings.get_mode()),
+                    Entry::Rumble => format!("Rumble: ◀ {:?} ▶", settings.get_rumble()),
+                    Entry::Profile => format!("Profile: ◀ {} ▶ (apply with South)", profile),
+                    Entry::Stop => "Stop session (South)".to_string(),
+                };
+                format!("{} {}", marker, line)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.notify("CtrlAssist menu".into(), body, NOTIFICATION_TIMEOUT_MS);
+    }
+
+    /// Show a notification in place of the last one, off the input thread as
+    /// it blocks on the notification server
+    fn notify(&self, summary: String, body: String, timeout_ms: u32) {
+        let notification = Arc::clone(&self.notification);
+        thread::spawn(move || {
+            let mut shown = Notification::new();
+            shown
+                .summary(&summary)
+                .body(&body)
+                .appname("CtrlAssist")
+                .timeout(Timeout::Milliseconds(timeout_ms));
+            let mut id = notification.lock();
+            if let Some(id) = *id {
+                shown.id(id);
+            }
+            match shown.show() {
+                Ok(handle) => *id = Some(handle.id()),
+                Err(e) => error!("Failed to show controller menu: {}", e),
+            }
+        });
+    }
+}
+
+/// The next or previous variant of a CLI enum, wrapping around
+fn cycle<T: ValueEnum + Clone + PartialEq>(current: &T, forward: bool) -> T {
+    let variants = T::value_variants();
+    let pos = variants.iter().position(|v| v == current).unwrap_or(0);
+    let next = if forward {
+        (pos + 1) % variants.len()
+    } else {
+        (pos + variants.len() - 1) % variants.len()
+    };
+    variants[next].clone()
+}
@@ -0,0 +1,136 @@
+use crate::gilrs_helper::GamepadResource;
+use crate::udev_helpers;
+use log::debug;
+use std::cell::OnceCell;
+use std::fmt;
+use std::process::Command;
+use udev::Enumerator;
+
+/// Charging state of a controller battery
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChargeState {
+    Charging,
+    Discharging,
+    Full,
+    Unknown,
+}
+
+impl ChargeState {
+    fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "charging" => ChargeState::Charging,
+            "discharging" | "not charging" => ChargeState::Discharging,
+            "full" | "fully-charged" => ChargeState::Full,
+            _ => ChargeState::Unknown,
+        }
+    }
+}
+
+/// Battery level and charging state of a controller
+#[derive(Debug, Clone, PartialEq)]
+pub struct PowerInfo {
+    /// Charge level in percent, if reported
+    pub level: Option<u8>,
+    pub state: ChargeState,
+}
+
+impl fmt::Display for PowerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.level {
+            Some(level) => write!(f, "{}% ({:?})", level, self.state),
+            None => write!(f, "{:?}", self.state),
+        }
+    }
+}
+
+/// Battery lookups for a batch of controllers, e.g. one refresh of a list
+///
+/// UPower is only queried once per batch, and only if a controller has no
+/// sysfs power supply.
+#[derive(Default)]
+pub struct PowerLookup {
+    upower_dump: OnceCell<Option<String>>,
+}
+
+impl PowerLookup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Look up battery info for a controller, trying sysfs before UPower
+    pub fn power_info(&self, resource: &GamepadResource) -> Option<PowerInfo> {
+        sysfs_power_info(resource).or_else(|| {
+            let dump = self.upower_dump.get_or_init(upower_dump).as_deref()?;
+            upower_power_info(dump, resource)
+        })
+    }
+}
+
+/// Reads the `power_supply` node sharing the controller's physical root
+///
+/// Controller drivers (hid-playstation, hid-nintendo, xpadneo, etc.) register a
+/// device-scoped power supply under the same HID device as the event node.
+fn sysfs_power_info(resource: &GamepadResource) -> Option<PowerInfo> {
+    let root = udev_helpers::find_physical_root_of(&resource.path).ok()??;
+
+    let mut enumerator = Enumerator::new().ok()?;
+    enumerator.match_subsystem("power_supply").ok()?;
+    enumerator.match_parent(&root).ok()?;
+
+    enumerator.scan_devices().ok()?.find_map(|device| {
+        let attr = |name: &str| {
+            device
+                .attribute_value(name)
+                .and_then(|v| v.to_str())
+                .map(|v| v.trim().to_string())
+        };
+
+        // Skip system batteries that happen to share a parent
+        if attr("scope").is_some_and(|scope| scope != "Device") {
+            return None;
+        }
+
+        debug!("Found power supply {}", device.syspath().display());
+        Some(PowerInfo {
+            level: attr("capacity").and_then(|v| v.parse().ok()),
+            state: attr("status").map_or(ChargeState::Unknown, |v| ChargeState::parse(&v)),
+        })
+    })
+}
+
+/// Output of `upower --dump`, if UPower is available
+fn upower_dump() -> Option<String> {
+    let output = Command::new("upower").arg("--dump").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Finds the UPower device whose serial matches the controller's uniq
+///
+/// Parses `upower --dump` output, which lists one device per blank-line
+/// separated block with indented `key: value` properties.
+fn upower_power_info(dump: &str, resource: &GamepadResource) -> Option<PowerInfo> {
+    let uniq = resource.device.unique_name().filter(|s| !s.is_empty())?;
+
+    dump.split("\n\n").find_map(|block| {
+        let value = |key: &str| {
+            block.lines().find_map(|line| {
+                let (k, v) = line.trim().split_once(':')?;
+                (k.trim() == key).then(|| v.trim().to_string())
+            })
+        };
+
+        if !value("serial").is_some_and(|serial| serial.eq_ignore_ascii_case(uniq)) {
+            return None;
+        }
+
+        Some(PowerInfo {
+            level: value("percentage")
+                .and_then(|v| v.trim_end_matches('%').parse::<f32>().ok())
+                .map(|v| v.round() as u8),
+            state: value("state").map_or(ChargeState::Unknown, |v| ChargeState::parse(&v)),
+        })
+    })
+}
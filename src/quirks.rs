@@ -0,0 +1,440 @@
+use crate::ff_helpers::MotorMap;
+use crate::gilrs_helper::GamepadResource;
+use evdev::{AbsoluteAxisCode, KeyCode};
+use gilrs::ev::Code;
+use gilrs::{Axis, Button, Event, EventType, GamepadId, Gilrs};
+use log::{info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Stick deflection beyond which a D-pad read from a stick counts as pressed
+const STICK_DPAD_THRESHOLD: f32 = 0.5;
+
+/// A stick axis, by gilrs name
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum StickAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+}
+
+impl StickAxis {
+    pub fn axis(self) -> Axis {
+        match self {
+            Self::LeftStickX => Axis::LeftStickX,
+            Self::LeftStickY => Axis::LeftStickY,
+            Self::RightStickX => Axis::RightStickX,
+            Self::RightStickY => Axis::RightStickY,
+        }
+    }
+}
+
+/// A virtual gamepad axis an extra source axis may drive
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum TargetAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+    /// The D-pad, e.g. from a flight stick's second hat
+    DPadX,
+    DPadY,
+}
+
+impl TargetAxis {
+    pub fn axis(self) -> Axis {
+        match self {
+            Self::LeftStickX => Axis::LeftStickX,
+            Self::LeftStickY => Axis::LeftStickY,
+            Self::RightStickX => Axis::RightStickX,
+            Self::RightStickY => Axis::RightStickY,
+            Self::LeftTrigger => Axis::LeftZ,
+            Self::RightTrigger => Axis::RightZ,
+            Self::DPadX => Axis::DPadX,
+            Self::DPadY => Axis::DPadY,
+        }
+    }
+
+    fn is_trigger(self) -> bool {
+        matches!(self, Self::LeftTrigger | Self::RightTrigger)
+    }
+}
+
+/// A virtual gamepad button an extra source button may press, by gilrs name
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum TargetButton {
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl TargetButton {
+    pub fn button(self) -> Button {
+        match self {
+            Self::South => Button::South,
+            Self::East => Button::East,
+            Self::North => Button::North,
+            Self::West => Button::West,
+            Self::LeftTrigger => Button::LeftTrigger,
+            Self::LeftTrigger2 => Button::LeftTrigger2,
+            Self::RightTrigger => Button::RightTrigger,
+            Self::RightTrigger2 => Button::RightTrigger2,
+            Self::Select => Button::Select,
+            Self::Start => Button::Start,
+            Self::Mode => Button::Mode,
+            Self::LeftThumb => Button::LeftThumb,
+            Self::RightThumb => Button::RightThumb,
+            Self::DPadUp => Button::DPadUp,
+            Self::DPadDown => Button::DPadDown,
+            Self::DPadLeft => Button::DPadLeft,
+            Self::DPadRight => Button::DPadRight,
+        }
+    }
+}
+
+/// A source button routed onto the virtual gamepad, e.g. a flight stick's
+/// trigger
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ButtonRoute {
+    /// Source button by evdev name, e.g. "BTN_TRIGGER"
+    pub from: String,
+    pub to: TargetButton,
+}
+
+impl ButtonRoute {
+    fn code(&self) -> Option<u16> {
+        KeyCode::from_str(&self.from).ok().map(|key| key.0)
+    }
+}
+
+/// A source axis routed onto the virtual gamepad, e.g. a wheel's pedals
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct AxisRoute {
+    /// Source axis by evdev name, e.g. "ABS_GAS"
+    pub from: String,
+    pub to: TargetAxis,
+    /// Whether the axis rests at its maximum, as some pedals do
+    #[serde(default)]
+    pub invert: bool,
+}
+
+impl AxisRoute {
+    fn code(&self) -> Option<u16> {
+        AbsoluteAxisCode::from_str(&self.from)
+            .ok()
+            .map(|axis| axis.0)
+    }
+}
+
+/// Wheel and pedal axes, which gilrs leaves unmapped, routed for any device
+/// whose quirk doesn't route them itself
+fn builtin_routes() -> Vec<AxisRoute> {
+    let route = |from: &str, to| AxisRoute {
+        from: from.into(),
+        to,
+        invert: false,
+    };
+    vec![
+        route("ABS_WHEEL", TargetAxis::LeftStickX),
+        route("ABS_GAS", TargetAxis::RightTrigger),
+        route("ABS_BRAKE", TargetAxis::LeftTrigger),
+    ]
+}
+
+/// Where a controller's D-pad is read from
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum DpadSource {
+    /// Only the hat, ignoring D-pad buttons also reported
+    Hat,
+    /// Only the buttons, ignoring a hat also reported
+    Buttons,
+    /// The left stick axes, for pads reporting their D-pad as a stick
+    Stick,
+}
+
+/// Oddities of a controller model, keyed by USB vendor and product ID
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(default)]
+pub struct Quirk {
+    /// Vendor ID, e.g. 0x0079
+    pub vendor: u16,
+    /// Product ID, e.g. 0x0011
+    pub product: u16,
+    /// Stick axes reported the wrong way around
+    pub invert_axes: Vec<StickAxis>,
+    /// Where the D-pad is read from, if not as gilrs maps it
+    pub dpad: Option<DpadSource>,
+    /// Share of full travel the analog triggers actually reach (0.1-1.0),
+    /// rescaled so reaching it counts as fully pressed
+    pub trigger_max: Option<f32>,
+    /// How the rumble motors are driven, e.g. Swap for pads reporting their
+    /// strong and weak motors the wrong way around
+    pub motors: Option<MotorMap>,
+    /// Axes routed onto the virtual gamepad, e.g. a wheel reporting its
+    /// steering as ABS_X and its pedals as ABS_Y and ABS_Z
+    pub axes: Vec<AxisRoute>,
+    /// Buttons routed onto the virtual gamepad, e.g. those of a flight stick
+    /// gilrs has no gamepad mapping for
+    pub buttons: Vec<ButtonRoute>,
+}
+
+/// Known oddities, each replaced by a user entry for the same model
+fn builtin_quirks() -> Vec<Quirk> {
+    vec![
+        // DragonRise SNES-style USB pads report the D-pad as the X/Y axes
+        Quirk {
+            vendor: 0x0079,
+            product: 0x0011,
+            dpad: Some(DpadSource::Stick),
+            ..Default::default()
+        },
+    ]
+}
+
+/// Quirk of a controller model, preferring a user entry over a built-in one
+pub fn lookup(user: &[Quirk], vendor: u16, product: u16) -> Option<Quirk> {
+    let matches = |quirk: &&Quirk| quirk.vendor == vendor && quirk.product == product;
+    user.iter()
+        .find(matches)
+        .cloned()
+        .or_else(|| builtin_quirks().iter().find(matches).cloned())
+}
+
+/// Quirk of a connected controller, if its model has one
+pub fn for_resource(user: &[Quirk], resource: &GamepadResource) -> Option<Quirk> {
+    let input_id = resource.device.input_id();
+    lookup(user, input_id.vendor(), input_id.product())
+}
+
+/// Quirks of a session's controllers, applied to their events as they arrive
+#[derive(Debug, Clone, Default)]
+pub struct DeviceQuirks {
+    user: Vec<Quirk>,
+    by_id: HashMap<GamepadId, Quirk>,
+    /// Routes of controllers without routes of their own, by evdev code
+    builtin_routes: HashMap<u16, AxisRoute>,
+}
+
+impl DeviceQuirks {
+    pub fn new(user: Vec<Quirk>) -> Self {
+        // Unknown names would never match an event, so the route silently
+        // does nothing
+        for quirk in &user {
+            let model = format!("{:04x}:{:04x}", quirk.vendor, quirk.product);
+            for route in quirk.axes.iter().filter(|route| route.code().is_none()) {
+                warn!(
+                    "Quirk {}: unknown axis {:?}, ignoring its route",
+                    model, route.from
+                );
+            }
+            for route in quirk.buttons.iter().filter(|route| route.code().is_none()) {
+                warn!(
+                    "Quirk {}: unknown button {:?}, ignoring its route",
+                    model, route.from
+                );
+            }
+        }
+        Self {
+            user,
+            by_id: HashMap::new(),
+            builtin_routes: builtin_routes()
+                .into_iter()
+                .filter_map(|route| Some((route.code()?, route)))
+                .collect(),
+        }
+    }
+
+    /// Look up the quirk of a controller joining the session
+    pub fn insert(&mut self, id: GamepadId, resource: &GamepadResource) {
+        match for_resource(&self.user, resource) {
+            Some(quirk) => {
+                info!("Applying quirks for {}: {:?}", resource.name, quirk);
+                self.by_id.insert(id, quirk);
+            }
+            None => {
+                self.by_id.remove(&id);
+            }
+        }
+    }
+
+    /// Correct a raw event before gilrs's filters, dropping it if it's a
+    /// duplicate report
+    pub fn translate(&self, event: Event, gilrs: &Gilrs) -> Event {
+        let event = self.route_axis(event, gilrs);
+        let event = self.route_button(event, gilrs);
+        self.correct(event)
+    }
+
+    /// Route a wheel, pedal, or other configured axis onto a gamepad axis
+    ///
+    /// The event takes on the code gilrs maps the target axis to, if any, so
+    /// the target's state follows it for the mux modes to read.
+    fn route_axis(&self, mut event: Event, gilrs: &Gilrs) -> Event {
+        let EventType::AxisChanged(_, value, code) = event.event else {
+            return event;
+        };
+        let Some(abs) = raw_code(code, evdev::EventType::ABSOLUTE) else {
+            return event;
+        };
+        let routes = self
+            .by_id
+            .get(&event.id)
+            .filter(|quirk| !quirk.axes.is_empty());
+        let route = match routes {
+            Some(quirk) => quirk.axes.iter().find(|route| route.code() == Some(abs)),
+            None => self.builtin_routes.get(&abs),
+        };
+        let Some(route) = route else {
+            return event;
+        };
+        let target = route.to.axis();
+        // Triggers rest at 0 rather than the center, so flip within 0-1
+        let value = match (route.invert, route.to.is_trigger()) {
+            (false, _) => value,
+            (true, true) => 1.0 - value,
+            (true, false) => -value,
+        };
+        let code = gilrs
+            .connected_gamepad(event.id)
+            .and_then(|gamepad| gamepad.axis_code(target))
+            .unwrap_or(code);
+        event.event = EventType::AxisChanged(target, value, code);
+        event
+    }
+
+    /// Route a configured button onto a gamepad button, as axes are routed
+    fn route_button(&self, mut event: Event, gilrs: &Gilrs) -> Event {
+        let code = match event.event {
+            EventType::ButtonPressed(_, code)
+            | EventType::ButtonRepeated(_, code)
+            | EventType::ButtonReleased(_, code)
+            | EventType::ButtonChanged(_, _, code) => code,
+            _ => return event,
+        };
+        let Some(key) = raw_code(code, evdev::EventType::KEY) else {
+            return event;
+        };
+        let Some(route) = self
+            .by_id
+            .get(&event.id)
+            .and_then(|quirk| quirk.buttons.iter().find(|route| route.code() == Some(key)))
+        else {
+            return event;
+        };
+        let target = route.to.button();
+        let code = gilrs
+            .connected_gamepad(event.id)
+            .and_then(|gamepad| gamepad.button_code(target))
+            .unwrap_or(code);
+        event.event = match event.event {
+            EventType::ButtonPressed(..) => EventType::ButtonPressed(target, code),
+            EventType::ButtonRepeated(..) => EventType::ButtonRepeated(target, code),
+            EventType::ButtonReleased(..) => EventType::ButtonReleased(target, code),
+            EventType::ButtonChanged(_, value, _) => EventType::ButtonChanged(target, value, code),
+            other => other,
+        };
+        event
+    }
+
+    fn correct(&self, mut event: Event) -> Event {
+        let Some(quirk) = self.by_id.get(&event.id) else {
+            return event;
+        };
+        match event.event {
+            EventType::AxisChanged(axis, value, code) => {
+                if quirk.dpad == Some(DpadSource::Stick) {
+                    let dpad_axis = match axis {
+                        Axis::LeftStickX => Some(Axis::DPadX),
+                        Axis::LeftStickY => Some(Axis::DPadY),
+                        _ => None,
+                    };
+                    if let Some(dpad_axis) = dpad_axis {
+                        event.event = EventType::AxisChanged(dpad_axis, snap_dpad(value), code);
+                        return event;
+                    }
+                }
+                if quirk.dpad == Some(DpadSource::Buttons)
+                    && matches!(axis, Axis::DPadX | Axis::DPadY)
+                {
+                    return event.drop();
+                }
+                let value = if quirk.invert_axes.iter().any(|a| a.axis() == axis) {
+                    -value
+                } else if matches!(axis, Axis::LeftZ | Axis::RightZ) {
+                    rescale_trigger(quirk, value)
+                } else {
+                    value
+                };
+                event.event = EventType::AxisChanged(axis, value, code);
+            }
+            EventType::ButtonPressed(button, _)
+            | EventType::ButtonRepeated(button, _)
+            | EventType::ButtonReleased(button, _)
+                if quirk.dpad == Some(DpadSource::Hat) && is_dpad(button) =>
+            {
+                return event.drop();
+            }
+            EventType::ButtonChanged(button, value, code) => {
+                if quirk.dpad == Some(DpadSource::Hat) && is_dpad(button) {
+                    return event.drop();
+                }
+                if matches!(button, Button::LeftTrigger2 | Button::RightTrigger2) {
+                    event.event =
+                        EventType::ButtonChanged(button, rescale_trigger(quirk, value), code);
+                }
+            }
+            _ => {}
+        }
+        event
+    }
+}
+
+/// Evdev code of a gilrs code, if it's of event type `kind`
+fn raw_code(code: Code, kind: evdev::EventType) -> Option<u16> {
+    let raw = code.into_u32();
+    ((raw >> 16) as u16 == kind.0).then_some((raw & 0xFFFF) as u16)
+}
+
+fn is_dpad(button: Button) -> bool {
+    matches!(
+        button,
+        Button::DPadUp | Button::DPadDown | Button::DPadLeft | Button::DPadRight
+    )
+}
+
+/// A stick value as a hat position of -1, 0, or 1
+fn snap_dpad(value: f32) -> f32 {
+    if value > STICK_DPAD_THRESHOLD {
+        1.0
+    } else if value < -STICK_DPAD_THRESHOLD {
+        -1.0
+    } else {
+        0.0
+    }
+}
+
+fn rescale_trigger(quirk: &Quirk, value: f32) -> f32 {
+    match quirk.trigger_max {
+        Some(max) => (value / max.clamp(0.1, 1.0)).min(1.0),
+        None => value,
+    }
+}
@@ -0,0 +1,190 @@
+use crate::crash_helpers;
+use crate::state;
+use crate::sudo_helpers;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directories udev reads rules from, in order of precedence
+const UDEV_RULE_DIRS: [&str; 4] = [
+    "/etc/udev/rules.d",
+    "/run/udev/rules.d",
+    "/usr/lib/udev/rules.d",
+    "/lib/udev/rules.d",
+];
+
+/// How many of the latest crash reports are archived
+const CRASH_REPORTS: usize = 5;
+
+/// How many lines of the system journal are archived
+const JOURNAL_LINES: &str = "500";
+
+/// Build the summary of a diagnostic report, given the `list --verbose` output
+///
+/// Like crash reports, hook commands are left out of the config. Logs are
+/// archived alongside by [`write_archive`].
+pub fn build_report(controllers: &str) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "CtrlAssist {}", env!("CARGO_PKG_VERSION"));
+    let kernel = fs::read_to_string("/proc/sys/kernel/osrelease");
+    let _ = writeln!(
+        report,
+        "Kernel: {}",
+        kernel.as_deref().map_or("unknown", str::trim)
+    );
+    let sandbox = if Path::new("/.flatpak-info").exists() {
+        "Flatpak"
+    } else {
+        "none"
+    };
+    let _ = writeln!(report, "Sandbox: {}\n", sandbox);
+
+    let _ = writeln!(report, "Input access:");
+    let _ = writeln!(report, "  uinput module: {}", uinput_module());
+    let _ = writeln!(report, "  /dev/uinput:   {}", node_access("/dev/uinput"));
+    let _ = writeln!(report, "  Event nodes:   {}\n", event_node_access());
+
+    let _ = writeln!(report, "udev rules for uinput:");
+    let rules = uinput_rules();
+    if rules.is_empty() {
+        let _ = writeln!(report, "  None found");
+    }
+    for rule in rules {
+        let _ = writeln!(report, "  {}", rule);
+    }
+
+    let _ = writeln!(report, "\nControllers:\n{}", controllers);
+
+    if let Some(config) = crash_helpers::scrubbed_config() {
+        let _ = writeln!(report, "Config:\n{}", config);
+    }
+
+    report
+}
+
+/// Save a diagnostic report as a gzipped tarball at `path`, with the summary,
+/// CtrlAssist's recent journal lines, the latest crash reports, and the
+/// audit log
+pub fn write_archive(summary: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(".tar.gz"))
+        .unwrap_or("ctrlassist-report");
+    let staging = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    let dir = staging.join(name);
+    fs::create_dir_all(&dir)?;
+    let result = stage_archive(summary, &dir).and_then(|()| {
+        let status = Command::new("tar")
+            .arg("-czf")
+            .arg(path)
+            .arg("-C")
+            .arg(&staging)
+            .arg(name)
+            .status()?;
+        if !status.success() {
+            return Err(format!("tar failed with {}", status).into());
+        }
+        sudo_helpers::hand_over(path);
+        Ok(())
+    });
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+fn stage_archive(summary: &str, dir: &Path) -> Result<(), Box<dyn Error>> {
+    fs::write(dir.join("report.txt"), summary)?;
+    // Logs are otherwise only kept in memory, so take what the journal has,
+    // e.g. from the tray or a service
+    if let Some(journal) = journal_lines() {
+        fs::write(dir.join("journal.log"), journal)?;
+    }
+    for crash in state::recent("crash", CRASH_REPORTS) {
+        if let Some(file_name) = crash.file_name() {
+            fs::copy(&crash, dir.join(file_name))?;
+        }
+    }
+    if let Some(audit) = state::dir().map(|state| state.join("audit.log"))
+        && audit.exists()
+    {
+        fs::copy(&audit, dir.join("audit.log"))?;
+    }
+    Ok(())
+}
+
+/// Recent journal lines of CtrlAssist processes, if journald has any
+fn journal_lines() -> Option<String> {
+    let output = Command::new("journalctl")
+        .args(["--no-pager", "--lines", JOURNAL_LINES, "_COMM=ctrlassist"])
+        .output()
+        .ok()?;
+    let lines = String::from_utf8_lossy(&output.stdout).into_owned();
+    (output.status.success() && !lines.trim().is_empty()).then_some(lines)
+}
+
+fn uinput_module() -> &'static str {
+    if Path::new("/sys/module/uinput").exists() {
+        "loaded"
+    } else if Path::new("/dev/uinput").exists() {
+        "built in"
+    } else {
+        "not loaded, try `sudo modprobe uinput`"
+    }
+}
+
+fn node_access(path: &str) -> String {
+    if !Path::new(path).exists() {
+        return "missing".into();
+    }
+    match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(_) => "writable".into(),
+        Err(e) => format!("not writable ({})", e),
+    }
+}
+
+/// How many input event nodes the current user can read
+fn event_node_access() -> String {
+    let Ok(entries) = fs::read_dir("/dev/input") else {
+        return "/dev/input unreadable".into();
+    };
+    let nodes: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("event"))
+        })
+        .collect();
+    let readable = nodes
+        .iter()
+        .filter(|path| OpenOptions::new().read(true).open(path).is_ok())
+        .count();
+    format!("{} of {} readable", readable, nodes.len())
+}
+
+/// Rule lines mentioning uinput, prefixed by their file
+fn uinput_rules() -> Vec<String> {
+    let mut rules = Vec::new();
+    for dir in UDEV_RULE_DIRS {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+        paths.sort();
+        for path in paths {
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            for line in content.lines() {
+                let line = line.trim();
+                if !line.starts_with('#') && line.contains("uinput") {
+                    rules.push(format!("{}: {}", path.display(), line));
+                }
+            }
+        }
+    }
+    rules
+}
@@ -0,0 +1,114 @@
+use crate::ff_helpers::{FfHealth, MotorMap, PhysicalFFDev, RumbleChannel};
+use crate::gilrs_helper::GamepadResource;
+use clap::ValueEnum;
+use evdev::{FFEffectData, FFEffectKind, FFReplay, FFTrigger};
+use parking_lot::Mutex;
+use std::error::Error;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Effect ID the test effects are uploaded under, as a game's would be
+const TEST_EFFECT_ID: i16 = 0;
+
+/// On and off time of each pulse
+const PULSE_PERIOD: Duration = Duration::from_millis(250);
+
+/// Magnitude steps of a ramp, from faint to full
+const RAMP_STEPS: u32 = 8;
+
+/// Magnitude of constant and pulse effects, about three quarters of full
+const TEST_MAGNITUDE: u16 = 0xC000;
+
+/// Test effect played by `ctrlassist rumble`
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum RumblePattern {
+    /// Steady rumble on both motors
+    #[default]
+    Constant,
+    /// Rumble switching on and off
+    Pulse,
+    /// Rumble rising from faint to full
+    Ramp,
+}
+
+/// Play a test `pattern` on `resource` for `duration`, through the same path
+/// the mux forwards a game's rumble on, describing how the device took it
+///
+/// Rumble that works here but not in a game points at the game or the
+/// virtual gamepad, rather than the controller or its driver.
+pub fn play(
+    resource: GamepadResource,
+    motors: MotorMap,
+    pattern: RumblePattern,
+    duration: Duration,
+) -> Result<String, Box<dyn Error>> {
+    let health = Arc::new(Mutex::new(FfHealth::default()));
+    let path = resource.path.clone();
+    let mut target = PhysicalFFDev::new(
+        resource,
+        Arc::clone(&health),
+        RumbleChannel::Both,
+        motors,
+        Vec::new(),
+    );
+
+    let result = match pattern {
+        RumblePattern::Constant => play_step(&mut target, TEST_MAGNITUDE, duration),
+        RumblePattern::Pulse => {
+            let pulses = (duration.as_millis() / (2 * PULSE_PERIOD.as_millis())).max(1);
+            (0..pulses).try_for_each(|_| {
+                play_step(&mut target, TEST_MAGNITUDE, PULSE_PERIOD)?;
+                thread::sleep(PULSE_PERIOD);
+                Ok(())
+            })
+        }
+        RumblePattern::Ramp => (1..=RAMP_STEPS).try_for_each(|step| {
+            let magnitude = (u32::from(u16::MAX) * step / RAMP_STEPS) as u16;
+            play_step(&mut target, magnitude, duration / RAMP_STEPS)
+        }),
+    };
+    let _ = target.erase_effect(TEST_EFFECT_ID);
+
+    let health = health.lock();
+    let mut report = vec![format!("Motor mapping: {:?}", motors)];
+    if let Some(target) = health.targets.get(&path)
+        && target.unsupported > 0
+    {
+        report.push("Device rejected rumble as an unsupported effect".into());
+    }
+    match result {
+        Ok(()) => {
+            report.push(format!("Played {:?} rumble on {}", pattern, path.display()));
+            Ok(report.join("\n"))
+        }
+        Err(e) => {
+            report.push(format!("Rumble failed on {}: {}", path.display(), e));
+            Err(report.join("\n").into())
+        }
+    }
+}
+
+/// Upload and play a rumble of `magnitude` on both motors, waiting until it
+/// has played out
+fn play_step(target: &mut PhysicalFFDev, magnitude: u16, length: Duration) -> std::io::Result<()> {
+    let effect = FFEffectData {
+        direction: 0,
+        trigger: FFTrigger {
+            button: 0,
+            interval: 0,
+        },
+        replay: FFReplay {
+            length: u16::try_from(length.as_millis()).unwrap_or(u16::MAX),
+            delay: 0,
+        },
+        kind: FFEffectKind::Rumble {
+            strong_magnitude: magnitude,
+            weak_magnitude: magnitude,
+        },
+    };
+    target.upload_effect(TEST_EFFECT_ID, effect)?;
+    target.control_effect(TEST_EFFECT_ID, true)?;
+    thread::sleep(length);
+    Ok(())
+}
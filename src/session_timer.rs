@@ -0,0 +1,150 @@
+use crate::game_profiles::GameProfile;
+use crate::mux_runtime::RuntimeSettings;
+use crate::notifier::{self, Note};
+use log::{info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// What happens once a session's time is up
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum LimitAction {
+    /// End the session
+    #[default]
+    Stop,
+    /// Switch to a profile's mode, e.g. one leaving control to the assist
+    Profile(String),
+}
+
+/// Screen-time boundary enforced at the input layer, with a warning ahead
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct TimeLimitConfig {
+    /// Session length before the action is taken (0 disables)
+    pub minutes: u64,
+    /// How long before the limit to warn the players
+    pub warn_minutes: u64,
+    pub action: LimitAction,
+}
+
+impl Default for TimeLimitConfig {
+    fn default() -> Self {
+        Self {
+            minutes: 0,
+            warn_minutes: 5,
+            action: LimitAction::Stop,
+        }
+    }
+}
+
+/// Counts down a session, warning once and then winding it down
+pub struct SessionTimer {
+    warn_at: Option<Instant>,
+    limit_at: Option<Instant>,
+    action: LimitAction,
+    profile: Option<GameProfile>,
+}
+
+impl SessionTimer {
+    /// The timer, counting from `started`, or None if disabled
+    ///
+    /// A session restarted after sleep or by kiosk mode keeps counting from
+    /// when the first one started, rather than getting the full time again.
+    pub fn new(
+        config: &TimeLimitConfig,
+        profiles: &BTreeMap<String, GameProfile>,
+        started: Instant,
+    ) -> Option<Self> {
+        if config.minutes == 0 {
+            return None;
+        }
+        let profile = match &config.action {
+            LimitAction::Stop => None,
+            LimitAction::Profile(name) => match profiles.get(name) {
+                Some(profile) => Some(profile.clone()),
+                None => {
+                    warn!("Time limit: profile '{}' not found, stopping instead", name);
+                    None
+                }
+            },
+        };
+        let now = Instant::now();
+        let limit = Duration::from_secs(config.minutes.saturating_mul(60));
+        let warn =
+            limit.saturating_sub(Duration::from_secs(config.warn_minutes.saturating_mul(60)));
+        // Limits too far off to represent are never reached
+        let limit_at = started.checked_add(limit);
+        // A restart within the warning period warns again, with the time left
+        let warn_at = started
+            .checked_add(warn)
+            .filter(|_| config.warn_minutes > 0 && limit_at.is_some_and(|at| now < at))
+            .map(|at| at.max(now));
+        info!("Time limit: {} minutes", config.minutes);
+        Some(Self {
+            warn_at,
+            limit_at,
+            action: config.action.clone(),
+            profile,
+        })
+    }
+
+    /// Warn or wind down once due, returning whether the session should stop
+    pub fn tick(&mut self, now: Instant, settings: &RuntimeSettings) -> bool {
+        if let Some(warn_at) = self.warn_at
+            && now >= warn_at
+        {
+            self.warn_at = None;
+            let left = self
+                .limit_at
+                .map_or(0, |at| at.duration_since(now).as_secs());
+            let then = match self.profile {
+                Some(_) => "switches to its wind-down profile",
+                None => "ends",
+            };
+            info!("Time limit: warning, {}s left", left);
+            settings.request_alert();
+            notify(format!(
+                "The session {} in {} minute(s)",
+                then,
+                left.div_ceil(60)
+            ));
+        }
+
+        if self.limit_at.is_none_or(|at| now < at) {
+            return false;
+        }
+        self.limit_at = None;
+        match (&self.action, &self.profile) {
+            (LimitAction::Profile(name), Some(profile)) => {
+                info!("Time limit reached, switching to profile {}", name);
+                let mut mode = settings.get_mode();
+                let mut mode_options = settings.get_mode_options();
+                profile.apply_mode(&mut mode, &mut mode_options);
+                settings.update_mode(mode);
+                settings.update_mode_options(mode_options);
+                notify(format!("Time's up: switched to {}", name));
+                false
+            }
+            _ => {
+                info!("Time limit reached, stopping session");
+                notify("Time's up: session stopped".into());
+                settings.request_stop();
+                true
+            }
+        }
+    }
+
+    /// Next instant the timer has something to do, if any
+    pub fn next_deadline(&self) -> Option<Instant> {
+        [self.warn_at, self.limit_at].into_iter().flatten().min()
+    }
+}
+
+/// Tell the players, and whoever watches over the session
+fn notify(body: String) {
+    notifier::send(Note {
+        timeout: Some(Duration::from_secs(10)),
+        ..Note::new("CtrlAssist", body)
+    });
+}
@@ -0,0 +1,188 @@
+use crate::evdev_helpers::{self, READ_POLL_INTERVAL, VIRTUAL_KEYS};
+use evdev::{Device, EventSummary, KeyCode};
+use gilrs::{Button, Event, EventType, GamepadId, Gilrs};
+use log::{debug, info, warn};
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often key changes from sibling nodes are forwarded
+const TICK_INTERVAL: Duration = Duration::from_millis(8);
+
+/// Buttons sibling keys may press, by their virtual gamepad key
+const SIBLING_BUTTONS: &[Button] = &[
+    Button::South,
+    Button::East,
+    Button::North,
+    Button::West,
+    Button::LeftTrigger,
+    Button::RightTrigger,
+    Button::LeftTrigger2,
+    Button::RightTrigger2,
+    Button::LeftThumb,
+    Button::RightThumb,
+    Button::Select,
+    Button::Start,
+    Button::Mode,
+    Button::DPadUp,
+    Button::DPadDown,
+    Button::DPadLeft,
+    Button::DPadRight,
+];
+
+/// Forwarding keys from a controller's other event nodes
+///
+/// Many controllers split inputs across several nodes, e.g. a share button
+/// on a consumer-control node, of which gilrs only reads the gamepad one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct SiblingConfig {
+    pub enabled: bool,
+    /// Sibling keys remapped onto virtual gamepad buttons by evdev name, e.g.
+    /// `KEY_RECORD = "BTN_SELECT"`; others pass through if the virtual gamepad
+    /// has them
+    pub keys: BTreeMap<String, String>,
+}
+
+impl SiblingConfig {
+    /// Resolve key remaps to evdev codes, skipping names evdev doesn't know
+    pub fn key_map(&self) -> HashMap<u16, u16> {
+        self.keys
+            .iter()
+            .filter_map(
+                |(from, to)| match (KeyCode::from_str(from), KeyCode::from_str(to)) {
+                    (Ok(from), Ok(to)) => Some((from.0, to.0)),
+                    _ => {
+                        warn!("Siblings: ignoring unknown key mapping {} = {}", from, to);
+                        None
+                    }
+                },
+            )
+            .collect()
+    }
+}
+
+/// A key change on a sibling node, already mapped to a virtual button
+#[derive(Debug, Clone, Copy)]
+pub struct KeyChange {
+    pub source: GamepadId,
+    pub code: u16,
+    pub pressed: bool,
+}
+
+/// Read keys from a sibling node of `source` until shutdown
+pub fn spawn_sibling_reader(
+    path: &Path,
+    source: GamepadId,
+    key_map: Arc<HashMap<u16, u16>>,
+    changes: Arc<Mutex<Vec<KeyChange>>>,
+    shutdown: Arc<AtomicBool>,
+) -> Result<thread::JoinHandle<()>, Box<dyn std::error::Error>> {
+    let mut device = Device::open(path)?;
+
+    info!("Siblings: reading keys from {}", path.display());
+    Ok(thread::spawn(move || {
+        while !shutdown.load(Ordering::SeqCst) {
+            let events = match evdev_helpers::wait_readable(&device, READ_POLL_INTERVAL)
+                .and_then(|readable| readable.then(|| device.fetch_events()).transpose())
+            {
+                Ok(Some(events)) => events,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Siblings: node stopped: {}", e);
+                    return;
+                }
+            };
+            for event in events {
+                let EventSummary::Key(_, key, value) = event.destructure() else {
+                    continue;
+                };
+                // Skip autorepeat, only presses and releases matter
+                if value > 1 {
+                    continue;
+                }
+                let code = key_map.get(&key.0).copied().unwrap_or(key.0);
+                if VIRTUAL_KEYS.iter().any(|k| k.0 == code) {
+                    changes.lock().push(KeyChange {
+                        source,
+                        code,
+                        pressed: value == 1,
+                    });
+                }
+            }
+        }
+    }))
+}
+
+/// Feeds sibling keys to the mux mode as button events of their controller
+///
+/// Keys go through the mode like any other button, so e.g. in Toggle mode
+/// only the active controller's share button reaches the game.
+pub struct SiblingInput {
+    changes: Option<Arc<Mutex<Vec<KeyChange>>>>,
+    last_tick: Instant,
+}
+
+impl SiblingInput {
+    pub fn new(changes: Option<Arc<Mutex<Vec<KeyChange>>>>) -> Self {
+        Self {
+            changes,
+            last_tick: Instant::now(),
+        }
+    }
+
+    /// Append button events for sibling key changes since the last tick
+    ///
+    /// Keys become the button of the virtual key they map to, under the code
+    /// gilrs uses for it on their controller; keys for buttons the controller
+    /// lacks are dropped.
+    pub fn tick(&mut self, gilrs: &Gilrs, now: Instant, out: &mut Vec<Event>) {
+        let Some(changes) = &self.changes else {
+            return;
+        };
+        if now < self.last_tick + TICK_INTERVAL {
+            return;
+        }
+        self.last_tick = now;
+
+        for change in changes.lock().drain(..) {
+            let Some(btn) = SIBLING_BUTTONS.iter().copied().find(|&btn| {
+                evdev_helpers::gilrs_button_to_evdev_key(btn).map(|key| key.0) == Some(change.code)
+            }) else {
+                continue;
+            };
+            let Some(code) = gilrs.gamepad(change.source).button_code(btn) else {
+                debug!(
+                    "Siblings: controller {} has no {:?} to press",
+                    change.source, btn
+                );
+                continue;
+            };
+            let (transition, value) = if change.pressed {
+                (EventType::ButtonPressed(btn, code), 1.0)
+            } else {
+                (EventType::ButtonReleased(btn, code), 0.0)
+            };
+            out.push(Event::new(change.source, transition));
+            out.push(Event::new(
+                change.source,
+                EventType::ButtonChanged(btn, value, code),
+            ));
+        }
+    }
+
+    /// When sibling keys are next forwarded, if any nodes are read
+    pub fn next_deadline(&self) -> Option<Instant> {
+        // Sibling input doesn't wake the input loop, so poll while nodes are read
+        self.changes
+            .as_ref()
+            .map(|_| self.last_tick + TICK_INTERVAL)
+    }
+}
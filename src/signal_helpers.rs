@@ -0,0 +1,72 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static CYCLE_MODE: AtomicBool = AtomicBool::new(false);
+static CYCLE_RUMBLE: AtomicBool = AtomicBool::new(false);
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+/// Runtime control requested by a signal to the CLI mux
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlSignal {
+    /// SIGUSR1: switch to the next mux mode
+    CycleMode,
+    /// SIGUSR2: switch to the next rumble target
+    CycleRumble,
+    /// SIGHUP: reload live options from the config file, when detached
+    Reload,
+}
+
+static SIGNALS: [(libc::c_int, ControlSignal, &AtomicBool); 3] = [
+    (libc::SIGUSR1, ControlSignal::CycleMode, &CYCLE_MODE),
+    (libc::SIGUSR2, ControlSignal::CycleRumble, &CYCLE_RUMBLE),
+    (libc::SIGHUP, ControlSignal::Reload, &RELOAD),
+];
+
+/// Only flags the signal, as little else is safe in a signal handler
+extern "C" fn flag_signal(signum: libc::c_int) {
+    for &(sig, _, flag) in &SIGNALS {
+        if sig == signum {
+            flag.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Whether the process has a controlling terminal, e.g. isn't run as a
+/// service
+fn has_terminal() -> bool {
+    std::fs::File::open("/dev/tty").is_ok()
+}
+
+/// Install handlers for the control signals
+///
+/// Must run after the Ctrl+C handler is set, as that also claims SIGHUP to
+/// shut down. With a controlling terminal SIGHUP is left to shut down, since
+/// it means the terminal closed and nobody is left to restore devices.
+pub fn install_control_handlers() -> io::Result<()> {
+    let reload = !has_terminal();
+    for &(sig, signal, _) in &SIGNALS {
+        if signal == ControlSignal::Reload && !reload {
+            continue;
+        }
+        // SAFETY: the handler only stores to atomics, which is async-signal-safe
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = flag_signal as extern "C" fn(libc::c_int) as usize;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(sig, &action, std::ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Take control signals received since the last call
+pub fn take_pending() -> Vec<ControlSignal> {
+    SIGNALS
+        .iter()
+        .filter(|(_, _, flag)| flag.swap(false, Ordering::SeqCst))
+        .map(|(_, signal, _)| *signal)
+        .collect()
+}
@@ -0,0 +1,128 @@
+use futures_util::StreamExt;
+use log::{info, warn};
+use std::future::Future;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use zbus::zvariant::OwnedFd;
+
+/// Longest a session may take to stop before sleep goes ahead anyway, within
+/// logind's default InhibitDelayMaxSec of 5s
+const QUIESCE_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// Time given to controllers to reappear after resume, before sessions start
+const RESUME_SETTLE: Duration = Duration::from_secs(2);
+
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<OwnedFd>;
+
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+/// A change of system sleep state
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SleepEvent {
+    /// The system is about to sleep
+    Suspending,
+    /// The system woke up
+    Resumed,
+}
+
+/// Follow system sleep through logind, calling `handle` before the system
+/// sleeps and after it wakes
+///
+/// Controller nodes go stale over sleep, and Bluetooth pads come back as new
+/// nodes, so sessions are stopped before and started afresh after. A delay
+/// inhibitor is held while awake, so sleep waits until `handle` has stopped
+/// the session and restored hidden controllers.
+pub async fn watch<F, Fut>(mut handle: F) -> zbus::Result<()>
+where
+    F: FnMut(SleepEvent) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let connection = zbus::Connection::system().await?;
+    let manager = ManagerProxy::new(&connection).await?;
+    let mut signals = manager.receive_prepare_for_sleep().await?;
+    let mut inhibitor = inhibit(&manager).await;
+
+    while let Some(signal) = signals.next().await {
+        if signal.args()?.start {
+            info!("System going to sleep, stopping session");
+            handle(SleepEvent::Suspending).await;
+            // Let sleep go ahead
+            drop(inhibitor.take());
+        } else {
+            info!("System resumed");
+            inhibitor = inhibit(&manager).await;
+            let _ = tokio::task::spawn_blocking(|| thread::sleep(RESUME_SETTLE)).await;
+            handle(SleepEvent::Resumed).await;
+        }
+    }
+    Ok(())
+}
+
+async fn inhibit(manager: &ManagerProxy<'_>) -> Option<OwnedFd> {
+    match manager
+        .inhibit(
+            "sleep",
+            "CtrlAssist",
+            "Restore hidden controllers before sleep",
+            "delay",
+        )
+        .await
+    {
+        Ok(fd) => Some(fd),
+        Err(e) => {
+            warn!(
+                "Sleep: failed to take inhibitor, hiding may outlast sleep: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Follow system sleep on a thread of its own, for callers without an async
+/// runtime
+///
+/// Each event comes with a sender to signal once it's been acted on; sleep is
+/// delayed until then, or until the sender is dropped.
+pub fn spawn() -> mpsc::Receiver<(SleepEvent, mpsc::Sender<()>)> {
+    let (event_tx, event_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                warn!("Sleep: failed to start runtime: {}", e);
+                return;
+            }
+        };
+        let result = runtime.block_on(watch(|event| {
+            let event_tx = event_tx.clone();
+            async move {
+                let (done_tx, done_rx) = mpsc::channel();
+                if event_tx.send((event, done_tx)).is_ok() {
+                    let _ =
+                        tokio::task::spawn_blocking(move || done_rx.recv_timeout(QUIESCE_TIMEOUT))
+                            .await;
+                }
+            }
+        }));
+        if let Err(e) = result {
+            warn!(
+                "Sleep: unable to follow system sleep, sessions won't survive it: {}",
+                e
+            );
+        }
+    });
+    event_rx
+}
@@ -0,0 +1,115 @@
+use crate::gilrs_helper::GamepadResource;
+use log::{info, warn};
+use std::fs;
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// EVIOCGRAB from linux/input.h, `_IOW('E', 0x90, int)`
+const EVIOCGRAB: libc::Ioctl = 0x4004_4590;
+
+/// A source node grabbed through the handle gilrs reads it with
+struct Grab {
+    fd: RawFd,
+    path: PathBuf,
+    /// Device number of the node, to tell if `fd` still refers to it
+    rdev: u64,
+}
+
+/// Exclusive grabs of the source controllers' event nodes for a session
+///
+/// A grab routes a node's events only to the handle holding it, so games and
+/// other readers see nothing from the sources whatever the hiding. It's taken
+/// on gilrs's own handle, found among this process's open files, so the mux
+/// keeps reading through it. The kernel releases a grab when its handle is
+/// closed, as gilrs does once a controller disconnects; the rest are
+/// released when dropped.
+#[derive(Default)]
+pub struct SourceGrabs {
+    grabs: Vec<Grab>,
+}
+
+impl SourceGrabs {
+    /// Grab a source's event node, leaving it ungrabbed if gilrs's handle
+    /// can't be told apart from others
+    pub fn grab(&mut self, resource: &GamepadResource) {
+        let path = fs::canonicalize(&resource.path).unwrap_or_else(|_| resource.path.clone());
+        let rdev = match fs::metadata(&path) {
+            Ok(metadata) => metadata.rdev(),
+            Err(e) => {
+                warn!("Grab: failed to stat {}: {}", path.display(), e);
+                return;
+            }
+        };
+        // gilrs also keeps a write-only handle for rumble, which reads nothing
+        let own = resource.device.as_raw_fd();
+        let handles: Vec<RawFd> = handles_on(&path)
+            .into_iter()
+            .filter(|&fd| fd != own && is_readable(fd))
+            .collect();
+        let [fd] = handles[..] else {
+            warn!(
+                "Grab: found {} handles reading {}, not grabbing it",
+                handles.len(),
+                path.display()
+            );
+            return;
+        };
+        match set_grab(fd, true) {
+            Ok(()) => {
+                info!("Grabbed {} ({})", resource.name, path.display());
+                self.grabs.push(Grab { fd, path, rdev });
+            }
+            Err(e) => warn!("Grab: failed to grab {}: {}", path.display(), e),
+        }
+    }
+
+    /// Release every grab still held, skipping handles closed since
+    pub fn release(&mut self) {
+        for grab in self.grabs.drain(..) {
+            // A closed handle's number may since have been reused
+            let same = fs::metadata(format!("/proc/self/fd/{}", grab.fd))
+                .is_ok_and(|metadata| metadata.rdev() == grab.rdev);
+            if !same {
+                continue;
+            }
+            match set_grab(grab.fd, false) {
+                Ok(()) => info!("Released grab of {}", grab.path.display()),
+                Err(e) => warn!("Failed to release grab of {}: {}", grab.path.display(), e),
+            }
+        }
+    }
+}
+
+impl Drop for SourceGrabs {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// This process's open handles on `path`
+fn handles_on(path: &Path) -> Vec<RawFd> {
+    let Ok(entries) = fs::read_dir("/proc/self/fd") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| fs::read_link(entry.path()).is_ok_and(|target| target == path))
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .collect()
+}
+
+fn is_readable(fd: RawFd) -> bool {
+    // SAFETY: F_GETFL only reads the flags of the descriptor
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    flags >= 0 && flags & libc::O_ACCMODE != libc::O_WRONLY
+}
+
+fn set_grab(fd: RawFd, grab: bool) -> io::Result<()> {
+    // SAFETY: EVIOCGRAB takes its argument by value and touches no memory
+    if unsafe { libc::ioctl(fd, EVIOCGRAB, libc::c_int::from(grab)) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use crate::sudo_helpers;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// State that outlives a session, `$XDG_STATE_HOME/ctrlassist`
+///
+/// Kept apart from the config directory, as it holds what CtrlAssist writes
+/// for itself: journals of system changes still to undo, backups, the audit
+/// log, and crash reports. Under sudo this is the state directory of the
+/// user who ran sudo, so they can restore and clean up without it.
+pub fn dir() -> Option<PathBuf> {
+    let base = match sudo_helpers::sudo_user() {
+        Some((uid, _)) => sudo_helpers::user_home(uid)?.join(".local/state"),
+        None => dirs::state_dir().or_else(dirs::data_local_dir)?,
+    };
+    Some(base.join("ctrlassist"))
+}
+
+/// Path of an entry in the state directory, e.g. `steam/journal.toml`,
+/// creating its parent directories
+pub fn path(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let path = dir()
+        .ok_or("Could not determine state directory")?
+        .join(name);
+    if let Some(parent) = path.parent() {
+        create_dirs(parent)?;
+    }
+    Ok(path)
+}
+
+/// Create a directory and its parents, handing those created under sudo to
+/// the invoking user
+fn create_dirs(dir: &Path) -> io::Result<()> {
+    if dir.is_dir() {
+        return Ok(());
+    }
+    if let Some(parent) = dir.parent() {
+        create_dirs(parent)?;
+    }
+    match fs::create_dir(dir) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+        _ => sudo_helpers::hand_over(dir),
+    }
+    Ok(())
+}
+
+/// Write an entry in the state directory, owned by the invoking user under sudo
+pub fn write(path: &Path, content: impl AsRef<[u8]>) -> io::Result<()> {
+    fs::write(path, content)?;
+    sudo_helpers::hand_over(path);
+    Ok(())
+}
+
+/// Save a record as TOML, replacing any earlier one
+pub fn save<T: Serialize>(name: &str, record: &T) -> Result<(), Box<dyn Error>> {
+    write(&path(name)?, toml::to_string_pretty(record)?)?;
+    Ok(())
+}
+
+/// A saved record, if there is a readable one
+pub fn load<T: DeserializeOwned>(name: &str) -> Option<T> {
+    let content = fs::read_to_string(dir()?.join(name)).ok()?;
+    toml::from_str(&content).ok()
+}
+
+/// Remove a saved record, e.g. once the changes it journals are undone
+pub fn remove(name: &str) {
+    if let Some(dir) = dir() {
+        let _ = fs::remove_file(dir.join(name));
+    }
+}
+
+/// Save a report as `<prefix>-<timestamp>.txt`, returning its path
+pub fn save_timestamped(prefix: &str, report: &str) -> Option<PathBuf> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let path = path(&format!("{}-{}.txt", prefix, timestamp)).ok()?;
+    write(&path, report).ok()?;
+    Some(path)
+}
+
+/// Saved reports with a prefix, most recent first, up to `count`
+pub fn recent(prefix: &str, count: usize) -> Vec<PathBuf> {
+    let prefix = format!("{}-", prefix);
+    let Some(entries) = dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<(SystemTime, PathBuf)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&prefix))
+        })
+        .filter_map(|path| Some((fs::metadata(&path).and_then(|m| m.modified()).ok()?, path)))
+        .collect();
+    paths.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    paths
+        .into_iter()
+        .take(count)
+        .map(|(_, path)| path)
+        .collect()
+}
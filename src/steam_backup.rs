@@ -0,0 +1,115 @@
+use crate::audit_log;
+use crate::state;
+use crate::sudo_helpers;
+use crate::udev_helpers::{parse_controller_blacklist, update_steam_config};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Record of a session's Steam blacklist changes, kept until they're undone
+///
+/// Saved before each change, so a crash or a replaced config.vdf can still be
+/// recovered with `ctrlassist restore --steam`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SteamJournal {
+    pub config_path: PathBuf,
+    /// Copy of config.vdf from before the first change
+    pub backup: PathBuf,
+    /// Controller IDs added to the blacklist, in order
+    pub added_ids: Vec<String>,
+}
+
+const JOURNAL: &str = "steam/journal.toml";
+
+impl SteamJournal {
+    /// Back up config.vdf and start a journal of a session's changes
+    pub fn begin(config_path: &Path) -> Result<Self, Box<dyn Error>> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let backup = state::path(&format!("steam/config-{}.vdf", timestamp))?;
+        fs::copy(config_path, &backup)
+            .map_err(|e| format!("Failed to back up Steam config: {}", e))?;
+        sudo_helpers::hand_over(&backup);
+        log::info!("Backed up Steam config to {}", backup.display());
+
+        Ok(Self {
+            config_path: config_path.to_path_buf(),
+            backup,
+            added_ids: Vec::new(),
+        })
+    }
+
+    /// Persist the journal, before the changes it records are made
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        state::save(JOURNAL, self)
+    }
+
+    /// The journal of a session whose changes weren't undone, if any
+    pub fn load() -> Option<Self> {
+        state::load(JOURNAL)
+    }
+
+    /// Remove the journal once its changes are undone; backups are kept
+    pub fn clear() {
+        state::remove(JOURNAL);
+    }
+}
+
+/// A blacklist with journaled IDs taken out
+///
+/// Session IDs were appended, so the last match of each is removed, keeping
+/// any earlier entry the user had themselves.
+pub fn without_ids(blacklist: &str, added_ids: &[String]) -> String {
+    let mut ids: Vec<&str> = blacklist.split(',').filter(|id| !id.is_empty()).collect();
+    for added in added_ids.iter().rev() {
+        if let Some(idx) = ids.iter().rposition(|id| id == added) {
+            ids.remove(idx);
+        }
+    }
+    ids.join(",")
+}
+
+/// Undo the blacklist changes of an unrestored session, describing what was done
+///
+/// Only the journaled IDs are removed, keeping any changes Steam made since.
+/// If config.vdf is gone or unreadable, the backup is restored instead.
+pub fn restore_steam() -> Result<String, Box<dyn Error>> {
+    let journal = SteamJournal::load().ok_or("No unrestored Steam blacklist changes found")?;
+
+    let msg = match fs::read_to_string(&journal.config_path) {
+        Ok(content) => {
+            let current = parse_controller_blacklist(&content).unwrap_or_default();
+            update_steam_config(
+                &journal.config_path,
+                &without_ids(&current, &journal.added_ids),
+            )?;
+            format!(
+                "Removed {} from the Steam blacklist in {}",
+                journal.added_ids.join(", "),
+                journal.config_path.display()
+            )
+        }
+        Err(e) => {
+            log::warn!("Steam config unreadable, restoring backup: {}", e);
+            fs::copy(&journal.backup, &journal.config_path)?;
+            sudo_helpers::hand_over(&journal.config_path);
+            audit_log::record(
+                "restore-backup",
+                &journal.config_path.display().to_string(),
+                "unreadable",
+                &journal.backup.display().to_string(),
+            );
+            format!(
+                "Restored {} from {}",
+                journal.config_path.display(),
+                journal.backup.display()
+            )
+        }
+    };
+
+    SteamJournal::clear();
+    Ok(msg)
+}
@@ -0,0 +1,190 @@
+use evdev::{AbsoluteAxisCode, EventType, InputEvent};
+use gilrs::GamepadId;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::time::{Duration, Instant};
+
+/// How often smoothed sticks are advanced while settling toward their target
+const TICK_INTERVAL: Duration = Duration::from_millis(8);
+
+/// Which controller's stick movements are smoothed
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum SmoothingSource {
+    Primary,
+    Assist,
+    #[default]
+    Both,
+}
+
+/// Exponential low-pass smoothing of the virtual sticks
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct SmoothingConfig {
+    /// Whether smoothing starts enabled; can be toggled live from the tray
+    pub enabled: bool,
+    /// Left stick cutoff frequency in Hz (0 disables)
+    pub left_cutoff_hz: f32,
+    /// Right stick cutoff frequency in Hz (0 disables)
+    pub right_cutoff_hz: f32,
+    /// Controller whose movements are smoothed; the other stays raw
+    pub source: SmoothingSource,
+}
+
+impl Default for SmoothingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            left_cutoff_hz: 4.0,
+            right_cutoff_hz: 4.0,
+            source: SmoothingSource::Both,
+        }
+    }
+}
+
+/// Smoothing state of a single virtual axis, in evdev units
+struct AxisState {
+    target: f32,
+    value: f32,
+    last: Instant,
+}
+
+/// Low-pass filter applied to stick events after mux combination
+pub struct StickSmoother {
+    enabled: bool,
+    /// Cutoff frequency per evdev axis code
+    cutoffs: HashMap<u16, f32>,
+    source: SmoothingSource,
+    primary_id: GamepadId,
+    assist_id: GamepadId,
+    axes: HashMap<u16, AxisState>,
+}
+
+impl StickSmoother {
+    pub fn new(config: &SmoothingConfig, primary_id: GamepadId, assist_id: GamepadId) -> Self {
+        let cutoffs = [
+            (AbsoluteAxisCode::ABS_X, config.left_cutoff_hz),
+            (AbsoluteAxisCode::ABS_Y, config.left_cutoff_hz),
+            (AbsoluteAxisCode::ABS_RX, config.right_cutoff_hz),
+            (AbsoluteAxisCode::ABS_RY, config.right_cutoff_hz),
+        ]
+        .into_iter()
+        .filter(|(_, hz)| *hz > 0.0)
+        .map(|(axis, hz)| (axis.0, hz))
+        .collect();
+
+        Self {
+            enabled: config.enabled,
+            cutoffs,
+            source: config.source.clone(),
+            primary_id,
+            assist_id,
+            axes: HashMap::new(),
+        }
+    }
+
+    fn applies_to(&self, id: GamepadId) -> bool {
+        match self.source {
+            SmoothingSource::Primary => id == self.primary_id,
+            SmoothingSource::Assist => id == self.assist_id,
+            SmoothingSource::Both => true,
+        }
+    }
+
+    /// Enable or disable smoothing, snapping any settling axes to their target
+    pub fn set_enabled(&mut self, enabled: bool, out: &mut Vec<InputEvent>) {
+        if self.enabled == enabled {
+            return;
+        }
+        self.enabled = enabled;
+        if enabled {
+            return;
+        }
+
+        for (&code, state) in &mut self.axes {
+            if state.value != state.target {
+                state.value = state.target;
+                out.push(InputEvent::new(
+                    EventType::ABSOLUTE.0,
+                    code,
+                    state.target as i32,
+                ));
+            }
+        }
+    }
+
+    /// Smooth the stick events of a mux output frame caused by `source`
+    pub fn process(&mut self, source: GamepadId, events: &mut [InputEvent], now: Instant) {
+        let smooth = self.enabled && self.applies_to(source);
+
+        for event in events.iter_mut() {
+            if event.event_type() != EventType::ABSOLUTE {
+                continue;
+            }
+            let Some(&cutoff) = self.cutoffs.get(&event.code()) else {
+                continue;
+            };
+
+            let target = event.value() as f32;
+            let state = self.axes.entry(event.code()).or_insert(AxisState {
+                target,
+                value: target,
+                last: now,
+            });
+            state.target = target;
+
+            if smooth {
+                advance(state, cutoff, now);
+                *event = InputEvent::new(EventType::ABSOLUTE.0, event.code(), state.value as i32);
+            } else {
+                state.value = target;
+                state.last = now;
+            }
+        }
+    }
+
+    /// Append events moving settling axes further toward their target
+    pub fn tick(&mut self, now: Instant, out: &mut Vec<InputEvent>) {
+        if !self.enabled {
+            return;
+        }
+
+        for (code, state) in &mut self.axes {
+            if state.value == state.target || now < state.last + TICK_INTERVAL {
+                continue;
+            }
+            advance(state, self.cutoffs[code], now);
+            out.push(InputEvent::new(
+                EventType::ABSOLUTE.0,
+                *code,
+                state.value as i32,
+            ));
+        }
+    }
+
+    /// When settling axes next need a tick, if any
+    pub fn next_deadline(&self) -> Option<Instant> {
+        if !self.enabled {
+            return None;
+        }
+        self.axes
+            .values()
+            .filter(|state| state.value != state.target)
+            .map(|state| state.last + TICK_INTERVAL)
+            .min()
+    }
+}
+
+/// Move an axis toward its target by the time elapsed since its last update
+fn advance(state: &mut AxisState, cutoff_hz: f32, now: Instant) {
+    let dt = now.saturating_duration_since(state.last).as_secs_f32();
+    let alpha = 1.0 - (-TAU * cutoff_hz * dt).exp();
+    state.value += alpha * (state.target - state.value);
+    state.last = now;
+
+    // Snap once within a unit, as output is quantized anyway
+    if (state.target - state.value).abs() < 1.0 {
+        state.value = state.target;
+    }
+}
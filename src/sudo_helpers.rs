@@ -0,0 +1,67 @@
+use std::ffi::{CStr, OsStr};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// The user who ran `sudo ctrlassist ...`, as (uid, gid), if running as root
+/// under sudo
+pub fn sudo_user() -> Option<(u32, u32)> {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    if unsafe { libc::geteuid() } != 0 {
+        return None;
+    }
+    let id = |var| std::env::var(var).ok()?.parse().ok();
+    Some((id("SUDO_UID")?, id("SUDO_GID")?))
+}
+
+/// The user whose access should be hidden: the invoking user under sudo,
+/// otherwise the current user.
+pub fn active_uid() -> u32 {
+    std::env::var("SUDO_UID")
+        .ok()
+        .and_then(|uid| uid.parse().ok())
+        // SAFETY: getuid has no preconditions and cannot fail
+        .unwrap_or_else(|| unsafe { libc::getuid() })
+}
+
+/// Home directory of a user from the password database
+pub fn user_home(uid: u32) -> Option<PathBuf> {
+    let mut buf = vec![0 as libc::c_char; 4096];
+    // SAFETY: passwd is plain old data, filled in by getpwuid_r
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    // SAFETY: buf outlives pwd's string pointers, which are only read below
+    let rc = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc != 0 || result.is_null() || pwd.pw_dir.is_null() {
+        return None;
+    }
+    // SAFETY: pw_dir is a NUL-terminated string within buf
+    let dir = unsafe { CStr::from_ptr(pwd.pw_dir) };
+    Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())))
+}
+
+/// Runtime directory of the invoking user, e.g. `/run/user/1000`
+///
+/// Under sudo `$XDG_RUNTIME_DIR` is usually unset or root's, so the
+/// invoking user's is used if it exists, for them to reach what's placed in it.
+pub fn runtime_dir() -> Option<PathBuf> {
+    match sudo_user() {
+        Some((uid, _)) => Some(PathBuf::from(format!("/run/user/{}", uid))).filter(|d| d.is_dir()),
+        None => dirs::runtime_dir(),
+    }
+}
+
+/// Give a file created under sudo to the invoking user, so they can read
+/// and remove it without sudo
+pub fn hand_over(path: &Path) {
+    let Some((uid, gid)) = sudo_user() else {
+        return;
+    };
+    if let Err(e) = std::os::unix::fs::chown(path, Some(uid), Some(gid)) {
+        log::debug!(
+            "Failed to hand {} over to uid {}: {}",
+            path.display(),
+            uid,
+            e
+        );
+    }
+}
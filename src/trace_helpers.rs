@@ -0,0 +1,217 @@
+use log::warn;
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Environment variable naming a file to write a Chrome trace of the input loop
+pub const CHROME_TRACE_ENV: &str = "CTRLASSIST_CHROME_TRACE";
+
+/// Power-of-two microsecond buckets, covering up to about 8 seconds
+const HISTOGRAM_BUCKETS: usize = 24;
+
+/// Stages of handling a single input event
+#[derive(Debug, Clone, Copy)]
+pub enum Span {
+    /// From gilrs reading the event to the input loop receiving it
+    Receive,
+    /// Filtering and mux mode processing
+    Mode,
+    /// Writing the resulting frame to the virtual device
+    Write,
+}
+
+impl Span {
+    pub fn name(self) -> &'static str {
+        match self {
+            Span::Receive => "receive",
+            Span::Mode => "mode",
+            Span::Write => "write",
+        }
+    }
+}
+
+/// Log-scale histogram of durations, cheap enough to update on every event
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    /// Bucket `i` counts durations below 2^(i+1) microseconds
+    buckets: [u64; HISTOGRAM_BUCKETS],
+    count: u64,
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    /// Upper bound of the bucket holding the given percentile (0-100)
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        let rank = ((percentile / 100.0) * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank.max(1) {
+                return Duration::from_micros(1 << (i + 1)).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+impl fmt::Display for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.count == 0 {
+            return write!(f, "no events");
+        }
+        write!(
+            f,
+            "p50 ≤ {:?}, p99 ≤ {:?}, max {:?} ({} events)",
+            self.percentile(50.0),
+            self.percentile(99.0),
+            self.max,
+            self.count
+        )
+    }
+}
+
+/// Latency histograms for each stage of a mux session
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStats {
+    pub receive: LatencyHistogram,
+    pub mode: LatencyHistogram,
+    pub write: LatencyHistogram,
+}
+
+impl LatencyStats {
+    /// One-line summary of each stage's 99th percentile
+    pub fn summary(&self) -> String {
+        format!(
+            "receive p99 ≤ {:?}, mode p99 ≤ {:?}, write p99 ≤ {:?}",
+            self.receive.percentile(99.0),
+            self.mode.percentile(99.0),
+            self.write.percentile(99.0)
+        )
+    }
+}
+
+impl fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Receive: {}", self.receive)?;
+        writeln!(f, "Mode:    {}", self.mode)?;
+        write!(f, "Write:   {}", self.write)
+    }
+}
+
+/// [`LatencyHistogram`] updated with relaxed atomics, so the input and
+/// writer threads record without taking a lock
+#[derive(Debug, Default)]
+struct AtomicHistogram {
+    buckets: [AtomicU64; HISTOGRAM_BUCKETS],
+    count: AtomicU64,
+    max_nanos: AtomicU64,
+}
+
+impl AtomicHistogram {
+    fn record(&self, duration: Duration) {
+        let micros = duration.as_micros().max(1) as u64;
+        let bucket = (micros.ilog2() as usize).min(HISTOGRAM_BUCKETS - 1);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.max_nanos
+            .fetch_max(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LatencyHistogram {
+        LatencyHistogram {
+            buckets: std::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed)),
+            count: self.count.load(Ordering::Relaxed),
+            max: Duration::from_nanos(self.max_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Lock-free latency recording shared by a session's threads
+#[derive(Debug, Default)]
+pub struct LatencyRecorder {
+    receive: AtomicHistogram,
+    mode: AtomicHistogram,
+    write: AtomicHistogram,
+}
+
+impl LatencyRecorder {
+    fn histogram(&self, span: Span) -> &AtomicHistogram {
+        match span {
+            Span::Receive => &self.receive,
+            Span::Mode => &self.mode,
+            Span::Write => &self.write,
+        }
+    }
+
+    /// Current histograms, for display
+    pub fn snapshot(&self) -> LatencyStats {
+        LatencyStats {
+            receive: self.receive.snapshot(),
+            mode: self.mode.snapshot(),
+            write: self.write.snapshot(),
+        }
+    }
+}
+
+/// Install a subscriber writing a Chrome trace of the runtime's spans
+/// (viewable in Perfetto or chrome://tracing), if [`CHROME_TRACE_ENV`] is set
+///
+/// The returned guard flushes the trace when dropped, so it must be held
+/// until the process is done.
+pub fn init_chrome_trace() -> Option<FlushGuard> {
+    let path = std::env::var_os(CHROME_TRACE_ENV)?;
+    let file = File::create(&path)
+        .inspect_err(|e| warn!("Failed to create Chrome trace {:?}: {}", path, e))
+        .ok()?;
+    let (layer, guard) = ChromeLayerBuilder::new()
+        .writer(BufWriter::new(file))
+        .include_args(true)
+        .build();
+    if let Err(e) =
+        tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+    {
+        warn!("Failed to install Chrome trace subscriber: {}", e);
+        return None;
+    }
+    Some(guard)
+}
+
+/// Records per-event stage durations into the session histograms
+///
+/// Stages are also traced as `tracing` spans where they run, so a Chrome
+/// trace or other subscriber shows where individual latency spikes originate.
+#[derive(Clone)]
+pub struct Tracer {
+    stats: Arc<LatencyRecorder>,
+}
+
+impl Tracer {
+    pub fn new(stats: Arc<LatencyRecorder>) -> Self {
+        Self { stats }
+    }
+
+    /// Record a stage that lasted `duration`
+    pub fn record(&self, span: Span, duration: Duration) {
+        self.stats.histogram(span).record(duration);
+        tracing::trace!(
+            stage = span.name(),
+            micros = duration.as_micros() as u64,
+            "{} took {:?}",
+            span.name(),
+            duration
+        );
+    }
+
+    /// Record the receive stage of an event gilrs timestamped at `time`
+    pub fn record_receive(&self, time: SystemTime) {
+        let Ok(latency) = SystemTime::now().duration_since(time) else {
+            return;
+        };
+        self.record(Span::Receive, latency);
+    }
+}
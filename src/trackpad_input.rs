@@ -0,0 +1,298 @@
+use crate::evdev_helpers::{self, READ_POLL_INTERVAL, VirtualGamepadInfo};
+use evdev::{
+    AbsoluteAxisCode, Device, EventSummary, EventType, FFEffectData, FFEffectKind, FFReplay,
+    FFTrigger, InputEvent,
+};
+use log::{info, warn};
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the target is updated while the trackpad is touched
+const TICK_INTERVAL: Duration = Duration::from_millis(8);
+/// Length of a single haptic tick
+const HAPTIC_TICK_MS: u16 = 15;
+/// Pad deflection needed to press a D-pad direction
+const DPAD_THRESHOLD: f32 = 0.5;
+
+/// Which trackpad of a Steam Controller or Steam Deck is read
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum TrackpadSide {
+    Left,
+    #[default]
+    Right,
+}
+
+/// Virtual input driven by the trackpad
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum TrackpadTarget {
+    LeftStick,
+    #[default]
+    RightStick,
+    DPad,
+}
+
+impl TrackpadTarget {
+    fn axes(self) -> [AbsoluteAxisCode; 2] {
+        match self {
+            Self::LeftStick => [AbsoluteAxisCode::ABS_X, AbsoluteAxisCode::ABS_Y],
+            Self::RightStick => [AbsoluteAxisCode::ABS_RX, AbsoluteAxisCode::ABS_RY],
+            Self::DPad => [AbsoluteAxisCode::ABS_HAT0X, AbsoluteAxisCode::ABS_HAT0Y],
+        }
+    }
+}
+
+/// Using the assist controller's trackpad as a stick or D-pad
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct TrackpadConfig {
+    pub enabled: bool,
+    pub pad: TrackpadSide,
+    pub target: TrackpadTarget,
+    /// Stick deflection at the pad's edge; above 1 reaches full deflection sooner
+    pub sensitivity: f32,
+    /// Pad travel between haptic ticks, as a fraction of its width (0 disables)
+    pub haptic_step: f32,
+}
+
+impl Default for TrackpadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            pad: TrackpadSide::default(),
+            target: TrackpadTarget::default(),
+            sensitivity: 1.0,
+            haptic_step: 0.1,
+        }
+    }
+}
+
+/// Finger position on the trackpad, from -1.0 to 1.0 on each axis
+///
+/// hid-steam reports the pad's center once the finger lifts, so a centered
+/// position counts as untouched.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrackpadPosition {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl TrackpadPosition {
+    fn is_touched(&self) -> bool {
+        self.x != 0.0 || self.y != 0.0
+    }
+}
+
+/// Axes a trackpad is reported on by hid-steam, which differ on the Deck
+fn pad_axes(device: &Device, side: TrackpadSide) -> [AbsoluteAxisCode; 2] {
+    let is_deck = VirtualGamepadInfo::steam_deck().product_id == Some(device.input_id().product());
+    match (side, is_deck) {
+        (TrackpadSide::Left, _) => [AbsoluteAxisCode::ABS_HAT0X, AbsoluteAxisCode::ABS_HAT0Y],
+        (TrackpadSide::Right, true) => [AbsoluteAxisCode::ABS_HAT1X, AbsoluteAxisCode::ABS_HAT1Y],
+        (TrackpadSide::Right, false) => [AbsoluteAxisCode::ABS_RX, AbsoluteAxisCode::ABS_RY],
+    }
+}
+
+/// Read a trackpad's position from a controller node until shutdown
+///
+/// Haptic ticks are played on the controller as the finger travels, where its
+/// driver supports rumble. Like the mouse reader, this may linger until the
+/// pad is next touched after shutdown.
+pub fn spawn_trackpad_reader(
+    path: &Path,
+    config: &TrackpadConfig,
+    position: Arc<Mutex<TrackpadPosition>>,
+    shutdown: Arc<AtomicBool>,
+) -> Result<thread::JoinHandle<()>, Box<dyn std::error::Error>> {
+    let mut device = Device::open(path)?;
+    let axes = pad_axes(&device, config.pad);
+
+    // Normalize by each axis' range, as reported by the driver
+    let range = |axis: AbsoluteAxisCode| {
+        device
+            .get_absinfo()
+            .ok()
+            .and_then(|mut infos| infos.find(|(code, _)| *code == axis))
+            .map_or(1.0, |(_, info)| info.maximum().max(1) as f32)
+    };
+    let ranges = [range(axes[0]), range(axes[1])];
+
+    let mut haptic = None;
+    if config.haptic_step > 0.0 {
+        let tick = FFEffectData {
+            direction: 0,
+            trigger: FFTrigger {
+                button: 0,
+                interval: 0,
+            },
+            replay: FFReplay {
+                length: HAPTIC_TICK_MS,
+                delay: 0,
+            },
+            kind: FFEffectKind::Rumble {
+                strong_magnitude: 0,
+                weak_magnitude: 0x6000,
+            },
+        };
+        match device.upload_ff_effect(tick) {
+            Ok(effect) => haptic = Some(effect),
+            Err(e) => warn!("Trackpad: haptic ticks unavailable: {}", e),
+        }
+    }
+    // Travel is measured over a width of 2.0, from -1.0 to 1.0
+    let haptic_step = config.haptic_step * 2.0;
+
+    info!(
+        "Trackpad: reading {:?} pad of {}",
+        config.pad,
+        path.display()
+    );
+    Ok(thread::spawn(move || {
+        let mut current = TrackpadPosition::default();
+        let mut last_tick: Option<TrackpadPosition> = None;
+
+        while !shutdown.load(Ordering::SeqCst) {
+            let events = match evdev_helpers::wait_readable(&device, READ_POLL_INTERVAL)
+                .and_then(|readable| readable.then(|| device.fetch_events()).transpose())
+            {
+                Ok(Some(events)) => events,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Trackpad: device stopped: {}", e);
+                    *position.lock() = TrackpadPosition::default();
+                    return;
+                }
+            };
+            for event in events {
+                if let EventSummary::AbsoluteAxis(_, axis, value) = event.destructure() {
+                    if axis == axes[0] {
+                        current.x = (value as f32 / ranges[0]).clamp(-1.0, 1.0);
+                    } else if axis == axes[1] {
+                        current.y = (value as f32 / ranges[1]).clamp(-1.0, 1.0);
+                    }
+                }
+            }
+            *position.lock() = current;
+
+            if !current.is_touched() {
+                last_tick = None;
+                continue;
+            }
+            let Some(effect) = &mut haptic else {
+                continue;
+            };
+            let from = *last_tick.get_or_insert(current);
+            if (current.x - from.x).hypot(current.y - from.y) >= haptic_step {
+                last_tick = Some(current);
+                let _ = effect.play(1);
+            }
+        }
+    }))
+}
+
+/// Drives a virtual stick or D-pad from the trackpad while it's touched
+///
+/// Like mouse aiming, the incoming output of the target is kept as a base and
+/// restored once the finger lifts.
+pub struct TrackpadInput {
+    config: TrackpadConfig,
+    position: Option<Arc<Mutex<TrackpadPosition>>>,
+    /// Last incoming values of the target axes, in evdev units
+    base: [i32; 2],
+    /// Last values written while touched, to skip unchanged output
+    last_output: Option<[i32; 2]>,
+    active: bool,
+    last_tick: Instant,
+}
+
+impl TrackpadInput {
+    pub fn new(config: TrackpadConfig, position: Option<Arc<Mutex<TrackpadPosition>>>) -> Self {
+        Self {
+            config,
+            position,
+            base: [evdev_helpers::AXIS_HALF as i32; 2],
+            last_output: None,
+            active: false,
+            last_tick: Instant::now(),
+        }
+    }
+
+    /// Record incoming output of the target, holding it back while touched
+    pub fn process(&mut self, events: &mut Vec<InputEvent>) {
+        if self.position.is_none() {
+            return;
+        }
+        let axes = self.config.target.axes();
+        events.retain(|event| {
+            if event.event_type() != EventType::ABSOLUTE {
+                return true;
+            }
+            let Some(index) = axes.iter().position(|axis| axis.0 == event.code()) else {
+                return true;
+            };
+            self.base[index] = event.value();
+            !self.active
+        });
+    }
+
+    /// Map a pad position onto the target's range, in evdev units
+    fn output(&self, value: f32) -> i32 {
+        let value = match self.config.target {
+            TrackpadTarget::DPad if value > DPAD_THRESHOLD => 1.0,
+            TrackpadTarget::DPad if value < -DPAD_THRESHOLD => -1.0,
+            TrackpadTarget::DPad => 0.0,
+            _ => (value * self.config.sensitivity).clamp(-1.0, 1.0),
+        };
+        evdev_helpers::scale_stick(value, false)
+    }
+
+    /// Append target events for the current trackpad position
+    pub fn tick(&mut self, now: Instant, out: &mut Vec<InputEvent>) {
+        let Some(position) = &self.position else {
+            return;
+        };
+        if now < self.last_tick + TICK_INTERVAL {
+            return;
+        }
+        self.last_tick = now;
+
+        let axes = self.config.target.axes();
+        let position = *position.lock();
+        if !position.is_touched() {
+            if self.active {
+                // Restore the incoming output of the target
+                self.active = false;
+                self.last_output = None;
+                out.push(axis_event(axes[0], self.base[0]));
+                out.push(axis_event(axes[1], self.base[1]));
+            }
+            return;
+        }
+        self.active = true;
+
+        let values = [self.output(position.x), self.output(position.y)];
+        let last = self.last_output.replace(values);
+        for (i, axis) in axes.into_iter().enumerate() {
+            if last.is_none_or(|last| last[i] != values[i]) {
+                out.push(axis_event(axis, values[i]));
+            }
+        }
+    }
+
+    /// When the target next needs updating, if a trackpad is in use
+    pub fn next_deadline(&self) -> Option<Instant> {
+        // Trackpad motion doesn't wake the input loop, so poll while one is read
+        self.position
+            .as_ref()
+            .map(|_| self.last_tick + TICK_INTERVAL)
+    }
+}
+
+fn axis_event(axis: AbsoluteAxisCode, value: i32) -> InputEvent {
+    InputEvent::new(EventType::ABSOLUTE.0, axis.0, value)
+}
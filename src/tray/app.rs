@@ -1,17 +1,29 @@
+use crate::consumers;
+use crate::game_profiles;
+use crate::gilrs_helper;
 use crate::mux_manager::{self, MuxConfig, MuxHandle};
-use crate::mux_modes::ModeType;
-use crate::{HideType, RumbleTarget, SpoofTarget};
+use crate::mux_modes::{HandoffButton, ModeOptions, ModeType};
+use crate::mux_runtime::RuntimeSettings;
+use crate::notifier::{self, SessionEvent};
+use crate::sleep_monitor::SleepEvent;
+use crate::steam_backup::SteamJournal;
+use crate::udev_helpers;
+use crate::{HideOrder, HideType, RumbleTarget, SpoofTarget};
+use clap::ValueEnum;
 use gilrs::Gilrs;
 use ksni::{Category, MenuItem, Status, ToolTip, Tray, menu};
-use log::{error, info};
-use notify_rust::Notification;
+use log::{error, info, warn};
+use notify_rust::{Notification, Timeout};
 use parking_lot::Mutex;
 use std::error::Error;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::thread;
+use std::time::Instant;
 
 use super::config::TrayConfig;
-use super::state::{MuxStatus, TrayState};
+use super::i18n::{tr, tr_args};
+use super::state::{ControllerInfo, MuxStatus, TrayState};
 
 pub struct CtrlAssistTray {
     state: Arc<Mutex<TrayState>>,
@@ -21,9 +33,18 @@ pub struct CtrlAssistTray {
 
 impl CtrlAssistTray {
     pub fn new() -> Result<Self, Box<dyn Error>> {
-        let gilrs = Gilrs::new().map_err(|e| format!("Failed to init Gilrs: {}", e))?;
-        let config = TrayConfig::load();
-        let state = TrayState::new(&gilrs, config);
+        let (config, problems) = TrayConfig::load_checked();
+        crate::crash_helpers::set_config_snapshot(&config);
+        notifier::configure(&config.notify);
+        if let Some(report) = problems {
+            Self::send_notification(
+                &tr("notify_config_invalid"),
+                &tr_args("notify_config_invalid_body", &[("problems", &report)]),
+            );
+        }
+        Self::notify_unrestored();
+        let gilrs = gilrs_helper::build_gilrs(&config.gilrs)?;
+        let state = TrayState::new(gilrs, config);
 
         Ok(Self {
             state: Arc::new(Mutex::new(state)),
@@ -32,28 +53,32 @@ impl CtrlAssistTray {
     }
 
     fn send_notification(summary: &str, body: &str) {
-        let summary = summary.to_string();
-        let body = body.to_string();
-        tokio::task::spawn_blocking(move || {
-            if let Err(e) = Notification::new()
-                .summary(&summary)
-                .body(&body)
-                .appname("CtrlAssist")
-                .show()
-            {
-                error!("Failed to send notification: {}", e);
-            }
-        });
+        notifier::notify(summary, body);
+    }
+
+    /// Point out hiding left in place by a session that crashed or lost power
+    fn notify_unrestored() {
+        let mut commands = Vec::new();
+        if SteamJournal::load().is_some() {
+            commands.push("--steam");
+        }
+        if udev_helpers::HideJournal::load().is_some() {
+            commands.push("--hide");
+        }
+        if !commands.is_empty() {
+            let command = format!("ctrlassist restore {}", commands.join(" "));
+            Self::send_notification(
+                &tr("notify_unrestored"),
+                &tr_args("notify_unrestored_body", &[("command", &command)]),
+            );
+        }
     }
 
     fn start_mux(&mut self) {
         let mut state = self.state.lock();
 
         if !state.is_valid_for_start() {
-            Self::send_notification(
-                "CtrlAssist - Cannot Start",
-                "Please select two different controllers first",
-            );
+            Self::send_notification(&tr("notify_cannot_start"), &tr("notify_select_controllers"));
             return;
         }
 
@@ -65,50 +90,126 @@ impl CtrlAssistTray {
             primary_id, assist_id
         );
 
+        // The selected or detected game profile overrides hide and spoof for
+        // the session, and its mode is selected as if from the Mode menu
+        let mut hide = state.hide.clone();
+        let mut spoof = state.spoof.clone();
+        let mut mode = state.mode.clone();
+        let mut mode_options = state.mode_options.clone();
+        let mut force_feedback = state.force_feedback.clone();
+        let mut disconnect = state.disconnect;
+        let profiles = game_profiles::with_builtins(&state.profiles);
+        match game_profiles::resolve(&profiles, state.profile.as_deref()) {
+            Ok(Some((name, profile))) => {
+                info!("Using profile: {}", name);
+                profile.apply(&mut hide, &mut spoof);
+                profile.apply_mode(&mut mode, &mut mode_options);
+                profile.apply_force_feedback(&mut force_feedback);
+                profile.apply_disconnect(&mut disconnect);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("{}", e),
+        }
+        state.mode = mode;
+
         // Create notification with settings
-        let notification_body = format!(
-            "Primary: {}\nAssist: {}\nMode: {:?}\nHide: {:?}\nSpoof: {:?}\nRumble: {:?}",
-            state.get_primary_name(),
-            state.get_assist_name(),
-            state.mode,
-            state.hide,
-            state.spoof,
-            state.rumble
+        let notification_body = tr_args(
+            "notify_starting_body",
+            &[
+                ("primary", &state.get_primary_name()),
+                ("assist", &state.get_assist_name()),
+                ("mode", &format!("{:?}", state.mode)),
+                ("hide", &format!("{:?}", hide)),
+                ("spoof", &format!("{:?}", spoof)),
+                ("rumble", &format!("{:?}", state.rumble)),
+            ],
         );
-        Self::send_notification("CtrlAssist - Starting", &notification_body);
+        Self::send_notification(&tr("notify_starting"), &notification_body);
 
         // Prepare config for mux
         let config = MuxConfig {
             primary_id,
             assist_id,
+            primary_partner: state.selected_primary_partner,
+            assist_partner: state.selected_assist_partner,
             mode: state.mode.clone(),
-            hide: state.hide.clone(),
-            spoof: state.spoof.clone(),
+            mode_options,
+            hide,
+            hide_order: state.hide_order.clone(),
+            hide_settle_ms: state.hide_settle_ms,
+            spoof,
             rumble: state.rumble.clone(),
+            paddles: state.paddles.clone(),
+            hooks: state.hooks.clone(),
+            timing: state.timing.clone(),
+            gilrs: state.gilrs_config.clone(),
+            debounce: state.debounce.clone(),
+            delay: state.delay.clone(),
+            hold: state.hold.clone(),
+            confirm: state.confirm.clone(),
+            smoothing: state.smoothing.clone(),
+            gyro: state.gyro.clone(),
+            mouse: state.mouse.clone(),
+            trackpad: state.trackpad.clone(),
+            siblings: state.siblings.clone(),
+            force_feedback,
+            quirks: state.quirks.clone(),
+            hidraw: state.hidraw,
+            session_name: state.session_name,
+            grab_sources: state.grab_sources,
+            game_mode: state.game_mode,
+            hide_confirm_secs: state.hide_confirm_secs,
+            pad_menu: state.pad_menu.clone(),
+            overlay_pause: state.overlay_pause.clone(),
+            mode_button: state.mode_button.clone(),
+            time_limit: state.time_limit.clone(),
+            time_limit_start: *state.time_limit_start.get_or_insert_with(Instant::now),
+            disconnect,
+            profiles,
         };
 
         // Use a channel for shutdown signaling
         let (shutdown_tx, shutdown_rx) = std::sync::mpsc::channel::<()>();
         self.shutdown_tx = Some(shutdown_tx);
 
+        // Lend the shared Gilrs instance to the mux for the session
+        let gilrs = state.gilrs.take();
         let state_arc = Arc::clone(&self.state);
         let handle = thread::spawn(move || {
-            match start_mux_with_state(config, state_arc) {
+            match start_mux_with_state(gilrs, config, Arc::clone(&state_arc)) {
                 Ok(mux_handle) => {
-                    // Wait for shutdown signal (blocks efficiently)
-                    let _ = shutdown_rx.recv();
+                    // Wait for shutdown signal or a failed runtime thread
+                    let faulted = mux_handle.wait(&shutdown_rx);
                     // Properly shutdown mux (unblocks FF thread)
-                    mux_handle.shutdown();
+                    let gilrs = mux_handle.shutdown();
+                    // Reclaim the Gilrs instance for controller refreshes
+                    let mut state = state_arc.lock();
+                    state.gilrs = gilrs;
+                    // Also when stopped from the controller menu rather than here
+                    state.status = MuxStatus::Stopped;
+                    if faulted {
+                        state.failed = true;
+                        Self::send_notification(&tr("notify_error"), &tr("notify_fault"));
+                    }
                 }
                 Err(e) => {
                     error!("Mux thread error: {}", e);
-                    Self::send_notification("CtrlAssist - Error", &format!("Mux failed: {}", e));
+                    {
+                        let mut state = state_arc.lock();
+                        state.status = MuxStatus::Stopped;
+                        state.failed = true;
+                    }
+                    Self::send_notification(
+                        &tr("notify_error"),
+                        &tr_args("notify_failed", &[("error", &e)]),
+                    );
                 }
             }
         });
 
         state.mux_handle = Some(handle);
         state.status = MuxStatus::Running;
+        state.failed = false;
 
         // Save config
         if let Err(e) = state.to_config().save() {
@@ -116,7 +217,13 @@ impl CtrlAssistTray {
         }
     }
 
+    /// Stop the session as asked for, so the next one gets the full time limit
     fn stop_mux(&mut self) {
+        self.end_mux();
+        self.state.lock().time_limit_start = None;
+    }
+
+    fn end_mux(&mut self) {
         let mut state = self.state.lock();
 
         if state.status == MuxStatus::Stopped {
@@ -142,20 +249,102 @@ impl CtrlAssistTray {
         state.shutdown_signal = None;
 
         info!("Mux stopped");
-        Self::send_notification("CtrlAssist", "Mux stopped");
+        Self::send_notification("CtrlAssist", &tr("notify_stopped"));
+    }
+
+    /// Act on a global shortcut: start, stop, or toggle the mux
+    pub(super) fn activate_shortcut(&mut self, id: &str) {
+        let running = self.state.lock().status == MuxStatus::Running;
+        match (id, running) {
+            ("start" | "toggle", false) => self.start_mux(),
+            ("stop" | "toggle", true) => self.stop_mux(),
+            _ => {}
+        }
+    }
+
+    /// Stop a running session before system sleep, and start it again after
+    pub(super) fn follow_sleep(&mut self, event: SleepEvent) {
+        match event {
+            SleepEvent::Suspending => {
+                let running = self.state.lock().status == MuxStatus::Running;
+                if running {
+                    self.end_mux();
+                }
+                self.state.lock().resume_after_sleep = running;
+            }
+            SleepEvent::Resumed => {
+                let resume = std::mem::take(&mut self.state.lock().resume_after_sleep);
+                if resume {
+                    info!("Restarting mux after resume");
+                    self.start_mux();
+                }
+            }
+        }
+    }
+
+    /// Change a mode parameter, live if running, and save it
+    fn set_mode_option(&mut self, change: impl FnOnce(&mut ModeOptions)) {
+        let mut state = self.state.lock();
+        change(&mut state.mode_options);
+
+        // If running, update live
+        if state.status == MuxStatus::Running
+            && let Some(runtime_settings) = &state.runtime_settings
+        {
+            runtime_settings.update_mode_options(state.mode_options.clone());
+        }
+
+        // Save config
+        if let Err(e) = state.to_config().save() {
+            error!("Failed to save config: {}", e);
+        }
+    }
+
+    /// Swap the running session's assist, keeping the virtual device
+    fn replace_assist(&mut self, id: gilrs::GamepadId, name: String) {
+        let mut state = self.state.lock();
+        let Some(settings) = state.runtime_settings.clone() else {
+            return;
+        };
+        info!("Replacing assist with {:?}", id);
+        settings.request_assist(id);
+        state.selected_assist = Some(id);
+        // The old assist's partner stays bound to it
+        state.selected_assist_partner = None;
+        // Controllers that connected mid-session aren't listed yet
+        if !state.controllers.iter().any(|c| c.id == id) {
+            state.controllers.push(ControllerInfo {
+                id,
+                name: name.clone(),
+                display_name: name.clone(),
+                serial: None,
+                power: None,
+            });
+        }
+        Self::send_notification(
+            &tr("notify_assist_replaced"),
+            &tr_args("notify_assist_replaced_body", &[("name", &name)]),
+        );
     }
 
     fn refresh_controllers(&self) {
         let mut state = self.state.lock();
-        if let Ok(gilrs) = Gilrs::new() {
-            let controllers: Vec<_> = gilrs
-                .gamepads()
-                .map(|(id, gamepad)| super::state::ControllerInfo {
-                    id,
-                    name: gamepad.name().to_string(),
-                })
-                .collect();
-            state.controllers = controllers;
+
+        // Recreate the shared instance if a failed session consumed it
+        if state.gilrs.is_none() && state.status == MuxStatus::Stopped {
+            match gilrs_helper::build_gilrs(&state.gilrs_config) {
+                Ok(gilrs) => state.gilrs = Some(gilrs),
+                Err(e) => error!("{}", e),
+            }
+        }
+
+        // Skip while the mux is using the shared instance
+        let state = &mut *state;
+        if let Some(gilrs) = state.gilrs.as_mut() {
+            // Drain pending events so hotplugged controllers are reflected
+            while gilrs_helper::next_event(gilrs, &state.gilrs_config, None).is_some() {}
+
+            state.controllers = ControllerInfo::discover(gilrs);
 
             // Try to keep selected controllers if still present
             if let Some(primary_id) = state.selected_primary {
@@ -173,6 +362,51 @@ impl CtrlAssistTray {
             } else {
                 state.selected_assist = state.controllers.get(1).map(|c| c.id);
             }
+
+            // Drop partners that are gone, rather than picking another
+            let controllers = &state.controllers;
+            for partner in [
+                &mut state.selected_primary_partner,
+                &mut state.selected_assist_partner,
+            ] {
+                if partner.is_some_and(|id| !controllers.iter().any(|c| c.id == id)) {
+                    *partner = None;
+                }
+            }
+        }
+    }
+}
+
+/// Session state shown by the tray icon at a glance
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SessionHealth {
+    Stopped,
+    Running,
+    /// The assist holds exclusive control, e.g. in Toggle mode
+    AssistActive,
+    /// Running without a source controller or with failing rumble
+    Degraded,
+    /// The last session failed to start or stopped unexpectedly
+    Failed,
+}
+
+impl SessionHealth {
+    fn of(state: &TrayState) -> Self {
+        match state.status {
+            MuxStatus::Stopped if state.failed => Self::Failed,
+            MuxStatus::Stopped => Self::Stopped,
+            MuxStatus::Running => {
+                let Some(settings) = &state.runtime_settings else {
+                    return Self::Running;
+                };
+                if settings.is_degraded() {
+                    Self::Degraded
+                } else if settings.get_active_id() == Some(settings.get_assist_id()) {
+                    Self::AssistActive
+                } else {
+                    Self::Running
+                }
+            }
         }
     }
 }
@@ -191,36 +425,95 @@ impl Tray for CtrlAssistTray {
     fn title(&self) -> String {
         let state = self.state.lock();
         match state.status {
-            MuxStatus::Running => "CtrlAssist [Running]".into(),
-            MuxStatus::Stopped => "CtrlAssist [Stopped]".into(),
+            MuxStatus::Running => tr("title_running"),
+            MuxStatus::Stopped => tr("title_stopped"),
         }
     }
 
     fn icon_name(&self) -> String {
-        let state = self.state.lock();
-        match state.status {
-            MuxStatus::Running => "input-gaming".into(),
-            MuxStatus::Stopped => "input-gaming-symbolic".into(),
+        match SessionHealth::of(&self.state.lock()) {
+            SessionHealth::Stopped => "input-gaming-symbolic",
+            SessionHealth::Running => "input-gaming",
+            SessionHealth::AssistActive => "system-users",
+            SessionHealth::Degraded => "dialog-warning",
+            SessionHealth::Failed => "dialog-error",
         }
+        .into()
     }
 
     fn status(&self) -> Status {
-        let state = self.state.lock();
-        match state.status {
-            MuxStatus::Running => Status::Active,
-            MuxStatus::Stopped => Status::Passive,
+        match SessionHealth::of(&self.state.lock()) {
+            SessionHealth::Stopped => Status::Passive,
+            SessionHealth::Running | SessionHealth::AssistActive => Status::Active,
+            SessionHealth::Degraded | SessionHealth::Failed => Status::NeedsAttention,
         }
     }
 
+    fn attention_icon_name(&self) -> String {
+        self.icon_name()
+    }
+
     fn tool_tip(&self) -> ToolTip {
         let state = self.state.lock();
         let description = match state.status {
-            MuxStatus::Running => format!(
-                "Muxing: {} + {}",
-                state.get_primary_name(),
-                state.get_assist_name()
-            ),
-            MuxStatus::Stopped => "Not running".to_string(),
+            MuxStatus::Running => {
+                let mut description = tr_args(
+                    "tooltip_running",
+                    &[
+                        ("primary", &state.get_primary_name()),
+                        ("assist", &state.get_assist_name()),
+                    ],
+                );
+                if let Some(runtime_settings) = &state.runtime_settings {
+                    if runtime_settings.has_disconnected() {
+                        description.push('\n');
+                        description.push_str(&tr("tooltip_disconnected"));
+                    }
+                    if runtime_settings.get_active_id() == Some(runtime_settings.get_assist_id()) {
+                        description.push('\n');
+                        description.push_str(&tr("tooltip_assist_active"));
+                    }
+                    let in_use = runtime_settings.get_consumers();
+                    description.push('\n');
+                    if in_use.is_empty() {
+                        description.push_str(&tr("tooltip_no_consumer"));
+                    } else {
+                        description.push_str(&tr_args(
+                            "tooltip_consumers",
+                            &[("names", &consumers::describe(&in_use))],
+                        ));
+                    }
+                    let latency = runtime_settings.get_latency();
+                    description.push('\n');
+                    description.push_str(&tr_args(
+                        "tooltip_latency",
+                        &[
+                            ("mode", &format!("{:?}", latency.mode.percentile(99.0))),
+                            ("write", &format!("{:?}", latency.write.percentile(99.0))),
+                        ],
+                    ));
+
+                    for target in runtime_settings.get_ff_health().targets.values() {
+                        let key = if target.is_healthy() {
+                            "tooltip_rumble_ok"
+                        } else {
+                            "tooltip_rumble_failing"
+                        };
+                        description.push('\n');
+                        description.push_str(&tr_args(
+                            key,
+                            &[
+                                ("name", &target.name),
+                                ("unsupported", &target.unsupported.to_string()),
+                                ("recoveries", &target.recoveries.to_string()),
+                            ],
+                        ));
+                    }
+                }
+                description
+            }
+            MuxStatus::Stopped if state.failed => tr("tooltip_failed"),
+            MuxStatus::Stopped => tr("tooltip_stopped"),
         };
 
         ToolTip {
@@ -239,7 +532,7 @@ impl Tray for CtrlAssistTray {
         vec![
             // Refresh controllers
             menu::StandardItem {
-                label: "Refresh Controllers".into(),
+                label: tr("menu_refresh"),
                 icon_name: "view-refresh".into(),
                 enabled: !is_running,
                 activate: Box::new(|this: &mut Self| {
@@ -250,13 +543,18 @@ impl Tray for CtrlAssistTray {
             .into(),
             // Controller Selection
             menu::SubMenu {
-                label: format!(
-                    "Primary: ({}) {}",
-                    state
-                        .selected_primary
-                        .map(|id| id.to_string())
-                        .unwrap_or_else(|| "#".to_string()),
-                    truncate_name(&state.get_primary_name())
+                label: tr_args(
+                    "menu_primary",
+                    &[
+                        (
+                            "id",
+                            &state
+                                .selected_primary
+                                .map(|id| id.to_string())
+                                .unwrap_or_else(|| "#".to_string()),
+                        ),
+                        ("name", &truncate_name(&state.get_primary_name())),
+                    ],
                 ),
                 icon_name: "input-gaming".into(),
                 enabled: !is_running,
@@ -267,7 +565,7 @@ impl Tray for CtrlAssistTray {
                         let controller_id = controller.id;
                         let is_selected = state.selected_primary == Some(controller_id);
                         menu::CheckmarkItem {
-                            label: format!("({}) {}", controller_id, controller.name),
+                            label: controller_label(controller),
                             checked: is_selected,
                             enabled: !is_running,
                             activate: Box::new(move |this: &mut Self| {
@@ -283,13 +581,18 @@ impl Tray for CtrlAssistTray {
             }
             .into(),
             menu::SubMenu {
-                label: format!(
-                    "Assist: ({}) {}",
-                    state
-                        .selected_assist
-                        .map(|id| id.to_string())
-                        .unwrap_or_else(|| "#".to_string()),
-                    truncate_name(&state.get_assist_name())
+                label: tr_args(
+                    "menu_assist",
+                    &[
+                        (
+                            "id",
+                            &state
+                                .selected_assist
+                                .map(|id| id.to_string())
+                                .unwrap_or_else(|| "#".to_string()),
+                        ),
+                        ("name", &truncate_name(&state.get_assist_name())),
+                    ],
                 ),
                 icon_name: "input-gaming".into(),
                 enabled: !is_running,
@@ -300,7 +603,7 @@ impl Tray for CtrlAssistTray {
                         let controller_id = controller.id;
                         let is_selected = state.selected_assist == Some(controller_id);
                         menu::CheckmarkItem {
-                            label: format!("({}) {}", controller_id, controller.name),
+                            label: controller_label(controller),
                             checked: is_selected,
                             enabled: !is_running,
                             activate: Box::new(move |this: &mut Self| {
@@ -315,64 +618,103 @@ impl Tray for CtrlAssistTray {
                 ..Default::default()
             }
             .into(),
+            // Live assist replacement
+            create_replace_assist_menu(&state, is_running),
+            // Composite partners, e.g. the other Joy-Con of a pair
+            create_partner_menu(PartnerRole::Primary, &state, is_running),
+            create_partner_menu(PartnerRole::Assist, &state, is_running),
             MenuItem::Separator,
             // Mux Mode
             menu::SubMenu {
-                label: format!("Mode: {:?}", state.mode),
+                label: tr_args("menu_mode", &[("value", &format!("{:?}", state.mode))]),
                 icon_name: "media-playlist-shuffle".into(),
                 enabled: true, // Dynamically configurable while running
-                submenu: vec![
-                    create_mode_item(ModeType::Priority, &state, true),
-                    create_mode_item(ModeType::Average, &state, true),
-                    create_mode_item(ModeType::Toggle, &state, true),
-                ],
+                // Every mode the CLI accepts, so new modes need no tray changes
+                submenu: ModeType::value_variants()
+                    .iter()
+                    .map(|mode| create_mode_item(mode.clone(), &state, true))
+                    .collect(),
                 ..Default::default()
             }
             .into(),
+            // Parameters of the selected mode
+            create_mode_options_menu(&state),
             // Hide Strategy
             menu::SubMenu {
-                label: format!("Hide: {:?}", state.hide),
+                label: tr_args("menu_hide", &[("value", &format!("{:?}", state.hide))]),
                 icon_name: "view-visible".into(),
                 enabled: !is_running,
                 submenu: vec![
                     create_hide_item(HideType::None, &state, is_running),
                     create_hide_item(HideType::Steam, &state, is_running),
                     create_hide_item(HideType::System, &state, is_running),
+                    create_hide_item(HideType::Acl, &state, is_running),
                 ],
                 ..Default::default()
             }
             .into(),
             // Spoof Target
             menu::SubMenu {
-                label: format!("Spoof: {:?}", state.spoof),
+                label: tr_args("menu_spoof", &[("value", &format!("{:?}", state.spoof))]),
                 icon_name: "edit-copy".into(),
                 enabled: !is_running,
                 submenu: vec![
                     create_spoof_item(SpoofTarget::None, &state, is_running),
                     create_spoof_item(SpoofTarget::Primary, &state, is_running),
                     create_spoof_item(SpoofTarget::Assist, &state, is_running),
+                    create_spoof_item(SpoofTarget::SteamDeck, &state, is_running),
+                    create_spoof_item(SpoofTarget::Xbox360, &state, is_running),
+                    create_spoof_item(SpoofTarget::Wheel, &state, is_running),
+                    create_spoof_item(SpoofTarget::Joystick, &state, is_running),
                 ],
                 ..Default::default()
             }
             .into(),
+            // Game Profile
+            create_profile_menu(&state, is_running),
             // Rumble Target
             menu::SubMenu {
-                label: format!("Rumble: {:?}", state.rumble),
+                label: tr_args("menu_rumble", &[("value", &format!("{:?}", state.rumble))]),
                 icon_name: "notification-active".into(),
                 enabled: true, // Dynamically configurable while running
                 submenu: vec![
                     create_rumble_item(RumbleTarget::Both, &state, true),
                     create_rumble_item(RumbleTarget::Primary, &state, true),
                     create_rumble_item(RumbleTarget::Assist, &state, true),
+                    create_rumble_item(RumbleTarget::Split, &state, true),
                     create_rumble_item(RumbleTarget::None, &state, true),
                 ],
                 ..Default::default()
             }
             .into(),
+            // Stick Smoothing
+            menu::CheckmarkItem {
+                label: tr("menu_smoothing"),
+                checked: state.smoothing.enabled,
+                enabled: true, // Dynamically configurable while running
+                activate: Box::new(|this: &mut Self| {
+                    let mut state = this.state.lock();
+                    state.smoothing.enabled = !state.smoothing.enabled;
+
+                    // If running, update live
+                    if state.status == MuxStatus::Running
+                        && let Some(runtime_settings) = &state.runtime_settings
+                    {
+                        runtime_settings.update_smoothing(state.smoothing.enabled);
+                    }
+
+                    // Save config
+                    if let Err(e) = state.to_config().save() {
+                        error!("Failed to save config: {}", e);
+                    }
+                }),
+                ..Default::default()
+            }
+            .into(),
             MenuItem::Separator,
             // Start/Stop
             menu::StandardItem {
-                label: "Start Mux".into(),
+                label: tr("menu_start"),
                 icon_name: "media-playback-start".into(),
                 enabled: !is_running && state.is_valid_for_start(),
                 activate: Box::new(|this: &mut Self| {
@@ -382,7 +724,7 @@ impl Tray for CtrlAssistTray {
             }
             .into(),
             menu::StandardItem {
-                label: "Stop Mux".into(),
+                label: tr("menu_stop"),
                 icon_name: "media-playback-stop".into(),
                 enabled: is_running,
                 activate: Box::new(|this: &mut Self| {
@@ -394,7 +736,7 @@ impl Tray for CtrlAssistTray {
             MenuItem::Separator,
             // Exit
             menu::StandardItem {
-                label: "Exit".into(),
+                label: tr("menu_exit"),
                 icon_name: "application-exit".into(),
                 activate: Box::new(|this: &mut Self| {
                     this.stop_mux();
@@ -407,7 +749,161 @@ impl Tray for CtrlAssistTray {
     }
 }
 
+/// Controller a partner submenu combines with
+#[derive(Clone, Copy)]
+enum PartnerRole {
+    Primary,
+    Assist,
+}
+
+impl PartnerRole {
+    fn partner_mut(self, state: &mut TrayState) -> &mut Option<gilrs::GamepadId> {
+        match self {
+            Self::Primary => &mut state.selected_primary_partner,
+            Self::Assist => &mut state.selected_assist_partner,
+        }
+    }
+}
+
 // Helper functions for menu items
+fn create_partner_menu(
+    role: PartnerRole,
+    state: &parking_lot::lock_api::MutexGuard<parking_lot::RawMutex, TrayState>,
+    is_running: bool,
+) -> MenuItem<CtrlAssistTray> {
+    let (label_key, leader, partner) = match role {
+        PartnerRole::Primary => (
+            "menu_primary_partner",
+            state.selected_primary,
+            state.selected_primary_partner,
+        ),
+        PartnerRole::Assist => (
+            "menu_assist_partner",
+            state.selected_assist,
+            state.selected_assist_partner,
+        ),
+    };
+    let name = partner
+        .and_then(|id| state.controllers.iter().find(|c| c.id == id))
+        .map_or_else(|| tr("menu_partner_none"), |c| truncate_name(&c.name));
+
+    let none_item = menu::CheckmarkItem {
+        label: tr("menu_partner_none"),
+        checked: partner.is_none(),
+        enabled: !is_running,
+        activate: Box::new(move |this: &mut CtrlAssistTray| {
+            *role.partner_mut(&mut this.state.lock()) = None;
+        }),
+        ..Default::default()
+    }
+    .into();
+    let controller_items = state
+        .controllers
+        .iter()
+        .filter(|controller| Some(controller.id) != leader)
+        .map(|controller| {
+            let controller_id = controller.id;
+            menu::CheckmarkItem {
+                label: controller_label(controller),
+                checked: partner == Some(controller_id),
+                enabled: !is_running,
+                activate: Box::new(move |this: &mut CtrlAssistTray| {
+                    *role.partner_mut(&mut this.state.lock()) = Some(controller_id);
+                }),
+                ..Default::default()
+            }
+            .into()
+        });
+
+    menu::SubMenu {
+        label: tr_args(label_key, &[("name", &name)]),
+        icon_name: "input-gaming".into(),
+        enabled: !is_running,
+        submenu: std::iter::once(none_item).chain(controller_items).collect(),
+        ..Default::default()
+    }
+    .into()
+}
+
+fn create_replace_assist_menu(
+    state: &parking_lot::lock_api::MutexGuard<parking_lot::RawMutex, TrayState>,
+    is_running: bool,
+) -> MenuItem<CtrlAssistTray> {
+    let settings = state.runtime_settings.as_ref().filter(|_| is_running);
+    let current = settings.map(|s| s.get_assist_id());
+    let candidates = settings
+        .map(|s| s.get_assist_candidates())
+        .unwrap_or_default();
+
+    menu::SubMenu {
+        label: tr("menu_replace_assist"),
+        icon_name: "input-gaming".into(),
+        enabled: !candidates.is_empty(),
+        submenu: candidates
+            .into_iter()
+            .map(|(id, name)| {
+                let label = format!("({}) {}", id, name);
+                menu::CheckmarkItem {
+                    label,
+                    checked: current == Some(id),
+                    enabled: current != Some(id),
+                    activate: Box::new(move |this: &mut CtrlAssistTray| {
+                        this.replace_assist(id, name.clone());
+                    }),
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect(),
+        ..Default::default()
+    }
+    .into()
+}
+
+fn create_profile_menu(
+    state: &parking_lot::lock_api::MutexGuard<parking_lot::RawMutex, TrayState>,
+    is_running: bool,
+) -> MenuItem<CtrlAssistTray> {
+    let auto_item = menu::CheckmarkItem {
+        label: tr("menu_profile_auto"),
+        checked: state.profile.is_none(),
+        enabled: !is_running,
+        activate: Box::new(|this: &mut CtrlAssistTray| {
+            this.state.lock().profile = None;
+        }),
+        ..Default::default()
+    }
+    .into();
+    let profile_items = game_profiles::with_builtins(&state.profiles)
+        .into_keys()
+        .map(|name| {
+            let profile_name = name.clone();
+            menu::CheckmarkItem {
+                checked: state.profile.as_ref() == Some(&name),
+                label: name,
+                enabled: !is_running,
+                activate: Box::new(move |this: &mut CtrlAssistTray| {
+                    this.state.lock().profile = Some(profile_name.clone());
+                }),
+                ..Default::default()
+            }
+            .into()
+        });
+
+    let value = state
+        .profile
+        .clone()
+        .unwrap_or_else(|| tr("menu_profile_auto"));
+    menu::SubMenu {
+        label: tr_args("menu_profile", &[("value", &value)]),
+        icon_name: "applications-games".into(),
+        enabled: !is_running,
+        submenu: std::iter::once(auto_item).chain(profile_items).collect(),
+        ..Default::default()
+    }
+    .into()
+}
+
 fn create_mode_item(
     mode: ModeType,
     state: &parking_lot::lock_api::MutexGuard<parking_lot::RawMutex, TrayState>,
@@ -431,8 +927,14 @@ fn create_mode_item(
                 {
                     runtime_settings.update_mode(mode.clone());
                     CtrlAssistTray::send_notification(
-                        "CtrlAssist - Mode Changed",
-                        &format!("Mux mode changed from {:?} to {:?}", old_mode, mode),
+                        &tr("notify_mode_changed"),
+                        &tr_args(
+                            "notify_mode_changed_body",
+                            &[
+                                ("old", &format!("{:?}", old_mode)),
+                                ("new", &format!("{:?}", mode)),
+                            ],
+                        ),
                     );
                 }
 
@@ -447,6 +949,147 @@ fn create_mode_item(
     .into()
 }
 
+/// Presets offered for the assist's share in Average mode
+const WEIGHT_PRESETS: [f32; 5] = [0.1, 0.3, 0.5, 0.7, 0.9];
+
+/// Presets offered for each controller's scale in Blend mode
+const BLEND_WEIGHT_PRESETS: [f32; 5] = [0.1, 0.3, 0.5, 0.7, 1.0];
+
+/// Presets offered for mode deadzones
+const DEADZONE_PRESETS: [f32; 5] = [0.05, 0.1, 0.15, 0.2, 0.3];
+
+/// Buttons offered for handing off control
+const HANDOFF_BUTTONS: [HandoffButton; 5] = [
+    HandoffButton::Mode,
+    HandoffButton::Start,
+    HandoffButton::Select,
+    HandoffButton::LeftThumb,
+    HandoffButton::RightThumb,
+];
+
+// Helper to create the parameters submenu of the selected mode, adjustable
+// while running
+fn create_mode_options_menu(
+    state: &parking_lot::lock_api::MutexGuard<parking_lot::RawMutex, TrayState>,
+) -> MenuItem<CtrlAssistTray> {
+    let options = &state.mode_options;
+    let submenu = match state.mode {
+        ModeType::Average => vec![
+            create_value_menu(
+                "menu_option_weight",
+                options.average.assist_weight,
+                &WEIGHT_PRESETS,
+                |options, value| options.average.assist_weight = value,
+            ),
+            create_value_menu(
+                "menu_option_deadzone",
+                options.average.deadzone,
+                &DEADZONE_PRESETS,
+                |options, value| options.average.deadzone = value,
+            ),
+        ],
+        ModeType::Blend => vec![
+            create_value_menu(
+                "menu_option_primary_weight",
+                options.blend.primary_weight,
+                &BLEND_WEIGHT_PRESETS,
+                |options, value| options.blend.primary_weight = value,
+            ),
+            create_value_menu(
+                "menu_option_weight",
+                options.blend.assist_weight,
+                &BLEND_WEIGHT_PRESETS,
+                |options, value| options.blend.assist_weight = value,
+            ),
+        ],
+        ModeType::Priority => vec![create_value_menu(
+            "menu_option_deadzone",
+            options.priority.deadzone,
+            &DEADZONE_PRESETS,
+            |options, value| options.priority.deadzone = value,
+        )],
+        ModeType::Toggle => vec![create_button_menu(
+            options.toggle.button,
+            |options, button| options.toggle.button = button,
+        )],
+        ModeType::Turn => vec![create_button_menu(
+            options.turn.button,
+            |options, button| options.turn.button = button,
+        )],
+        ModeType::DeadMan => vec![create_button_menu(
+            options.dead_man.button,
+            |options, button| options.dead_man.button = button,
+        )],
+    };
+
+    menu::SubMenu {
+        label: tr("menu_mode_options"),
+        icon_name: "preferences-system".into(),
+        enabled: true, // Dynamically configurable while running
+        submenu,
+        ..Default::default()
+    }
+    .into()
+}
+
+// Helper to create a numeric parameter's submenu of presets; values set in
+// the config file show in the label, with no preset checked
+fn create_value_menu(
+    label_key: &str,
+    current: f32,
+    presets: &[f32],
+    set: fn(&mut ModeOptions, f32),
+) -> MenuItem<CtrlAssistTray> {
+    menu::SubMenu {
+        label: tr_args(label_key, &[("value", &current.to_string())]),
+        submenu: presets
+            .iter()
+            .map(|&value| {
+                menu::CheckmarkItem {
+                    label: value.to_string(),
+                    checked: (current - value).abs() < f32::EPSILON,
+                    activate: Box::new(move |this: &mut CtrlAssistTray| {
+                        this.set_mode_option(|options| set(options, value));
+                    }),
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect(),
+        ..Default::default()
+    }
+    .into()
+}
+
+// Helper to create the handoff button's submenu
+fn create_button_menu(
+    current: HandoffButton,
+    set: fn(&mut ModeOptions, HandoffButton),
+) -> MenuItem<CtrlAssistTray> {
+    menu::SubMenu {
+        label: tr_args(
+            "menu_option_button",
+            &[("value", &format!("{:?}", current))],
+        ),
+        submenu: HANDOFF_BUTTONS
+            .iter()
+            .map(|&button| {
+                menu::CheckmarkItem {
+                    label: format!("{:?}", button),
+                    checked: current == button,
+                    activate: Box::new(move |this: &mut CtrlAssistTray| {
+                        this.set_mode_option(|options| set(options, button));
+                    }),
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect(),
+        ..Default::default()
+    }
+    .into()
+}
+
 fn create_hide_item(
     hide: HideType,
     state: &parking_lot::lock_api::MutexGuard<parking_lot::RawMutex, TrayState>,
@@ -457,6 +1100,7 @@ fn create_hide_item(
         (HideType::None, HideType::None)
             | (HideType::Steam, HideType::Steam)
             | (HideType::System, HideType::System)
+            | (HideType::Acl, HideType::Acl)
     );
 
     menu::CheckmarkItem {
@@ -482,6 +1126,10 @@ fn create_spoof_item(
         (SpoofTarget::None, SpoofTarget::None)
             | (SpoofTarget::Primary, SpoofTarget::Primary)
             | (SpoofTarget::Assist, SpoofTarget::Assist)
+            | (SpoofTarget::SteamDeck, SpoofTarget::SteamDeck)
+            | (SpoofTarget::Xbox360, SpoofTarget::Xbox360)
+            | (SpoofTarget::Wheel, SpoofTarget::Wheel)
+            | (SpoofTarget::Joystick, SpoofTarget::Joystick)
     );
 
     menu::CheckmarkItem {
@@ -508,6 +1156,7 @@ fn create_rumble_item(
             | (RumbleTarget::Primary, RumbleTarget::Primary)
             | (RumbleTarget::Assist, RumbleTarget::Assist)
             | (RumbleTarget::None, RumbleTarget::None)
+            | (RumbleTarget::Split, RumbleTarget::Split)
     );
 
     menu::CheckmarkItem {
@@ -526,10 +1175,13 @@ fn create_rumble_item(
                 {
                     runtime_settings.update_rumble(rumble.clone());
                     CtrlAssistTray::send_notification(
-                        "CtrlAssist - Rumble Changed",
-                        &format!(
-                            "Rumble target changed from {:?} to {:?}",
-                            old_rumble, rumble
+                        &tr("notify_rumble_changed"),
+                        &tr_args(
+                            "notify_rumble_changed_body",
+                            &[
+                                ("old", &format!("{:?}", old_rumble)),
+                                ("new", &format!("{:?}", rumble)),
+                            ],
                         ),
                     );
                 }
@@ -547,11 +1199,44 @@ fn create_rumble_item(
 
 // Helper function to start mux and update state
 fn start_mux_with_state(
+    gilrs: Option<Gilrs>,
     config: MuxConfig,
     state_arc: Arc<Mutex<TrayState>>,
 ) -> Result<MuxHandle, Box<dyn Error>> {
-    let gilrs = Gilrs::new().map_err(|e| format!("Failed to init Gilrs: {}", e))?;
-    let (mux_handle, runtime_settings) = mux_manager::start_mux(gilrs, config)?;
+    let gilrs = match gilrs {
+        Some(gilrs) => gilrs,
+        None => gilrs_helper::build_gilrs(&config.gilrs)?,
+    };
+
+    // Summarize what System hiding will touch, for the confirmation prompt
+    let confirm = if matches!(config.hide, HideType::System)
+        && config.hide_confirm_secs > 0
+        && config.hide_order != HideOrder::OnUse
+    {
+        let resources = gilrs_helper::discover_gamepad_resources(&gilrs);
+        let paths: Vec<PathBuf> = [
+            Some(config.primary_id),
+            Some(config.assist_id),
+            config.primary_partner,
+            config.assist_partner,
+        ]
+        .into_iter()
+        .flatten()
+        .filter_map(|id| resources.get(&id))
+        .flat_map(|resource| udev_helpers::system_hide_paths(resource).unwrap_or_default())
+        .collect();
+        Some((paths, config.hide_confirm_secs))
+    } else {
+        None
+    };
+
+    let (mux_handle, runtime_settings) =
+        mux_manager::start_mux(gilrs, config).inspect_err(|e| {
+            notifier::post_event(SessionEvent::Error, format!("Failed to start mux: {}", e));
+        })?;
+    if let Some((paths, secs)) = confirm {
+        confirm_hide(paths, secs, Arc::clone(&runtime_settings));
+    }
 
     // Store handle reference in state
     {
@@ -564,6 +1249,44 @@ fn start_mux_with_state(
     Ok(mux_handle)
 }
 
+/// Ask to keep System hiding, which is rolled back unless confirmed in time
+fn confirm_hide(paths: Vec<PathBuf>, secs: u64, settings: Arc<RuntimeSettings>) {
+    let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+    let body = tr_args(
+        "notify_hide_confirm_body",
+        &[("secs", &secs.to_string()), ("paths", &paths.join("\n"))],
+    );
+    // Waiting for the action blocks, so it gets its own thread
+    thread::spawn(move || {
+        let shown = Notification::new()
+            .summary(&tr("notify_hide_confirm"))
+            .body(&body)
+            .appname("CtrlAssist")
+            .action("keep", &tr("notify_hide_keep"))
+            .timeout(Timeout::Milliseconds(secs.saturating_mul(1000) as u32))
+            .show();
+        match shown {
+            Ok(handle) => handle.wait_for_action(|action| {
+                if action == "keep" && settings.confirm_hide() {
+                    info!("System hiding confirmed");
+                }
+            }),
+            Err(e) => error!("Failed to send notification: {}", e),
+        }
+    });
+}
+
+// Helper to label a controller with its ID and battery status, if known
+fn controller_label(controller: &ControllerInfo) -> String {
+    match &controller.power {
+        Some(power) => format!(
+            "({}) {} 🔋 {}",
+            controller.id, controller.display_name, power
+        ),
+        None => format!("({}) {}", controller.id, controller.display_name),
+    }
+}
+
 // Helper to truncate controller name for SubMenu label
 fn truncate_name(name: &str) -> String {
     const MAX_LEN: usize = 17;
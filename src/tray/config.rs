@@ -1,32 +1,306 @@
-use crate::mux_modes::ModeType;
-use crate::{HideType, RumbleTarget, SpoofTarget};
+use crate::button_filters::{ConfirmConfig, DebounceConfig, HoldTimingConfig};
+use crate::disconnect_policy::DisconnectPolicy;
+use crate::ff_helpers::FfConfig;
+use crate::game_profiles::{self, GameProfile};
+use crate::gilrs_helper::GilrsConfig;
+use crate::gyro_aim::GyroConfig;
+use crate::hooks::HookConfig;
+use crate::input_delay::DelayConfig;
+use crate::mode_button::ModeButtonConfig;
+use crate::mouse_aim::MouseConfig;
+use crate::mux_modes::{ModeOptions, ModeType};
+use crate::mux_runtime::TimingConfig;
+use crate::notifier::NotifyConfig;
+use crate::overlay_pause::OverlayPauseConfig;
+use crate::pad_menu::PadMenuConfig;
+use crate::quirks::Quirk;
+use crate::session_timer::TimeLimitConfig;
+use crate::sibling_nodes::SiblingConfig;
+use crate::stick_filters::SmoothingConfig;
+use crate::trackpad_input::TrackpadConfig;
+use crate::{HideOrder, HideType, PaddleBinding, RumbleTarget, SpoofTarget};
 use log::{info, warn};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+/// Top-level keys accepted in the config file
+const KNOWN_KEYS: &[&str] = &[
+    "primary_name",
+    "primary_serial",
+    "assist_name",
+    "assist_serial",
+    "primary_partner_name",
+    "primary_partner_serial",
+    "assist_partner_name",
+    "assist_partner_serial",
+    "mode",
+    "modes",
+    "hide",
+    "hide_order",
+    "hide_settle_ms",
+    "hide_confirm_secs",
+    "spoof",
+    "rumble",
+    "paddles",
+    "hooks",
+    "timing",
+    "gilrs",
+    "debounce",
+    "delay",
+    "hold",
+    "confirm",
+    "smoothing",
+    "gyro",
+    "mouse",
+    "trackpad",
+    "siblings",
+    "force_feedback",
+    "hidraw",
+    "session_name",
+    "grab_sources",
+    "game_mode",
+    "pad_menu",
+    "overlay_pause",
+    "mode_button",
+    "time_limit",
+    "disconnect",
+    "notify",
+    "quirks",
+    "profile",
+    "profiles",
+];
+
+/// Prefix of environment variables overriding config keys
+const ENV_PREFIX: &str = "CTRLASSIST_";
+
+/// Keys accepted in each nested table
+const KNOWN_TABLE_KEYS: &[(&str, &[&str])] = &[
+    (
+        "modes",
+        &["average", "priority", "toggle", "turn", "dead_man", "blend"],
+    ),
+    (
+        "hooks",
+        &["on_start", "on_stop", "on_handoff", "on_disconnect"],
+    ),
+    (
+        "timing",
+        &[
+            "event_timeout_ms",
+            "virtual_device_timeout_ms",
+            "retry_interval_ms",
+        ],
+    ),
+    (
+        "gilrs",
+        &[
+            "jitter_filter",
+            "deadzone_filter",
+            "env_mappings",
+            "included_mappings",
+            "extra_mappings",
+        ],
+    ),
+    ("debounce", &["primary_ms", "assist_ms"]),
+    ("confirm", &["primary", "assist", "window_ms"]),
+    ("delay", &["primary_ms", "assist_ms"]),
+    ("hold", &["primary", "assist"]),
+    (
+        "smoothing",
+        &["enabled", "left_cutoff_hz", "right_cutoff_hz", "source"],
+    ),
+    (
+        "gyro",
+        &[
+            "enabled",
+            "sensitivity",
+            "activation",
+            "invert_x",
+            "invert_y",
+        ],
+    ),
+    (
+        "mouse",
+        &[
+            "enabled",
+            "device",
+            "sensitivity",
+            "curve",
+            "grab",
+            "invert_y",
+        ],
+    ),
+    (
+        "trackpad",
+        &["enabled", "pad", "target", "sensitivity", "haptic_step"],
+    ),
+    ("siblings", &["enabled", "keys"]),
+    ("pad_menu", &["enabled", "chord"]),
+    ("overlay_pause", &["enabled", "chord", "output"]),
+    ("mode_button", &["primary", "assist"]),
+    ("time_limit", &["minutes", "warn_minutes", "action"]),
+    (
+        "notify",
+        &[
+            "backends",
+            "webhook_url",
+            "webhook_template",
+            "webhook_events",
+            "low_battery_percent",
+        ],
+    ),
+    (
+        "force_feedback",
+        &[
+            "effects",
+            "slots",
+            "match_targets",
+            "primary_motors",
+            "assist_motors",
+            "blocked_effects",
+        ],
+    ),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
 pub struct TrayConfig {
     /// Last selected primary controller (by name for best-effort matching)
     pub primary_name: Option<String>,
+    /// Last selected primary controller's evdev uniq or phys, preferred over name
+    pub primary_serial: Option<String>,
     /// Last selected assist controller (by name)
     pub assist_name: Option<String>,
+    /// Last selected assist controller's evdev uniq or phys, preferred over name
+    pub assist_serial: Option<String>,
+    /// Last selected partner combined with the primary, e.g. the other Joy-Con
+    #[serde(default)]
+    pub primary_partner_name: Option<String>,
+    #[serde(default)]
+    pub primary_partner_serial: Option<String>,
+    /// Last selected partner combined with the assist
+    #[serde(default)]
+    pub assist_partner_name: Option<String>,
+    #[serde(default)]
+    pub assist_partner_serial: Option<String>,
     /// Last used mux mode
     #[serde(default)]
     pub mode: ModeType,
+    /// Parameters for each mux mode
+    #[serde(default)]
+    pub modes: ModeOptions,
     /// Last used hide strategy
     #[serde(default)]
     pub hide: HideType,
+    /// Whether to hide before or after creating the virtual device
+    #[serde(default)]
+    pub hide_order: HideOrder,
+    /// Delay in milliseconds between hiding and virtual device creation
+    #[serde(default)]
+    pub hide_settle_ms: u64,
+    /// Seconds to confirm System hiding before it's rolled back, 0 to keep it
+    #[serde(default)]
+    pub hide_confirm_secs: u64,
     /// Last used spoof target
     #[serde(default)]
     pub spoof: SpoofTarget,
     /// Last used rumble target
     #[serde(default)]
     pub rumble: RumbleTarget,
+    /// Binding for Steam Deck back paddles
+    #[serde(default)]
+    pub paddles: PaddleBinding,
+    /// User commands run on session lifecycle events
+    #[serde(default)]
+    pub hooks: HookConfig,
+    /// Event loop timing parameters
+    #[serde(default)]
+    pub timing: TimingConfig,
+    /// Gilrs event filters and mappings
+    #[serde(default)]
+    pub gilrs: GilrsConfig,
+    /// Per-controller button debounce windows
+    #[serde(default)]
+    pub debounce: DebounceConfig,
+    /// Per-controller artificial input delay
+    #[serde(default)]
+    pub delay: DelayConfig,
+    /// Per-controller minimum hold and press durations
+    #[serde(default)]
+    pub hold: HoldTimingConfig,
+    /// Per-controller buttons needing a second press to go through
+    #[serde(default)]
+    pub confirm: ConfirmConfig,
+    /// Low-pass smoothing of the virtual sticks
+    #[serde(default)]
+    pub smoothing: SmoothingConfig,
+    /// Gyro aiming from the assist controller
+    #[serde(default)]
+    pub gyro: GyroConfig,
+    /// Mouse aiming onto the right stick
+    #[serde(default)]
+    pub mouse: MouseConfig,
+    /// Steam Controller or Steam Deck trackpad of the assist as a stick or D-pad
+    #[serde(default)]
+    pub trackpad: TrackpadConfig,
+    /// Keys from the controllers' other event nodes, e.g. share buttons
+    #[serde(default)]
+    pub siblings: SiblingConfig,
+    /// Force feedback advertised by the virtual device
+    #[serde(default)]
+    pub force_feedback: FfConfig,
+    /// Present the virtual device over uhid too, for games reading hidraw
+    #[serde(default)]
+    pub hidraw: bool,
+    /// Append the session to the virtual device's name, to tell sessions apart
+    #[serde(default)]
+    pub session_name: bool,
+    /// Exclusively grab the source controllers, so only CtrlAssist reads them
+    #[serde(default)]
+    pub grab_sources: bool,
+    /// Register with Feral GameMode while a session runs
+    #[serde(default)]
+    pub game_mode: bool,
+    /// Menu opened by a chord on either controller
+    #[serde(default)]
+    pub pad_menu: PadMenuConfig,
+    /// Freezing of output while a system chord is held, e.g. for overlays
+    #[serde(default)]
+    pub overlay_pause: OverlayPauseConfig,
+    /// What each controller's Mode button sends the game
+    #[serde(default)]
+    pub mode_button: ModeButtonConfig,
+    /// Session length limit, warned about ahead, e.g. for screen time
+    #[serde(default)]
+    pub time_limit: TimeLimitConfig,
+    /// What happens to a lost controller's inputs, e.g. held for a reconnect
+    #[serde(default)]
+    pub disconnect: DisconnectPolicy,
+    /// Where notifications go, e.g. a webhook for headless sessions
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    /// Controller model oddities, on top of the built-in ones
+    #[serde(default)]
+    pub quirks: Vec<Quirk>,
+    /// Last selected game profile, or none to detect one from running games
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Hide and spoof overrides per game, by profile name
+    #[serde(default)]
+    pub profiles: BTreeMap<String, GameProfile>,
 }
 
 impl TrayConfig {
+    /// JSON Schema of the config file, including profiles, for editor
+    /// completion and tools generating configs
+    pub fn schema() -> schemars::Schema {
+        schemars::schema_for!(TrayConfig)
+    }
+
     /// Get the config file path ($XDG_CONFIG_HOME/ctrlassist/config.toml)
     pub fn config_path() -> Result<PathBuf, Box<dyn Error>> {
         let config_dir = dirs::config_dir()
@@ -39,23 +313,37 @@ impl TrayConfig {
 
     /// Load config from disk, or return default if not found
     pub fn load() -> Self {
+        Self::load_checked().0
+    }
+
+    /// Load config from disk, along with a report of any problems in it
+    ///
+    /// Keys with bad values fall back to their defaults while the rest of the
+    /// file is kept, and the original is backed up as
+    /// `config.toml.<unix time>.bak` before a save could drop what was wrong
+    /// with it.
+    pub fn load_checked() -> (Self, Option<String>) {
+        let mut table = toml::Table::new();
+        let mut file = None;
+        let mut syntax_error = None;
         match Self::config_path() {
             Ok(path) => {
                 if path.exists() {
                     match fs::read_to_string(&path) {
-                        Ok(content) => match toml::from_str(&content) {
-                            Ok(config) => {
+                        Ok(content) => match content.parse::<toml::Table>() {
+                            Ok(parsed) => {
                                 info!("Loaded config from {}", path.display());
-                                return config;
+                                table = parsed;
                             }
                             Err(e) => {
-                                warn!("Failed to parse config file: {}", e);
+                                syntax_error = Some(format!("Invalid TOML syntax:\n{}", e));
                             }
                         },
                         Err(e) => {
                             warn!("Failed to read config file: {}", e);
                         }
                     }
+                    file = Some(path);
                 }
             }
             Err(e) => {
@@ -63,8 +351,211 @@ impl TrayConfig {
             }
         }
 
-        info!("Using default configuration");
-        Self::default()
+        let (config, report) = match Self::validate_table(table.clone()) {
+            Ok(config) => (config, syntax_error),
+            Err(report) => (Self::salvage(table), Some(report)),
+        };
+        if let Some(report) = &report {
+            warn!("Problems in config:\n{}", report);
+            if let Some(path) = file
+                && let Err(e) = Self::back_up(&path)
+            {
+                warn!("Failed to back up config file: {}", e);
+            }
+        }
+        (config, report)
+    }
+
+    /// Apply `CTRLASSIST_*` environment overrides, for a `mux` session,
+    /// along with a report of any bad values among them
+    ///
+    /// Kept apart from [`Self::load`], so overrides never reach exports,
+    /// reports, or saved settings. A bad value leaves the loaded one in place.
+    pub fn with_env_overrides(self) -> (Self, Option<String>) {
+        let overrides = env_overrides();
+        if overrides.is_empty() {
+            return (self, None);
+        }
+        let keys: Vec<&str> = overrides.keys().map(String::as_str).collect();
+        info!("Config overridden from environment: {}", keys.join(", "));
+        let Some(mut base) = toml::to_string(&self)
+            .ok()
+            .and_then(|content| content.parse::<toml::Table>().ok())
+        else {
+            return (self, None);
+        };
+        let mut table = base.clone();
+        merge(&mut table, overrides);
+        match Self::validate_table(table.clone()) {
+            Ok(config) => (config, None),
+            Err(report) => {
+                warn!("Problems in config environment variables:\n{}", report);
+                merge(&mut base, Self::salvage_table(table, &[]));
+                (base.try_into().unwrap_or(self), Some(report))
+            }
+        }
+    }
+
+    /// Keep every key that is valid on its own, descending into tables so
+    /// only the offending keys within them are dropped
+    fn salvage(table: toml::Table) -> Self {
+        let table = Self::salvage_table(table, &[]);
+        table.try_into().unwrap_or_default()
+    }
+
+    fn salvage_table(table: toml::Table, path: &[String]) -> toml::Table {
+        let mut kept = toml::Table::new();
+        for (key, value) in table {
+            if path.is_empty() && !KNOWN_KEYS.contains(&key.as_str()) {
+                continue;
+            }
+            if Self::fits(path, &key, &value) {
+                kept.insert(key, value);
+                continue;
+            }
+            if let toml::Value::Table(nested) = value {
+                let mut nested_path = path.to_vec();
+                nested_path.push(key.clone());
+                let nested = toml::Value::Table(Self::salvage_table(nested, &nested_path));
+                if Self::fits(path, &key, &nested) {
+                    kept.insert(key, nested);
+                }
+            }
+        }
+        kept
+    }
+
+    /// Whether a config holding only `value`, at `key` under the tables in
+    /// `path`, is valid
+    fn fits(path: &[String], key: &str, value: &toml::Value) -> bool {
+        let mut single = toml::Table::new();
+        single.insert(key.to_string(), value.clone());
+        for parent in path.iter().rev() {
+            let mut outer = toml::Table::new();
+            outer.insert(parent.clone(), toml::Value::Table(single));
+            single = outer;
+        }
+        single.try_into::<Self>().is_ok()
+    }
+
+    /// Copy the config file aside before a save could drop what was wrong
+    /// with it, once per distinct content so earlier originals are kept
+    pub fn back_up(path: &Path) -> Result<(), Box<dyn Error>> {
+        let content = fs::read(path)?;
+        let dir = path.parent().ok_or("Config file has no parent directory")?;
+        for entry in fs::read_dir(dir)?.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with("config.toml.")
+                && name.ends_with(".bak")
+                && fs::read(entry.path()).is_ok_and(|backup| backup == content)
+            {
+                return Ok(());
+            }
+        }
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let backup = dir.join(format!("config.toml.{}.bak", secs));
+        fs::write(&backup, content)?;
+        info!("Backed up config to {}", backup.display());
+        Ok(())
+    }
+
+    /// Load config from disk, failing on any problem instead of falling back
+    /// to defaults, e.g. so an export never passes off defaults as the file
+    pub fn load_strict() -> Result<Self, Box<dyn Error>> {
+        let path = Self::config_path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        Ok(Self::validate(&content)?)
+    }
+
+    /// Parse JSON config content, reporting problems as for TOML
+    pub fn validate_json(content: &str) -> Result<Self, String> {
+        let mut value: serde_json::Value =
+            serde_json::from_str(content).map_err(|e| format!("Invalid JSON syntax:\n{}", e))?;
+        // TOML has no null, which JSON exports use for unset options
+        strip_nulls(&mut value);
+        let table = toml::Table::try_from(value)
+            .map_err(|e| format!("JSON can't be read as config: {}", e))?;
+        Self::validate_table(table)
+    }
+
+    /// Parse config content, reporting every offending key in a readable list
+    pub fn validate(content: &str) -> Result<Self, String> {
+        let table: toml::Table = content
+            .parse()
+            .map_err(|e| format!("Invalid TOML syntax:\n{}", e))?;
+        Self::validate_table(table)
+    }
+
+    fn validate_table(table: toml::Table) -> Result<Self, String> {
+        let mut problems = Vec::new();
+        for key in table.keys() {
+            if !KNOWN_KEYS.contains(&key.as_str()) {
+                problems.push(unknown_key(key, "", KNOWN_KEYS));
+            }
+        }
+        for (section, known) in KNOWN_TABLE_KEYS {
+            let Some(nested) = table.get(*section).and_then(|v| v.as_table()) else {
+                continue;
+            };
+            for key in nested.keys() {
+                if !known.contains(&key.as_str()) {
+                    problems.push(unknown_key(key, &format!("{}.", section), known));
+                }
+            }
+        }
+        // Profile names are free-form, so check the keys one level deeper
+        if let Some(profiles) = table.get("profiles").and_then(|v| v.as_table()) {
+            for (name, profile) in profiles {
+                let Some(profile) = profile.as_table() else {
+                    continue;
+                };
+                for key in profile.keys() {
+                    if !game_profiles::PROFILE_KEYS.contains(&key.as_str()) {
+                        problems.push(unknown_key(
+                            key,
+                            &format!("profiles.{}.", name),
+                            game_profiles::PROFILE_KEYS,
+                        ));
+                    }
+                }
+            }
+        }
+
+        // Check each known key on its own so one bad value doesn't mask the rest
+        for (key, value) in &table {
+            if !KNOWN_KEYS.contains(&key.as_str()) {
+                continue;
+            }
+            let mut single = toml::Table::new();
+            single.insert(key.clone(), value.clone());
+            if let Err(e) = single.try_into::<Self>() {
+                let message = e.message();
+                let mut problem = format!("  - `{}`: {}", key, message);
+                // Point out the variant meant, from those the error lists
+                if let Some((found, expected)) = unknown_variant(message)
+                    && let Some(suggestion) = closest(found, &expected)
+                {
+                    problem.push_str(&format!(" (did you mean `{}`?)", suggestion));
+                }
+                problems.push(problem);
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(format!(
+                "Found {} problem(s) in config:\n{}",
+                problems.len(),
+                problems.join("\n")
+            ));
+        }
+
+        table
+            .try_into()
+            .map_err(|e: toml::de::Error| e.message().to_string())
     }
 
     /// Save config to disk
@@ -73,6 +564,119 @@ impl TrayConfig {
         let content = toml::to_string_pretty(self)?;
         fs::write(&path, content)?;
         info!("Saved config to {}", path.display());
+        crate::crash_helpers::set_config_snapshot(self);
         Ok(())
     }
 }
+
+/// Config keys set by environment variables, e.g. `CTRLASSIST_MODE=Average`,
+/// or `CTRLASSIST_SMOOTHING__ENABLED=true` for a key in a table
+///
+/// Values are read as TOML, such as `true`, `200`, or `["FF_RUMBLE"]`, or
+/// else as plain strings, so enum variants need no quotes.
+pub fn env_overrides() -> toml::Table {
+    let mut table = toml::Table::new();
+    'vars: for (var, raw) in std::env::vars() {
+        let Some(name) = var.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let name = name.to_lowercase();
+        let path: Vec<&str> = name.split("__").collect();
+        // Other CTRLASSIST_ variables, e.g. for tracing, aren't config keys
+        if !KNOWN_KEYS.contains(&path[0]) || path.iter().any(|key| key.is_empty()) {
+            continue;
+        }
+        let value = format!("value = {}", raw)
+            .parse::<toml::Table>()
+            .ok()
+            .and_then(|mut parsed| parsed.remove("value"))
+            .unwrap_or(toml::Value::String(raw));
+
+        let (last, parents) = path.split_last().expect("split yields a key");
+        let mut target = &mut table;
+        for key in parents {
+            let entry = target
+                .entry(key.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            let Some(nested) = entry.as_table_mut() else {
+                continue 'vars;
+            };
+            target = nested;
+        }
+        target.insert(last.to_string(), value);
+    }
+    table
+}
+
+/// Drop null object members, recursively
+fn strip_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, member| !member.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+/// Merge `overlay` into `base`, combining tables and replacing other values
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(nested)) => {
+                merge(existing, nested);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Report an unknown key, suggesting the known one likely meant
+fn unknown_key(key: &str, prefix: &str, known: &[&str]) -> String {
+    match closest(key, known) {
+        Some(suggestion) => format!(
+            "  - unknown key `{}{}` (did you mean `{}{}`?)",
+            prefix, key, prefix, suggestion
+        ),
+        None => format!("  - unknown key `{}{}`", prefix, key),
+    }
+}
+
+/// The found and expected variants of an "unknown variant `X`, expected one
+/// of `A`, `B`" error from serde
+fn unknown_variant(message: &str) -> Option<(&str, Vec<&str>)> {
+    let rest = message.strip_prefix("unknown variant `")?;
+    let (found, expected) = rest.split_once('`')?;
+    let expected = expected.split('`').skip(1).step_by(2).collect();
+    Some((found, expected))
+}
+
+/// The candidate closest to a misspelling, if close enough to be a typo
+fn closest<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let word = word.to_lowercase();
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(&word, &candidate.to_lowercase()), *candidate))
+        .filter(|(distance, candidate)| *distance <= (candidate.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two strings, by character
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
@@ -0,0 +1,104 @@
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::sync::LazyLock;
+
+/// Built-in English strings, used for any key a translation lacks
+const FALLBACK: &str = include_str!("locales/en.toml");
+
+static CATALOG: LazyLock<Catalog> = LazyLock::new(Catalog::load);
+
+/// Flat map of message keys to strings for the detected locale
+struct Catalog {
+    fallback: HashMap<String, String>,
+    translated: HashMap<String, String>,
+}
+
+impl Catalog {
+    fn load() -> Self {
+        let fallback = parse(FALLBACK).unwrap_or_default();
+        let translated = locale_language()
+            .filter(|lang| lang != "en")
+            .and_then(|lang| {
+                let path = dirs::data_dir()?
+                    .join("ctrlassist/locales")
+                    .join(format!("{}.toml", lang));
+                let content = fs::read_to_string(&path).ok()?;
+                match parse(&content) {
+                    Ok(strings) => {
+                        info!("Loaded translations from {}", path.display());
+                        Some(strings)
+                    }
+                    Err(e) => {
+                        warn!("Failed to parse {}: {}", path.display(), e);
+                        None
+                    }
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            fallback,
+            translated,
+        }
+    }
+}
+
+fn parse(content: &str) -> Result<HashMap<String, String>, toml::de::Error> {
+    toml::from_str(content)
+}
+
+/// Language code of the message locale, e.g. "de" for `de_DE.UTF-8`
+fn locale_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| {
+            let lang = value.split(['_', '.', '@']).next()?.to_lowercase();
+            (!lang.is_empty() && lang != "c" && lang != "posix").then_some(lang)
+        })
+}
+
+/// Look up a user-facing string, falling back to English, then the key itself
+pub fn tr(key: &str) -> String {
+    CATALOG
+        .translated
+        .get(key)
+        .or_else(|| CATALOG.fallback.get(key))
+        .cloned()
+        .unwrap_or_else(|| key.to_string())
+}
+
+/// Look up a string and substitute its `{name}` placeholders
+///
+/// Substitution is a single pass over the template, so braces within the
+/// values, e.g. a device named `{name}`, are left as they are.
+pub fn tr_args(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let template = tr(key);
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template.as_str();
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let arg = after.find('}').and_then(|end| {
+            let name = &after[..end];
+            args.iter()
+                .find(|(arg, _)| *arg == name)
+                .map(|(_, value)| (end, value))
+        });
+        match arg {
+            Some((end, value)) => {
+                text.push_str(&value.to_string());
+                rest = &after[end + 1..];
+            }
+            None => {
+                text.push('{');
+                rest = after;
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
@@ -1,13 +1,21 @@
 use futures_util::TryFutureExt;
 mod app;
-mod config;
+pub mod config;
+mod i18n;
+mod shortcuts;
 mod state;
 
 pub use app::CtrlAssistTray;
 
+use crate::sleep_monitor;
 use ashpd::is_sandboxed;
 use ksni::TrayMethods;
+use log::warn;
 use std::error::Error;
+use std::time::Duration;
+
+/// How often the icon and tooltip are refreshed to reflect session health
+const HEALTH_REFRESH: Duration = Duration::from_secs(1);
 
 pub async fn run_tray() -> Result<(), Box<dyn Error>> {
     let tray = CtrlAssistTray::new()?;
@@ -26,14 +34,43 @@ pub async fn run_tray() -> Result<(), Box<dyn Error>> {
             .await
     };
 
-    handle_result?;
+    let handle = handle_result?;
+
+    // Keyboards can't be read from a sandbox, so hotkeys go through the portal
+    if is_sandboxed {
+        let shortcuts_handle = handle.clone();
+        tokio::spawn(async move {
+            if let Err(e) = shortcuts::listen(shortcuts_handle).await {
+                warn!("Global shortcuts unavailable: {}", e);
+            }
+        });
+    }
+
+    // Stop the session before system sleep, and start it again after
+    let sleep_handle = handle.clone();
+    tokio::spawn(async move {
+        let result = sleep_monitor::watch(|event| {
+            let handle = sleep_handle.clone();
+            async move {
+                handle.update(move |tray| tray.follow_sleep(event)).await;
+            }
+        })
+        .await;
+        if let Err(e) = result {
+            warn!("Unable to follow system sleep: {}", e);
+        }
+    });
 
     println!("CtrlAssist system tray started");
     println!("Configure and control the mux from your system tray");
     println!("Press Ctrl+C to exit");
 
-    // Run forever
-    std::thread::park();
-
-    Ok(())
+    // Run forever, refreshing what the session's health changes, such as a
+    // controller disconnecting, as nothing else prompts the tray to update
+    loop {
+        std::thread::sleep(HEALTH_REFRESH);
+        if futures::executor::block_on(handle.update(|_| {})).is_none() {
+            return Err("Tray service stopped".into());
+        }
+    }
 }
@@ -0,0 +1,50 @@
+use super::app::CtrlAssistTray;
+use super::i18n::tr;
+use ashpd::desktop::global_shortcuts::{GlobalShortcuts, NewShortcut};
+use futures_util::StreamExt;
+use ksni::Handle;
+use log::info;
+
+/// Shortcut IDs with the locale key of their description
+///
+/// No triggers are suggested; the desktop asks the user to assign keys when
+/// the shortcuts are first bound, and remembers them afterwards.
+const SHORTCUTS: [(&str, &str); 3] = [
+    ("start", "shortcut_start"),
+    ("stop", "shortcut_stop"),
+    ("toggle", "shortcut_toggle"),
+];
+
+/// Bind start/stop/toggle shortcuts through the GlobalShortcuts portal, and
+/// act on them until the tray stops
+///
+/// Inside a sandbox, keyboards can't be read directly, so the desktop relays
+/// the key presses instead.
+pub async fn listen(handle: Handle<CtrlAssistTray>) -> ashpd::Result<()> {
+    let portal = GlobalShortcuts::new().await?;
+    // Shortcuts stay bound only while the session lives
+    let session = portal.create_session().await?;
+    let shortcuts: Vec<NewShortcut> = SHORTCUTS
+        .iter()
+        .map(|(id, key)| NewShortcut::new(*id, tr(key)))
+        .collect();
+    portal
+        .bind_shortcuts(&session, &shortcuts, None)
+        .await?
+        .response()?;
+    info!("Global shortcuts bound through the desktop portal");
+
+    let mut activated = portal.receive_activated().await?;
+    while let Some(event) = activated.next().await {
+        let id = event.shortcut_id().to_string();
+        info!("Global shortcut activated: {}", id);
+        if handle
+            .update(move |tray| tray.activate_shortcut(&id))
+            .await
+            .is_none()
+        {
+            break;
+        }
+    }
+    Ok(())
+}
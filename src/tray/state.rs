@@ -1,10 +1,32 @@
-use crate::mux_modes::ModeType;
-use crate::{HideType, RumbleTarget, SpoofTarget};
+use crate::button_filters::{ConfirmConfig, DebounceConfig, HoldTimingConfig};
+use crate::disconnect_policy::DisconnectPolicy;
+use crate::ff_helpers::FfConfig;
+use crate::game_profiles::GameProfile;
+use crate::gilrs_helper::{self, GilrsConfig};
+use crate::gyro_aim::GyroConfig;
+use crate::hooks::HookConfig;
+use crate::input_delay::DelayConfig;
+use crate::mode_button::ModeButtonConfig;
+use crate::mouse_aim::MouseConfig;
+use crate::mux_modes::{ModeOptions, ModeType};
+use crate::mux_runtime::TimingConfig;
+use crate::notifier::NotifyConfig;
+use crate::overlay_pause::OverlayPauseConfig;
+use crate::pad_menu::PadMenuConfig;
+use crate::power_helpers::{PowerInfo, PowerLookup};
+use crate::quirks::Quirk;
+use crate::session_timer::TimeLimitConfig;
+use crate::sibling_nodes::SiblingConfig;
+use crate::stick_filters::SmoothingConfig;
+use crate::trackpad_input::TrackpadConfig;
+use crate::{HideOrder, HideType, PaddleBinding, RumbleTarget, SpoofTarget};
 use gilrs::{GamepadId, Gilrs};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
 use std::thread;
+use std::time::Instant;
 
 use super::config::TrayConfig;
 
@@ -12,6 +34,55 @@ use super::config::TrayConfig;
 pub struct ControllerInfo {
     pub id: GamepadId,
     pub name: String,
+    /// Name with connection type and serial suffix for menus
+    pub display_name: String,
+    /// evdev uniq or phys, for telling identical controllers apart
+    pub serial: Option<String>,
+    /// Battery level and charging state, if reported
+    pub power: Option<PowerInfo>,
+}
+
+impl ControllerInfo {
+    /// List connected controllers with their evdev identifying details
+    ///
+    /// CtrlAssist virtual gamepads are left out, as selecting one would feed
+    /// the mux its own output; chaining is only possible from the CLI.
+    pub fn discover(gilrs: &Gilrs) -> Vec<Self> {
+        let resources = gilrs_helper::discover_gamepad_resources(gilrs);
+        let power = PowerLookup::new();
+        gilrs
+            .gamepads()
+            .filter(|(id, _)| !resources.get(id).is_some_and(|r| r.is_ctrlassist()))
+            .map(|(id, gamepad)| {
+                let resource = resources.get(&id);
+                Self {
+                    id,
+                    name: gamepad.name().to_string(),
+                    display_name: resource
+                        .map(|r| r.display_name())
+                        .unwrap_or_else(|| gamepad.name().to_string()),
+                    serial: resource.and_then(|r| r.serial()),
+                    power: resource.and_then(|r| power.power_info(r)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Find a saved controller, preferring an exact serial match over the name
+fn match_saved(
+    controllers: &[ControllerInfo],
+    name: Option<&String>,
+    serial: Option<&String>,
+) -> Option<GamepadId> {
+    serial
+        .and_then(|serial| {
+            controllers
+                .iter()
+                .find(|c| c.serial.as_ref() == Some(serial))
+        })
+        .or_else(|| name.and_then(|name| controllers.iter().find(|c| &c.name == name)))
+        .map(|c| c.id)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -21,22 +92,92 @@ pub enum MuxStatus {
 }
 
 pub struct TrayState {
+    /// Shared Gilrs instance, lent to the mux while it is running
+    pub gilrs: Option<Gilrs>,
     /// Available controllers
     pub controllers: Vec<ControllerInfo>,
     /// Currently selected primary controller ID
     pub selected_primary: Option<GamepadId>,
     /// Currently selected assist controller ID
     pub selected_assist: Option<GamepadId>,
+    /// Controller combined with the primary as one, e.g. the other Joy-Con
+    pub selected_primary_partner: Option<GamepadId>,
+    /// Controller combined with the assist as one
+    pub selected_assist_partner: Option<GamepadId>,
     /// Current mux mode
     pub mode: ModeType,
+    /// Parameters for each mux mode
+    pub mode_options: ModeOptions,
     /// Current hide strategy
     pub hide: HideType,
+    /// Whether to hide before or after creating the virtual device
+    pub hide_order: HideOrder,
+    /// Delay in milliseconds between hiding and virtual device creation
+    pub hide_settle_ms: u64,
+    /// Seconds to confirm System hiding before it's rolled back, 0 to keep it
+    pub hide_confirm_secs: u64,
     /// Current spoof target
     pub spoof: SpoofTarget,
     /// Current rumble target
     pub rumble: RumbleTarget,
+    /// Binding for Steam Deck back paddles
+    pub paddles: PaddleBinding,
+    /// User commands run on session lifecycle events
+    pub hooks: HookConfig,
+    /// Event loop timing parameters
+    pub timing: TimingConfig,
+    /// Gilrs event filters and mappings
+    pub gilrs_config: GilrsConfig,
+    /// Per-controller button debounce windows
+    pub debounce: DebounceConfig,
+    /// Per-controller artificial input delay
+    pub delay: DelayConfig,
+    /// Per-controller minimum hold and press durations
+    pub hold: HoldTimingConfig,
+    /// Per-controller buttons needing a second press to go through
+    pub confirm: ConfirmConfig,
+    /// Low-pass smoothing of the virtual sticks
+    pub smoothing: SmoothingConfig,
+    /// Gyro aiming from the assist controller
+    pub gyro: GyroConfig,
+    /// Mouse aiming onto the right stick
+    pub mouse: MouseConfig,
+    /// Assist trackpad as a stick or D-pad
+    pub trackpad: TrackpadConfig,
+    /// Keys from the controllers' other event nodes
+    pub siblings: SiblingConfig,
+    /// Force feedback advertised by the virtual device
+    pub force_feedback: FfConfig,
+    /// Present the virtual device over uhid too, for games reading hidraw
+    pub hidraw: bool,
+    /// Append the session to the virtual device's name, to tell sessions apart
+    pub session_name: bool,
+    /// Exclusively grab the source controllers, so only CtrlAssist reads them
+    pub grab_sources: bool,
+    /// Register with Feral GameMode while a session runs
+    pub game_mode: bool,
+    /// Menu opened by a chord on either controller
+    pub pad_menu: PadMenuConfig,
+    /// Freezing of output while a system chord is held, e.g. for overlays
+    pub overlay_pause: OverlayPauseConfig,
+    /// What each controller's Mode button sends the game
+    pub mode_button: ModeButtonConfig,
+    /// Session length limit, warned about ahead, e.g. for screen time
+    pub time_limit: TimeLimitConfig,
+    /// What happens to a lost controller's inputs, e.g. held for a reconnect
+    pub disconnect: DisconnectPolicy,
+    /// Where notifications go, e.g. a webhook for headless sessions
+    pub notify: NotifyConfig,
+    /// Controller model oddities, on top of the built-in ones
+    pub quirks: Vec<Quirk>,
+    /// Selected game profile, or none to detect one from running games
+    pub profile: Option<String>,
+    /// Hide and spoof overrides per game, by profile name
+    pub profiles: BTreeMap<String, GameProfile>,
     /// Mux running status
     pub status: MuxStatus,
+    /// Whether the last session failed to start or stopped unexpectedly
+    pub failed: bool,
     /// Mux thread handle (if running)
     pub mux_handle: Option<thread::JoinHandle<()>>,
     /// Shutdown signal for mux thread
@@ -45,70 +186,176 @@ pub struct TrayState {
     pub virtual_device_path: Option<PathBuf>,
     /// Shared runtime settings for live updates
     pub runtime_settings: Option<Arc<crate::mux_runtime::RuntimeSettings>>,
+    /// Session stopped for system sleep, to start again on resume
+    pub resume_after_sleep: bool,
+    /// When the time limit started counting, kept until an explicit Stop so
+    /// sessions restarted after sleep share one limit
+    pub time_limit_start: Option<Instant>,
 }
 
 impl TrayState {
-    pub fn new(gilrs: &Gilrs, config: TrayConfig) -> Self {
-        let controllers: Vec<ControllerInfo> = gilrs
-            .gamepads()
-            .map(|(id, gamepad)| ControllerInfo {
-                id,
-                name: gamepad.name().to_string(),
-            })
-            .collect();
-
-        // Try to match saved controller names to current controllers (best-effort)
-        let selected_primary = config
-            .primary_name
-            .as_ref()
-            .and_then(|name| controllers.iter().find(|c| &c.name == name))
-            .map(|c| c.id)
-            .or_else(|| controllers.first().map(|c| c.id));
-
-        let selected_assist = config
-            .assist_name
-            .as_ref()
-            .and_then(|name| controllers.iter().find(|c| &c.name == name))
-            .map(|c| c.id)
-            .or_else(|| controllers.get(1).map(|c| c.id));
+    pub fn new(gilrs: Gilrs, config: TrayConfig) -> Self {
+        let controllers = ControllerInfo::discover(&gilrs);
+
+        // Try to match saved controllers by serial, then name (best-effort)
+        let selected_primary = match_saved(
+            &controllers,
+            config.primary_name.as_ref(),
+            config.primary_serial.as_ref(),
+        )
+        .or_else(|| controllers.first().map(|c| c.id));
+
+        let selected_assist = match_saved(
+            &controllers,
+            config.assist_name.as_ref(),
+            config.assist_serial.as_ref(),
+        )
+        .or_else(|| controllers.get(1).map(|c| c.id));
+
+        // Partners are only restored if saved, never picked by default
+        let selected_primary_partner = match_saved(
+            &controllers,
+            config.primary_partner_name.as_ref(),
+            config.primary_partner_serial.as_ref(),
+        );
+        let selected_assist_partner = match_saved(
+            &controllers,
+            config.assist_partner_name.as_ref(),
+            config.assist_partner_serial.as_ref(),
+        );
 
         Self {
+            gilrs: Some(gilrs),
             controllers,
             selected_primary,
             selected_assist,
+            selected_primary_partner,
+            selected_assist_partner,
             mode: config.mode,
+            mode_options: config.modes,
             hide: config.hide,
+            hide_order: config.hide_order,
+            hide_settle_ms: config.hide_settle_ms,
+            hide_confirm_secs: config.hide_confirm_secs,
             spoof: config.spoof,
             rumble: config.rumble,
+            paddles: config.paddles,
+            hooks: config.hooks,
+            timing: config.timing,
+            gilrs_config: config.gilrs,
+            debounce: config.debounce,
+            delay: config.delay,
+            hold: config.hold,
+            confirm: config.confirm,
+            smoothing: config.smoothing,
+            gyro: config.gyro,
+            mouse: config.mouse,
+            trackpad: config.trackpad,
+            siblings: config.siblings,
+            force_feedback: config.force_feedback,
+            hidraw: config.hidraw,
+            session_name: config.session_name,
+            grab_sources: config.grab_sources,
+            game_mode: config.game_mode,
+            pad_menu: config.pad_menu,
+            overlay_pause: config.overlay_pause,
+            mode_button: config.mode_button,
+            time_limit: config.time_limit,
+            disconnect: config.disconnect,
+            notify: config.notify,
+            quirks: config.quirks,
+            profile: config.profile,
+            profiles: config.profiles,
             status: MuxStatus::Stopped,
             mux_handle: None,
             runtime_settings: None,
+            failed: false,
             shutdown_signal: None,
             virtual_device_path: None,
+            resume_after_sleep: false,
+            time_limit_start: None,
         }
     }
 
     pub fn to_config(&self) -> TrayConfig {
+        let primary = self
+            .selected_primary
+            .and_then(|id| self.controllers.iter().find(|c| c.id == id));
+        let assist = self
+            .selected_assist
+            .and_then(|id| self.controllers.iter().find(|c| c.id == id));
+        let primary_partner = self
+            .selected_primary_partner
+            .and_then(|id| self.controllers.iter().find(|c| c.id == id));
+        let assist_partner = self
+            .selected_assist_partner
+            .and_then(|id| self.controllers.iter().find(|c| c.id == id));
         TrayConfig {
-            primary_name: self
-                .selected_primary
-                .and_then(|id| self.controllers.iter().find(|c| c.id == id))
-                .map(|c| c.name.clone()),
-            assist_name: self
-                .selected_assist
-                .and_then(|id| self.controllers.iter().find(|c| c.id == id))
-                .map(|c| c.name.clone()),
+            primary_name: primary.map(|c| c.name.clone()),
+            primary_serial: primary.and_then(|c| c.serial.clone()),
+            assist_name: assist.map(|c| c.name.clone()),
+            assist_serial: assist.and_then(|c| c.serial.clone()),
+            primary_partner_name: primary_partner.map(|c| c.name.clone()),
+            primary_partner_serial: primary_partner.and_then(|c| c.serial.clone()),
+            assist_partner_name: assist_partner.map(|c| c.name.clone()),
+            assist_partner_serial: assist_partner.and_then(|c| c.serial.clone()),
             mode: self.mode.clone(),
+            modes: self.mode_options.clone(),
             hide: self.hide.clone(),
+            hide_order: self.hide_order.clone(),
+            hide_settle_ms: self.hide_settle_ms,
+            hide_confirm_secs: self.hide_confirm_secs,
             spoof: self.spoof.clone(),
             rumble: self.rumble.clone(),
+            paddles: self.paddles.clone(),
+            hooks: self.hooks.clone(),
+            timing: self.timing.clone(),
+            gilrs: self.gilrs_config.clone(),
+            debounce: self.debounce.clone(),
+            delay: self.delay.clone(),
+            hold: self.hold.clone(),
+            confirm: self.confirm.clone(),
+            smoothing: self.smoothing.clone(),
+            gyro: self.gyro.clone(),
+            mouse: self.mouse.clone(),
+            trackpad: self.trackpad.clone(),
+            siblings: self.siblings.clone(),
+            force_feedback: self.force_feedback.clone(),
+            hidraw: self.hidraw,
+            session_name: self.session_name,
+            grab_sources: self.grab_sources,
+            game_mode: self.game_mode,
+            pad_menu: self.pad_menu.clone(),
+            overlay_pause: self.overlay_pause.clone(),
+            mode_button: self.mode_button.clone(),
+            time_limit: self.time_limit.clone(),
+            disconnect: self.disconnect,
+            notify: self.notify.clone(),
+            quirks: self.quirks.clone(),
+            profile: self.profile.clone(),
+            profiles: self.profiles.clone(),
         }
     }
 
     pub fn is_valid_for_start(&self) -> bool {
+        // Every selected controller, partners included, must be distinct
+        let selected: Vec<GamepadId> = [
+            self.selected_primary,
+            self.selected_assist,
+            self.selected_primary_partner,
+            self.selected_assist_partner,
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let distinct = selected
+            .iter()
+            .enumerate()
+            .all(|(i, id)| !selected[..i].contains(id));
+
         self.selected_primary.is_some()
             && self.selected_assist.is_some()
-            && self.selected_primary != self.selected_assist
+            && distinct
             && self.status == MuxStatus::Stopped
     }
 
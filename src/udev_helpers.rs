@@ -1,21 +1,74 @@
 use crate::HideType;
+use crate::audit_log;
 use crate::gilrs_helper::GamepadResource;
+use crate::state;
+use crate::steam_backup::{SteamJournal, without_ids};
+use crate::sudo_helpers::{active_uid, user_home};
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::error::Error;
 use std::fs;
 use std::io::{self, Write};
-use std::os::unix::fs::PermissionsExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
 use std::path::{Path, PathBuf};
+use std::process::Command;
 use udev::{Device, Enumerator};
 
 const MODE_ROOT_ONLY: u32 = 0o600;
 const MODE_ROOT_GROUP: u32 = 0o660;
+const DEFAULT_SEAT: &str = "seat0";
+const HIDE_JOURNAL: &str = "hide.toml";
 
 /// A RAII guard that hides devices and automatically restores them when dropped.
 pub struct ScopedDeviceHider {
     hide_type: HideType,
     system_state: SystemHideState,
     steam_state: SteamHideState,
+    acl_state: AclHideState,
+    /// Hidden nodes not yet restored, including any left by an earlier session
+    journal: HideJournal,
+}
+
+/// Record of device nodes hidden and not yet restored, kept in the state directory
+///
+/// Saved after each change, so a crash or power loss can still be recovered
+/// with `ctrlassist restore --hide`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HideJournal {
+    /// Nodes restricted to root by system hiding
+    #[serde(default)]
+    pub system: Vec<PathBuf>,
+    /// ACL entries removed by ACL hiding
+    #[serde(default)]
+    pub acl: Vec<AclEntry>,
+}
+
+/// An ACL entry removed from a device node, with its original permissions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AclEntry {
+    pub path: PathBuf,
+    pub uid: u32,
+    pub perms: String,
+}
+
+impl HideJournal {
+    /// The journal of nodes left hidden, if any
+    pub fn load() -> Option<Self> {
+        state::load::<Self>(HIDE_JOURNAL).filter(|journal| !journal.is_empty())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.system.is_empty() && self.acl.is_empty()
+    }
+
+    /// Persist the journal, or remove it once nothing is left hidden
+    fn save(&self) {
+        if self.is_empty() {
+            state::remove(HIDE_JOURNAL);
+        } else if let Err(e) = state::save(HIDE_JOURNAL, self) {
+            log::warn!("Failed to save hide journal: {}", e);
+        }
+    }
 }
 
 /// Tracks system-level permission changes
@@ -23,11 +76,21 @@ struct SystemHideState {
     hidden_paths: HashSet<PathBuf>,
 }
 
+/// Tracks ACL entries removed for the active user
+struct AclHideState {
+    /// User whose ACL entry is removed (the invoking user under sudo)
+    uid: u32,
+    /// Removed entries: device node -> original permissions (e.g. "rw-")
+    removed: Vec<(PathBuf, String)>,
+}
+
 /// Tracks Steam config modifications
 struct SteamHideState {
     config_path: Option<PathBuf>,
     original_blacklist: Option<String>,
     added_ids: Vec<String>,
+    /// Backup and record of changes, for recovery if restoring fails
+    journal: Option<SteamJournal>,
 }
 
 impl ScopedDeviceHider {
@@ -41,7 +104,26 @@ impl ScopedDeviceHider {
                 config_path: None,
                 original_blacklist: None,
                 added_ids: Vec::new(),
+                journal: None,
+            },
+            acl_state: AclHideState {
+                uid: active_uid(),
+                removed: Vec::new(),
             },
+            journal: HideJournal::default(),
+        }
+    }
+
+    /// Start journaling on first hide, keeping entries an earlier session left
+    fn begin_journal(&mut self) {
+        if self.journal.is_empty()
+            && let Some(stale) = HideJournal::load()
+        {
+            log::warn!(
+                "A previous session left {} device node(s) hidden; run `ctrlassist restore --hide` to restore them",
+                stale.system.len() + stale.acl.len()
+            );
+            self.journal = stale;
         }
     }
 
@@ -53,31 +135,36 @@ impl ScopedDeviceHider {
         match self.hide_type {
             HideType::None => Ok(()),
             HideType::System => self.hide_system(resource),
+            HideType::Acl => self.hide_acl(resource),
             HideType::Steam => self.hide_steam(resource),
         }
     }
 
     /// System hiding: restrict device permissions
     fn hide_system(&mut self, resource: &GamepadResource) -> Result<(), Box<dyn Error>> {
-        let event_path = resource.path.as_path();
-
-        // Find the specific udev device
-        let device = match find_device_by_path(event_path)? {
-            Some(d) => d,
-            None => {
-                self.system_state.hide_and_track(event_path);
-                return Ok(());
+        self.begin_journal();
+        for node in find_gamepad_devnodes(resource)? {
+            if self.system_state.hide_and_track(&node) && !self.journal.system.contains(&node) {
+                self.journal.system.push(node);
+                self.journal.save();
             }
-        };
-
-        // Find the physical parent and all related nodes
-        let physical_root = find_physical_root(&device);
-        let related_nodes = find_related_devnodes(&physical_root)?;
-
-        for node in related_nodes {
-            self.system_state.hide_and_track(&node);
         }
+        Ok(())
+    }
 
+    /// ACL hiding: remove only the active user's ACL entry from device nodes
+    fn hide_acl(&mut self, resource: &GamepadResource) -> Result<(), Box<dyn Error>> {
+        self.begin_journal();
+        for node in find_gamepad_devnodes(resource)? {
+            if let Some(perms) = self.acl_state.hide_and_track(&node) {
+                self.journal.acl.push(AclEntry {
+                    path: node,
+                    uid: self.acl_state.uid,
+                    perms,
+                });
+                self.journal.save();
+            }
+        }
         Ok(())
     }
 
@@ -87,10 +174,7 @@ impl ScopedDeviceHider {
         let config_path = match &self.steam_state.config_path {
             Some(path) => path,
             None => {
-                let home = dirs::home_dir().ok_or(
-                    "Could not determine home directory; Steam config path is required for Steam hiding"
-                )?;
-                let path = home.join(".local/share/Steam/config/config.vdf");
+                let path = steam_config_path()?;
                 self.steam_state.config_path = Some(path);
                 self.steam_state.config_path.as_ref().unwrap()
             }
@@ -124,12 +208,44 @@ impl ScopedDeviceHider {
                 format!("Failed to read Steam config ({}): {}", detail, e)
             })?;
 
-            let original_blacklist = parse_controller_blacklist(&config_content);
-            self.steam_state.original_blacklist = Some(original_blacklist.unwrap_or_default());
+            let mut original_blacklist =
+                parse_controller_blacklist(&config_content).unwrap_or_default();
+            match SteamJournal::load() {
+                // Adopt what an unrestored session added, so it's restored
+                // with this session's IDs rather than becoming the original
+                Some(stale) if stale.config_path == *config_path => {
+                    log::warn!(
+                        "A previous session left {} in the Steam blacklist; removing them when this session ends",
+                        stale.added_ids.join(", ")
+                    );
+                    original_blacklist = without_ids(&original_blacklist, &stale.added_ids);
+                    self.steam_state.added_ids = stale.added_ids.clone();
+                    self.steam_state.journal = Some(stale);
+                }
+                Some(stale) => {
+                    return Err(format!(
+                        "A previous session left {} in the Steam blacklist of {}; run `ctrlassist restore --steam` first",
+                        stale.added_ids.join(", "),
+                        stale.config_path.display()
+                    )
+                    .into());
+                }
+                // Hiding on use hides again after each restore, which keeps
+                // the first backup rather than piling up copies
+                None if self.steam_state.journal.is_some() => {}
+                None => self.steam_state.journal = Some(SteamJournal::begin(config_path)?),
+            }
+            self.steam_state.original_blacklist = Some(original_blacklist);
         }
 
-        // Add new ID
-        self.steam_state.added_ids.push(id_pair.clone());
+        // Add new ID, journaling it before the config is touched
+        if !self.steam_state.added_ids.contains(&id_pair) {
+            self.steam_state.added_ids.push(id_pair.clone());
+        }
+        if let Some(journal) = &mut self.steam_state.journal {
+            journal.added_ids = self.steam_state.added_ids.clone();
+            journal.save()?;
+        }
 
         // Build new blacklist
         let mut all_ids = Vec::new();
@@ -149,47 +265,132 @@ impl ScopedDeviceHider {
 }
 
 impl SystemHideState {
-    fn hide_and_track(&mut self, path: &Path) {
+    /// Hide a node, returning whether it was newly hidden
+    fn hide_and_track(&mut self, path: &Path) -> bool {
         // Skip if we are already tracking this path to avoid redundant syscalls
         if self.hidden_paths.contains(path) {
-            return;
+            return false;
         }
 
         match set_permissions(path, MODE_ROOT_ONLY) {
             Ok(_) => {
                 self.hidden_paths.insert(path.to_path_buf());
                 log::info!("Hidden (system): {}", path.display());
+                true
+            }
+            Err(e) => {
+                log::warn!("Failed to hide {}: {}", path.display(), e);
+                false
             }
-            Err(e) => log::warn!("Failed to hide {}: {}", path.display(), e),
         }
     }
 }
 
-// Ensure devices are restored when the struct goes out of scope (e.g. app exit/panic).
-impl Drop for ScopedDeviceHider {
-    fn drop(&mut self) {
+impl AclHideState {
+    /// Hide a node, returning the permissions of the removed entry if newly hidden
+    fn hide_and_track(&mut self, path: &Path) -> Option<String> {
+        // Skip if we are already tracking this path to avoid redundant calls
+        if self.removed.iter().any(|(p, _)| p == path) {
+            return None;
+        }
+
+        let perms = match get_user_acl(path, self.uid) {
+            Ok(Some(perms)) => perms,
+            Ok(None) => {
+                log::debug!(
+                    "No ACL entry for uid {} on {}, skipping",
+                    self.uid,
+                    path.display()
+                );
+                return None;
+            }
+            Err(e) => {
+                log::warn!("Failed to read ACL of {}: {}", path.display(), e);
+                return None;
+            }
+        };
+
+        match run_setfacl(path, "-x", &format!("u:{}", self.uid)) {
+            Ok(()) => {
+                log::info!("Hidden (acl): {}", path.display());
+                audit_log::record(
+                    "setfacl",
+                    &path.display().to_string(),
+                    &format!("u:{}:{}", self.uid, perms),
+                    "removed",
+                );
+                self.removed.push((path.to_path_buf(), perms.clone()));
+                Some(perms)
+            }
+            Err(e) => {
+                log::warn!("Failed to hide {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+impl ScopedDeviceHider {
+    /// Restore every hidden device now, e.g. to roll back unconfirmed hiding
+    ///
+    /// Devices hidden later are tracked afresh, so this is safe to call more
+    /// than once.
+    pub fn restore(&mut self) {
         match self.hide_type {
             HideType::None => {}
             HideType::System => {
                 // Restore system permissions
-                for path in &self.system_state.hidden_paths {
-                    if let Err(e) = set_permissions(path, MODE_ROOT_GROUP) {
+                for path in self.system_state.hidden_paths.drain() {
+                    if let Err(e) = set_permissions(&path, MODE_ROOT_GROUP) {
                         log::error!("Failed to restore {}: {}", path.display(), e);
                     } else {
                         log::info!("Restored (system): {}", path.display());
+                        self.journal.system.retain(|p| p != &path);
                     }
                 }
+                self.journal.save();
+            }
+            HideType::Acl => {
+                // Restore removed ACL entries with their original permissions
+                for (path, perms) in self.acl_state.removed.drain(..) {
+                    let entry = format!("u:{}:{}", self.acl_state.uid, perms);
+                    if let Err(e) = run_setfacl(&path, "-m", &entry) {
+                        log::error!("Failed to restore {}: {}", path.display(), e);
+                    } else {
+                        log::info!("Restored (acl): {}", path.display());
+                        audit_log::record(
+                            "setfacl",
+                            &path.display().to_string(),
+                            "removed",
+                            &entry,
+                        );
+                        let uid = self.acl_state.uid;
+                        self.journal
+                            .acl
+                            .retain(|e| !(e.path == path && e.uid == uid));
+                    }
+                }
+                self.journal.save();
             }
             HideType::Steam => {
                 // Restore original Steam config
                 if let (Some(config_path), Some(original)) = (
                     &self.steam_state.config_path,
-                    &self.steam_state.original_blacklist,
+                    self.steam_state.original_blacklist.take(),
                 ) {
-                    if let Err(e) = update_steam_config(config_path, original) {
-                        log::error!("Failed to restore Steam config: {}", e);
+                    if let Err(e) = update_steam_config(config_path, &original) {
+                        log::error!(
+                            "Failed to restore Steam config, recover with `ctrlassist restore --steam`: {}",
+                            e
+                        );
                     } else {
                         log::info!("Restored Steam blacklist to original state");
+                        SteamJournal::clear();
+                    }
+                    self.steam_state.added_ids.clear();
+                    // Kept for its backup, should hiding start again
+                    if let Some(journal) = &mut self.steam_state.journal {
+                        journal.added_ids.clear();
                     }
                 }
             }
@@ -197,8 +398,103 @@ impl Drop for ScopedDeviceHider {
     }
 }
 
+/// Restore device nodes a session left hidden, describing what was done
+///
+/// Nodes that no longer exist, e.g. an unplugged controller, are recreated by
+/// udev with default permissions and are only dropped from the journal.
+pub fn restore_hidden() -> Result<String, Box<dyn Error>> {
+    let mut journal = HideJournal::load().ok_or("No hidden device nodes found")?;
+    let mut restored = Vec::new();
+    let mut failed = Vec::new();
+
+    for path in std::mem::take(&mut journal.system) {
+        if !path.exists() {
+            continue;
+        }
+        match set_permissions(&path, MODE_ROOT_GROUP) {
+            Ok(()) => restored.push(path.display().to_string()),
+            Err(e) => {
+                log::error!("Failed to restore {}: {}", path.display(), e);
+                journal.system.push(path);
+            }
+        }
+    }
+    for entry in std::mem::take(&mut journal.acl) {
+        if !entry.path.exists() {
+            continue;
+        }
+        let acl = format!("u:{}:{}", entry.uid, entry.perms);
+        match run_setfacl(&entry.path, "-m", &acl) {
+            Ok(()) => {
+                audit_log::record(
+                    "setfacl",
+                    &entry.path.display().to_string(),
+                    "removed",
+                    &acl,
+                );
+                restored.push(entry.path.display().to_string());
+            }
+            Err(e) => {
+                log::error!("Failed to restore {}: {}", entry.path.display(), e);
+                journal.acl.push(entry);
+            }
+        }
+    }
+
+    failed.extend(journal.system.iter().map(|p| p.display().to_string()));
+    failed.extend(journal.acl.iter().map(|e| e.path.display().to_string()));
+    journal.save();
+    if !failed.is_empty() {
+        return Err(format!(
+            "Failed to restore {}; try again with sudo",
+            failed.join(", ")
+        )
+        .into());
+    }
+    if restored.is_empty() {
+        return Ok("Hidden device nodes are gone; nothing to restore".into());
+    }
+    Ok(format!("Restored {}", restored.join(", ")))
+}
+
+// Ensure devices are restored when the struct goes out of scope (e.g. app exit/panic).
+impl Drop for ScopedDeviceHider {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
 // --- Steam Config Helpers ---
 
+/// Locates the invoking user's Steam config.vdf, refusing one they don't own.
+///
+/// Under sudo, `$HOME` is usually root's, so the home directory of the user
+/// who ran sudo is looked up instead; editing root's or another account's
+/// Steam config would hide nothing and could corrupt it.
+fn steam_config_path() -> Result<PathBuf, Box<dyn Error>> {
+    let uid = active_uid();
+    let home = user_home(uid).or_else(dirs::home_dir).ok_or(
+        "Could not determine home directory; Steam config path is required for Steam hiding",
+    )?;
+    let path = home.join(".local/share/Steam/config/config.vdf");
+
+    if let Ok(metadata) = fs::metadata(&path)
+        && metadata.uid() != uid
+    {
+        return Err(format!(
+            "Refusing to edit {}: owned by uid {}, not the invoking user (uid {})",
+            path.display(),
+            metadata.uid(),
+            uid
+        )
+        .into());
+    }
+    if std::env::var_os("SUDO_UID").is_some() {
+        log::info!("Running under sudo, using Steam config of uid {}", uid);
+    }
+    Ok(path)
+}
+
 /// Parses the value of the `controller_blacklist` key from Steam's VDF config file.
 ///
 /// Expects lines in the format:
@@ -210,7 +506,7 @@ impl Drop for ScopedDeviceHider {
 /// - None: if the key is not found in the provided content.
 ///
 /// This function does not fully parse VDF, but searches for the key in a line-oriented manner.
-fn parse_controller_blacklist(content: &str) -> Option<String> {
+pub fn parse_controller_blacklist(content: &str) -> Option<String> {
     for line in content.lines() {
         let trimmed = line.trim();
         if trimmed.starts_with("\"controller_blacklist\"") {
@@ -243,8 +539,9 @@ fn parse_controller_blacklist(content: &str) -> Option<String> {
 /// - The function expects the config.vdf to contain an InstallConfigStore section.
 /// - If a controller_blacklist key exists, it will be replaced; otherwise, it will be inserted after the opening brace of InstallConfigStore.
 /// - The function does not fully parse VDF, but operates line-by-line and assumes a typical indentation and structure.
-fn update_steam_config(config_path: &Path, new_blacklist: &str) -> Result<(), Box<dyn Error>> {
+pub fn update_steam_config(config_path: &Path, new_blacklist: &str) -> Result<(), Box<dyn Error>> {
     let content = fs::read_to_string(config_path)?;
+    let old_blacklist = parse_controller_blacklist(&content).unwrap_or_default();
     let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
 
     let mut found = false;
@@ -289,11 +586,209 @@ fn update_steam_config(config_path: &Path, new_blacklist: &str) -> Result<(), Bo
     file.write_all(new_content.as_bytes())?;
     file.sync_all()?;
 
+    audit_log::record(
+        "steam-blacklist",
+        &config_path.display().to_string(),
+        &format!("\"{}\"", old_blacklist),
+        &format!("\"{}\"", new_blacklist),
+    );
     Ok(())
 }
 
+// --- ACL Helpers ---
+
+/// Reads the permissions of a named user ACL entry (e.g. "rw-"), if present.
+///
+/// Uses `getfacl --numeric` so entries are reported by uid, in the format:
+///     user:<uid>:<perms>
+fn get_user_acl(path: &Path, uid: u32) -> io::Result<Option<String>> {
+    let output = Command::new("getfacl")
+        .args(["--omit-header", "--absolute-names", "--numeric"])
+        .arg(path)
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    let prefix = format!("user:{}:", uid);
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        // Drop any trailing "#effective:" comment
+        .map(|perms| {
+            perms
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        }))
+}
+
+/// Runs `setfacl <flag> <entry> <path>`, e.g. to remove or modify an entry.
+fn run_setfacl(path: &Path, flag: &str, entry: &str) -> io::Result<()> {
+    let output = Command::new("setfacl")
+        .arg(flag)
+        .arg(entry)
+        .arg(path)
+        .output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+// --- Seat Helpers ---
+
+/// The seat of the current session, from `XDG_SEAT`, defaulting to seat0.
+pub fn current_seat() -> String {
+    std::env::var("XDG_SEAT")
+        .ok()
+        .filter(|seat| !seat.is_empty())
+        .unwrap_or_else(|| DEFAULT_SEAT.to_string())
+}
+
+/// The seat a device node is assigned to.
+///
+/// Like logind, uses the `ID_SEAT` property of the nearest ancestor that sets
+/// one, defaulting to seat0 when none does.
+pub fn device_seat(path: &Path) -> io::Result<String> {
+    let rdev = fs::metadata(path)?.rdev();
+    let device = Device::from_devnum(udev::DeviceType::Character, rdev)?;
+
+    let seat = std::iter::successors(Some(device), |d| d.parent())
+        .find_map(|d| {
+            d.property_value("ID_SEAT")
+                .and_then(|v| v.to_str())
+                .map(|v| v.to_string())
+        })
+        .unwrap_or_else(|| DEFAULT_SEAT.to_string());
+    Ok(seat)
+}
+
+/// Whether a device node belongs to the current seat, assuming so if unknown.
+pub fn is_on_seat(path: &Path, seat: &str) -> bool {
+    match device_seat(path) {
+        Ok(device_seat) => device_seat == seat,
+        Err(e) => {
+            log::debug!("Could not determine seat of {}: {}", path.display(), e);
+            true
+        }
+    }
+}
+
 // --- Device Discovery Helpers ---
 
+/// Device nodes System hiding would restrict for a controller, so they can
+/// be confirmed before anything changes.
+pub fn system_hide_paths(resource: &GamepadResource) -> io::Result<Vec<PathBuf>> {
+    find_gamepad_devnodes(resource)
+}
+
+/// Finds all devnodes belonging to the same physical device as the gamepad,
+/// falling back to just its event node if it can't be found via udev.
+fn find_gamepad_devnodes(resource: &GamepadResource) -> io::Result<Vec<PathBuf>> {
+    let event_path = resource.path.as_path();
+
+    // Find the specific udev device
+    let device = match find_device_by_path(event_path)? {
+        Some(d) => d,
+        None => return Ok(vec![event_path.to_path_buf()]),
+    };
+
+    // Find the physical parent and all related nodes on this seat
+    let physical_root = find_physical_root(&device);
+    let seat = current_seat();
+    Ok(find_related_devnodes(&physical_root)?
+        .into_iter()
+        .filter(|node| is_on_seat(node, &seat))
+        .collect())
+}
+
+/// Finds the motion sensor event node of the same physical device as the
+/// gamepad, e.g. the "Motion Sensors" node of DualShock 4 or DualSense pads.
+pub fn find_motion_devnode(resource: &GamepadResource) -> io::Result<Option<PathBuf>> {
+    let motion = find_gamepad_devnodes(resource)?
+        .into_iter()
+        .filter(|path| *path != resource.path)
+        .filter(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|s| s.starts_with("event"))
+        })
+        .find(|path| {
+            evdev::Device::open(path)
+                .is_ok_and(|device| device.properties().contains(evdev::PropType::ACCELEROMETER))
+        });
+    Ok(motion)
+}
+
+/// Finds the other event nodes of the same physical controller, such as its
+/// consumer-control keys, grouped by udev parent, a shared uniq, or the same
+/// USB interface.
+///
+/// Motion sensors are left out, as gyro aiming reads them on its own, and so
+/// are gamepads and the `excluded` nodes, e.g. the session's sources, which
+/// gilrs already reads.
+pub fn find_sibling_devnodes(
+    resource: &GamepadResource,
+    excluded: &[PathBuf],
+) -> io::Result<Vec<PathBuf>> {
+    let is_event_node = |path: &Path| {
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|s| s.starts_with("event"))
+    };
+    let mut siblings: Vec<PathBuf> = find_gamepad_devnodes(resource)?
+        .into_iter()
+        .filter(|path| is_event_node(path))
+        .collect();
+
+    // Some nodes hang off another HID parent, e.g. over uhid, but still share
+    // the controller's uniq or USB interface path ("usb-.../inputN"). A
+    // Bluetooth phys is only the adapter's address, shared by every device
+    // paired with it, so it never groups nodes.
+    let uniq = resource.device.unique_name().filter(|s| !s.is_empty());
+    let interface = resource
+        .device
+        .physical_path()
+        .filter(|phys| phys.starts_with("usb-") && phys.contains("/input"));
+    let seat = current_seat();
+    for (path, device) in evdev::enumerate() {
+        let same_uniq = uniq.is_some() && device.unique_name() == uniq;
+        let same_interface = interface.is_some() && device.physical_path() == interface;
+        if (same_uniq || same_interface) && !siblings.contains(&path) && is_on_seat(&path, &seat) {
+            siblings.push(path);
+        }
+    }
+
+    siblings.retain(|path| {
+        *path != resource.path
+            && !excluded.contains(path)
+            && evdev::Device::open(path).is_ok_and(|device| {
+                !device.properties().contains(evdev::PropType::ACCELEROMETER)
+                    && !is_gamepad(&device)
+            })
+    });
+    Ok(siblings)
+}
+
+/// Whether a device has gamepad or joystick buttons, so gilrs reads it
+fn is_gamepad(device: &evdev::Device) -> bool {
+    device.supported_keys().is_some_and(|keys| {
+        keys.contains(evdev::KeyCode::BTN_SOUTH) || keys.contains(evdev::KeyCode::BTN_TRIGGER)
+    })
+}
+
+/// Finds the physical root (USB or Bluetooth) of the device owning an event node.
+pub fn find_physical_root_of(event_path: &Path) -> io::Result<Option<Device>> {
+    Ok(find_device_by_path(event_path)?.map(|device| find_physical_root(&device)))
+}
+
 fn find_device_by_path(target_path: &Path) -> io::Result<Option<Device>> {
     let mut enumerator = Enumerator::new()?;
     enumerator.match_subsystem("input")?;
@@ -351,5 +846,13 @@ fn find_related_devnodes(parent_device: &Device) -> io::Result<Vec<PathBuf>> {
 }
 
 fn set_permissions(path: &Path, mode: u32) -> io::Result<()> {
-    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+    let before = fs::metadata(path)?.permissions().mode() & 0o777;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    audit_log::record(
+        "chmod",
+        &path.display().to_string(),
+        &format!("{:o}", before),
+        &format!("{:o}", mode),
+    );
+    Ok(())
 }
@@ -0,0 +1,328 @@
+use crate::evdev_helpers::{self, AXIS_HALF, VirtualGamepadInfo};
+use evdev::{AbsoluteAxisCode, EventType, InputEvent, KeyCode};
+use log::debug;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+
+const UHID_PATH: &str = "/dev/uhid";
+
+// Event types and sizes from linux/uhid.h
+const UHID_DESTROY: u32 = 1;
+const UHID_GET_REPORT: u32 = 9;
+const UHID_GET_REPORT_REPLY: u32 = 10;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+const UHID_SET_REPORT: u32 = 13;
+const UHID_SET_REPORT_REPLY: u32 = 14;
+/// `UHID_INPUT_REPORT` of `enum uhid_report_type`
+const UHID_INPUT_REPORT: u8 = 2;
+/// Size of the packed `struct uhid_event`, whose largest member is create2
+const UHID_EVENT_SIZE: usize = 4 + 128 + 64 + 64 + 2 + 2 + 4 * 4 + UHID_DATA_MAX;
+const UHID_DATA_MAX: usize = 4096;
+const BUS_USB: u16 = 0x03;
+
+/// Generic HID gamepad: 13 buttons, a hat, four 16-bit stick axes, and two
+/// 16-bit triggers, which hidraw readers such as Wine parse without a driver
+const REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x05, // Usage (Gamepad)
+    0xA1, 0x01, // Collection (Application)
+    0x05, 0x09, //   Usage Page (Button)
+    0x19, 0x01, //   Usage Minimum (1)
+    0x29, 0x0D, //   Usage Maximum (13)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x0D, //   Report Count (13)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x75, 0x03, //   Report Size (3)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x03, //   Input (Constant), padding
+    0x05, 0x01, //   Usage Page (Generic Desktop)
+    0x09, 0x39, //   Usage (Hat Switch)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x07, //   Logical Maximum (7)
+    0x35, 0x00, //   Physical Minimum (0)
+    0x46, 0x3B, 0x01, //   Physical Maximum (315)
+    0x65, 0x14, //   Unit (Degrees)
+    0x75, 0x04, //   Report Size (4)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x42, //   Input (Data, Variable, Absolute, Null State)
+    0x65, 0x00, //   Unit (None)
+    0x81, 0x03, //   Input (Constant), padding
+    0x09, 0x30, //   Usage (X)
+    0x09, 0x31, //   Usage (Y)
+    0x09, 0x33, //   Usage (Rx)
+    0x09, 0x34, //   Usage (Ry)
+    0x09, 0x32, //   Usage (Z)
+    0x09, 0x35, //   Usage (Rz)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x27, 0xFF, 0xFF, 0x00, 0x00, //   Logical Maximum (65535)
+    0x75, 0x10, //   Report Size (16)
+    0x95, 0x06, //   Report Count (6)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0xC0, // End Collection
+];
+
+/// Buttons in report order, by their bit
+const REPORT_BUTTONS: [KeyCode; 13] = [
+    KeyCode::BTN_SOUTH,
+    KeyCode::BTN_EAST,
+    KeyCode::BTN_WEST,
+    KeyCode::BTN_NORTH,
+    KeyCode::BTN_TL,
+    KeyCode::BTN_TR,
+    KeyCode::BTN_TL2,
+    KeyCode::BTN_TR2,
+    KeyCode::BTN_SELECT,
+    KeyCode::BTN_START,
+    KeyCode::BTN_MODE,
+    KeyCode::BTN_THUMBL,
+    KeyCode::BTN_THUMBR,
+];
+
+/// Axes in report order
+const REPORT_AXES: [AbsoluteAxisCode; 6] = [
+    AbsoluteAxisCode::ABS_X,
+    AbsoluteAxisCode::ABS_Y,
+    AbsoluteAxisCode::ABS_RX,
+    AbsoluteAxisCode::ABS_RY,
+    AbsoluteAxisCode::ABS_Z,
+    AbsoluteAxisCode::ABS_RZ,
+];
+
+/// Virtual gamepad state as sent in input reports
+struct ReportState {
+    buttons: u16,
+    /// D-pad as -1..=1 per direction
+    dpad_x: i32,
+    dpad_y: i32,
+    axes: [u16; 6],
+}
+
+impl Default for ReportState {
+    fn default() -> Self {
+        let mid = AXIS_HALF as u16;
+        Self {
+            buttons: 0,
+            dpad_x: 0,
+            dpad_y: 0,
+            axes: [mid, mid, mid, mid, 0, 0],
+        }
+    }
+}
+
+impl ReportState {
+    /// Fold a frame of default layout events in, returning whether anything changed
+    fn apply(&mut self, events: &[InputEvent]) -> bool {
+        let mut changed = false;
+        for event in events {
+            match event.event_type() {
+                EventType::KEY => {
+                    let pressed = event.value() != 0;
+                    let key = KeyCode(event.code());
+                    if let Some(bit) = REPORT_BUTTONS.iter().position(|&k| k == key) {
+                        let mask = 1 << bit;
+                        let buttons = if pressed {
+                            self.buttons | mask
+                        } else {
+                            self.buttons & !mask
+                        };
+                        changed |= buttons != self.buttons;
+                        self.buttons = buttons;
+                        continue;
+                    }
+                    let (axis, direction) = match key {
+                        KeyCode::BTN_DPAD_LEFT => (&mut self.dpad_x, -1),
+                        KeyCode::BTN_DPAD_RIGHT => (&mut self.dpad_x, 1),
+                        KeyCode::BTN_DPAD_UP => (&mut self.dpad_y, -1),
+                        KeyCode::BTN_DPAD_DOWN => (&mut self.dpad_y, 1),
+                        _ => continue,
+                    };
+                    let value = if pressed {
+                        direction
+                    } else if *axis == direction {
+                        0
+                    } else {
+                        *axis
+                    };
+                    changed |= value != *axis;
+                    *axis = value;
+                }
+                EventType::ABSOLUTE => {
+                    let code = AbsoluteAxisCode(event.code());
+                    if let Some(idx) = REPORT_AXES.iter().position(|&a| a == code) {
+                        let value = event.value().clamp(0, u16::MAX as i32) as u16;
+                        changed |= value != self.axes[idx];
+                        self.axes[idx] = value;
+                    }
+                    // The hat mirrors D-pad buttons, which are already folded in
+                }
+                _ => {}
+            }
+        }
+        changed
+    }
+
+    /// Hat switch position, clockwise from up, or 8 for the null state
+    fn hat(&self) -> u8 {
+        match (self.dpad_x, self.dpad_y) {
+            (0, -1) => 0,
+            (1, -1) => 1,
+            (1, 0) => 2,
+            (1, 1) => 3,
+            (0, 1) => 4,
+            (-1, 1) => 5,
+            (-1, 0) => 6,
+            (-1, -1) => 7,
+            _ => 8,
+        }
+    }
+
+    /// Write the input report into `out`, returning its length
+    fn write_report(&self, out: &mut [u8]) -> usize {
+        out[..2].copy_from_slice(&self.buttons.to_le_bytes());
+        out[2] = self.hat();
+        for (i, axis) in self.axes.iter().enumerate() {
+            out[3 + 2 * i..5 + 2 * i].copy_from_slice(&axis.to_le_bytes());
+        }
+        3 + 2 * self.axes.len()
+    }
+}
+
+/// The virtual gamepad presented over uhid, for games reading hidraw
+///
+/// Destroyed when dropped.
+pub struct UhidGamepad {
+    file: File,
+    state: ReportState,
+    /// Buffers for events written and read, reused as reports are sent
+    /// every frame
+    event: Vec<u8>,
+    request: Vec<u8>,
+}
+
+impl UhidGamepad {
+    pub fn create(info: &VirtualGamepadInfo) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(UHID_PATH)?;
+
+        let mut event = vec![0u8; UHID_EVENT_SIZE];
+        event[..4].copy_from_slice(&UHID_CREATE2.to_ne_bytes());
+        let name = info.name.as_bytes();
+        let name_len = name.len().min(127);
+        event[4..4 + name_len].copy_from_slice(&name[..name_len]);
+        // Marked like the uinput device, so the evdev node the kernel adds is excluded too
+        let phys = evdev_helpers::virtual_phys();
+        event[4 + 128..4 + 128 + phys.len()].copy_from_slice(phys.as_bytes());
+        // Unlike uinput, uhid can set uniq, so sessions tell apart there too
+        let uniq = format!("ctrlassist-{}", evdev_helpers::session_id());
+        event[4 + 128 + 64..4 + 128 + 64 + uniq.len()].copy_from_slice(uniq.as_bytes());
+        let mut offset = 4 + 128 + 64 + 64;
+        for field in [REPORT_DESCRIPTOR.len() as u16, BUS_USB] {
+            event[offset..offset + 2].copy_from_slice(&field.to_ne_bytes());
+            offset += 2;
+        }
+        for field in [
+            u32::from(info.vendor_id.unwrap_or_default()),
+            u32::from(info.product_id.unwrap_or_default()),
+            0, // version
+            0, // country
+        ] {
+            event[offset..offset + 4].copy_from_slice(&field.to_ne_bytes());
+            offset += 4;
+        }
+        event[offset..offset + REPORT_DESCRIPTOR.len()].copy_from_slice(REPORT_DESCRIPTOR);
+
+        let mut gamepad = Self {
+            file,
+            state: ReportState::default(),
+            event: vec![0u8; UHID_EVENT_SIZE],
+            request: vec![0u8; UHID_EVENT_SIZE],
+        };
+        gamepad.file.write_all(&event)?;
+        gamepad.send_report()?;
+        Ok(gamepad)
+    }
+
+    /// Mirror a frame of default layout events, before any layout conversion
+    pub fn update(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        self.drain_requests();
+        if self.state.apply(events) {
+            self.send_report()?;
+        }
+        Ok(())
+    }
+
+    fn send_report(&mut self) -> io::Result<()> {
+        let event = &mut self.event;
+        event[..4].copy_from_slice(&UHID_INPUT2.to_ne_bytes());
+        let len = self.state.write_report(&mut event[6..]);
+        event[4..6].copy_from_slice(&(len as u16).to_ne_bytes());
+        self.file.write_all(&event[..4 + 2 + UHID_DATA_MAX])
+    }
+
+    /// Answer get and set report requests, which the kernel otherwise waits
+    /// on until they time out, and discard start, open, and the like, so the
+    /// kernel's queue doesn't fill up
+    fn drain_requests(&mut self) {
+        while let Ok(n) = self.file.read(&mut self.request) {
+            if n < 4 {
+                break;
+            }
+            let request = &self.request;
+            let kind = u32::from_ne_bytes([request[0], request[1], request[2], request[3]]);
+            let id = [request[4], request[5], request[6], request[7]];
+            let result = match kind {
+                // Only the input report exists; there are no feature reports
+                UHID_GET_REPORT if request[9] == UHID_INPUT_REPORT => self.reply_get_report(id, 0),
+                UHID_GET_REPORT => self.reply_get_report(id, libc::EIO as u16),
+                UHID_SET_REPORT => self.reply_set_report(id, libc::EIO as u16),
+                _ => {
+                    debug!("uhid: ignoring request of type {}", kind);
+                    Ok(())
+                }
+            };
+            if let Err(e) = result {
+                debug!("uhid: failed to answer request of type {}: {}", kind, e);
+            }
+        }
+    }
+
+    /// Reply to `UHID_GET_REPORT` with the current input report, or an error
+    fn reply_get_report(&mut self, id: [u8; 4], err: u16) -> io::Result<()> {
+        let event = &mut self.event;
+        event[..4].copy_from_slice(&UHID_GET_REPORT_REPLY.to_ne_bytes());
+        event[4..8].copy_from_slice(&id);
+        event[8..10].copy_from_slice(&err.to_ne_bytes());
+        let len = if err == 0 {
+            self.state.write_report(&mut event[12..])
+        } else {
+            0
+        };
+        event[10..12].copy_from_slice(&(len as u16).to_ne_bytes());
+        self.file.write_all(&event[..4 + 8 + UHID_DATA_MAX])
+    }
+
+    /// Reply to `UHID_SET_REPORT`, which a gamepad without output or feature
+    /// reports can only refuse
+    fn reply_set_report(&mut self, id: [u8; 4], err: u16) -> io::Result<()> {
+        let event = &mut self.event;
+        event[..4].copy_from_slice(&UHID_SET_REPORT_REPLY.to_ne_bytes());
+        event[4..8].copy_from_slice(&id);
+        event[8..10].copy_from_slice(&err.to_ne_bytes());
+        self.file.write_all(&event[..4 + 4 + 2])
+    }
+}
+
+impl Drop for UhidGamepad {
+    fn drop(&mut self) {
+        self.event.fill(0);
+        self.event[..4].copy_from_slice(&UHID_DESTROY.to_ne_bytes());
+        let _ = self.file.write_all(&self.event);
+    }
+}